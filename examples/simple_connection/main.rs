@@ -182,6 +182,13 @@ async fn run_server(transport_type: TransportType, port: u16) -> Result<()> {
                             TransportEvent::Closed(closed_connection) => {
                                 info!("Connection closed: {}", closed_connection);
                             }
+                            TransportEvent::Malformed(malformed) => {
+                                info!(
+                                    "Malformed message from {}: {}",
+                                    malformed.source, malformed.error
+                                );
+                            }
+                            _ => {}
                         }
                     }
                     None => {
@@ -3,11 +3,20 @@ use crate::transaction::key::{TransactionKey, TransactionRole};
 use crate::transaction::transaction::Transaction;
 use crate::transaction::{make_via_branch, random_text, CNONCE_LEN};
 use crate::Result;
-use rsip::headers::auth::AuthQop;
-use rsip::prelude::{HasHeaders, HeadersExt, ToTypedHeader};
+use rsip::headers::auth::{Algorithm, AuthQop, Qop};
+use rsip::prelude::{HasHeaders, HeadersExt, ToTypedHeader, UntypedHeader};
 use rsip::services::DigestGenerator;
 use rsip::typed::{Authorization, ProxyAuthorization};
-use rsip::{Header, Param, Response};
+use rsip::{Header, Param, Request, Response};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+/// How long a nonce issued by [`DigestChallenge::for_request`] is accepted
+pub const NONCE_LIFETIME: Duration = Duration::from_secs(300);
 
 /// SIP Authentication Credentials
 ///
@@ -74,7 +83,7 @@ use rsip::{Header, Param, Response};
 ///     offer: Some(sdp_bytes),
 ///     contact: rsip::Uri::try_from("sip:alice@192.168.1.100:5060")?,
 ///     credential: Some(credential),
-///     headers: None,
+///     ..Default::default()
 /// };
 /// # Ok(())
 /// # }
@@ -86,6 +95,476 @@ pub struct Credential {
     pub realm: Option<String>,
 }
 
+/// Parsed Digest Authentication Challenge
+///
+/// `DigestChallenge` is the parsed form of a `WWW-Authenticate` or
+/// `Proxy-Authenticate` header value using the Digest scheme. It is used by
+/// [`handle_client_authenticate`] internally, and is exposed so applications
+/// can implement their own retry logic on top of [`compute_response`].
+///
+/// # Fields
+///
+/// * `realm` - Protection realm the credentials apply to
+/// * `nonce` - Server nonce to be hashed into the response
+/// * `qop` - Quality of protection offered by the server; `auth` is preferred
+///   when the server offers several
+/// * `algorithm` - Hash algorithm, `None` means MD5
+/// * `opaque` - Opaque value that must be echoed back unchanged
+/// * `stale` - The previous request was rejected only because its nonce expired
+///
+/// # Examples
+///
+/// ```rust
+/// # use rsipstack::dialog::authenticate::DigestChallenge;
+/// # fn example() -> rsipstack::Result<()> {
+/// let challenge = DigestChallenge::parse(
+///     r#"Digest realm="example.com", nonce="abc123", qop="auth", stale=TRUE"#,
+/// )?;
+/// assert_eq!(challenge.realm, "example.com");
+/// assert!(challenge.stale);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DigestChallenge {
+    pub realm: String,
+    pub nonce: String,
+    pub qop: Option<Qop>,
+    pub algorithm: Option<Algorithm>,
+    pub opaque: Option<String>,
+    pub stale: bool,
+}
+
+impl DigestChallenge {
+    /// Parse a challenge from a `WWW-Authenticate`/`Proxy-Authenticate` value
+    ///
+    /// Parameter names are matched case-insensitively, values may be quoted
+    /// or bare tokens, and a `qop` list such as `"auth,auth-int"` is accepted.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(DigestChallenge)` - The parsed challenge
+    /// * `Err(Error)` - The scheme is not Digest, or `realm`/`nonce` is missing
+    pub fn parse(value: &str) -> Result<Self> {
+        let value = value.trim();
+        let (scheme, params) = value.split_once(char::is_whitespace).unwrap_or((value, ""));
+        if !scheme.eq_ignore_ascii_case("digest") {
+            return Err(crate::Error::Error(format!(
+                "unsupported authentication scheme: {}",
+                scheme
+            )));
+        }
+
+        let mut realm = None;
+        let mut nonce = None;
+        let mut qop = None;
+        let mut algorithm = None;
+        let mut opaque = None;
+        let mut stale = false;
+
+        for (name, value) in split_auth_params(params) {
+            match name.to_ascii_lowercase().as_str() {
+                "realm" => realm = Some(value),
+                "nonce" => nonce = Some(value),
+                "opaque" => opaque = Some(value),
+                "stale" => stale = value.eq_ignore_ascii_case("true"),
+                "algorithm" => algorithm = Some(Algorithm::try_from(value.as_str())?),
+                "qop" => {
+                    let offered = value
+                        .split(',')
+                        .filter_map(|q| Qop::try_from(q.trim()).ok())
+                        .collect::<Vec<_>>();
+                    qop = offered
+                        .iter()
+                        .find(|q| **q == Qop::Auth)
+                        .or(offered.first())
+                        .cloned();
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            realm: realm.ok_or(crate::Error::Error("missing realm in challenge".to_string()))?,
+            nonce: nonce.ok_or(crate::Error::Error("missing nonce in challenge".to_string()))?,
+            qop,
+            algorithm,
+            opaque,
+            stale,
+        })
+    }
+
+    /// Create a challenge for an inbound request (UAS side)
+    ///
+    /// The nonce is bound to the request's Call-ID and carries its issue
+    /// time, so [`verify_authorization`] can check the retried request
+    /// without keeping any state. It is accepted for [`NONCE_LIFETIME`].
+    ///
+    /// # Parameters
+    ///
+    /// * `request` - The request to challenge
+    /// * `realm` - Protection realm announced to the client
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::authenticate::DigestChallenge;
+    /// # fn example(request: rsip::Request) -> rsipstack::Result<()> {
+    /// let challenge = DigestChallenge::for_request(&request, "example.com")?;
+    /// let header = rsip::Header::ProxyAuthenticate(challenge.to_string().into());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn for_request(request: &Request, realm: &str) -> Result<Self> {
+        let call_id = request.call_id_header()?.value().to_string();
+        let issued = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Ok(Self {
+            realm: realm.to_string(),
+            nonce: format!("{:016x}{:016x}", issued, nonce_mac(issued, realm, &call_id)),
+            qop: Some(Qop::Auth),
+            algorithm: Some(Algorithm::Md5),
+            opaque: None,
+            stale: false,
+        })
+    }
+
+    /// Extract the challenge from a 401 or 407 response
+    ///
+    /// Looks at `WWW-Authenticate` first and falls back to `Proxy-Authenticate`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok((DigestChallenge, bool))` - The challenge, and whether it came
+    ///   from `Proxy-Authenticate`
+    /// * `Err(Error)` - No challenge header was found or it could not be parsed
+    pub fn from_response(resp: &Response) -> Result<(Self, bool)> {
+        if let Some(h) = resp.www_authenticate_header() {
+            return Ok((Self::parse(h.value())?, false));
+        }
+        match rsip::header_opt!(resp.headers().iter(), Header::ProxyAuthenticate) {
+            Some(h) => Ok((Self::parse(h.value())?, true)),
+            None => Err(crate::Error::Error(
+                "missing proxy/www authenticate".to_string(),
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for DigestChallenge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Digest realm=\"{}\", nonce=\"{}\"",
+            self.realm, self.nonce
+        )?;
+        if let Some(algorithm) = &self.algorithm {
+            write!(f, ", algorithm={}", algorithm)?;
+        }
+        if let Some(qop) = &self.qop {
+            write!(f, ", qop=\"{}\"", qop)?;
+        }
+        if let Some(opaque) = &self.opaque {
+            write!(f, ", opaque=\"{}\"", opaque)?;
+        }
+        if self.stale {
+            write!(f, ", stale=TRUE")?;
+        }
+        Ok(())
+    }
+}
+
+fn nonce_mac(issued: u64, realm: &str, call_id: &str) -> u64 {
+    // keyed with random keys once per process
+    static KEY: OnceLock<RandomState> = OnceLock::new();
+    let mut hasher = KEY.get_or_init(RandomState::new).build_hasher();
+    (issued, realm, call_id).hash(&mut hasher);
+    hasher.finish()
+}
+
+fn check_nonce(nonce: &str, realm: &str, call_id: &str) -> bool {
+    if nonce.len() != 32 || !nonce.is_ascii() {
+        return false;
+    }
+    let (issued, mac) = nonce.split_at(16);
+    let (issued, mac) = match (
+        u64::from_str_radix(issued, 16),
+        u64::from_str_radix(mac, 16),
+    ) {
+        (Ok(issued), Ok(mac)) => (issued, mac),
+        _ => return false,
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    mac == nonce_mac(issued, realm, call_id)
+        && now.saturating_sub(issued) <= NONCE_LIFETIME.as_secs()
+}
+
+/// Most nonces [`NonceCounts`] remembers, the oldest are forgotten first
+pub(crate) const MAX_NONCE_COUNTS: usize = 4096;
+
+/// Nonce counts accepted by [`verify_authorization`]
+///
+/// A count must be higher than any count accepted before for the same
+/// nonce, so a captured request cannot be replayed while the nonce is
+/// still valid. Nonces older than [`NONCE_LIFETIME`] are forgotten, since
+/// [`verify_authorization`] rejects them anyway, and at most
+/// `MAX_NONCE_COUNTS` are kept.
+///
+/// The endpoint keeps one in [`EndpointInner::nonce_counts`] for
+/// [`ServerInviteDialog::verify_authorization`].
+///
+/// [`EndpointInner::nonce_counts`]: crate::transaction::endpoint::EndpointInner::nonce_counts
+/// [`ServerInviteDialog::verify_authorization`]: crate::dialog::server_dialog::ServerInviteDialog::verify_authorization
+#[derive(Default)]
+pub struct NonceCounts {
+    counts: Mutex<NonceCountMap>,
+}
+
+#[derive(Default)]
+struct NonceCountMap {
+    counts: HashMap<String, u8>,
+    order: VecDeque<(Instant, String)>,
+}
+
+impl NonceCounts {
+    /// Accept the nonce count of a verified qop response
+    ///
+    /// Returns `false` for a count not higher than the last one accepted
+    /// for `nonce`.
+    pub fn accept(&self, nonce: &str, nc: u8) -> bool {
+        let mut map = match self.counts.lock() {
+            Ok(map) => map,
+            Err(e) => {
+                debug!("nonce counts are poisoned: {}", e);
+                return false;
+            }
+        };
+        let now = Instant::now();
+        while let Some((at, _)) = map.order.front() {
+            if now.duration_since(*at) <= NONCE_LIFETIME && map.order.len() < MAX_NONCE_COUNTS {
+                break;
+            }
+            if let Some((_, expired)) = map.order.pop_front() {
+                map.counts.remove(&expired);
+            }
+        }
+        match map.counts.get_mut(nonce) {
+            Some(last) if nc <= *last => false,
+            Some(last) => {
+                *last = nc;
+                true
+            }
+            None => {
+                map.counts.insert(nonce.to_string(), nc);
+                map.order.push_back((now, nonce.to_string()));
+                true
+            }
+        }
+    }
+
+    /// Number of nonces remembered
+    pub fn len(&self) -> usize {
+        self.counts
+            .lock()
+            .map(|map| map.counts.len())
+            .unwrap_or_default()
+    }
+
+    /// Whether no nonce is remembered
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Username of the `Authorization` or `Proxy-Authorization` of a request
+pub fn authorization_username(request: &Request) -> Option<String> {
+    find_authorization(request).map(|auth| auth.username)
+}
+
+fn find_authorization(request: &Request) -> Option<Authorization> {
+    request.headers.iter().find_map(|h| match h {
+        Header::Authorization(auth) => auth.typed().ok(),
+        Header::ProxyAuthorization(auth) => auth.typed().ok().map(|auth| auth.0),
+        _ => None,
+    })
+}
+
+/// Verify the digest credentials of a request (UAS side)
+///
+/// Checks the `Authorization` or `Proxy-Authorization` of a request sent
+/// in answer to a challenge from [`DigestChallenge::for_request`]: the
+/// username and realm must match, the digest uri must be the
+/// Request-URI, the nonce must have been issued for this Call-ID within
+/// [`NONCE_LIFETIME`], and the digest response must match the one computed
+/// from `cred`.
+///
+/// With qop, the nonce count must also be higher than the last one accepted
+/// in `counts` for the nonce, so a request verifies only once; a replay of
+/// it fails.
+///
+/// # Parameters
+///
+/// * `request` - The retried request carrying the credentials
+/// * `realm` - Realm of the challenge
+/// * `cred` - Expected credentials of the user
+/// * `counts` - Nonce counts accepted so far, shared by all checks
+///
+/// # Returns
+///
+/// `true` if the request is authenticated
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use rsipstack::dialog::authenticate::{
+/// #     authorization_username, verify_authorization, Credential, NonceCounts,
+/// # };
+/// # fn example(request: rsip::Request, counts: &NonceCounts) {
+/// let username = authorization_username(&request).unwrap_or_default();
+/// let credential = Credential {
+///     username,
+///     password: "secret123".to_string(),
+///     realm: None,
+/// };
+/// if verify_authorization(&request, "example.com", &credential, counts) {
+///     println!("authenticated");
+/// }
+/// # }
+/// ```
+pub fn verify_authorization(
+    request: &Request,
+    realm: &str,
+    cred: &Credential,
+    counts: &NonceCounts,
+) -> bool {
+    let auth = match find_authorization(request) {
+        Some(auth) => auth,
+        None => return false,
+    };
+    let call_id = match request.call_id_header() {
+        Ok(call_id) => call_id.value().to_string(),
+        Err(_) => return false,
+    };
+    if auth.username != cred.username
+        || auth.realm != realm
+        || auth.uri != request.uri
+        || !check_nonce(&auth.nonce, realm, &call_id)
+    {
+        return false;
+    }
+    let challenge = DigestChallenge {
+        realm: auth.realm.clone(),
+        nonce: auth.nonce.clone(),
+        qop: None,
+        algorithm: auth.algorithm,
+        opaque: None,
+        stale: false,
+    };
+    let expected = compute_response(
+        &challenge,
+        cred,
+        &request.method,
+        &auth.uri,
+        auth.qop.as_ref(),
+    );
+    if !expected.eq_ignore_ascii_case(&auth.response) {
+        return false;
+    }
+    match &auth.qop {
+        Some(AuthQop::Auth { nc, .. }) | Some(AuthQop::AuthInt { nc, .. }) => {
+            counts.accept(&auth.nonce, *nc)
+        }
+        None => true,
+    }
+}
+
+fn split_auth_params(params: &str) -> Vec<(String, String)> {
+    let mut result = vec![];
+    let mut rest = params.trim();
+    while !rest.is_empty() {
+        let (name, after) = match rest.split_once('=') {
+            Some(kv) => kv,
+            None => break,
+        };
+        let name = name.trim().trim_start_matches(',').trim().to_string();
+        let after = after.trim_start();
+        let (value, remaining) = if let Some(quoted) = after.strip_prefix('"') {
+            match quoted.find('"') {
+                Some(end) => (&quoted[..end], &quoted[end + 1..]),
+                None => (quoted, ""),
+            }
+        } else {
+            match after.find(',') {
+                Some(end) => (&after[..end], &after[end..]),
+                None => (after, ""),
+            }
+        };
+        result.push((name, value.trim().to_string()));
+        rest = remaining.trim_start().trim_start_matches(',').trim_start();
+    }
+    result
+}
+
+/// Compute the digest response for a challenge
+///
+/// Computes the `response` value of an `Authorization` or
+/// `Proxy-Authorization` header according to RFC 2617 / RFC 7616.
+///
+/// # Parameters
+///
+/// * `challenge` - The parsed challenge
+/// * `cred` - User credentials
+/// * `method` - Method of the request being authenticated
+/// * `uri` - Request-URI of the request being authenticated
+/// * `qop` - The qop, cnonce and nonce count to use, or `None` for RFC 2069 style digests
+///
+/// # Returns
+///
+/// The hex encoded digest response
+///
+/// # Examples
+///
+/// ```rust
+/// # use rsipstack::dialog::authenticate::{compute_response, Credential, DigestChallenge};
+/// # fn example() -> rsipstack::Result<()> {
+/// let challenge = DigestChallenge::parse(r#"Digest realm="example.com", nonce="abc123""#)?;
+/// let credential = Credential {
+///     username: "alice".to_string(),
+///     password: "secret123".to_string(),
+///     realm: None,
+/// };
+/// let uri = rsip::Uri::try_from("sip:example.com")?;
+/// let response = compute_response(&challenge, &credential, &rsip::Method::Register, &uri, None);
+/// assert_eq!(response.len(), 32);
+/// # Ok(())
+/// # }
+/// ```
+pub fn compute_response(
+    challenge: &DigestChallenge,
+    cred: &Credential,
+    method: &rsip::Method,
+    uri: &rsip::Uri,
+    qop: Option<&AuthQop>,
+) -> String {
+    DigestGenerator {
+        username: cred.username.as_str(),
+        password: cred.password.as_str(),
+        // Use MD5 as default algorithm if none specified (RFC 2617 compatibility)
+        algorithm: challenge.algorithm.unwrap_or(Algorithm::Md5),
+        nonce: challenge.nonce.as_str(),
+        method,
+        qop,
+        uri,
+        realm: challenge.realm.as_str(),
+    }
+    .compute()
+}
+
 /// Handle client-side authentication challenge
 ///
 /// This function processes a 401 Unauthorized or 407 Proxy Authentication Required
@@ -190,17 +669,11 @@ pub async fn handle_client_authenticate(
     resp: Response,
     cred: &Credential,
 ) -> Result<Transaction> {
-    let header = match resp.www_authenticate_header() {
-        Some(h) => Header::WwwAuthenticate(h.clone()),
-        None => {
-            let proxy_header = rsip::header_opt!(resp.headers().iter(), Header::ProxyAuthenticate);
-            let proxy_header = proxy_header.ok_or(crate::Error::DialogError(
-                "missing proxy/www authenticate".to_string(),
-                DialogId::try_from(&tx.original)?,
-            ))?;
-            Header::ProxyAuthenticate(proxy_header.clone())
-        }
-    };
+    let (challenge, is_proxy) =
+        DigestChallenge::from_response(&resp).map_err(|e| match DialogId::try_from(&tx.original) {
+            Ok(id) => crate::Error::DialogError(e.to_string(), id),
+            Err(e) => e,
+        })?;
 
     let mut new_req = tx.original.clone();
     new_req.cseq_header_mut()?.mut_seq(new_seq)?;
@@ -210,29 +683,18 @@ pub async fn handle_client_authenticate(
         nc: 1,
     };
 
-    let challenge = match &header {
-        Header::WwwAuthenticate(h) => h.typed()?,
-        Header::ProxyAuthenticate(h) => h.typed()?.0,
-        _ => unreachable!(),
-    };
-
-    // Use MD5 as default algorithm if none specified (RFC 2617 compatibility)
-    let algorithm = challenge.algorithm.unwrap_or(rsip::headers::auth::Algorithm::Md5);
-    
-    let response = DigestGenerator {
-        username: cred.username.as_str(),
-        password: cred.password.as_str(),
-        algorithm,
-        nonce: challenge.nonce.as_str(),
-        method: &tx.original.method,
-        qop: Some(&auth_qop),
-        uri: &tx.original.uri,
-        realm: challenge.realm.as_str(),
-    }
-    .compute();
+    let algorithm = challenge.algorithm.unwrap_or(Algorithm::Md5);
+    let realm = challenge.realm.clone();
+    let response = compute_response(
+        &challenge,
+        cred,
+        &tx.original.method,
+        &tx.original.uri,
+        Some(&auth_qop),
+    );
 
     let auth = Authorization {
-        scheme: challenge.scheme,
+        scheme: rsip::headers::auth::Scheme::Digest,
         username: cred.username.clone(),
         realm: challenge.realm,
         nonce: challenge.nonce,
@@ -251,26 +713,19 @@ pub async fn handle_client_authenticate(
     params.push(Param::Other("rport".into(), None));
     new_req.headers_mut().unique_push(via_header.into());
 
-    new_req.headers_mut().retain(|h| {
-        !matches!(
-            h,
-            Header::ProxyAuthenticate(_)
-                | Header::Authorization(_)
-                | Header::WwwAuthenticate(_)
-                | Header::ProxyAuthorization(_)
-        )
+    // keep the credentials of other realms, so a request challenged by a
+    // proxy and then by the registrar answers both
+    new_req.headers_mut().retain(|h| match h {
+        Header::ProxyAuthenticate(_) | Header::WwwAuthenticate(_) => false,
+        Header::Authorization(auth) => auth.typed().is_ok_and(|auth| auth.realm != realm),
+        Header::ProxyAuthorization(auth) => auth.typed().is_ok_and(|auth| auth.0.realm != realm),
+        _ => true,
     });
 
-    match header {
-        Header::WwwAuthenticate(_) => {
-            new_req.headers_mut().unique_push(auth.into());
-        }
-        Header::ProxyAuthenticate(_) => {
-            new_req
-                .headers_mut()
-                .unique_push(ProxyAuthorization(auth).into());
-        }
-        _ => unreachable!(),
+    if is_proxy {
+        new_req.headers_mut().push(ProxyAuthorization(auth).into());
+    } else {
+        new_req.headers_mut().push(auth.into());
     }
     let key = TransactionKey::from_request(&new_req, TransactionRole::Client)?;
     let mut new_tx = Transaction::new_client(
@@ -282,3 +737,43 @@ pub async fn handle_client_authenticate(
     new_tx.destination = tx.destination.clone();
     Ok(new_tx)
 }
+
+/// Budget of authenticated retries for one request
+///
+/// Every challenge is answered until `max` retries were sent. A challenge
+/// for a realm that was already answered means the credentials were
+/// rejected, unless it is flagged `stale=true`, which only asks for a
+/// fresh nonce.
+pub(crate) struct AuthRetries {
+    max: u32,
+    sent: u32,
+    realms: Vec<String>,
+}
+
+impl AuthRetries {
+    pub(crate) fn new(max: u32) -> Self {
+        Self {
+            max,
+            sent: 0,
+            realms: vec![],
+        }
+    }
+
+    /// Whether the 401/407 `resp` should be answered with a retry
+    pub(crate) fn retry(&mut self, resp: &Response) -> bool {
+        if self.sent >= self.max {
+            debug!("no authenticated retry left after {}", self.sent);
+            return false;
+        }
+        // a broken challenge is reported by handle_client_authenticate
+        if let Ok((challenge, _)) = DigestChallenge::from_response(resp) {
+            if !challenge.stale && self.realms.contains(&challenge.realm) {
+                debug!("credentials for realm {} rejected", challenge.realm);
+                return false;
+            }
+            self.realms.push(challenge.realm);
+        }
+        self.sent += 1;
+        true
+    }
+}
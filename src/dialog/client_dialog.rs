@@ -1,15 +1,16 @@
 use super::dialog::DialogInnerRef;
 use super::DialogId;
 use crate::dialog::{
-    authenticate::handle_client_authenticate,
-    dialog::{DialogState, TerminatedReason},
+    authenticate::{handle_client_authenticate, AuthRetries},
+    dialog::{DialogState, Q850Cause, SessionId, TerminatedReason},
 };
-use crate::rsip_ext::RsipResponseExt;
+use crate::rsip_ext::{extract_uri_from_contact, sanitize_for_log, RsipResponseExt};
 use crate::transaction::transaction::Transaction;
 use crate::Result;
 use rsip::prelude::{HeadersExt, UntypedHeader};
-use rsip::{Response, SipMessage, StatusCode, Header};
+use rsip::{Request, Response, SipMessage, StatusCode, Header};
 use rsip::headers::Route;
+use std::collections::HashSet;
 use std::sync::atomic::Ordering;
 use tokio_util::sync::CancellationToken;
 use tracing::{info, trace, warn};
@@ -92,6 +93,26 @@ pub struct ClientInviteDialog {
     pub(super) inner: DialogInnerRef,
 }
 
+/// A forked early dialog of an outgoing INVITE
+///
+/// When an INVITE forks, each branch that answers with a provisional or
+/// final response carrying its own To tag creates a separate early dialog.
+/// All of them arrive on the one INVITE client transaction, so branches
+/// are told apart by their To tag. `EarlyBranch` records enough about each
+/// of them to clean up the branches that lose once one of them is answered.
+///
+/// # Fields
+///
+/// * `to_tag` - To tag identifying the early dialog
+/// * `invite` - The INVITE the branch answered, used to build a matching CANCEL
+/// * `answer` - The 2xx response if the branch answered
+#[derive(Clone, Debug)]
+pub struct EarlyBranch {
+    pub to_tag: String,
+    pub invite: Request,
+    pub answer: Option<Response>,
+}
+
 impl ClientInviteDialog {
     /// Get the dialog identifier
     ///
@@ -109,6 +130,21 @@ impl ClientInviteDialog {
         &self.inner.cancel_token
     }
 
+    /// Get the end-to-end session identifier (RFC 7989)
+    ///
+    /// `remote` is filled in once the peer's `Session-ID` arrives.
+    pub fn session_id(&self) -> SessionId {
+        self.inner.session_id()
+    }
+
+    /// Get the `Allow` and `Supported` headers advertising our capabilities
+    ///
+    /// Meant for in-dialog requests that are answered, such as BYE. ACK and
+    /// CANCEL never carry them.
+    pub fn capability_headers(&self) -> Vec<Header> {
+        self.inner.capability_headers()
+    }
+
     /// Set the public address for future Via headers
     ///
     /// Sets the discovered public address to be used in Via headers for
@@ -142,7 +178,7 @@ impl ClientInviteDialog {
         self.inner.set_public_address(addr);
     }
     
-    /// Update route set from 200 OK response (UAC behavior)
+    /// Update route set from 2xx response (UAC behavior)
     /// 
     /// According to RFC 3261 section 12.1.1, the UAC builds the route set
     /// from Record-Route headers in the 200 OK response, in reverse order.
@@ -150,7 +186,13 @@ impl ClientInviteDialog {
         let mut route_set = vec![];
         for h in resp.headers.iter() {
             if let Header::RecordRoute(rr) = h {
-                route_set.push(Route::from(rr.value()));
+                // a single header may carry several comma separated entries
+                for entry in rr.value().split(',') {
+                    let entry = entry.trim();
+                    if !entry.is_empty() {
+                        route_set.push(Route::from(entry));
+                    }
+                }
             }
         }
         
@@ -189,12 +231,36 @@ impl ClientInviteDialog {
     /// # }
     /// ```
     pub async fn bye(&self) -> Result<()> {
+        self.bye_with_headers(None).await
+    }
+
+    /// Send a BYE request carrying extra headers
+    ///
+    /// Works like [`ClientInviteDialog::bye`], adding `headers` to the BYE, e.g. a
+    /// `Reason` or the dialog's [`ClientInviteDialog::capability_headers`].
+    ///
+    /// # Parameters
+    ///
+    /// * `headers` - Optional additional headers to include
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::client_dialog::ClientInviteDialog;
+    /// # async fn example() -> rsipstack::Result<()> {
+    /// # let dialog: ClientInviteDialog = todo!();
+    /// let headers = dialog.capability_headers();
+    /// dialog.bye_with_headers(Some(headers)).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn bye_with_headers(&self, headers: Option<Vec<Header>>) -> Result<()> {
         if !self.inner.is_confirmed() {
             return Ok(());
         }
         let request = self
             .inner
-            .make_request(rsip::Method::Bye, None, None, None, None, None)?;
+            .make_request(rsip::Method::Bye, None, None, None, headers, None)?;
 
         match self.inner.do_request(request).await {
             Ok(_) => {}
@@ -230,16 +296,251 @@ impl ClientInviteDialog {
     /// # }
     /// ```
     pub async fn cancel(&self) -> Result<()> {
-        let mut cancel_request = self.inner.initial_request.clone();
-        cancel_request.method = rsip::Method::Cancel;
-        cancel_request
-            .cseq_header_mut()?
-            .mut_seq(self.inner.get_local_seq())?;
-        cancel_request.body = vec![];
+        let cancel_request =
+            Self::make_cancel(&self.inner.initial_request, self.inner.get_local_seq())?;
         self.inner.do_request(cancel_request).await?;
         Ok(())
     }
 
+    /// Get the SDP received in a provisional response
+    ///
+    /// Returns the body of the most recent 18x response that carried one,
+    /// typically the early media answer sent with 183 Session Progress.
+    /// The same body is also delivered with [`DialogState::Early`].
+    ///
+    /// # Returns
+    ///
+    /// * `Some(Vec<u8>)` - The early media SDP
+    /// * `None` - No provisional response carried a body
+    pub fn early_media_sdp(&self) -> Option<Vec<u8>> {
+        self.inner.early_media_sdp.lock().unwrap().clone()
+    }
+
+    /// Get the SDP answer of the established session
+    ///
+    /// Returns the body of the 200 OK that confirmed the dialog. When the 200 OK
+    /// has no body the early media SDP is reused, as the answer was already given
+    /// in the provisional response. This value supersedes
+    /// [`ClientInviteDialog::early_media_sdp`] once the dialog is confirmed.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(Vec<u8>)` - The final SDP answer
+    /// * `None` - The dialog is not confirmed yet or no SDP was received
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::client_dialog::ClientInviteDialog;
+    /// # fn example(dialog: ClientInviteDialog) {
+    /// if let Some(answer) = dialog.remote_sdp() {
+    ///     println!("answer: {}", String::from_utf8_lossy(&answer));
+    /// }
+    /// # }
+    /// ```
+    pub fn remote_sdp(&self) -> Option<Vec<u8>> {
+        self.inner.remote_sdp.lock().unwrap().clone()
+    }
+
+    /// Get the diagnostics of the failure that terminated the dialog
+    ///
+    /// Set before the dialog moves to [`DialogState::Terminated`] with
+    /// `TerminatedReason::UasOther`, so it can be read when that state is
+    /// received.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(String)` - The text of the `Warning` and `Error-Info` headers
+    ///   of the final failure response, joined with `; `
+    /// * `None` - The dialog did not fail or the response had neither header
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::client_dialog::ClientInviteDialog;
+    /// # fn example(dialog: ClientInviteDialog) {
+    /// if let Some(details) = dialog.failure_details() {
+    ///     println!("call failed: {}", details);
+    /// }
+    /// # }
+    /// ```
+    pub fn failure_details(&self) -> Option<String> {
+        self.inner.failure_details.lock().unwrap().clone()
+    }
+
+    /// Get the forked early dialogs seen for this INVITE
+    ///
+    /// Returns every branch that answered the INVITE with a To tag, including
+    /// the one that eventually won. Branches are removed once they have been
+    /// cleaned up by [`ClientInviteDialog::cancel_others`].
+    pub fn early_branches(&self) -> Vec<EarlyBranch> {
+        self.inner.early_branches.lock().unwrap().clone()
+    }
+
+    /// Record a response for a forked branch of the INVITE
+    ///
+    /// Responses without a To tag do not create an early dialog and are
+    /// ignored. A 2xx response marks the branch as answered.
+    pub(super) fn track_branch(&self, invite: &Request, resp: &Response) {
+        let to_tag = match resp.to_header().and_then(|to| to.tag()) {
+            Ok(Some(tag)) => tag.value().to_string(),
+            _ => return,
+        };
+        let answer = match resp.status_code.kind() {
+            rsip::StatusCodeKind::Successful => Some(resp.clone()),
+            _ => None,
+        };
+        let mut branches = self.inner.early_branches.lock().unwrap();
+        match branches.iter_mut().find(|b| b.to_tag == to_tag) {
+            Some(branch) => {
+                if answer.is_some() {
+                    branch.answer = answer;
+                }
+            }
+            None => {
+                info!("tracking early branch to_tag:{}", to_tag);
+                branches.push(EarlyBranch {
+                    to_tag,
+                    invite: invite.clone(),
+                    answer,
+                });
+            }
+        }
+    }
+
+    /// Clean up the forked branches that lost
+    ///
+    /// Once one early dialog has been answered, the remaining branches are no
+    /// longer wanted. Branches still pending share the INVITE transaction,
+    /// so one CANCEL built from that INVITE goes to the forking proxy, which
+    /// cancels every branch it still has open. Branches that answered late
+    /// get an ACK followed by a BYE.
+    ///
+    /// This is called automatically when the INVITE is answered and when
+    /// another fork answers after that, but can also be used directly.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - All losing branches were cleaned up
+    /// * `Err(Error)` - Failed to send a CANCEL or BYE
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::client_dialog::ClientInviteDialog;
+    /// # async fn example() -> rsipstack::Result<()> {
+    /// # let dialog: ClientInviteDialog = todo!();
+    /// // Drop every fork except the one that answered
+    /// dialog.cancel_others().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn cancel_others(&self) -> Result<()> {
+        let winner = self.id().to_tag;
+        let losers = {
+            let mut branches = self.inner.early_branches.lock().unwrap();
+            let (winners, losers): (Vec<_>, Vec<_>) =
+                branches.drain(..).partition(|b| b.to_tag == winner);
+            *branches = winners;
+            losers
+        };
+        // pending forks of the same INVITE are all cancelled by one CANCEL
+        let mut cancelled = HashSet::new();
+        for branch in losers {
+            match &branch.answer {
+                Some(resp) => {
+                    info!("branch {} answered late, sending BYE", branch.to_tag);
+                    self.bye_branch(resp).await?;
+                }
+                None => {
+                    let invite = branch.invite.via_header()?.value().to_string();
+                    if !cancelled.insert(invite) {
+                        continue;
+                    }
+                    info!("cancelling early branch {}", branch.to_tag);
+                    self.cancel_branch(&branch).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn cancel_branch(&self, branch: &EarlyBranch) -> Result<()> {
+        let seq = branch.invite.cseq_header()?.seq()?;
+        let cancel_request = Self::make_cancel(&branch.invite, seq)?;
+        self.inner.do_request(cancel_request).await?;
+        Ok(())
+    }
+
+    /// Build the CANCEL for an INVITE (RFC 3261 §9.1)
+    ///
+    /// The Request-URI, Call-ID, From, To and Route headers are copied from
+    /// the INVITE and the only Via is the INVITE's top Via, branch included,
+    /// so the CANCEL matches the INVITE transaction even when it has to be
+    /// sent over a freshly dialed connection.
+    fn make_cancel(invite: &Request, seq: u32) -> Result<Request> {
+        let mut headers: Vec<Header> = vec![Header::Via(invite.via_header()?.clone())];
+        headers.extend(invite.headers.iter().cloned().filter(|h| {
+            matches!(
+                h,
+                Header::From(_)
+                    | Header::To(_)
+                    | Header::CallId(_)
+                    | Header::Route(_)
+                    | Header::MaxForwards(_)
+                    | Header::UserAgent(_)
+            )
+        }));
+        headers.push(Header::CSeq(
+            rsip::typed::CSeq {
+                seq,
+                method: rsip::Method::Cancel,
+            }
+            .into(),
+        ));
+        Ok(Request {
+            method: rsip::Method::Cancel,
+            uri: invite.uri.clone(),
+            version: invite.version.clone(),
+            headers: headers.into(),
+            body: vec![],
+        })
+    }
+
+    async fn bye_branch(&self, resp: &Response) -> Result<()> {
+        let to = resp.to_header()?.clone();
+        let target = match resp.contact_header() {
+            Ok(contact) => extract_uri_from_contact(contact.value())?,
+            Err(_) => self.inner.remote_uri.lock().unwrap().clone(),
+        };
+
+        let mut ack = self.inner.make_request(
+            rsip::Method::Ack,
+            resp.cseq_header()?.seq().ok(),
+            None,
+            None,
+            None,
+            None,
+        )?;
+        ack.uri = target.clone();
+        ack.headers.unique_push(Header::To(to.clone()));
+        let (connection, destination) = self
+            .inner
+            .endpoint_inner
+            .transport_layer
+            .lookup(&ack.uri, self.inner.endpoint_inner.transport_tx.clone())
+            .await?;
+        connection.send(ack.into(), Some(&destination)).await?;
+
+        let mut bye = self
+            .inner
+            .make_request(rsip::Method::Bye, None, None, None, None, None)?;
+        bye.uri = target;
+        bye.headers.unique_push(Header::To(to));
+        self.inner.do_request(bye).await?;
+        Ok(())
+    }
+
     /// Send a re-INVITE request to modify the session
     ///
     /// Sends a re-INVITE request within an established dialog to modify
@@ -272,6 +573,47 @@ impl ClientInviteDialog {
         &self,
         headers: Option<Vec<rsip::Header>>,
         body: Option<Vec<u8>>,
+    ) -> Result<Option<rsip::Response>> {
+        self.reinvite_with_transport(headers, body, None).await
+    }
+
+    /// Send a re-INVITE over a specific transport
+    ///
+    /// Same as [`reinvite`](Self::reinvite), but sends this one request over
+    /// `transport` instead of the transport the dialog normally uses, e.g.
+    /// a large re-INVITE over TCP on a UDP dialog. Later requests are not
+    /// affected.
+    ///
+    /// # Parameters
+    ///
+    /// * `headers` - Optional additional headers to include
+    /// * `body` - Optional message body (typically new SDP)
+    /// * `transport` - Transport to force for this request, `None` keeps the default
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(Response))` - Response to the re-INVITE
+    /// * `Ok(None)` - Dialog not confirmed, no request sent
+    /// * `Err(Error)` - Failed to connect or send re-INVITE
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::client_dialog::ClientInviteDialog;
+    /// # async fn example() -> rsipstack::Result<()> {
+    /// # let dialog: ClientInviteDialog = todo!();
+    /// # let large_sdp = vec![];
+    /// let response = dialog
+    ///     .reinvite_with_transport(None, Some(large_sdp), Some(rsip::Transport::Tcp))
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn reinvite_with_transport(
+        &self,
+        headers: Option<Vec<rsip::Header>>,
+        body: Option<Vec<u8>>,
+        transport: Option<rsip::Transport>,
     ) -> Result<Option<rsip::Response>> {
         if !self.inner.is_confirmed() {
             return Ok(None);
@@ -279,7 +621,7 @@ impl ClientInviteDialog {
         let request =
             self.inner
                 .make_request(rsip::Method::Invite, None, None, None, headers, body)?;
-        let resp = self.inner.do_request(request.clone()).await;
+        let resp = self.inner.do_request_over(request.clone(), transport).await;
         match resp {
             Ok(Some(ref resp)) => {
                 if resp.status_code == StatusCode::OK {
@@ -402,13 +744,17 @@ impl ClientInviteDialog {
     /// * `INFO` - Handles information exchange
     /// * `OPTIONS` - Handles capability queries
     /// * `UPDATE` - Handles session updates
+    /// * `REFER` - Accepts call transfers when enabled, see `DialogState::Refer`
+    /// * `NOTIFY` - Accepts notifications of active subscriptions, 481 otherwise
     /// * `INVITE` - Handles re-INVITE (when confirmed)
     pub async fn handle(&mut self, mut tx: Transaction) -> Result<()> {
         trace!(
-            "handle request: {:?} state:{}",
-            tx.original,
+            "handle request: {} state:{}",
+            sanitize_for_log(&tx.original.to_string()),
             self.inner.state.lock().unwrap()
         );
+        self.inner.update_session_id(&tx.original.headers);
+        self.inner.touch();
 
         let cseq = tx.original.cseq_header()?.seq()?;
         let remote_seq = self.inner.remote_seq.load(Ordering::Relaxed);
@@ -430,6 +776,8 @@ impl ClientInviteDialog {
                 rsip::Method::Info => return self.handle_info(tx).await,
                 rsip::Method::Options => return self.handle_options(tx).await,
                 rsip::Method::Update => return self.handle_update(tx).await,
+                rsip::Method::Refer => return self.handle_refer(tx).await,
+                rsip::Method::Notify => return self.handle_notify(tx).await,
                 _ => {
                     info!("invalid request method: {:?}", tx.original.method);
                     tx.reply(rsip::StatusCode::MethodNotAllowed).await?;
@@ -450,6 +798,7 @@ impl ClientInviteDialog {
 
     async fn handle_bye(&mut self, mut tx: Transaction) -> Result<()> {
         info!("received bye");
+        *self.inner.hangup_cause.lock().unwrap() = Q850Cause::from_request(&tx.original);
         self.inner
             .transition(DialogState::Terminated(self.id(), TerminatedReason::UasBye))?;
         tx.reply(rsip::StatusCode::OK).await?;
@@ -464,20 +813,24 @@ impl ClientInviteDialog {
         Ok(())
     }
 
-    async fn handle_options(&mut self, mut tx: Transaction) -> Result<()> {
+    async fn handle_options(&mut self, tx: Transaction) -> Result<()> {
         info!("received options {}", tx.original.uri);
-        self.inner
-            .transition(DialogState::Options(self.id(), tx.original.clone()))?;
-        tx.reply(rsip::StatusCode::OK).await?;
-        Ok(())
+        self.inner.handle_options(tx).await
     }
 
-    async fn handle_update(&mut self, mut tx: Transaction) -> Result<()> {
+    async fn handle_update(&mut self, tx: Transaction) -> Result<()> {
         info!("received update {}", tx.original.uri);
-        self.inner
-            .transition(DialogState::Updated(self.id(), tx.original.clone()))?;
-        tx.reply(rsip::StatusCode::OK).await?;
-        Ok(())
+        self.inner.handle_update(tx).await
+    }
+
+    async fn handle_refer(&mut self, tx: Transaction) -> Result<()> {
+        info!("received refer {}", tx.original.uri);
+        self.inner.handle_refer(tx).await
+    }
+
+    async fn handle_notify(&mut self, tx: Transaction) -> Result<()> {
+        info!("received notify {}", tx.original.uri);
+        self.inner.handle_notify(tx).await
     }
 
     pub(super) async fn process_invite(
@@ -485,7 +838,7 @@ impl ClientInviteDialog {
         mut tx: Transaction,
     ) -> Result<(DialogId, Option<Response>)> {
         self.inner.transition(DialogState::Calling(self.id()))?;
-        let mut auth_sent = false;
+        let mut auth = AuthRetries::new(self.inner.endpoint_inner.max_auth_retries);
         tx.send().await?;
         let mut dialog_id = self.id();
         let mut final_response = None;
@@ -493,17 +846,31 @@ impl ClientInviteDialog {
             match msg {
                 SipMessage::Request(_) => {}
                 SipMessage::Response(resp) => {
+                    if resp.status_code != StatusCode::Trying {
+                        self.inner.update_session_id(&resp.headers);
+                    }
                     match resp.status_code {
                         StatusCode::Trying => {
                             self.inner.transition(DialogState::Trying(self.id()))?;
                             continue;
                         }
-                        StatusCode::Ringing | StatusCode::SessionProgress => {
+                        StatusCode::Ringing
+                        | StatusCode::CallIsBeingForwarded
+                        | StatusCode::Queued
+                        | StatusCode::SessionProgress => {
+                            self.track_branch(&tx.original, &resp);
+                            if !resp.body.is_empty() {
+                                self.inner
+                                    .early_media_sdp
+                                    .lock()
+                                    .unwrap()
+                                    .replace(resp.body.clone());
+                            }
                             self.inner.transition(DialogState::Early(self.id(), resp))?;
                             continue;
                         }
                         StatusCode::ProxyAuthenticationRequired | StatusCode::Unauthorized => {
-                            if auth_sent {
+                            if !auth.retry(&resp) {
                                 final_response = Some(resp.clone());
                                 info!("received {} response after auth sent", resp.status_code);
                                 self.inner.transition(DialogState::Terminated(
@@ -512,7 +879,6 @@ impl ClientInviteDialog {
                                 ))?;
                                 break;
                             }
-                            auth_sent = true;
                             if let Some(credential) = &self.inner.credential {
                                 tx = handle_client_authenticate(
                                     self.inner.increment_local_seq(),
@@ -529,13 +895,22 @@ impl ClientInviteDialog {
                                     self.id(),
                                     TerminatedReason::ProxyAuthRequired,
                                 ))?;
+                                if self.inner.endpoint_inner.strict_auth {
+                                    return Err(crate::Error::AuthenticationRequired(
+                                        resp.status_code,
+                                    ));
+                                }
                             }
                             continue;
                         }
                         _ => {}
                     };
+                    if resp.status_code.kind() == rsip::StatusCodeKind::Successful {
+                        self.track_branch(&tx.original, &resp);
+                    }
                     final_response = Some(resp.clone());
-                    match resp.to_header()?.tag()? {
+                    let to_tag = resp.to_header()?.tag()?;
+                    match &to_tag {
                         Some(tag) => self.inner.update_remote_tag(tag.value())?,
                         None => {}
                     }
@@ -558,9 +933,16 @@ impl ClientInviteDialog {
                     };
 
                     // For 2xx responses, update route set BEFORE creating ACK
-                    if resp.status_code == StatusCode::OK {
+                    if resp.status_code.kind() == rsip::StatusCodeKind::Successful {
                         // For UAC, build route set from Record-Route headers in 200 OK response
                         self.update_route_set_from_response(&resp);
+                        // the 2xx answer is authoritative over any early media SDP
+                        let answer = if resp.body.is_empty() {
+                            self.inner.early_media_sdp.lock().unwrap().clone()
+                        } else {
+                            Some(resp.body.clone())
+                        };
+                        *self.inner.remote_sdp.lock().unwrap() = answer;
                     }
 
                     let ack = self.inner.make_request(
@@ -582,16 +964,46 @@ impl ClientInviteDialog {
                         }
                     }
 
+                    if to_tag.is_none()
+                        && resp.status_code.kind() == rsip::StatusCodeKind::Successful
+                    {
+                        // acked to stop retransmissions, but there is no dialog to confirm
+                        warn!(
+                            "{} without To tag, cannot establish dialog {}",
+                            resp.status_code,
+                            self.id()
+                        );
+                        self.inner.transition(DialogState::Terminated(
+                            self.id(),
+                            TerminatedReason::UasOther(Some(resp.status_code.clone())),
+                        ))?;
+                        return Err(crate::Error::DialogError(
+                            format!("{} response without To tag", resp.status_code),
+                            self.id(),
+                        ));
+                    }
+
                     match resp.status_code {
                         StatusCode::OK => {
                             self.inner
                                 .transition(DialogState::Confirmed(dialog_id.clone()))?;
+                            if self.inner.early_branches.lock().unwrap().len() > 1 {
+                                let dialog = self.clone();
+                                tokio::spawn(async move {
+                                    if let Err(e) = dialog.cancel_others().await {
+                                        warn!("failed to clean up forked branches: {}", e);
+                                    }
+                                });
+                            }
+                            tokio::spawn(self.clone().handle_late_answers(tx));
+                            break;
                         }
                         _ => {
                             let mut reason = format!("{}", resp.status_code);
                             if let Some(reason_phrase) = resp.reason_phrase() {
                                 reason = format!("{};{}", reason, reason_phrase);
                             }
+                            *self.inner.failure_details.lock().unwrap() = resp.diagnostics();
                             self.inner.transition(DialogState::Terminated(
                                 self.id(),
                                 TerminatedReason::UasOther(Some(resp.status_code.clone())),
@@ -604,4 +1016,48 @@ impl ClientInviteDialog {
         }
         Ok((dialog_id, final_response))
     }
+
+    /// Handle 2xx responses arriving after the dialog was confirmed
+    ///
+    /// The INVITE transaction keeps running for 64*T1 after the ACK (RFC
+    /// 6026). Retransmissions of the winning 2xx are ACKed again unless
+    /// disabled with `EndpointBuilder::with_reack_2xx`, answers of other
+    /// forks are ACKed and hung up with a BYE.
+    async fn handle_late_answers(self, mut tx: Transaction) {
+        let winner = self.id().to_tag;
+        while let Some(msg) = tx.receive().await {
+            let resp = match msg {
+                SipMessage::Response(resp)
+                    if resp.status_code.kind() == rsip::StatusCodeKind::Successful =>
+                {
+                    resp
+                }
+                _ => continue,
+            };
+            let to_tag = match resp.to_header().and_then(|to| to.tag()) {
+                Ok(Some(tag)) => tag.value().to_string(),
+                _ => continue,
+            };
+            if to_tag == winner {
+                if !self.inner.endpoint_inner.reack_2xx {
+                    continue;
+                }
+                if let Some(ack) = tx.last_ack.clone() {
+                    if let Err(e) = tx.send_ack(ack).await {
+                        warn!("failed to resend ACK: {}", e);
+                    }
+                }
+                continue;
+            }
+            info!(
+                "fork {} answered after dialog {} was confirmed",
+                to_tag,
+                self.id()
+            );
+            self.track_branch(&tx.original, &resp);
+            if let Err(e) = self.cancel_others().await {
+                warn!("failed to clean up late branch: {}", e);
+            }
+        }
+    }
 }
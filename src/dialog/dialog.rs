@@ -1,14 +1,15 @@
 use super::{
-    authenticate::{handle_client_authenticate, Credential},
-    client_dialog::ClientInviteDialog,
+    authenticate::{handle_client_authenticate, AuthRetries, Credential},
+    client_dialog::{ClientInviteDialog, EarlyBranch},
     server_dialog::ServerInviteDialog,
     DialogId,
 };
 use crate::{
-    rsip_ext::extract_uri_from_contact,
+    rsip_ext::{extract_uri_from_contact, RsipHeadersExt, RsipResponseExt},
     transaction::{
         endpoint::EndpointInnerRef,
         key::{TransactionKey, TransactionRole},
+        next_cseq,
         transaction::{Transaction, TransactionEventSender},
     },
     Result,
@@ -20,13 +21,103 @@ use rsip::{
     Header, Param, Request, Response, SipMessage, StatusCode,
 };
 use std::sync::{
-    atomic::{AtomicU32, Ordering},
+    atomic::{AtomicBool, AtomicU32, Ordering},
     Arc, Mutex,
 };
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, info};
 
+/// Smallest session interval allowed by RFC 4028, also our default `Min-SE`
+pub const DEFAULT_MIN_SE: u32 = 90;
+
+/// Methods advertised in `Allow` when the endpoint was built without any
+const DIALOG_METHODS: [rsip::Method; 9] = [
+    rsip::Method::Invite,
+    rsip::Method::Ack,
+    rsip::Method::Cancel,
+    rsip::Method::Bye,
+    rsip::Method::Options,
+    rsip::Method::Info,
+    rsip::Method::Update,
+    rsip::Method::Refer,
+    rsip::Method::Notify,
+];
+
+/// Name of the end-to-end session identifier header (RFC 7989)
+pub const SESSION_ID_HEADER: &str = "Session-ID";
+
+/// UUID sent as the remote half before the peer's half is known
+const NULL_SESSION_UUID: &str = "00000000000000000000000000000000";
+
+/// End-to-end session identifier (RFC 7989)
+///
+/// Each user agent picks its own UUID for a session, `remote` is the one the
+/// peer picked and stays `None` until it arrives. Together the two halves
+/// identify the session across B2BUAs, so both are worth logging.
+///
+/// Formats as the `Session-ID` header value, `local;remote=remote`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SessionId {
+    pub local: String,
+    pub remote: Option<String>,
+}
+
+impl SessionId {
+    /// Create a session identifier with a fresh local UUID
+    pub fn new() -> Self {
+        Self {
+            local: uuid::Uuid::new_v4().simple().to_string(),
+            remote: None,
+        }
+    }
+
+    /// Parse a `Session-ID` header value as seen by its sender
+    ///
+    /// `local` is the sender's UUID and `remote` the value of its `remote`
+    /// parameter. The null UUID is treated as absent.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.split(';').map(str::trim);
+        let local = parts.next().filter(|uuid| Self::is_uuid(uuid))?;
+        let remote = parts
+            .filter_map(|param| param.split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("remote"))
+            .map(|(_, uuid)| uuid.trim())
+            .filter(|uuid| Self::is_uuid(uuid) && *uuid != NULL_SESSION_UUID)
+            .map(|uuid| uuid.to_ascii_lowercase());
+        Some(Self {
+            local: local.to_ascii_lowercase(),
+            remote,
+        })
+    }
+
+    fn is_uuid(value: &str) -> bool {
+        value.len() == 32 && value.chars().all(|c| c.is_ascii_hexdigit())
+    }
+
+    /// The `Session-ID` header carrying this identifier
+    pub fn to_header(&self) -> Header {
+        Header::Other(SESSION_ID_HEADER.into(), self.to_string())
+    }
+}
+
+impl Default for SessionId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Display for SessionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{};remote={}",
+            self.local,
+            self.remote.as_deref().unwrap_or(NULL_SESSION_UUID)
+        )
+    }
+}
+
 /// SIP Dialog State
 ///
 /// Represents the various states a SIP dialog can be in during its lifecycle.
@@ -43,6 +134,8 @@ use tracing::{debug, info};
 /// * `Notify` - Dialog received a NOTIFY request  
 /// * `Info` - Dialog received an INFO request
 /// * `Options` - Dialog received an OPTIONS request
+/// * `Refer` - Dialog received a REFER request, already answered with 202 Accepted;
+///   only with `EndpointBuilder::with_accept_refer`
 /// * `Terminated` - Dialog has been terminated
 ///
 /// # Examples
@@ -74,9 +167,213 @@ pub enum DialogState {
     Notify(DialogId, rsip::Request),
     Info(DialogId, rsip::Request),
     Options(DialogId, rsip::Request),
+    Refer(DialogId, rsip::Request),
     Terminated(DialogId, TerminatedReason),
 }
 
+/// Implicit subscription created by an accepted REFER (RFC 3515)
+///
+/// Progress of the referred request is reported with
+/// [`Dialog::notify_refer`]. No subscription is created when the REFER
+/// carried `Refer-Sub: false` and the endpoint supports `norefersub`
+/// (RFC 4488).
+///
+/// # Fields
+///
+/// * `id` - CSeq number of the REFER, used as the `id` of `Event: refer`
+/// * `refer_to` - Value of the REFER's `Refer-To` header
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReferSubscription {
+    pub id: u32,
+    pub refer_to: String,
+}
+
+/// Subscription on which this side receives NOTIFYs (RFC 6665)
+///
+/// Created when a REFER sent with [`Dialog::refer`] is accepted. A NOTIFY
+/// whose `Event` matches no active subscription is rejected with 481.
+///
+/// # Fields
+///
+/// * `event` - Event package, e.g. `refer`
+/// * `id` - `id` parameter of the `Event` header, if any
+#[derive(Clone, Debug, PartialEq)]
+pub struct EventSubscription {
+    pub event: String,
+    pub id: Option<String>,
+}
+
+impl EventSubscription {
+    /// Parse the value of an `Event` header, e.g. `refer;id=93809824`
+    pub fn from_event(value: &str) -> Self {
+        let mut parts = value.split(';');
+        let event = parts.next().unwrap_or_default().trim().to_lowercase();
+        let id = parts.find_map(|param| {
+            let (name, value) = param.split_once('=')?;
+            name.trim()
+                .eq_ignore_ascii_case("id")
+                .then(|| value.trim().to_string())
+        });
+        Self { event, id }
+    }
+}
+
+/// Essential state of a confirmed dialog, kept for failover
+///
+/// Taken with [`Dialog::serialize_state`] and turned back into a dialog on
+/// another instance with `DialogLayer::restore_dialog`, which can then
+/// keep sending in-dialog requests such as BYE. All fields are strings and
+/// numbers, so the application can persist them in any format.
+///
+/// # Fields
+///
+/// * `role` - `Client` for a dialog we called out on, `Server` for one we answered
+/// * `id` - Call-ID and tags of the dialog
+/// * `remote_uri` - Remote target, the Request-URI of in-dialog requests
+/// * `route_set` - Route header values of in-dialog requests, in order
+/// * `local_seq` - CSeq of the last request we sent
+/// * `remote_seq` - CSeq of the last request we received
+/// * `local_contact` - Contact we advertise, if any
+/// * `from` - Local party, as sent in From
+/// * `to` - Remote party with its tag, as sent in To
+#[derive(Clone, Debug, PartialEq)]
+pub struct DialogSnapshot {
+    pub role: TransactionRole,
+    pub id: DialogId,
+    pub remote_uri: String,
+    pub route_set: Vec<String>,
+    pub local_seq: u32,
+    pub remote_seq: u32,
+    pub local_contact: Option<String>,
+    pub from: String,
+    pub to: String,
+}
+
+/// ISDN cause carried by a `Reason: Q.850;cause=...` header (ITU-T Q.850)
+///
+/// Gateways put it on the BYE that tears down a call, so an application can
+/// tell a normal hangup from a network failure. Causes without a variant of
+/// their own are kept as `Other`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Q850Cause {
+    /// 1, unallocated (unassigned) number
+    UnallocatedNumber,
+    /// 16, normal call clearing
+    NormalClearing,
+    /// 17, user busy
+    UserBusy,
+    /// 18, no user responding
+    NoUserResponding,
+    /// 19, no answer from user (user alerted)
+    NoAnswer,
+    /// 21, call rejected
+    CallRejected,
+    /// 27, destination out of order
+    DestinationOutOfOrder,
+    /// 28, invalid number format
+    InvalidNumberFormat,
+    /// 31, normal, unspecified
+    NormalUnspecified,
+    /// 34, no circuit/channel available (congestion)
+    Congestion,
+    /// 38, network out of order
+    NetworkOutOfOrder,
+    /// 41, temporary failure
+    TemporaryFailure,
+    /// 42, switching equipment congestion
+    SwitchingEquipmentCongestion,
+    /// 102, recovery on timer expiry
+    RecoveryOnTimerExpiry,
+    Other(u16),
+}
+
+impl Q850Cause {
+    /// Numeric cause value
+    pub fn code(&self) -> u16 {
+        match self {
+            Q850Cause::UnallocatedNumber => 1,
+            Q850Cause::NormalClearing => 16,
+            Q850Cause::UserBusy => 17,
+            Q850Cause::NoUserResponding => 18,
+            Q850Cause::NoAnswer => 19,
+            Q850Cause::CallRejected => 21,
+            Q850Cause::DestinationOutOfOrder => 27,
+            Q850Cause::InvalidNumberFormat => 28,
+            Q850Cause::NormalUnspecified => 31,
+            Q850Cause::Congestion => 34,
+            Q850Cause::NetworkOutOfOrder => 38,
+            Q850Cause::TemporaryFailure => 41,
+            Q850Cause::SwitchingEquipmentCongestion => 42,
+            Q850Cause::RecoveryOnTimerExpiry => 102,
+            Q850Cause::Other(code) => *code,
+        }
+    }
+
+    /// Parse the value of a `Reason` header
+    ///
+    /// Returns the cause of the `Q.850` protocol entry, `None` when the
+    /// header only carries other protocols such as `SIP`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rsipstack::dialog::dialog::Q850Cause;
+    ///
+    /// let cause = Q850Cause::from_reason(r#"Q.850;cause=16;text="Normal call clearing""#);
+    /// assert_eq!(cause, Some(Q850Cause::NormalClearing));
+    /// assert_eq!(Q850Cause::from_reason("SIP;cause=200"), None);
+    /// ```
+    pub fn from_reason(value: &str) -> Option<Self> {
+        crate::rsip_ext::split_header_values(value)
+            .into_iter()
+            .find_map(|entry| {
+                let mut parts = entry.split(';');
+                if !parts.next()?.trim().eq_ignore_ascii_case("Q.850") {
+                    return None;
+                }
+                parts.find_map(|param| {
+                    let (name, value) = param.split_once('=')?;
+                    if !name.trim().eq_ignore_ascii_case("cause") {
+                        return None;
+                    }
+                    value.trim().parse::<u16>().ok().map(Self::from)
+                })
+            })
+    }
+
+    /// Q.850 cause of the first `Reason` header of `request` that has one
+    pub fn from_request(request: &Request) -> Option<Self> {
+        request.headers.iter().find_map(|h| match h {
+            Header::Other(name, value) if name.eq_ignore_ascii_case("Reason") => {
+                Self::from_reason(value)
+            }
+            _ => None,
+        })
+    }
+}
+
+impl From<u16> for Q850Cause {
+    fn from(code: u16) -> Self {
+        match code {
+            1 => Q850Cause::UnallocatedNumber,
+            16 => Q850Cause::NormalClearing,
+            17 => Q850Cause::UserBusy,
+            18 => Q850Cause::NoUserResponding,
+            19 => Q850Cause::NoAnswer,
+            21 => Q850Cause::CallRejected,
+            27 => Q850Cause::DestinationOutOfOrder,
+            28 => Q850Cause::InvalidNumberFormat,
+            31 => Q850Cause::NormalUnspecified,
+            34 => Q850Cause::Congestion,
+            38 => Q850Cause::NetworkOutOfOrder,
+            41 => Q850Cause::TemporaryFailure,
+            42 => Q850Cause::SwitchingEquipmentCongestion,
+            102 => Q850Cause::RecoveryOnTimerExpiry,
+            code => Q850Cause::Other(code),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum TerminatedReason {
     Timeout,
@@ -157,6 +454,16 @@ pub enum Dialog {
 /// * `state_sender` - Channel for sending state updates
 /// * `tu_sender` - Transaction user sender
 /// * `initial_request` - The initial request that created this dialog
+/// * `early_branches` - Forked early dialogs seen while the INVITE was pending (UAC only)
+/// * `pending_update` - Server transaction of an UPDATE offer awaiting the application's answer
+/// * `pending_options` - Server transaction of an in-dialog OPTIONS awaiting its 200 OK
+/// * `pending_invite` - An INVITE has not been answered with a final response yet (UAS only)
+/// * `refer_subscriptions` - Implicit subscriptions created by accepted REFERs
+/// * `subscriptions` - Subscriptions this side receives NOTIFYs for
+/// * `ack_timeout` - How long to wait for the ACK to a 2xx (UAS only)
+/// * `early_media_sdp` - SDP received in the last provisional response (UAC only)
+/// * `remote_sdp` - SDP answer received in the final 2xx response (UAC only)
+/// * `session_id` - End-to-end session identifier (RFC 7989)
 pub struct DialogInner {
     pub role: TransactionRole,
     pub cancel_token: CancellationToken,
@@ -167,7 +474,7 @@ pub struct DialogInner {
     pub local_contact: Option<rsip::Uri>,
 
     pub remote_seq: AtomicU32,
-    pub remote_uri: rsip::Uri,
+    pub remote_uri: Mutex<rsip::Uri>,
 
     pub from: String,
     pub to: Mutex<String>,
@@ -179,6 +486,23 @@ pub struct DialogInner {
     pub(super) tu_sender: TuSenderRef,
     pub(super) initial_request: Request,
     pub(super) public_address: Mutex<Option<crate::transport::SipAddr>>,
+    pub(super) inbound_addr: Mutex<Option<crate::transport::SipAddr>>,
+    pub(super) early_branches: Mutex<Vec<EarlyBranch>>,
+    pub(super) pending_update: Mutex<Option<Transaction>>,
+    pub(super) pending_options: Mutex<Option<Transaction>>,
+    pub(super) pending_invite: AtomicBool,
+    pub(super) refer_subscriptions: Mutex<Vec<ReferSubscription>>,
+    pub(super) subscriptions: Mutex<Vec<EventSubscription>>,
+    pub(super) ack_timeout: Mutex<std::time::Duration>,
+    pub(super) min_se: AtomicU32,
+    pub(super) session_expires: Mutex<Option<u32>>,
+    pub(super) early_media_sdp: Mutex<Option<Vec<u8>>>,
+    pub(super) remote_sdp: Mutex<Option<Vec<u8>>>,
+    pub(super) session_id: Mutex<SessionId>,
+    pub(super) idle_timeout: Mutex<Option<std::time::Duration>>,
+    pub(super) last_activity: Mutex<tokio::time::Instant>,
+    pub(super) failure_details: Mutex<Option<String>>,
+    pub(super) hangup_cause: Mutex<Option<Q850Cause>>,
 }
 
 pub type DialogStateReceiver = UnboundedReceiver<DialogState>;
@@ -191,6 +515,38 @@ impl DialogState {
     pub fn is_confirmed(&self) -> bool {
         matches!(self, DialogState::Confirmed(_))
     }
+
+    /// Dialog is in early state (1xx response with a To tag, except 100)
+    pub fn is_early(&self) -> bool {
+        matches!(self, DialogState::Early(_, _))
+    }
+
+    /// Dialog has been terminated
+    pub fn is_terminated(&self) -> bool {
+        matches!(self, DialogState::Terminated(_, _))
+    }
+
+    /// Dialog is established, i.e. confirmed
+    pub fn is_established(&self) -> bool {
+        self.is_confirmed()
+    }
+
+    /// Get the dialog identifier carried by this state
+    pub fn dialog_id(&self) -> &DialogId {
+        match self {
+            DialogState::Calling(id)
+            | DialogState::Trying(id)
+            | DialogState::Early(id, _)
+            | DialogState::WaitAck(id, _)
+            | DialogState::Confirmed(id)
+            | DialogState::Updated(id, _)
+            | DialogState::Notify(id, _)
+            | DialogState::Info(id, _)
+            | DialogState::Options(id, _)
+            | DialogState::Refer(id, _)
+            | DialogState::Terminated(id, _) => id,
+        }
+    }
 }
 
 impl DialogInner {
@@ -215,7 +571,7 @@ impl DialogInner {
                 // Server dialog: they sent the initial request
                 // local_seq is for our requests (BYE, etc.) - use random
                 // remote_seq is for their requests - use theirs
-                (crate::transaction::generate_random_cseq(), initial_cseq)
+                (endpoint_inner.make_cseq(), initial_cseq)
             }
         };
 
@@ -258,6 +614,20 @@ impl DialogInner {
         } else {
             log::info!("UAC Dialog {} created with empty route set (will be populated from 200 OK)", id);
         }
+        let ack_timeout = endpoint_inner.t1x64;
+        let received = initial_request
+            .headers
+            .get_other(SESSION_ID_HEADER)
+            .and_then(SessionId::parse);
+        let session_id = match (&role, received) {
+            // keep the half the INVITE was sent with
+            (TransactionRole::Client, Some(sent)) => sent,
+            (TransactionRole::Server, Some(received)) => SessionId {
+                remote: Some(received.local),
+                ..SessionId::new()
+            },
+            (_, None) => SessionId::new(),
+        };
         Ok(Self {
             role,
             cancel_token: CancellationToken::new(),
@@ -265,7 +635,7 @@ impl DialogInner {
             from,
             to: Mutex::new(to),
             local_seq: AtomicU32::new(local_cseq),
-            remote_uri,
+            remote_uri: Mutex::new(remote_uri),
             remote_seq: AtomicU32::new(remote_cseq),
             credential,
             route_set: Mutex::new(route_set),
@@ -276,23 +646,172 @@ impl DialogInner {
             initial_request,
             local_contact,
             public_address: Mutex::new(None),
+            inbound_addr: Mutex::new(None),
+            early_branches: Mutex::new(vec![]),
+            pending_update: Mutex::new(None),
+            pending_options: Mutex::new(None),
+            pending_invite: AtomicBool::new(false),
+            refer_subscriptions: Mutex::new(vec![]),
+            subscriptions: Mutex::new(vec![]),
+            ack_timeout: Mutex::new(ack_timeout),
+            min_se: AtomicU32::new(DEFAULT_MIN_SE),
+            session_expires: Mutex::new(None),
+            early_media_sdp: Mutex::new(None),
+            remote_sdp: Mutex::new(None),
+            session_id: Mutex::new(session_id),
+            idle_timeout: Mutex::new(None),
+            last_activity: Mutex::new(tokio::time::Instant::now()),
+            failure_details: Mutex::new(None),
+            hangup_cause: Mutex::new(None),
         })
     }
 
     pub fn is_confirmed(&self) -> bool {
         self.state.lock().unwrap().is_confirmed()
     }
+
+    /// Get the end-to-end session identifier (RFC 7989)
+    pub fn session_id(&self) -> SessionId {
+        self.session_id.lock().unwrap().clone()
+    }
+
+    /// `Allow` and, when option tags are configured, `Supported` headers
+    ///
+    /// For in-dialog requests that are answered, such as BYE, for peers that
+    /// key their behaviour off our capabilities. Not for ACK or CANCEL.
+    pub fn capability_headers(&self) -> Vec<Header> {
+        let mut headers = vec![self.allow_header()];
+        if let Some(supported) = self.endpoint_inner.supported_header() {
+            headers.push(supported);
+        }
+        headers
+    }
+
+    /// Record in-dialog activity, restarting the idle timeout
+    pub(super) fn touch(&self) {
+        *self.last_activity.lock().unwrap() = tokio::time::Instant::now();
+    }
+
+    /// Send a BYE once the dialog has been idle for its idle timeout
+    ///
+    /// Runs until the dialog is cancelled or terminated. Any in-dialog
+    /// request, sent or received, restarts the countdown, and so does
+    /// `Dialog::touch`. Does nothing when no idle timeout is set.
+    pub(super) fn start_idle_timer(self: &Arc<Self>) {
+        let timeout = match *self.idle_timeout.lock().unwrap() {
+            Some(timeout) => timeout,
+            None => return,
+        };
+        let inner = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let deadline = *inner.last_activity.lock().unwrap() + timeout;
+                tokio::select! {
+                    _ = inner.cancel_token.cancelled() => return,
+                    _ = tokio::time::sleep_until(deadline) => {}
+                }
+                let id = match &*inner.state.lock().unwrap() {
+                    DialogState::Terminated(_, _) => return,
+                    DialogState::Confirmed(id) => id.clone(),
+                    // the countdown starts once the dialog is confirmed
+                    _ => {
+                        inner.touch();
+                        continue;
+                    }
+                };
+                if *inner.last_activity.lock().unwrap() + timeout > tokio::time::Instant::now() {
+                    continue;
+                }
+                info!("dialog {} idle for {:?}, sending BYE", id, timeout);
+                match inner.make_request(rsip::Method::Bye, None, None, None, None, None) {
+                    Ok(request) => {
+                        if let Err(e) = inner.do_request(request).await {
+                            info!("idle bye error: {}", e);
+                        }
+                    }
+                    Err(e) => info!("idle bye error: {}", e),
+                }
+                inner
+                    .transition(DialogState::Terminated(id, TerminatedReason::Timeout))
+                    .ok();
+                return;
+            }
+        });
+    }
+
+    /// Learn the peer's half of the session identifier from a message
+    pub(super) fn update_session_id(&self, headers: &rsip::Headers) {
+        let received = match headers
+            .get_other(SESSION_ID_HEADER)
+            .and_then(SessionId::parse)
+        {
+            Some(received) => received.local,
+            None => return,
+        };
+        let mut session_id = self.session_id.lock().unwrap();
+        if received != session_id.local && session_id.remote.as_ref() != Some(&received) {
+            debug!("session id {} remote half: {}", session_id.local, received);
+            session_id.remote = Some(received);
+        }
+    }
+
+    /// Negotiate the session timer of an inbound INVITE (RFC 4028)
+    ///
+    /// Returns `Err(min_se)` when the requested `Session-Expires` is below
+    /// our `Min-SE` and the request must be rejected with 422. Otherwise
+    /// the chosen interval, never below the peer's `Min-SE`, is recorded
+    /// and returned; `None` means the peer did not ask for a session timer.
+    pub(super) fn negotiate_session_timer(
+        &self,
+        request: &Request,
+    ) -> std::result::Result<Option<u32>, u32> {
+        let session_expires = request
+            .headers
+            .get_other("Session-Expires")
+            .or_else(|| request.headers.get_other("x"))
+            .and_then(parse_delta_seconds);
+        let interval = match session_expires {
+            Some(interval) => interval,
+            None => {
+                *self.session_expires.lock().unwrap() = None;
+                return Ok(None);
+            }
+        };
+        let min_se = self.min_se.load(Ordering::Relaxed);
+        if interval < min_se {
+            info!("session interval {} below Min-SE {}", interval, min_se);
+            return Err(min_se);
+        }
+        let peer_min_se = request
+            .headers
+            .get_other("Min-SE")
+            .and_then(parse_delta_seconds)
+            .unwrap_or(DEFAULT_MIN_SE);
+        let interval = interval.max(peer_min_se);
+        *self.session_expires.lock().unwrap() = Some(interval);
+        Ok(Some(interval))
+    }
     pub fn get_local_seq(&self) -> u32 {
         self.local_seq.load(Ordering::Relaxed)
     }
     pub fn increment_local_seq(&self) -> u32 {
-        self.local_seq.fetch_add(1, Ordering::Relaxed);
-        self.local_seq.load(Ordering::Relaxed)
+        let last = self
+            .local_seq
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |seq| {
+                Some(next_cseq(seq))
+            })
+            .unwrap_or_default();
+        next_cseq(last)
     }
 
     pub fn increment_remote_seq(&self) -> u32 {
-        self.remote_seq.fetch_add(1, Ordering::Relaxed);
-        self.remote_seq.load(Ordering::Relaxed)
+        let last = self
+            .remote_seq
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |seq| {
+                Some(next_cseq(seq))
+            })
+            .unwrap_or_default();
+        next_cseq(last)
     }
 
 
@@ -304,11 +823,42 @@ impl DialogInner {
         Ok(())
     }
 
+    /// Contact URI to advertise in this dialog
+    ///
+    /// A server dialog on an endpoint with several listen addresses points
+    /// the static `local_contact` at the address and transport the INVITE
+    /// arrived on, so the peer reaches us the same way it came in.
+    pub(super) fn contact_uri(&self) -> Option<rsip::Uri> {
+        let mut contact = self.local_contact.clone()?;
+        if self.endpoint_inner.transport_layer.get_addrs().len() <= 1 {
+            return Some(contact);
+        }
+        if let Some(addr) = self.inbound_addr.lock().unwrap().as_ref() {
+            contact.host_with_port = addr.addr.clone();
+            contact.params.retain(|p| !matches!(p, Param::Transport(_)));
+            match addr.r#type {
+                Some(rsip::Transport::Udp) | None => {}
+                Some(transport) => contact.params.push(Param::Transport(transport)),
+            }
+        }
+        Some(contact)
+    }
+
     pub fn set_public_address(&self, addr: crate::transport::SipAddr) {
         info!("Dialog public address set to: {}", addr);
         *self.public_address.lock().unwrap() = Some(addr);
     }
 
+    /// Build an in-dialog request
+    ///
+    /// The topmost Via is generated from `addr` (or the public address) and
+    /// `branch`. Any Via headers passed in `headers` are stacked beneath it
+    /// in the order given, which allows presenting a pre-existing Via chain.
+    ///
+    /// `Allow` and `Supported` from [`DialogInner::capability_headers`] may be
+    /// passed for requests that get a response, such as BYE, re-INVITE or
+    /// UPDATE. RFC 3261 does not allow them on ACK and CANCEL, so they are
+    /// dropped from those.
     pub(super) fn make_request(
         &self,
         method: rsip::Method,
@@ -318,7 +868,13 @@ impl DialogInner {
         headers: Option<Vec<rsip::Header>>,
         body: Option<Vec<u8>>,
     ) -> Result<rsip::Request> {
-        let mut headers = headers.unwrap_or_default();
+        let (extra_vias, mut headers): (Vec<_>, Vec<_>) = headers
+            .unwrap_or_default()
+            .into_iter()
+            .partition(|h| matches!(h, Header::Via(_)));
+        if matches!(method, rsip::Method::Ack | rsip::Method::Cancel) {
+            headers.retain(|h| !matches!(h, Header::Allow(_) | Header::Supported(_)));
+        }
         let cseq_header = CSeq {
             seq: cseq.unwrap_or_else(|| self.increment_local_seq()),
             method,
@@ -327,7 +883,10 @@ impl DialogInner {
         // Use the stored public address if available and addr is not provided
         let via_addr = addr.or_else(|| self.public_address.lock().unwrap().clone());
         let via = self.endpoint_inner.get_via(via_addr, branch)?;
-        headers.push(via.into());
+        headers.insert(0, via.into());
+        for (i, extra_via) in extra_vias.into_iter().enumerate() {
+            headers.insert(i + 1, extra_via);
+        }
         headers.push(Header::CallId(
             self.id.lock().unwrap().call_id.clone().into(),
         ));
@@ -338,9 +897,8 @@ impl DialogInner {
             self.endpoint_inner.user_agent.clone().into(),
         ));
 
-        self.local_contact
-            .as_ref()
-            .map(|c| headers.push(Contact::from(c.clone()).into()));
+        self.contact_uri()
+            .map(|c| headers.push(Contact::from(c).into()));
 
         // Debug: Log route set being added to request
         let route_set = self.route_set.lock().unwrap();
@@ -350,16 +908,24 @@ impl DialogInner {
             headers.push(Header::Route(route.clone()));
         }
         headers.push(Header::MaxForwards(70.into()));
+        let custom_session_id = headers.iter().any(
+            |h| matches!(h, Header::Other(name, _) if name.eq_ignore_ascii_case(SESSION_ID_HEADER)),
+        );
+        if !custom_session_id {
+            headers.push(self.session_id().to_header());
+        }
 
-        body.as_ref().map(|b| {
-            headers.push(Header::ContentLength((b.len() as u32).into()));
-        });
+        // bodiless requests carry Content-Length: 0 as well, which stream
+        // transports require (RFC 3261 20.14)
+        let body = body.unwrap_or_default();
+        headers.retain(|h| !matches!(h, Header::ContentLength(_)));
+        headers.push(Header::ContentLength((body.len() as u32).into()));
 
         let req = rsip::Request {
             method,
-            uri: self.remote_uri.clone(),
+            uri: self.remote_uri.lock().unwrap().clone(),
             headers: headers.into(),
-            body: body.unwrap_or_default(),
+            body,
             version: rsip::Version::V2,
         };
         Ok(req)
@@ -373,9 +939,8 @@ impl DialogInner {
         body: Option<Vec<u8>>,
     ) -> rsip::Response {
         let mut resp_headers = rsip::Headers::default();
-        self.local_contact
-            .as_ref()
-            .map(|c| resp_headers.push(Contact::from(c.clone()).into()));
+        self.contact_uri()
+            .map(|c| resp_headers.push(Contact::from(c).into()));
 
         for header in request.headers.iter() {
             match header {
@@ -423,13 +988,16 @@ impl DialogInner {
             }
         }
 
-        body.as_ref().map(|b| {
-            resp_headers.push(Header::ContentLength((b.len() as u32).into()));
-        });
+        resp_headers.unique_push(Header::ContentLength(
+            (body.as_ref().map_or(0, |b| b.len()) as u32).into(),
+        ));
 
         resp_headers.unique_push(Header::UserAgent(
             self.endpoint_inner.user_agent.clone().into(),
         ));
+        if resp_headers.get_other(SESSION_ID_HEADER).is_none() {
+            resp_headers.push(self.session_id().to_header());
+        }
 
         Response {
             status_code: status,
@@ -440,7 +1008,33 @@ impl DialogInner {
     }
 
     pub(super) async fn do_request(&self, request: Request) -> Result<Option<rsip::Response>> {
+        self.do_request_over(request, None).await
+    }
+
+    /// Send an in-dialog request, optionally forcing the transport
+    ///
+    /// With `transport` set, the next hop (first Route or Request-URI) is
+    /// resolved as usual but dialed over the given transport, and the
+    /// topmost Via is rewritten to match. The dialog itself is unchanged,
+    /// so later requests go back to the default transport.
+    pub(super) async fn do_request_over(
+        &self,
+        mut request: Request,
+        transport: Option<rsip::Transport>,
+    ) -> Result<Option<rsip::Response>> {
         let method = request.method().to_owned();
+        self.touch();
+        if let Some(transport) = transport {
+            let mut via = request.via_header()?.typed()?;
+            via.transport = transport;
+            if let Some(top) = request
+                .headers
+                .iter_mut()
+                .find(|h| matches!(h, Header::Via(_)))
+            {
+                *top = via.into();
+            }
+        }
         
         // Debug: Log route headers
         let route_count = request.headers.iter().filter(|h| matches!(h, Header::Route(_))).count();
@@ -449,7 +1043,7 @@ impl DialogInner {
         // For requests with Route headers, we need to implement loose routing (RFC 3261 16.12)
         // The request is sent to the first Route URI, not the Request-URI
         let route_header = request.route_header();
-        let (connection, destination) = if let Some(route) = route_header {
+        let next_hop = if let Some(route) = route_header {
             match route.typed() {
                 Ok(typed_route) => {
                     if let Some(first_uri) = typed_route.uris().first() {
@@ -458,34 +1052,52 @@ impl DialogInner {
                         // Clean the URI for routing (remove lr, did, etc. parameters)
                         let mut route_uri = first_uri.uri.clone();
                         route_uri.params.retain(|p| matches!(p, rsip::Param::Transport(_)));
-                        
-                        // Lookup connection to the first Route URI
-                        match self.endpoint_inner.transport_layer.lookup(&route_uri, self.endpoint_inner.transport_tx.clone()).await {
-                            Ok((conn, resolved_addr)) => {
-                                log::info!("do_request {}: Using route destination: {}", method, resolved_addr);
-                                (Some(conn), Some(resolved_addr))
-                            }
-                            Err(e) => {
-                                log::error!("do_request {}: Failed to lookup route: {}", method, e);
-                                (None, None)
-                            }
-                        }
+                        Some(route_uri)
                     } else {
                         log::warn!("do_request {}: Route header has no URIs", method);
-                        (None, None)
+                        None
                     }
                 }
                 Err(e) => {
                     log::error!("do_request {}: Failed to parse route header: {}", method, e);
-                    (None, None)
+                    None
                 }
             }
+        } else if transport.is_some() {
+            // No Route headers, but the transport is forced: dial the Request-URI ourselves
+            Some(request.uri.clone())
         } else {
             // No Route headers - send directly to Request-URI
             log::info!("do_request {}: No Route headers, sending to Request-URI: {}", method, request.uri);
-            (None, None)
+            None
         };
 
+        let (connection, destination) = match next_hop {
+            Some(uri) => {
+                let transport_layer = &self.endpoint_inner.transport_layer;
+                let sender = self.endpoint_inner.transport_tx.clone();
+                let lookup = match transport {
+                    Some(transport) => transport_layer.lookup_with_transport(&uri, transport, sender).await,
+                    None => transport_layer.lookup(&uri, sender).await,
+                };
+                match lookup {
+                    Ok((conn, resolved_addr)) => {
+                        log::info!("do_request {}: Using route destination: {}", method, resolved_addr);
+                        (Some(conn), Some(resolved_addr))
+                    }
+                    Err(e) if transport.is_some() => return Err(e),
+                    Err(e) => {
+                        log::error!("do_request {}: Failed to lookup route: {}", method, e);
+                        (None, None)
+                    }
+                }
+            }
+            None => (None, None),
+        };
+
+        let expected_id = DialogId::try_from(&request)
+            .ok()
+            .filter(|id| !id.to_tag.is_empty());
         let key = TransactionKey::from_request(&request, TransactionRole::Client)?;
         let mut tx = Transaction::new_client(key, request, self.endpoint_inner.clone(), connection);
         
@@ -497,10 +1109,19 @@ impl DialogInner {
         }
         
         tx.send().await?;
-        let mut auth_sent = false;
+        let mut auth = AuthRetries::new(self.endpoint_inner.max_auth_retries);
 
         while let Some(msg) = tx.receive().await {
             match msg {
+                SipMessage::Response(resp) if !Self::response_matches(expected_id.as_ref(), &resp) => {
+                    info!(
+                        "discarding {} response to {} with mismatched dialog identifiers, expected {}",
+                        resp.status_code,
+                        method,
+                        expected_id.as_ref().map(|id| id.to_string()).unwrap_or_default()
+                    );
+                    continue;
+                }
                 SipMessage::Response(resp) => match resp.status_code {
                     StatusCode::Trying => {
                         continue;
@@ -511,15 +1132,19 @@ impl DialogInner {
                     }
                     StatusCode::ProxyAuthenticationRequired | StatusCode::Unauthorized => {
                         let id = self.id.lock().unwrap().clone();
-                        if auth_sent {
+                        // the call is over once a BYE was sent, the caller
+                        // terminates the dialog whatever the outcome
+                        let terminate = method != rsip::Method::Bye;
+                        if !auth.retry(&resp) {
                             info!("received {} response after auth sent", resp.status_code);
-                            self.transition(DialogState::Terminated(
-                                id,
-                                TerminatedReason::ProxyAuthRequired,
-                            ))?;
-                            break;
+                            if terminate {
+                                self.transition(DialogState::Terminated(
+                                    id,
+                                    TerminatedReason::ProxyAuthRequired,
+                                ))?;
+                            }
+                            return Ok(None);
                         }
-                        auth_sent = true;
                         if let Some(cred) = &self.credential {
                             let new_seq = match method {
                                 rsip::Method::Cancel => self.get_local_seq(),
@@ -529,22 +1154,401 @@ impl DialogInner {
                             tx.send().await?;
                             continue;
                         } else {
-                            info!("received 407 response without auth option");
-                            self.transition(DialogState::Terminated(
-                                id,
-                                TerminatedReason::ProxyAuthRequired,
-                            ))?;
+                            info!("received {} response without auth option", resp.status_code);
+                            if terminate {
+                                self.transition(DialogState::Terminated(
+                                    id,
+                                    TerminatedReason::ProxyAuthRequired,
+                                ))?;
+                            }
+                            if self.endpoint_inner.strict_auth {
+                                return Err(crate::Error::AuthenticationRequired(
+                                    resp.status_code,
+                                ));
+                            }
+                            return Ok(Some(resp));
                         }
                     }
                     _ => {
                         debug!("dialog do_request done: {:?}", resp.status_code);
+                        self.update_session_id(&resp.headers);
                         return Ok(Some(resp));
                     }
                 },
                 _ => break,
             }
         }
-        Ok(None)
+        Err(crate::Error::TransactionTerminated(tx.key.clone()))
+    }
+
+    /// Send an in-dialog OPTIONS and return the final status
+    ///
+    /// A 481 means the peer no longer knows the dialog, which then
+    /// terminates (RFC 5057).
+    pub(super) async fn probe(&self) -> Result<StatusCode> {
+        let id = self.id.lock().unwrap().clone();
+        if !self.is_confirmed() {
+            return Err(crate::Error::DialogError(
+                "dialog is not confirmed".to_string(),
+                id,
+            ));
+        }
+        let request = self.make_request(rsip::Method::Options, None, None, None, None, None)?;
+        let resp = match self.do_request(request).await? {
+            Some(resp) => resp,
+            None => {
+                return Err(crate::Error::DialogError(
+                    "no final response to OPTIONS".to_string(),
+                    id,
+                ))
+            }
+        };
+        if resp.status_code == StatusCode::CallTransactionDoesNotExist {
+            info!("dialog {} is gone at the peer", id);
+            *self.failure_details.lock().unwrap() = resp.diagnostics();
+            self.transition(DialogState::Terminated(
+                id,
+                TerminatedReason::UasOther(Some(resp.status_code.clone())),
+            ))?;
+        }
+        Ok(resp.status_code)
+    }
+
+    /// Check that a response belongs to the in-dialog request it answers
+    ///
+    /// Call-ID, From tag and To tag must match the request that was sent.
+    /// Requests sent without a To tag are not checked.
+    fn response_matches(expected: Option<&DialogId>, resp: &Response) -> bool {
+        match expected {
+            Some(expected) => match DialogId::try_from(resp) {
+                Ok(id) => id == *expected,
+                Err(_) => false,
+            },
+            None => true,
+        }
+    }
+
+    /// Handle an inbound UPDATE
+    ///
+    /// An UPDATE is answered with 200 OK right away. Only when the endpoint
+    /// was built with `EndpointBuilder::with_manual_update`, an UPDATE
+    /// carrying an offer is kept pending until the application answers it
+    /// with [`Dialog::respond_update`].
+    pub(super) async fn handle_update(&self, mut tx: Transaction) -> Result<()> {
+        let id = self.id.lock().unwrap().clone();
+        if tx.original.body.is_empty() || !self.endpoint_inner.manual_update {
+            self.transition(DialogState::Updated(id, tx.original.clone()))?;
+            tx.reply(StatusCode::OK).await?;
+            return Ok(());
+        }
+        if self.pending_update.lock().unwrap().is_some() {
+            // an offer is already outstanding (RFC 3311 section 5.2)
+            info!("rejecting UPDATE while another offer is pending");
+            tx.reply(StatusCode::ServerInternalError).await?;
+            return Ok(());
+        }
+        let request = tx.original.clone();
+        self.pending_update.lock().unwrap().replace(tx);
+        self.transition(DialogState::Updated(id, request))
+    }
+
+    /// Handle an inbound in-dialog OPTIONS
+    ///
+    /// The request is surfaced as `DialogState::Options` and answered with
+    /// 200 OK right away, unless the endpoint was built with
+    /// `EndpointBuilder::with_manual_options`, in which case it is kept
+    /// pending for [`Dialog::respond_options`].
+    pub(super) async fn handle_options(&self, mut tx: Transaction) -> Result<()> {
+        let id = self.id.lock().unwrap().clone();
+        let request = tx.original.clone();
+        if !self.endpoint_inner.manual_options {
+            self.transition(DialogState::Options(id, request.clone()))?;
+            let resp = self.options_response(&request);
+            return tx.respond(resp).await;
+        }
+        let previous = self.pending_options.lock().unwrap().replace(tx);
+        if let Some(mut previous) = previous {
+            let resp = self.options_response(&previous.original);
+            previous.respond(resp).await.ok();
+        }
+        self.transition(DialogState::Options(id, request))
+    }
+
+    pub(super) async fn respond_options(&self, request: &Request) -> Result<()> {
+        let key = TransactionKey::from_request(request, TransactionRole::Server)?;
+        let mut tx = {
+            let mut pending = self.pending_options.lock().unwrap();
+            match pending.as_ref() {
+                Some(tx) if tx.key == key => pending.take().unwrap(),
+                _ => {
+                    return Err(crate::Error::DialogError(
+                        "no pending OPTIONS for request".to_string(),
+                        self.id.lock().unwrap().clone(),
+                    ))
+                }
+            }
+        };
+        let resp = self.options_response(request);
+        tx.respond(resp).await
+    }
+
+    /// 200 OK to an OPTIONS, listing our capabilities
+    fn options_response(&self, request: &Request) -> Response {
+        let supported = self
+            .endpoint_inner
+            .supported_header()
+            .unwrap_or_else(|| Header::Supported("timer".into()));
+        let headers = vec![
+            self.allow_header(),
+            Header::Accept("application/sdp".into()),
+            supported,
+        ];
+        self.make_response(request, StatusCode::OK, Some(headers), None)
+    }
+
+    /// `Allow` listing the endpoint's methods, or the dialog methods by default
+    fn allow_header(&self) -> Header {
+        let allows = if self.endpoint_inner.allows.is_empty() {
+            DIALOG_METHODS
+                .into_iter()
+                .filter(|m| *m != rsip::Method::Refer || self.endpoint_inner.accept_refer)
+                .collect()
+        } else {
+            self.endpoint_inner.allows.clone()
+        };
+        let allow = allows
+            .iter()
+            .map(|m| m.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        Header::Allow(allow.into())
+    }
+
+    /// Handle an inbound REFER (RFC 3515)
+    ///
+    /// The REFER is answered with 202 Accepted and surfaced as
+    /// `DialogState::Refer`. An implicit subscription is recorded unless the
+    /// referrer asked for none with `Refer-Sub: false` and the endpoint
+    /// supports `norefersub`, in which case the 202 echoes `Refer-Sub: false`.
+    /// Unless the endpoint accepts REFER, it is answered with 405.
+    pub(super) async fn handle_refer(&self, mut tx: Transaction) -> Result<()> {
+        if !self.endpoint_inner.accept_refer {
+            info!("invalid request method: {:?}", tx.original.method);
+            tx.reply(StatusCode::MethodNotAllowed).await?;
+            return Ok(());
+        }
+        let id = self.id.lock().unwrap().clone();
+        let request = tx.original.clone();
+        let refer_to = match request.headers.get_other("Refer-To") {
+            Some(refer_to) => refer_to.to_string(),
+            None => {
+                info!("rejecting REFER without Refer-To");
+                tx.reply(StatusCode::BadRequest).await?;
+                return Ok(());
+            }
+        };
+        let no_subscription = request
+            .headers
+            .get_other("Refer-Sub")
+            .map(|value| value.trim().eq_ignore_ascii_case("false"))
+            .unwrap_or(false)
+            && self.endpoint_inner.is_supported("norefersub");
+
+        let mut headers = vec![];
+        if no_subscription {
+            headers.push(Header::Other("Refer-Sub".into(), "false".into()));
+        } else {
+            let cseq = request.cseq_header()?.seq()?;
+            self.refer_subscriptions
+                .lock()
+                .unwrap()
+                .push(ReferSubscription { id: cseq, refer_to });
+        }
+        // rsip maps "Accepted" to 201, build the 202 explicitly
+        let accepted = StatusCode::Other(202, "Accepted".into());
+        let resp = self.make_response(&request, accepted, Some(headers), None);
+        tx.respond(resp).await?;
+        self.transition(DialogState::Refer(id, request))
+    }
+
+    /// Send a REFER asking the peer to contact `refer_to` (RFC 3515)
+    ///
+    /// Once the REFER is accepted, the implicit `refer` subscription is
+    /// recorded so the peer's NOTIFYs are matched, unless the peer answered
+    /// with `Refer-Sub: false`.
+    pub(super) async fn refer(
+        &self,
+        refer_to: &rsip::Uri,
+        headers: Option<Vec<Header>>,
+    ) -> Result<Option<Response>> {
+        if !self.is_confirmed() {
+            return Ok(None);
+        }
+        let mut headers = headers.unwrap_or_default();
+        headers.push(Header::Other("Refer-To".into(), format!("<{}>", refer_to)));
+        let request =
+            self.make_request(rsip::Method::Refer, None, None, None, Some(headers), None)?;
+        let resp = self.do_request(request).await?;
+        if let Some(resp) = &resp {
+            let no_subscription = resp
+                .headers
+                .get_other("Refer-Sub")
+                .is_some_and(|value| value.trim().eq_ignore_ascii_case("false"));
+            if resp.status_code.kind() == rsip::StatusCodeKind::Successful && !no_subscription {
+                // the id is the CSeq of the REFER that was accepted, which
+                // changes when the REFER was resent with credentials
+                let cseq = resp.cseq_header()?.seq()?;
+                self.subscriptions.lock().unwrap().push(EventSubscription {
+                    event: "refer".to_string(),
+                    id: Some(cseq.to_string()),
+                });
+            }
+        }
+        Ok(resp)
+    }
+
+    /// Handle an inbound NOTIFY (RFC 6665)
+    ///
+    /// A NOTIFY matching an active subscription is answered with 200 OK and
+    /// surfaced as `DialogState::Notify`; a terminated `Subscription-State`
+    /// ends the subscription. An `Event` without `id` matches the oldest
+    /// subscription of its package, since RFC 3515 lets the notifier omit
+    /// the id of the first REFER. Any other NOTIFY is rejected with 481.
+    pub(super) async fn handle_notify(&self, mut tx: Transaction) -> Result<()> {
+        let id = self.id.lock().unwrap().clone();
+        let request = tx.original.clone();
+        let event = request.headers.iter().find_map(|h| match h {
+            Header::Event(event) => Some(EventSubscription::from_event(event.value())),
+            _ => None,
+        });
+        let event = match event {
+            Some(event) => event,
+            None => {
+                info!("rejecting NOTIFY without Event");
+                tx.reply(StatusCode::BadEvent).await?;
+                return Ok(());
+            }
+        };
+        let terminated = request.headers.iter().any(|h| match h {
+            Header::SubscriptionState(state) => state
+                .value()
+                .trim()
+                .to_lowercase()
+                .starts_with("terminated"),
+            _ => false,
+        });
+        let matched = {
+            let mut subscriptions = self.subscriptions.lock().unwrap();
+            let exact = subscriptions.iter().position(|sub| *sub == event);
+            let position = match (exact, &event.id) {
+                (None, None) => subscriptions
+                    .iter()
+                    .position(|sub| sub.event == event.event),
+                _ => exact,
+            };
+            match position {
+                Some(position) => {
+                    if terminated {
+                        subscriptions.remove(position);
+                    }
+                    true
+                }
+                None => false,
+            }
+        };
+        if !matched {
+            info!("rejecting NOTIFY without subscription: {:?}", event);
+            tx.reply(StatusCode::CallTransactionDoesNotExist).await?;
+            return Ok(());
+        }
+        tx.reply(StatusCode::OK).await?;
+        self.transition(DialogState::Notify(id, request))
+    }
+
+    /// Report the progress of a referred request with a NOTIFY
+    ///
+    /// The subscription ends, and is forgotten, once `status` is final.
+    pub(super) async fn notify_refer(
+        &self,
+        id: u32,
+        status: StatusCode,
+    ) -> Result<Option<Response>> {
+        let known = self
+            .refer_subscriptions
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|sub| sub.id == id);
+        if !known {
+            return Err(crate::Error::DialogError(
+                format!("no refer subscription with id {}", id),
+                self.id.lock().unwrap().clone(),
+            ));
+        }
+        let is_final = status.code() >= 200;
+        let subscription_state = if is_final {
+            "terminated;reason=noresource"
+        } else {
+            "active;expires=60"
+        };
+        let headers = vec![
+            Header::Event(format!("refer;id={}", id).into()),
+            Header::SubscriptionState(subscription_state.into()),
+            Header::ContentType("message/sipfrag;version=2.0".into()),
+        ];
+        let body = format!("SIP/2.0 {}\r\n", status).into_bytes();
+        if is_final {
+            self.refer_subscriptions
+                .lock()
+                .unwrap()
+                .retain(|sub| sub.id != id);
+        }
+        let request = self.make_request(
+            rsip::Method::Notify,
+            None,
+            None,
+            None,
+            Some(headers),
+            Some(body),
+        )?;
+        self.do_request(request).await
+    }
+
+    pub(super) async fn respond_update(
+        &self,
+        request: &Request,
+        answer: Option<Vec<u8>>,
+    ) -> Result<()> {
+        let key = TransactionKey::from_request(request, TransactionRole::Server)?;
+        let mut tx = {
+            let mut pending = self.pending_update.lock().unwrap();
+            match pending.as_ref() {
+                Some(tx) if tx.key == key => pending.take().unwrap(),
+                _ => {
+                    return Err(crate::Error::DialogError(
+                        "no pending UPDATE for request".to_string(),
+                        self.id.lock().unwrap().clone(),
+                    ))
+                }
+            }
+        };
+
+        // UPDATE is a target refresh request (RFC 3311 section 5.2)
+        if let Ok(contact) = request.contact_header() {
+            match extract_uri_from_contact(contact.value()) {
+                Ok(uri) => {
+                    info!("refreshing remote target from UPDATE: {}", uri);
+                    *self.remote_uri.lock().unwrap() = uri;
+                }
+                Err(e) => info!("invalid contact in UPDATE: {}", e),
+            }
+        }
+
+        let headers = answer
+            .as_ref()
+            .map(|_| vec![Header::ContentType("application/sdp".into())]);
+        let resp = self.make_response(request, StatusCode::OK, headers, answer);
+        tx.respond(resp).await
     }
 
     pub(super) fn transition(&self, state: DialogState) -> Result<()> {
@@ -557,11 +1561,15 @@ impl DialogInner {
             DialogState::Updated(_, _)
             | DialogState::Notify(_, _)
             | DialogState::Info(_, _)
-            | DialogState::Options(_, _) => {
+            | DialogState::Options(_, _)
+            | DialogState::Refer(_, _) => {
                 return Ok(());
             }
             _ => {}
         }
+        if state.is_confirmed() {
+            self.touch();
+        }
         let mut old_state = self.state.lock().unwrap();
         info!("transitioning state: {} -> {}", old_state, state);
         *old_state = state;
@@ -581,6 +1589,7 @@ impl std::fmt::Display for DialogState {
             DialogState::Notify(id, _) => write!(f, "{}(Notify)", id),
             DialogState::Info(id, _) => write!(f, "{}(Info)", id),
             DialogState::Options(id, _) => write!(f, "{}(Options)", id),
+            DialogState::Refer(id, _) => write!(f, "{}(Refer)", id),
             DialogState::Terminated(id, reason) => write!(f, "{}(Terminated {:?})", id, reason),
         }
     }
@@ -599,6 +1608,121 @@ impl Dialog {
             Dialog::ClientInvite(d) => d.handle(tx).await,
         }
     }
+
+    /// Get the request that created this dialog
+    ///
+    /// Returns a copy of the INVITE sent (client dialogs) or received
+    /// (server dialogs) when the dialog was created, e.g. to re-offer its
+    /// SDP or to build a `Replaces` header.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::dialog::Dialog;
+    /// # fn example(dialog: Dialog) {
+    /// let invite = dialog.initial_request();
+    /// println!("dialog created by {} {}", invite.method, invite.uri);
+    /// # }
+    /// ```
+    pub fn initial_request(&self) -> Request {
+        self.inner().initial_request.clone()
+    }
+
+    /// Get the end-to-end session identifier (RFC 7989)
+    ///
+    /// The local half is sent in the `Session-ID` header of every request
+    /// and response of the dialog, the remote half is learned from the
+    /// peer's messages. Log it to correlate the call across B2BUAs.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::dialog::Dialog;
+    /// # fn example(dialog: Dialog) {
+    /// println!("dialog {} session {}", dialog.id(), dialog.session_id());
+    /// # }
+    /// ```
+    pub fn session_id(&self) -> SessionId {
+        self.inner().session_id()
+    }
+
+    /// Get the diagnostics of the failure that terminated the dialog
+    ///
+    /// Holds the text of the `Warning` and `Error-Info` headers of the final
+    /// failure response, see [`ClientInviteDialog::failure_details`].
+    pub fn failure_details(&self) -> Option<String> {
+        self.inner().failure_details.lock().unwrap().clone()
+    }
+
+    /// Get the Q.850 cause of the BYE that terminated the dialog
+    ///
+    /// Parsed from the `Reason` header of a received BYE and set before the
+    /// dialog moves to `Terminated` with `UacBye` or `UasBye`. `None` when
+    /// the BYE had no Q.850 reason or was sent by us.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::dialog::{Dialog, Q850Cause};
+    /// # fn example(dialog: Dialog) {
+    /// if dialog.hangup_cause() == Some(Q850Cause::Congestion) {
+    ///     println!("call {} dropped by the network", dialog.id());
+    /// }
+    /// # }
+    /// ```
+    pub fn hangup_cause(&self) -> Option<Q850Cause> {
+        *self.inner().hangup_cause.lock().unwrap()
+    }
+
+    pub(super) fn inner(&self) -> &DialogInnerRef {
+        match self {
+            Dialog::ServerInvite(d) => &d.inner,
+            Dialog::ClientInvite(d) => &d.inner,
+        }
+    }
+
+    /// Record activity the stack cannot see, e.g. RTP or a media keepalive
+    ///
+    /// Restarts the idle timeout set with `DialogLayer::set_idle_timeout`.
+    pub fn touch(&self) {
+        self.inner().touch();
+    }
+
+    /// Capture the state needed to restore this dialog elsewhere
+    ///
+    /// Meant for confirmed dialogs; pending transactions, subscriptions and
+    /// timers are not part of the snapshot.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::dialog::Dialog;
+    /// # fn example(dialog: Dialog) {
+    /// let snapshot = dialog.serialize_state();
+    /// println!("{} {} cseq {}", snapshot.id, snapshot.remote_uri, snapshot.local_seq);
+    /// # }
+    /// ```
+    pub fn serialize_state(&self) -> DialogSnapshot {
+        let inner = self.inner();
+        DialogSnapshot {
+            role: inner.role.clone(),
+            id: inner.id.lock().unwrap().clone(),
+            remote_uri: inner.remote_uri.lock().unwrap().to_string(),
+            route_set: inner
+                .route_set
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|route| route.value().to_string())
+                .collect(),
+            local_seq: inner.local_seq.load(Ordering::Relaxed),
+            remote_seq: inner.remote_seq.load(Ordering::Relaxed),
+            local_contact: inner.local_contact.as_ref().map(|uri| uri.to_string()),
+            from: inner.from.clone(),
+            to: inner.to.lock().unwrap().clone(),
+        }
+    }
+
     pub fn on_remove(&self) {
         match self {
             Dialog::ServerInvite(d) => {
@@ -610,6 +1734,204 @@ impl Dialog {
         }
     }
 
+    /// Answer an inbound UPDATE
+    ///
+    /// Sends a 200 OK to an UPDATE surfaced through `DialogState::Updated`,
+    /// carrying the SDP answer when the UPDATE contained an offer, and
+    /// refreshes the remote target from the UPDATE's Contact. Only needed
+    /// when the endpoint was built with `EndpointBuilder::with_manual_update`;
+    /// otherwise the UPDATE has already been answered.
+    ///
+    /// # Parameters
+    ///
+    /// * `request` - The UPDATE request from `DialogState::Updated`
+    /// * `answer` - Optional SDP answer
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - 200 OK was sent
+    /// * `Err(Error)` - No pending UPDATE matches `request`, or sending failed
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::dialog::{Dialog, DialogState};
+    /// # async fn example() -> rsipstack::Result<()> {
+    /// # let dialog: Dialog = todo!();
+    /// # let state: DialogState = todo!();
+    /// # let answer_sdp = vec![];
+    /// if let DialogState::Updated(_, request) = state {
+    ///     if request.method == rsip::Method::Update {
+    ///         dialog.respond_update(&request, Some(answer_sdp)).await?;
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn respond_update(&self, request: &Request, answer: Option<Vec<u8>>) -> Result<()> {
+        match self {
+            Dialog::ServerInvite(d) => d.inner.respond_update(request, answer).await,
+            Dialog::ClientInvite(d) => d.inner.respond_update(request, answer).await,
+        }
+    }
+
+    /// Answer an in-dialog OPTIONS
+    ///
+    /// Sends a 200 OK with `Allow`, `Accept` and `Supported` headers to an
+    /// OPTIONS surfaced through `DialogState::Options`, e.g. a keepalive
+    /// probe sent by the peer during a call. The dialog stays as it is.
+    /// Only needed when the endpoint was built with
+    /// `EndpointBuilder::with_manual_options`; otherwise the OPTIONS has
+    /// already been answered.
+    ///
+    /// # Parameters
+    ///
+    /// * `request` - The OPTIONS request from `DialogState::Options`
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - 200 OK was sent
+    /// * `Err(Error)` - No pending OPTIONS matches `request`, or sending failed
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::dialog::{Dialog, DialogState};
+    /// # async fn example() -> rsipstack::Result<()> {
+    /// # let dialog: Dialog = todo!();
+    /// # let state: DialogState = todo!();
+    /// if let DialogState::Options(_, request) = state {
+    ///     dialog.respond_options(&request).await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn respond_options(&self, request: &Request) -> Result<()> {
+        self.inner().respond_options(request).await
+    }
+
+    /// Get the implicit subscriptions created by accepted REFERs
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::dialog::Dialog;
+    /// # fn example(dialog: Dialog) {
+    /// for subscription in dialog.refer_subscriptions() {
+    ///     println!("refer {} to {}", subscription.id, subscription.refer_to);
+    /// }
+    /// # }
+    /// ```
+    pub fn refer_subscriptions(&self) -> Vec<ReferSubscription> {
+        self.inner().refer_subscriptions.lock().unwrap().clone()
+    }
+
+    /// Get the subscriptions this side receives NOTIFYs for
+    pub fn subscriptions(&self) -> Vec<EventSubscription> {
+        self.inner().subscriptions.lock().unwrap().clone()
+    }
+
+    /// Transfer the peer to `refer_to` with a REFER (RFC 3515)
+    ///
+    /// The peer reports the progress of the transfer with NOTIFYs, which
+    /// are surfaced as `DialogState::Notify` until the implicit
+    /// subscription ends.
+    ///
+    /// # Parameters
+    ///
+    /// * `refer_to` - Target the peer should contact
+    /// * `headers` - Optional additional headers, e.g. `Refer-Sub: false`
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(Response))` - Final response to the REFER
+    /// * `Ok(None)` - Dialog not confirmed, no request sent
+    /// * `Err(Error)` - Failed to send the REFER
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::dialog::Dialog;
+    /// # async fn example(dialog: Dialog) -> rsipstack::Result<()> {
+    /// let target = rsip::Uri::try_from("sip:carol@example.com")?;
+    /// if let Some(resp) = dialog.refer(&target, None).await? {
+    ///     println!("transfer {}", resp.status_code);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn refer(
+        &self,
+        refer_to: &rsip::Uri,
+        headers: Option<Vec<Header>>,
+    ) -> Result<Option<Response>> {
+        self.inner().refer(refer_to, headers).await
+    }
+
+    /// Report the progress of a REFER to the referrer
+    ///
+    /// Sends a NOTIFY with a `message/sipfrag` body carrying `status` on the
+    /// implicit subscription created by the REFER. A final `status` ends
+    /// the subscription.
+    ///
+    /// # Parameters
+    ///
+    /// * `id` - Id of the subscription, see [`ReferSubscription`]
+    /// * `status` - Status of the referred request, e.g. 100 Trying or 200 OK
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(Response))` - Response to the NOTIFY
+    /// * `Err(Error)` - No subscription with that id, or sending failed
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::dialog::{Dialog, DialogState};
+    /// # async fn example() -> rsipstack::Result<()> {
+    /// # let dialog: Dialog = todo!();
+    /// # let state: DialogState = todo!();
+    /// if let DialogState::Refer(_, _) = state {
+    ///     for subscription in dialog.refer_subscriptions() {
+    ///         dialog
+    ///             .notify_refer(subscription.id, rsip::StatusCode::Trying)
+    ///             .await?;
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn notify_refer(&self, id: u32, status: StatusCode) -> Result<Option<Response>> {
+        self.inner().notify_refer(id, status).await
+    }
+
+    /// Check that the peer still knows this dialog
+    ///
+    /// Sends an in-dialog OPTIONS and waits for its final response, a cheap
+    /// liveness check during long calls. When the peer answers
+    /// `481 Call/Transaction Does Not Exist` the dialog transitions to
+    /// `Terminated`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(StatusCode)` - Final status of the OPTIONS
+    /// * `Err(Error)` - The dialog is not confirmed, or the OPTIONS failed
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::dialog::Dialog;
+    /// # async fn example(dialog: Dialog) -> rsipstack::Result<()> {
+    /// if dialog.probe().await? != rsip::StatusCode::OK {
+    ///     dialog.hangup().await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn probe(&self) -> Result<StatusCode> {
+        self.inner().probe().await
+    }
+
     pub async fn hangup(&self) -> Result<()> {
         match self {
             Dialog::ServerInvite(d) => d.bye().await,
@@ -623,3 +1945,9 @@ impl Dialog {
         }
     }
 }
+
+/// Parse the delta-seconds of a `Session-Expires` or `Min-SE` value,
+/// ignoring any parameters such as `;refresher=uac`
+fn parse_delta_seconds(value: &str) -> Option<u32> {
+    value.split(';').next()?.trim().parse().ok()
+}
@@ -1,18 +1,26 @@
 use super::authenticate::Credential;
-use super::dialog::DialogStateSender;
+use super::client_dialog::ClientInviteDialog;
+use super::dialog::{DialogSnapshot, DialogStateSender};
 use super::{dialog::Dialog, server_dialog::ServerInviteDialog, DialogId};
-use crate::dialog::dialog::DialogInner;
-use crate::transaction::key::TransactionRole;
-use crate::transaction::make_tag;
-use crate::transaction::{endpoint::EndpointInnerRef, transaction::Transaction};
+use crate::dialog::dialog::{DialogInner, DialogState, DEFAULT_MIN_SE};
+use crate::transaction::key::{TransactionKey, TransactionRole};
+use crate::transaction::{
+    endpoint::EndpointInnerRef,
+    transaction::{Transaction, TransactionEvent},
+};
+use crate::transaction::{make_tag, next_cseq};
 use crate::Result;
 use rsip::Request;
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
 use std::{
     collections::HashMap,
-    sync::{Arc, RwLock},
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
 };
-use tracing::info;
+use tracing::{info, warn};
+
+/// Seconds advertised in `Retry-After` when an INVITE is refused for the dialog limit
+const MAX_DIALOGS_RETRY_AFTER: u32 = 5;
 
 /// Internal Dialog Layer State
 ///
@@ -24,6 +32,12 @@ use tracing::info;
 ///
 /// * `last_seq` - Atomic counter for generating unique sequence numbers
 /// * `dialogs` - Thread-safe map of active dialogs indexed by DialogId
+/// * `max_dialogs` - Maximum number of concurrent dialogs, 0 means unlimited
+/// * `min_se` - Smallest session interval accepted on inbound INVITEs
+/// * `ack_timeout` - How long server dialogs wait for the ACK to their 2xx, `None` for 64*T1
+/// * `idle_timeout` - Inactivity after which confirmed dialogs are ended with a BYE, `None` to disable
+/// * `default_contact` - Contact used for outbound INVITEs instead of `InviteOption::contact`, e.g. a pub-GRUU
+/// * `service_route` - Route headers pre-loaded on outbound INVITEs, e.g. a registrar's Service-Route
 ///
 /// # Thread Safety
 ///
@@ -33,6 +47,12 @@ use tracing::info;
 pub struct DialogLayerInner {
     pub(super) last_seq: AtomicU32,
     pub(super) dialogs: RwLock<HashMap<DialogId, Dialog>>,
+    pub(super) max_dialogs: AtomicUsize,
+    pub(super) min_se: AtomicU32,
+    pub(super) ack_timeout: Mutex<Option<Duration>>,
+    pub(super) idle_timeout: Mutex<Option<Duration>>,
+    pub(super) default_contact: Mutex<Option<rsip::Uri>>,
+    pub(super) service_route: Mutex<Vec<rsip::headers::Route>>,
 }
 pub type DialogLayerInnerRef = Arc<DialogLayerInner>;
 
@@ -135,11 +155,18 @@ pub struct DialogLayer {
 
 impl DialogLayer {
     pub fn new(endpoint: EndpointInnerRef) -> Self {
+        let last_seq = endpoint.make_cseq();
         Self {
             endpoint,
             inner: Arc::new(DialogLayerInner {
-                last_seq: AtomicU32::new(crate::transaction::generate_random_cseq()),
+                last_seq: AtomicU32::new(last_seq),
                 dialogs: RwLock::new(HashMap::new()),
+                max_dialogs: AtomicUsize::new(0),
+                min_se: AtomicU32::new(DEFAULT_MIN_SE),
+                ack_timeout: Mutex::new(None),
+                idle_timeout: Mutex::new(None),
+                default_contact: Mutex::new(None),
+                service_route: Mutex::new(vec![]),
             }),
         }
     }
@@ -165,7 +192,128 @@ impl DialogLayer {
             }
         }
         id.to_tag = make_tag().to_string(); // generate to tag
+        self.refuse_over_limit(tx, &id)?;
+        self.create_server_invite(id, tx, state_sender, credential, contact)
+    }
+
+    /// Create a server dialog for a received INVITE
+    ///
+    /// This is the explicit constructor for applications that receive
+    /// INVITEs through their own transport wiring. The dialog is created
+    /// with a fresh To-tag and the route set of the INVITE's
+    /// `Record-Route` headers, inserted into the layer and its transaction
+    /// is handled in the background, so it can be answered right away with
+    /// `accept` or `reject`.
+    ///
+    /// # Parameters
+    ///
+    /// * `request` - The received initial INVITE
+    /// * `tx` - Server transaction the INVITE arrived on
+    /// * `state_sender` - Channel for dialog state updates
+    /// * `credential` - Credentials for authenticated in-dialog requests
+    /// * `local_contact` - Contact URI put in our responses
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ServerInviteDialog)` - The new dialog, ready to answer
+    /// * `Err(Error)` - The request is not an initial INVITE of `tx`, or the
+    ///   dialog limit is reached
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::dialog_layer::DialogLayer;
+    /// # use rsipstack::transaction::transaction::Transaction;
+    /// # async fn example() -> rsipstack::Result<()> {
+    /// # let dialog_layer: DialogLayer = todo!();
+    /// # let tx: Transaction = todo!();
+    /// let (state_sender, _state_receiver) = tokio::sync::mpsc::unbounded_channel();
+    /// let dialog = dialog_layer.accept_invite(
+    ///     tx.original.clone(),
+    ///     tx,
+    ///     state_sender,
+    ///     None,
+    ///     None,
+    /// )?;
+    /// dialog.accept(None, None)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn accept_invite(
+        &self,
+        request: Request,
+        mut tx: Transaction,
+        state_sender: DialogStateSender,
+        credential: Option<Credential>,
+        local_contact: Option<rsip::Uri>,
+    ) -> Result<ServerInviteDialog> {
+        let mut id = DialogId::try_from(&request)?;
+        if request.method != rsip::Method::Invite
+            || TransactionKey::from_request(&request, TransactionRole::Server)? != tx.key
+        {
+            return Err(crate::Error::DialogError(
+                "request is not the INVITE of the transaction".to_string(),
+                id,
+            ));
+        }
+        if !id.to_tag.is_empty() {
+            return Err(crate::Error::DialogError(
+                "not an initial INVITE".to_string(),
+                id,
+            ));
+        }
+        id.to_tag = make_tag().to_string();
+        if let Err(e) = self.refuse_over_limit(&tx, &id) {
+            tokio::spawn(async move { while tx.receive().await.is_some() {} });
+            return Err(e);
+        }
+
+        let dialog = self.create_server_invite(id, &tx, state_sender, credential, local_contact)?;
+        dialog
+            .inner
+            .tu_sender
+            .lock()
+            .unwrap()
+            .replace(tx.tu_sender.clone());
+        let mut handler = dialog.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handler.handle(tx).await {
+                info!("server invite dialog {} handle error: {}", handler.id(), e);
+            }
+        });
+        Ok(dialog)
+    }
 
+    /// Answer an initial INVITE with 503 once the dialog limit is reached
+    ///
+    /// The response carries `Retry-After` and is queued on the server
+    /// transaction, so it goes out as soon as the transaction is driven.
+    fn refuse_over_limit(&self, tx: &Transaction, id: &DialogId) -> Result<()> {
+        let max = match self.max_dialogs() {
+            Some(max) if self.len() >= max => max,
+            _ => return Ok(()),
+        };
+        info!("dialog limit reached ({max}), answering {id} with 503");
+        let mut resp =
+            self.endpoint
+                .make_response(&tx.original, rsip::StatusCode::ServiceUnavailable, None);
+        resp.headers
+            .push(rsip::headers::RetryAfter::new(MAX_DIALOGS_RETRY_AFTER.to_string()).into());
+        tx.tu_sender.send(TransactionEvent::Respond(resp)).ok();
+        Err(crate::Error::DialogError(
+            format!("dialog limit reached: {}", max),
+            id.clone(),
+        ))
+    }
+
+    fn create_server_invite(
+        &self,
+        id: DialogId,
+        tx: &Transaction,
+        state_sender: DialogStateSender,
+        credential: Option<Credential>,
+        contact: Option<rsip::Uri>,
+    ) -> Result<ServerInviteDialog> {
         let dlg_inner = DialogInner::new(
             TransactionRole::Server,
             id.clone(),
@@ -175,22 +323,353 @@ impl DialogLayer {
             credential,
             contact,
         )?;
+        dlg_inner
+            .min_se
+            .store(self.inner.min_se.load(Ordering::Relaxed), Ordering::Relaxed);
+        *dlg_inner.ack_timeout.lock().unwrap() = self.ack_timeout();
+        *dlg_inner.idle_timeout.lock().unwrap() = self.idle_timeout();
+        *dlg_inner.inbound_addr.lock().unwrap() =
+            tx.connection.as_ref().map(|c| c.get_addr().clone());
 
         let dialog = ServerInviteDialog {
             inner: Arc::new(dlg_inner),
         };
-        self.inner
-            .dialogs
-            .write()
-            .unwrap()
-            .insert(id.clone(), Dialog::ServerInvite(dialog.clone()));
+        self.insert_dialog(id.clone(), Dialog::ServerInvite(dialog.clone()))?;
+        dialog.inner.start_idle_timer();
         info!("server invite dialog created: {id}");
         Ok(dialog)
     }
 
+    /// Restore a dialog from a snapshot taken on another instance
+    ///
+    /// The dialog is inserted in the layer as confirmed, with the target,
+    /// route set and CSeq numbers of the snapshot, so in-dialog requests
+    /// such as BYE continue where the original instance stopped. Requests
+    /// from the peer are matched to it like to any other dialog.
+    ///
+    /// # Parameters
+    ///
+    /// * `snapshot` - State taken with [`Dialog::serialize_state`]
+    /// * `state_sender` - Channel for dialog state updates
+    /// * `credential` - Credentials for authenticated in-dialog requests
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Dialog)` - The restored dialog
+    /// * `Err(Error)` - The snapshot has invalid URIs or headers, or its
+    ///   dialog id is in use
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::dialog::DialogSnapshot;
+    /// # use rsipstack::dialog::dialog_layer::DialogLayer;
+    /// # async fn example() -> rsipstack::Result<()> {
+    /// # let dialog_layer: DialogLayer = todo!();
+    /// # let snapshot: DialogSnapshot = todo!();
+    /// let (state_sender, _state_receiver) = tokio::sync::mpsc::unbounded_channel();
+    /// let dialog = dialog_layer.restore_dialog(snapshot, state_sender, None)?;
+    /// dialog.hangup().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn restore_dialog(
+        &self,
+        snapshot: DialogSnapshot,
+        state_sender: DialogStateSender,
+        credential: Option<Credential>,
+    ) -> Result<Dialog> {
+        use rsip::prelude::UntypedHeader;
+
+        let id = snapshot.id.clone();
+        let remote_uri = rsip::Uri::try_from(snapshot.remote_uri.as_str())?;
+        let local_contact = snapshot
+            .local_contact
+            .as_deref()
+            .map(rsip::Uri::try_from)
+            .transpose()?;
+        // the dialog takes its parties and target from the initial request,
+        // stand one in as the peer or we would have sent it
+        let (from, to, seq) = match snapshot.role {
+            TransactionRole::Client => (&snapshot.from, &snapshot.to, snapshot.local_seq),
+            TransactionRole::Server => (&snapshot.to, &snapshot.from, snapshot.remote_seq),
+        };
+        let initial_request = Request {
+            method: rsip::Method::Invite,
+            uri: remote_uri.clone(),
+            headers: vec![
+                rsip::headers::From::new(from.clone()).into(),
+                rsip::headers::To::new(to.clone()).into(),
+                rsip::headers::CallId::new(id.call_id.clone()).into(),
+                rsip::headers::CSeq::new(format!("{} INVITE", seq)).into(),
+                rsip::headers::Contact::new(format!("<{}>", remote_uri)).into(),
+            ]
+            .into(),
+            version: rsip::Version::V2,
+            body: vec![],
+        };
+        let mut dlg_inner = DialogInner::new(
+            snapshot.role.clone(),
+            id.clone(),
+            initial_request,
+            self.endpoint.clone(),
+            state_sender,
+            credential,
+            local_contact,
+        )?;
+        *dlg_inner.route_set.get_mut().unwrap() = snapshot
+            .route_set
+            .into_iter()
+            .map(rsip::headers::Route::new)
+            .collect();
+        dlg_inner
+            .local_seq
+            .store(snapshot.local_seq, Ordering::Relaxed);
+        dlg_inner
+            .remote_seq
+            .store(snapshot.remote_seq, Ordering::Relaxed);
+        *dlg_inner.state.get_mut().unwrap() = DialogState::Confirmed(id.clone());
+        *dlg_inner.idle_timeout.get_mut().unwrap() = self.idle_timeout();
+
+        let inner = Arc::new(dlg_inner);
+        let dialog = match snapshot.role {
+            TransactionRole::Client => Dialog::ClientInvite(ClientInviteDialog {
+                inner: inner.clone(),
+            }),
+            TransactionRole::Server => Dialog::ServerInvite(ServerInviteDialog {
+                inner: inner.clone(),
+            }),
+        };
+        self.insert_dialog(id.clone(), dialog.clone())?;
+        inner.start_idle_timer();
+        info!("dialog restored: {id}");
+        Ok(dialog)
+    }
+
+    /// Limit the number of concurrent dialogs
+    ///
+    /// Once `max` dialogs are active, new outbound INVITEs fail and inbound
+    /// INVITEs are refused by `get_or_create_server_invite`, which answers
+    /// them with `503 Service Unavailable` and a `Retry-After` on their
+    /// transaction. The application still drives that transaction, e.g. with
+    /// `tx.receive()`, for the response to go out. Existing dialogs are not
+    /// affected.
+    ///
+    /// # Parameters
+    ///
+    /// * `max` - Maximum number of dialogs, 0 removes the limit
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::dialog_layer::DialogLayer;
+    /// # fn example() {
+    /// # let dialog_layer: DialogLayer = todo!();
+    /// dialog_layer.set_max_dialogs(32);
+    /// # }
+    /// ```
+    pub fn set_max_dialogs(&self, max: usize) {
+        self.inner.max_dialogs.store(max, Ordering::Relaxed);
+    }
+
+    pub fn max_dialogs(&self) -> Option<usize> {
+        match self.inner.max_dialogs.load(Ordering::Relaxed) {
+            0 => None,
+            max => Some(max),
+        }
+    }
+
+    /// Set the local `Min-SE` for inbound INVITEs (RFC 4028)
+    ///
+    /// Server dialogs created afterwards reject an INVITE whose
+    /// `Session-Expires` is below `secs` with `422 Session Interval Too Small`.
+    /// Values below the RFC minimum of 90 seconds are raised to it.
+    ///
+    /// # Parameters
+    ///
+    /// * `secs` - Minimum session interval in seconds
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::dialog_layer::DialogLayer;
+    /// # fn example() {
+    /// # let dialog_layer: DialogLayer = todo!();
+    /// dialog_layer.set_min_se(1800);
+    /// # }
+    /// ```
+    pub fn set_min_se(&self, secs: u32) {
+        self.inner
+            .min_se
+            .store(secs.max(DEFAULT_MIN_SE), Ordering::Relaxed);
+    }
+
+    pub fn min_se(&self) -> u32 {
+        self.inner.min_se.load(Ordering::Relaxed)
+    }
+
+    /// Set how long server dialogs wait for the ACK to their 2xx
+    ///
+    /// This is the dialog level equivalent of Timer H. When no ACK arrives
+    /// in time after `accept`, the dialog stops retransmitting the 2xx and
+    /// transitions to `Terminated(Timeout)`. Applies to server dialogs
+    /// created afterwards, the default is 64*T1 (32 seconds).
+    ///
+    /// # Parameters
+    ///
+    /// * `timeout` - Time to wait for the ACK
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::dialog_layer::DialogLayer;
+    /// # use std::time::Duration;
+    /// # fn example() {
+    /// # let dialog_layer: DialogLayer = todo!();
+    /// dialog_layer.set_ack_timeout(Duration::from_secs(10));
+    /// # }
+    /// ```
+    pub fn set_ack_timeout(&self, timeout: Duration) {
+        self.inner.ack_timeout.lock().unwrap().replace(timeout);
+    }
+
+    pub fn ack_timeout(&self) -> Duration {
+        self.inner
+            .ack_timeout
+            .lock()
+            .unwrap()
+            .unwrap_or(self.endpoint.t1x64)
+    }
+
+    /// Tear down confirmed dialogs that stay idle for `timeout`
+    ///
+    /// A dialog with no in-dialog request in either direction for `timeout`
+    /// sends a BYE and transitions to `Terminated(Timeout)`. Activity the
+    /// stack cannot see, such as RTP, can be reported with `Dialog::touch`.
+    /// Applies to dialogs created afterwards and is off by default.
+    ///
+    /// # Parameters
+    ///
+    /// * `timeout` - Allowed inactivity, `None` disables the idle timeout
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::dialog_layer::DialogLayer;
+    /// # use std::time::Duration;
+    /// # fn example() {
+    /// # let dialog_layer: DialogLayer = todo!();
+    /// dialog_layer.set_idle_timeout(Some(Duration::from_secs(3600)));
+    /// # }
+    /// ```
+    pub fn set_idle_timeout(&self, timeout: Option<Duration>) {
+        *self.inner.idle_timeout.lock().unwrap() = timeout;
+    }
+
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        *self.inner.idle_timeout.lock().unwrap()
+    }
+
+    /// Use `contact` as the Contact of outbound INVITEs
+    ///
+    /// When set, it replaces `InviteOption::contact` in the INVITE and as
+    /// the local contact of the dialog. Feed it the pub-GRUU obtained by a
+    /// [`Registration`](super::registration::Registration), so requests
+    /// towards the dialog are routed back through the registrar.
+    ///
+    /// # Parameters
+    ///
+    /// * `contact` - Contact URI, `None` to use `InviteOption::contact` again
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::dialog_layer::DialogLayer;
+    /// # use rsipstack::dialog::registration::Registration;
+    /// # async fn example() -> rsipstack::Result<()> {
+    /// # let dialog_layer: DialogLayer = todo!();
+    /// # let mut registration: Registration = todo!();
+    /// registration.register(&"sip:registrar.example.com".to_string()).await?;
+    /// dialog_layer.set_default_contact(registration.pub_gruu().cloned());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_default_contact(&self, contact: Option<rsip::Uri>) {
+        *self.inner.default_contact.lock().unwrap() = contact;
+    }
+
+    pub fn default_contact(&self) -> Option<rsip::Uri> {
+        self.inner.default_contact.lock().unwrap().clone()
+    }
+
+    /// Pre-load `route` on outbound INVITEs
+    ///
+    /// Each entry becomes a Route header of the INVITE, unless
+    /// `InviteOption::headers` carries a Route, and the INVITE is sent to
+    /// the first one. Feed it the Service-Route (RFC 3608) obtained by a
+    /// [`Registration`](super::registration::Registration), so calls take
+    /// the path the registrar mandates. In-dialog requests follow the
+    /// Record-Route of the 2xx as usual.
+    ///
+    /// # Parameters
+    ///
+    /// * `route` - Route headers in order, empty to send INVITEs directly
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::dialog_layer::DialogLayer;
+    /// # use rsipstack::dialog::registration::Registration;
+    /// # async fn example() -> rsipstack::Result<()> {
+    /// # let dialog_layer: DialogLayer = todo!();
+    /// # let mut registration: Registration = todo!();
+    /// registration.register(&"sip:registrar.example.com".to_string()).await?;
+    /// dialog_layer.set_service_route(registration.service_route().to_vec());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_service_route(&self, route: Vec<rsip::headers::Route>) {
+        *self.inner.service_route.lock().unwrap() = route;
+    }
+
+    pub fn service_route(&self) -> Vec<rsip::headers::Route> {
+        self.inner.service_route.lock().unwrap().clone()
+    }
+
+    pub(super) fn insert_dialog(&self, id: DialogId, dialog: Dialog) -> Result<()> {
+        let mut dialogs = self.inner.dialogs.write().unwrap();
+        if let Some(existing) = dialogs.get(&id) {
+            let same = Arc::ptr_eq(existing.inner(), dialog.inner());
+            if !same && !existing.inner().state.lock().unwrap().is_terminated() {
+                warn!("dialog id collision, {id} is in use by an active dialog");
+                return Err(crate::Error::DialogError(
+                    "dialog id collision".to_string(),
+                    id,
+                ));
+            }
+        }
+        if let Some(max) = self.max_dialogs() {
+            if dialogs.len() >= max && !dialogs.contains_key(&id) {
+                info!("dialog limit reached ({max}), refusing {id}");
+                return Err(crate::Error::DialogError(
+                    format!("dialog limit reached: {}", max),
+                    id,
+                ));
+            }
+        }
+        dialogs.insert(id, dialog);
+        Ok(())
+    }
+
     pub fn increment_last_seq(&self) -> u32 {
-        self.inner.last_seq.fetch_add(1, Ordering::Relaxed);
-        self.inner.last_seq.load(Ordering::Relaxed)
+        let last = self
+            .inner
+            .last_seq
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |seq| {
+                Some(next_cseq(seq))
+            })
+            .unwrap_or_default();
+        next_cseq(last)
     }
 
     pub fn len(&self) -> usize {
@@ -228,4 +707,54 @@ impl DialogLayer {
         let id = DialogId::try_from(req).ok()?;
         self.get_dialog(&id)
     }
+
+    /// Cancel every outbound INVITE that has not been answered yet
+    ///
+    /// Sends a CANCEL for each client dialog still in the Calling, Trying
+    /// or Early state, e.g. on shutdown or to stop all ringing calls.
+    /// Confirmed dialogs are left alone. Every pending INVITE is cancelled
+    /// even if some CANCELs fail.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - All CANCELs were sent
+    /// * `Err(Error)` - The first CANCEL that failed
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::dialog_layer::DialogLayer;
+    /// # async fn example() -> rsipstack::Result<()> {
+    /// # let dialog_layer: DialogLayer = todo!();
+    /// dialog_layer.cancel_pending_invites().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn cancel_pending_invites(&self) -> Result<()> {
+        let pending = self
+            .inner
+            .dialogs
+            .read()
+            .unwrap()
+            .values()
+            .filter_map(|dialog| match dialog {
+                Dialog::ClientInvite(dialog) => match *dialog.inner.state.lock().unwrap() {
+                    DialogState::Calling(_) | DialogState::Trying(_) | DialogState::Early(_, _) => {
+                        Some(dialog.clone())
+                    }
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let results = futures::future::join_all(pending.iter().map(|dialog| async move {
+            info!("cancelling pending invite {}", dialog.id());
+            dialog.cancel().await.inspect_err(|e| {
+                warn!("failed to cancel {}: {}", dialog.id(), e);
+            })
+        }))
+        .await;
+        results.into_iter().collect()
+    }
 }
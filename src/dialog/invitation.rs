@@ -1,19 +1,23 @@
 use super::{
     authenticate::Credential,
     client_dialog::ClientInviteDialog,
-    dialog::{DialogInner, DialogStateSender},
+    dialog::{DialogInner, DialogStateSender, SessionId, SESSION_ID_HEADER},
     dialog_layer::DialogLayer,
 };
 use crate::{
     dialog::{dialog::Dialog, DialogId},
+    rsip_ext::{encode_user_to_user, RsipHeadersExt},
     transaction::{
         key::{TransactionKey, TransactionRole},
-        make_tag,
+        make_tag, make_via_branch_with_token,
         transaction::Transaction,
     },
     Result,
 };
-use rsip::{Request, Response};
+use rsip::{
+    prelude::{ToTypedHeader, UntypedHeader},
+    Request, Response,
+};
 use std::sync::Arc;
 use tracing::{debug, info};
 
@@ -33,6 +37,15 @@ use tracing::{debug, info};
 /// * `contact` - Contact URI for this user agent
 /// * `credential` - Optional authentication credentials
 /// * `headers` - Optional additional headers to include
+/// * `branch_token` - Optional trace token appended to the generated Via branch
+/// * `expires` - Optional call setup timeout in seconds (Expires header)
+/// * `user_to_user` - Optional UUI data as `(encoding, payload)`, sent in a
+///   `User-to-User` header (RFC 7433); encoding is `hex` or `base64`
+/// * `replaces` - Optional dialog to replace, sent in a `Replaces` header
+///   (RFC 3891) with `Require: replaces`
+///
+/// Every field except `caller`, `callee` and `contact` is optional, so
+/// options are usually written with `..Default::default()`.
 ///
 /// # Examples
 ///
@@ -48,8 +61,7 @@ use tracing::{debug, info};
 ///     content_type: Some("application/sdp".to_string()),
 ///     offer: Some(sdp_offer_bytes),
 ///     contact: "sip:alice@192.168.1.100:5060".try_into()?,
-///     credential: None,
-///     headers: None,
+///     ..Default::default()
 /// };
 /// # Ok(())
 /// # }
@@ -87,6 +99,7 @@ use tracing::{debug, info};
 ///     contact: "sip:alice@192.168.1.100:5060".try_into()?,
 ///     credential: Some(auth_credential),
 ///     headers: Some(custom_headers),
+///     ..Default::default()
 /// };
 /// # Ok(())
 /// # }
@@ -112,11 +125,12 @@ use tracing::{debug, info};
 ///     offer: Some(sdp_bytes),
 ///     contact: "sip:alice@192.168.1.100:5060".try_into()?,
 ///     credential: Some(credential),
-///     headers: None,
+///     ..Default::default()
 /// };
 /// # Ok(())
 /// # }
 /// ```
+#[derive(Default)]
 pub struct InviteOption {
     pub caller: rsip::Uri,
     pub callee: rsip::Uri,
@@ -125,9 +139,80 @@ pub struct InviteOption {
     pub contact: rsip::Uri,
     pub credential: Option<Credential>,
     pub headers: Option<Vec<rsip::Header>>,
+    pub branch_token: Option<String>,
+    pub expires: Option<u32>,
+    pub user_to_user: Option<(String, Vec<u8>)>,
+    pub replaces: Option<DialogId>,
+    /// Body types acceptable in responses, sent as the `Accept` header
+    pub accept: Vec<String>,
+    /// Call subject, sent as the `Subject` header
+    pub subject: Option<String>,
+    /// Call priority, one of `emergency`, `urgent`, `normal` or `non-urgent`
+    pub priority: Option<rsip::typed::Priority>,
+    /// Call-IDs of earlier calls this one answers, sent as the `In-Reply-To` header
+    pub in_reply_to: Vec<String>,
+}
+
+impl InviteOption {
+    /// Mark the callee as a telephone number for PSTN gateways
+    ///
+    /// Adds `user=phone` to the callee URI, so it appears on both the
+    /// Request-URI and the To header, and strips visual separators
+    /// (`-`, `.`, `(`, `)`, spaces) from the user part.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::invitation::InviteOption;
+    /// # fn example() -> rsipstack::Result<()> {
+    /// # let mut invite_option: InviteOption = todo!();
+    /// invite_option.callee = "sip:+1-555-123-4567@gw.example.com".try_into()?;
+    /// invite_option.with_user_phone();
+    /// // Request-URI: sip:+15551234567@gw.example.com;user=phone
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_user_phone(&mut self) -> &mut Self {
+        if let Some(auth) = self.callee.auth.as_mut() {
+            auth.user
+                .retain(|c| !matches!(c, '-' | '.' | '(' | ')' | ' '));
+        }
+        self.callee
+            .params
+            .retain(|p| !matches!(p, rsip::Param::User(_)));
+        self.callee
+            .params
+            .push(rsip::Param::User(rsip::param::User::new("phone")));
+        self
+    }
+
+    /// Contact URI sent in the INVITE
+    ///
+    /// Some servers reject anonymous Contacts, so a `contact` without user
+    /// part gets the credential username, or the caller's user part when
+    /// there is no credential, like the Contact of a REGISTER.
+    pub fn contact_uri(&self) -> rsip::Uri {
+        let mut contact = self.contact.clone();
+        if contact.auth.is_none() {
+            let user = match self.credential.as_ref() {
+                Some(cred) => Some(cred.username.clone()),
+                None => self.caller.auth.as_ref().map(|auth| auth.user.clone()),
+            };
+            contact.auth = user.map(|user| rsip::Auth {
+                user,
+                password: None,
+            });
+        }
+        contact
+    }
 }
 
 impl DialogLayer {
+    /// Contact of an outbound INVITE, the default contact if one is set
+    fn invite_contact(&self, opt: &InviteOption) -> rsip::Uri {
+        self.default_contact().unwrap_or_else(|| opt.contact_uri())
+    }
+
     /// Create an INVITE request from options
     ///
     /// Constructs a properly formatted SIP INVITE request based on the
@@ -149,10 +234,22 @@ impl DialogLayer {
     /// * Via header with branch parameter
     /// * From header with tag parameter
     /// * To header (without tag for initial request)
-    /// * Contact header
+    /// * Contact header, with the credential username or the caller's user
+    ///   part when `contact` has no user part, or the layer's
+    ///   [`DialogLayer::default_contact`] when set
     /// * Content-Type header
     /// * CSeq header with incremented sequence number
     /// * Call-ID header
+    /// * Expires header, when `expires` is set
+    /// * User-to-User header, when `user_to_user` is set
+    /// * Replaces and `Require: replaces` headers, when `replaces` is set
+    /// * Route headers, from [`DialogLayer::service_route`] unless `headers`
+    ///   carries a Route
+    ///
+    /// The `replaces` dialog is matched by the callee, so its `to_tag` must be
+    /// the callee's local tag. Pass the [`DialogId`] unchanged when the replaced
+    /// call was placed towards the callee; when the callee placed it, swap the
+    /// two tags.
     ///
     /// # Examples
     ///
@@ -176,6 +273,12 @@ impl DialogLayer {
         opt: &InviteOption,
         public_address: Option<(std::net::IpAddr, u16)>,
     ) -> Result<Request> {
+        if let Some(rsip::typed::Priority::Other(token)) = opt.priority.as_ref() {
+            return Err(crate::Error::Error(format!(
+                "invalid Priority token: {}",
+                token
+            )));
+        }
         let last_seq = self.increment_last_seq();
         let to = rsip::typed::To {
             display_name: None,
@@ -199,14 +302,16 @@ impl DialogLayer {
                 port: Some(port.into()),
             },
         });
-        let via = self.endpoint.get_via(via_addr, None)?;
+        // Append the trace token to a random branch, so the branch stays unique
+        let branch = opt.branch_token.as_deref().map(make_via_branch_with_token);
+        let via = self.endpoint.get_via(via_addr, branch)?;
         let mut request =
             self.endpoint
                 .make_request(rsip::Method::Invite, recipient, via, form, to, last_seq);
 
         let contact = rsip::typed::Contact {
             display_name: None,
-            uri: opt.contact.clone(),
+            uri: self.invite_contact(opt),
             params: vec![],
         };
 
@@ -220,12 +325,89 @@ impl DialogLayer {
                 .unwrap_or("application/sdp".to_string())
                 .into(),
         ));
+        if let Some(expires) = opt.expires {
+            request
+                .headers
+                .unique_push(rsip::Header::Expires(expires.into()));
+        }
+        if let Some(subject) = opt.subject.as_ref() {
+            request
+                .headers
+                .unique_push(rsip::Header::Subject(subject.clone().into()));
+        }
+        if let Some(priority) = opt.priority.as_ref() {
+            request
+                .headers
+                .unique_push(rsip::Header::Priority(priority.to_string().into()));
+        }
+        if !opt.accept.is_empty() {
+            request
+                .headers
+                .unique_push(rsip::Header::Accept(opt.accept.join(", ").into()));
+        }
+        if !opt.in_reply_to.is_empty() {
+            request
+                .headers
+                .unique_push(rsip::Header::InReplyTo(opt.in_reply_to.join(", ").into()));
+        }
+        for route in self.service_route() {
+            request.headers.push(route.into());
+        }
         // can override default headers
         if let Some(headers) = opt.headers.as_ref() {
             for header in headers {
                 request.headers.unique_push(header.clone());
             }
         }
+        if !request
+            .headers
+            .iter()
+            .any(|h| matches!(h, rsip::Header::Supported(_)))
+        {
+            if let Some(supported) = self.endpoint.supported_header() {
+                request.headers.push(supported);
+            }
+        }
+        // a B2BUA passes the Session-ID of the inbound leg in the custom headers
+        if request.headers.get_other(SESSION_ID_HEADER).is_none() {
+            request.headers.push(SessionId::new().to_header());
+        }
+        if let Some((encoding, data)) = opt.user_to_user.as_ref() {
+            // pushed after custom headers, unique_push would drop other extension headers
+            if request.headers.get_other("User-to-User").is_none() {
+                request.headers.push(rsip::Header::Other(
+                    "User-to-User".into(),
+                    encode_user_to_user(encoding, data)?,
+                ));
+            }
+        }
+        if let Some(id) = opt.replaces.as_ref() {
+            request.headers.push(rsip::Header::Other(
+                "Replaces".into(),
+                format!(
+                    "{};to-tag={};from-tag={}",
+                    id.call_id, id.to_tag, id.from_tag
+                ),
+            ));
+            let require = request.headers.iter_mut().find_map(|h| match h {
+                rsip::Header::Require(require) => Some(require),
+                _ => None,
+            });
+            match require {
+                Some(require) => {
+                    let value = require.value().to_string();
+                    if !value
+                        .split(',')
+                        .any(|tag| tag.trim().eq_ignore_ascii_case("replaces"))
+                    {
+                        *require = rsip::headers::Require::new(format!("{}, replaces", value));
+                    }
+                }
+                None => request
+                    .headers
+                    .push(rsip::headers::Require::new("replaces").into()),
+            }
+        }
         Ok(request)
     }
 
@@ -328,6 +510,7 @@ impl DialogLayer {
     /// * Invalid SIP URIs or headers
     /// * Transaction timeouts
     /// * Protocol violations
+    /// * The limit set by `DialogLayer::set_max_dialogs` was reached
     ///
     /// # Authentication
     ///
@@ -372,6 +555,7 @@ impl DialogLayer {
         ));
 
         let id = DialogId::try_from(&request)?;
+        let contact = self.invite_contact(&opt);
         let dlg_inner = DialogInner::new(
             TransactionRole::Client,
             id.clone(),
@@ -379,9 +563,10 @@ impl DialogLayer {
             self.endpoint.clone(),
             state_sender,
             opt.credential,
-            Some(opt.contact),
+            Some(contact),
         )?;
 
+        *dlg_inner.idle_timeout.lock().unwrap() = self.idle_timeout();
         let dialog = ClientInviteDialog {
             inner: Arc::new(dlg_inner),
         };
@@ -401,13 +586,24 @@ impl DialogLayer {
 
         let key =
             TransactionKey::from_request(&dialog.inner.initial_request, TransactionRole::Client)?;
-        let tx = Transaction::new_client(key, request.clone(), self.endpoint.clone(), None);
+        let mut tx = Transaction::new_client(key, request.clone(), self.endpoint.clone(), None);
+        // loose routing: a pre-loaded route sends the INVITE to its first hop
+        if let Some(mut next_hop) = first_route_uri(&request) {
+            next_hop
+                .params
+                .retain(|p| matches!(p, rsip::Param::Transport(_)));
+            let (connection, destination) = self
+                .endpoint
+                .transport_layer
+                .lookup(&next_hop, self.endpoint.transport_tx.clone())
+                .await?;
+            info!("sending INVITE to first Route: {}", destination);
+            tx.connection = Some(connection);
+            tx.destination = Some(destination);
+        }
 
-        self.inner
-            .dialogs
-            .write()
-            .unwrap()
-            .insert(id.clone(), Dialog::ClientInvite(dialog.clone()));
+        self.insert_dialog(id.clone(), Dialog::ClientInvite(dialog.clone()))?;
+        dialog.inner.start_idle_timer();
 
         info!("client invite dialog created: {:?}", id);
 
@@ -418,12 +614,15 @@ impl DialogLayer {
                     id, new_dialog_id
                 );
                 self.inner.dialogs.write().unwrap().remove(&id);
-                // update with new dialog id
-                self.inner
-                    .dialogs
-                    .write()
-                    .unwrap()
-                    .insert(new_dialog_id, Dialog::ClientInvite(dialog.clone()));
+                // update with new dialog id, never clobbering another live dialog
+                if let Err(e) =
+                    self.insert_dialog(new_dialog_id, Dialog::ClientInvite(dialog.clone()))
+                {
+                    if dialog.inner.is_confirmed() {
+                        dialog.bye().await.ok();
+                    }
+                    return Err(e);
+                }
                 return Ok((dialog, resp));
             }
             Err(e) => {
@@ -433,3 +632,13 @@ impl DialogLayer {
         }
     }
 }
+
+/// URI of the topmost Route header of `request`
+fn first_route_uri(request: &Request) -> Option<rsip::Uri> {
+    let route = request.headers.iter().find_map(|h| match h {
+        rsip::Header::Route(route) => Some(route),
+        _ => None,
+    })?;
+    let route = route.typed().ok()?;
+    route.uris().first().map(|uri| uri.uri.clone())
+}
@@ -52,6 +52,9 @@ pub struct DialogId {
     pub to_tag: String,
 }
 
+/// Dialog id of a request
+///
+/// The To tag is left empty for an out-of-dialog request such as an initial INVITE.
 impl TryFrom<&Request> for DialogId {
     type Error = crate::Error;
 
@@ -76,6 +79,11 @@ impl TryFrom<&Request> for DialogId {
     }
 }
 
+/// Dialog id of a response, from the UAC's point of view
+///
+/// Call-ID and From tag identify our side, the To tag is the one the UAS
+/// assigned. A response without a To tag (e.g. 100 Trying) does not
+/// establish a dialog and is rejected.
 impl TryFrom<&Response> for DialogId {
     type Error = crate::Error;
 
@@ -100,8 +108,74 @@ impl TryFrom<&Response> for DialogId {
     }
 }
 
+impl DialogId {
+    /// Check whether `other` identifies the same dialog
+    ///
+    /// The UAC and the UAS store the tags from opposite perspectives, so the
+    /// Call-ID must be equal while the two tags are compared as an unordered
+    /// pair.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rsipstack::dialog::DialogId;
+    ///
+    /// let uac = DialogId {
+    ///     call_id: "call-1".to_string(),
+    ///     from_tag: "alice".to_string(),
+    ///     to_tag: "bob".to_string(),
+    /// };
+    /// let uas = DialogId {
+    ///     call_id: "call-1".to_string(),
+    ///     from_tag: "bob".to_string(),
+    ///     to_tag: "alice".to_string(),
+    /// };
+    /// assert!(uac.matches(&uas));
+    /// ```
+    pub fn matches(&self, other: &DialogId) -> bool {
+        if self.call_id != other.call_id {
+            return false;
+        }
+        (self.from_tag == other.from_tag && self.to_tag == other.to_tag)
+            || (self.from_tag == other.to_tag && self.to_tag == other.from_tag)
+    }
+}
+
 impl std::fmt::Display for DialogId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}-{}-{}", self.call_id, self.from_tag, self.to_tag)
     }
 }
+
+/// Parse a dialog id written by `Display`, `call_id-from_tag-to_tag`
+///
+/// The tags are taken from the end, so a Call-ID may contain `-`; ids
+/// whose tags contain `-` do not round-trip. The to tag is empty for an
+/// early dialog.
+///
+/// # Examples
+///
+/// ```rust
+/// use rsipstack::dialog::DialogId;
+///
+/// let id: DialogId = "a84b4c76-e667@pc33.atlanta.com-1928301774-".parse().unwrap();
+/// assert_eq!(id.call_id, "a84b4c76-e667@pc33.atlanta.com");
+/// assert_eq!(id.from_tag, "1928301774");
+/// assert!(id.to_tag.is_empty());
+/// assert_eq!(id.to_string().parse::<DialogId>().unwrap(), id);
+/// ```
+impl std::str::FromStr for DialogId {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.rsplitn(3, '-');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(to_tag), Some(from_tag), Some(call_id)) if !call_id.is_empty() => Ok(DialogId {
+                call_id: call_id.to_string(),
+                from_tag: from_tag.to_string(),
+                to_tag: to_tag.to_string(),
+            }),
+            _ => Err(Error::Error(format!("invalid dialog id: {}", s))),
+        }
+    }
+}
@@ -1,24 +1,123 @@
-use super::{
-    authenticate::{handle_client_authenticate, Credential},
-    DialogId,
-};
+use super::authenticate::{handle_client_authenticate, AuthRetries, Credential};
 use crate::{
-    rsip_ext::RsipResponseExt,
+    rsip_ext::{split_header_values, RsipHeadersExt, RsipResponseExt},
     transaction::{
         endpoint::EndpointInnerRef,
         key::{TransactionKey, TransactionRole},
-        make_tag,
+        make_tag, next_cseq,
         transaction::Transaction,
     },
-    transport::{SipAddr},
+    transport::{
+        keepalive::{Keepalive, KeepaliveHandle},
+        SipAddr, SipConnection,
+    },
     Error, Result,
 };
 use get_if_addrs::get_if_addrs;
-use rsip::{HostWithPort, Param, Response, SipMessage, StatusCode, prelude::ToTypedHeader};
-use rsip_dns::trust_dns_resolver::TokioAsyncResolver;
-use rsip_dns::ResolvableExt;
+use rsip::{
+    prelude::{ToTypedHeader, UntypedHeader},
+    HostWithPort, Param, Response, SipMessage, StatusCode,
+};
 use std::net::IpAddr;
-use tracing::{debug, info};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::{sync::watch, task::JoinHandle, time::Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+/// First retry delay of [`Registration::start_refresh`] after a failure
+const REFRESH_BACKOFF_MIN: Duration = Duration::from_secs(2);
+/// Longest retry delay of [`Registration::start_refresh`]
+const REFRESH_BACKOFF_MAX: Duration = Duration::from_secs(300);
+
+/// Contact carried by a REGISTER
+enum RegisterContact {
+    /// Our own binding, rebuilt when the server reports a new public address
+    Binding(rsip::typed::Contact),
+    /// Someone else's binding to remove, sent as is
+    Remove(rsip::typed::Contact),
+    /// Our binding to remove, sent along with the extra contacts
+    Unbind(rsip::typed::Contact),
+    /// Every binding of the AOR, sent as `Contact: *` with `Expires: 0`
+    All,
+    /// No Contact, querying the bindings of the AOR
+    Query,
+}
+
+/// Snapshot of a registration for monitoring
+///
+/// Returned by [`Registration::status`], e.g. to report registration
+/// health from a `/healthz` endpoint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegistrationStatus {
+    /// A binding was registered and has not expired yet
+    pub registered: bool,
+    /// Expiration time in seconds, see [`Registration::expires`]
+    pub expires: u32,
+    /// Public IP and port detected by the server
+    pub public_address: Option<(IpAddr, u16)>,
+    /// When the last successful REGISTER completed
+    pub last_registered_at: Option<Instant>,
+    /// Failure of the last REGISTER, cleared by the next success
+    pub last_error: Option<String>,
+}
+
+/// Address family of the local interface used for REGISTER
+///
+/// * `V4Only` - IPv4 addresses only
+/// * `V6Only` - IPv6 addresses only, e.g. on IPv6-only networks
+/// * `PreferV6` - An IPv6 address if there is one, IPv4 otherwise
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AddrFamilyPreference {
+    #[default]
+    V4Only,
+    V6Only,
+    PreferV6,
+}
+
+/// State published by [`Registration::start_refresh`]
+///
+/// * `Registering` - Registering after a start or a failure
+/// * `Active` - The registrar accepted the binding for `expires` seconds
+/// * `Failed` - The last REGISTER was rejected with `status`, or got no
+///   final response when `status` is `None`; it is retried with backoff
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegistrationState {
+    Registering,
+    Active { expires: u32 },
+    Failed { status: Option<StatusCode> },
+}
+
+/// Refresh task started by [`Registration::start_refresh`]
+///
+/// Dropping the handle stops the task, and the binding then expires at the
+/// registrar. Call [`RefreshHandle::stop`] instead to get the registration
+/// back, e.g. to `unregister` it on shutdown.
+pub struct RefreshHandle {
+    task: JoinHandle<Registration>,
+    cancel_token: CancellationToken,
+}
+
+impl RefreshHandle {
+    /// Stop the task and wait for it to return the registration
+    pub async fn stop(mut self) -> Result<Registration> {
+        self.cancel_token.cancel();
+        (&mut self.task)
+            .await
+            .map_err(|e| Error::Error(format!("registration refresh task failed: {}", e)))
+    }
+
+    /// Whether the task has stopped, after a cancellation or once every
+    /// receiver of its state was dropped
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+}
+
+impl Drop for RefreshHandle {
+    fn drop(&mut self) {
+        self.cancel_token.cancel();
+    }
+}
 
 /// SIP Registration Client
 ///
@@ -116,9 +215,72 @@ pub struct Registration {
     pub endpoint: EndpointInnerRef,
     pub credential: Option<Credential>,
     pub contact: Option<rsip::typed::Contact>,
+    /// Display name of the From and Contact headers, for registrars that
+    /// store it as the caller-id name. A `contact` with its own display
+    /// name keeps it.
+    pub display_name: Option<String>,
+    /// Expiration to ask for, sent as the `expires` parameter of our
+    /// Contact. The registrar may grant less, see [`Registration::expires`].
+    /// `None` leaves it to the registrar, or to an `expires` parameter of
+    /// `contact`. Raised to the registrar's `Min-Expires` after a 423.
+    pub requested_expires: Option<u32>,
+    /// Further Contacts bound to the AOR by the same REGISTER, e.g. a
+    /// softphone next to a desk phone. They are sent as is, so each may
+    /// carry its own `expires` parameter, and removed by `unregister`
+    /// along with ours. See [`Registration::contact_expires`] for what
+    /// the registrar granted them.
+    pub extra_contacts: Vec<rsip::typed::Contact>,
+    /// `reg-id` of our Contact, sent along with the instance ID set by
+    /// [`Registration::set_instance_id`] (RFC 5626). Registrations of the
+    /// same instance over different flows use different values.
+    pub reg_id: u32,
+    /// Keep `contact` when the server reports a new public address
+    ///
+    /// By default the Contact is rebuilt from the public address. When set,
+    /// only its host and port are replaced, so the user part, display name
+    /// and parameters such as `+sip.instance` or a GRUU survive.
+    pub sticky_contact: bool,
+    /// Local address for the Via and the initial Contact, used instead of
+    /// discovering one from the network interfaces. A public address the
+    /// registrar reported still takes precedence. Without a transport, the
+    /// one resolved for the registrar is used.
+    pub local_addr: Option<SipAddr>,
+    /// Address family of the local address used when no public address
+    /// is known, see [`Registration::find_local_interface`]
+    pub addr_family: AddrFamilyPreference,
+    /// Only use addresses of the network interface with this name
+    pub interface: Option<String>,
+    /// Keepalive used when the registration flow runs over UDP
+    ///
+    /// RFC 5626 keeps UDP flows alive with STUN, which is not built in, so
+    /// UDP registrations send no keepalives unless a payload the registrar
+    /// or edge proxy understands is set here. See [`Registration::keepalive`].
+    pub udp_keepalive: Option<Keepalive>,
     pub allow: rsip::headers::Allow,
     /// Public address detected by the server (IP and port)
     pub public_address: Option<rsip::HostWithPort>,
+    /// Server time from the Date header of the last 200 OK
+    pub server_time: Option<SystemTime>,
+    /// Server time minus local time in seconds, measured when the 200 OK arrived
+    pub clock_skew: Option<i64>,
+    /// Server passed to the last `register` call
+    pub server: Option<String>,
+    negotiated_transport: Option<rsip::Transport>,
+    bindings: Vec<rsip::typed::Contact>,
+    pub_gruu: Option<rsip::Uri>,
+    service_route: Vec<rsip::headers::Route>,
+    flow_timer: Option<u32>,
+    keepalive: Option<(SipConnection, KeepaliveHandle)>,
+    last_registered_at: Option<Instant>,
+    last_error: Option<String>,
+    last_addr: Option<SipAddr>,
+    last_contact: Option<rsip::typed::Contact>,
+    public_address_changed: bool,
+    /// Binding at the private address, removed by the re-REGISTER that
+    /// moves the Contact to the public one
+    stale_contact: Option<rsip::typed::Contact>,
+    outbound: Option<bool>,
+    instance_id: Option<String>,
 }
 
 impl Registration {
@@ -159,12 +321,38 @@ impl Registration {
     /// ```
     pub fn new(endpoint: EndpointInnerRef, credential: Option<Credential>) -> Self {
         Self {
-            last_seq: crate::transaction::generate_random_cseq(),
+            last_seq: endpoint.make_cseq(),
             endpoint,
             credential,
             contact: None,
+            display_name: None,
+            requested_expires: None,
+            extra_contacts: vec![],
+            sticky_contact: false,
+            local_addr: None,
+            addr_family: AddrFamilyPreference::default(),
+            interface: None,
+            udp_keepalive: None,
             allow: Default::default(),
             public_address: None,
+            server_time: None,
+            clock_skew: None,
+            server: None,
+            negotiated_transport: None,
+            bindings: vec![],
+            pub_gruu: None,
+            service_route: vec![],
+            flow_timer: None,
+            keepalive: None,
+            last_registered_at: None,
+            last_error: None,
+            last_addr: None,
+            last_contact: None,
+            public_address_changed: false,
+            stale_contact: None,
+            outbound: None,
+            instance_id: None,
+            reg_id: 1,
         }
     }
 
@@ -201,11 +389,531 @@ impl Registration {
         self.public_address.clone()
     }
 
+    /// Get the server time reported by the registrar
+    ///
+    /// Returns the time from the `Date` header of the last successful
+    /// REGISTER response, if the registrar sent one.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::registration::Registration;
+    /// # fn example() {
+    /// # let registration: Registration = todo!();
+    /// if let Some(server_time) = registration.server_time() {
+    ///     println!("Registrar time: {:?}", server_time);
+    /// }
+    /// # }
+    /// ```
+    pub fn server_time(&self) -> Option<SystemTime> {
+        self.server_time
+    }
+
+    /// Get the clock skew against the registrar
+    ///
+    /// Returns the difference between the registrar's `Date` header and the
+    /// local clock in seconds, measured when the response arrived. A positive
+    /// value means the local clock is behind the server. Large skews can break
+    /// digest authentication on servers that timestamp their nonces.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::registration::Registration;
+    /// # fn example() {
+    /// # let registration: Registration = todo!();
+    /// if let Some(skew) = registration.clock_skew() {
+    ///     if skew.abs() > 30 {
+    ///         println!("Local clock is off by {} seconds", skew);
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub fn clock_skew(&self) -> Option<i64> {
+        self.clock_skew
+    }
+
+    /// Get the transport the registration was made over
+    ///
+    /// Returns the transport (UDP, TCP, TLS, ...) resolved for the registrar
+    /// by the last successful `register`, or `None` before the first 200 OK.
+    /// Use it to pick the same transport for media setup and later dialogs.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::registration::Registration;
+    /// # fn example() {
+    /// # let registration: Registration = todo!();
+    /// if registration.negotiated_transport() == Some(rsip::Transport::Tcp) {
+    ///     println!("Registered over TCP");
+    /// }
+    /// # }
+    /// ```
+    pub fn negotiated_transport(&self) -> Option<rsip::Transport> {
+        self.negotiated_transport
+    }
+
+    /// Get the bindings listed by the registrar
+    ///
+    /// Returns every Contact of the last successful REGISTER response,
+    /// including other devices registered for the same address of record.
+    /// A single Contact header carrying several comma-separated bindings
+    /// yields one entry per binding.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::registration::Registration;
+    /// # fn example() {
+    /// # let registration: Registration = todo!();
+    /// for binding in registration.bindings() {
+    ///     println!("registered: {}", binding.uri);
+    /// }
+    /// # }
+    /// ```
+    pub fn bindings(&self) -> &[rsip::typed::Contact] {
+        &self.bindings
+    }
+
+    /// Get the public GRUU assigned to our binding
+    ///
+    /// A registrar supporting GRUU (RFC 5627) returns a `pub-gruu` parameter
+    /// on the Contact of our binding when the REGISTER carried a
+    /// `+sip.instance` and `Supported: gruu`. Use it as the Contact of
+    /// outbound dialogs with `DialogLayer::set_default_contact`. `None`
+    /// when no binding of the response is ours, the GRUU of another device
+    /// of the AOR is never used.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::registration::Registration;
+    /// # fn example() {
+    /// # let registration: Registration = todo!();
+    /// if let Some(gruu) = registration.pub_gruu() {
+    ///     println!("reachable at {}", gruu);
+    /// }
+    /// # }
+    /// ```
+    pub fn pub_gruu(&self) -> Option<&rsip::Uri> {
+        self.pub_gruu.as_ref()
+    }
+
+    /// Get the Service-Route returned by the registrar (RFC 3608)
+    ///
+    /// The route set the registrar wants requests of this registration to
+    /// take, in order, replaced by every accepted REGISTER. Pre-load it on
+    /// outbound INVITEs with `DialogLayer::set_service_route`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::dialog_layer::DialogLayer;
+    /// # use rsipstack::dialog::registration::Registration;
+    /// # async fn example() -> rsipstack::Result<()> {
+    /// # let dialog_layer: DialogLayer = todo!();
+    /// # let mut registration: Registration = todo!();
+    /// registration.register(&"sip:registrar.example.com".to_string()).await?;
+    /// dialog_layer.set_service_route(registration.service_route().to_vec());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn service_route(&self) -> &[rsip::headers::Route] {
+        &self.service_route
+    }
+
+    /// Collect the Service-Route headers of a REGISTER response, one Route
+    /// per URI
+    fn parse_service_route(resp: &Response) -> Vec<rsip::headers::Route> {
+        resp.headers
+            .iter()
+            .filter_map(|h| match h {
+                rsip::Header::Other(name, value) if name.eq_ignore_ascii_case("Service-Route") => {
+                    Some(value.as_str())
+                }
+                _ => None,
+            })
+            .flat_map(split_header_values)
+            .map(|value| rsip::headers::Route::new(value.trim()))
+            .collect()
+    }
+
+    /// Find the `pub-gruu` of our binding in a REGISTER response
+    ///
+    /// Our binding is the one with the URI of our Contact or with our
+    /// `+sip.instance`. The typed Contact parser cannot read the quoted
+    /// parameter values, so the raw Contact values are scanned instead.
+    fn find_pub_gruu(&self, resp: &Response) -> Option<rsip::Uri> {
+        let ours = self.contact.as_ref().or(self.last_contact.as_ref());
+        resp.headers
+            .iter()
+            .filter_map(|h| match h {
+                rsip::Header::Contact(contact) => Some(contact.value()),
+                _ => None,
+            })
+            .flat_map(split_header_values)
+            .find_map(|value| {
+                let (binding, gruu) = parse_pub_gruu(value)?;
+                let same_uri = ours.is_some_and(|ours| same_binding(&binding, &ours.uri));
+                let same_instance = self
+                    .instance_id
+                    .as_deref()
+                    .is_some_and(|id| contact_instance(value) == Some(id));
+                (same_uri || same_instance).then_some(gruu)
+            })
+    }
+
+    /// Whether the next REGISTER refreshes an accepted registration
+    ///
+    /// `false` until the registrar accepted a binding, and again after
+    /// `register` is called for another server. The initial REGISTER
+    /// carries the outbound (RFC 5626) `ob` parameter of the Contact;
+    /// refreshes keep it only if the registrar answered with
+    /// `Require: outbound`, see [`Registration::outbound_supported`].
+    pub fn is_refresh(&self) -> bool {
+        self.outbound.is_some()
+    }
+
+    /// Whether the registrar confirmed outbound support (RFC 5626)
+    ///
+    /// `None` before the first accepted REGISTER, otherwise whether its
+    /// 200 OK carried `outbound` in a `Require` or `Supported` header.
+    pub fn outbound_supported(&self) -> Option<bool> {
+        self.outbound
+    }
+
+    /// Set the instance ID of this user agent for outbound (RFC 5626)
+    ///
+    /// The Contact of our binding then carries `+sip.instance` with the
+    /// UUID URN and `reg-id` from [`Registration::reg_id`], and the
+    /// REGISTER carries `Supported: outbound`. Use the same UUID across
+    /// restarts so the registrar replaces the old flow instead of adding
+    /// a binding.
+    ///
+    /// # Parameters
+    ///
+    /// * `uuid` - Stable UUID of this device
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::registration::Registration;
+    /// # fn example() {
+    /// # let mut registration: Registration = todo!();
+    /// let uuid = uuid::Uuid::parse_str("f81d4fae-7dec-11d0-a765-00a0c91e6bf6").unwrap();
+    /// registration.set_instance_id(uuid);
+    /// registration.reg_id = 1;
+    /// # }
+    /// ```
+    pub fn set_instance_id(&mut self, uuid: uuid::Uuid) {
+        self.instance_id = Some(format!("urn:uuid:{}", uuid.hyphenated()));
+    }
+
+    /// Instance ID URN set by [`Registration::set_instance_id`]
+    pub fn instance_id(&self) -> Option<&str> {
+        self.instance_id.as_deref()
+    }
+
+    /// Apply the display name, requested expiration and outbound state to
+    /// the Contact of our binding
+    fn binding_contact(&self, mut contact: rsip::typed::Contact) -> rsip::typed::Contact {
+        if contact.display_name.is_none() {
+            contact.display_name = self.display_name.clone();
+        }
+        if let Some(expires) = self.requested_expires {
+            let expires = rsip::param::Expires::new(expires.to_string());
+            contact.params.retain(|p| !matches!(p, Param::Expires(_)));
+            contact.params.push(Param::Expires(expires));
+        }
+        if let Some(instance_id) = &self.instance_id {
+            contact.params.retain(|p| {
+                !matches!(p, Param::Other(name, _)
+                    if name.value().eq_ignore_ascii_case("+sip.instance")
+                        || name.value().eq_ignore_ascii_case("reg-id"))
+            });
+            contact.params.push(Param::Other(
+                "+sip.instance".into(),
+                Some(format!("\"<{}>\"", instance_id).into()),
+            ));
+            contact.params.push(Param::Other(
+                "reg-id".into(),
+                Some(self.reg_id.to_string().into()),
+            ));
+        }
+        // drop `ob` and `reg-id` from the Contact of a refresh if outbound
+        // was not negotiated, `+sip.instance` still serves GRUU
+        if self.outbound == Some(false) {
+            contact.params.retain(|p| {
+                !matches!(p, Param::Other(name, _)
+                    if name.value().eq_ignore_ascii_case("ob")
+                        || name.value().eq_ignore_ascii_case("reg-id"))
+            });
+        }
+        contact
+    }
+
+    /// `Supported` header of a REGISTER for our binding, adding `outbound`
+    /// to the endpoint's option tags once an instance ID is set
+    fn register_supported(&self) -> Option<rsip::Header> {
+        if self.instance_id.is_none() || self.outbound == Some(false) {
+            return self.endpoint.supported_header();
+        }
+        let mut tags = self.endpoint.supported.clone();
+        if !self.endpoint.is_supported("outbound") {
+            tags.push("outbound".to_string());
+        }
+        Some(rsip::Header::Supported(tags.join(", ").into()))
+    }
+
+    /// Get a snapshot of the registration state
+    ///
+    /// Collects whether a binding is currently registered, its expiration,
+    /// the public address and the outcome of the last REGISTER into one
+    /// struct for health checks and monitoring.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::registration::Registration;
+    /// # fn example() {
+    /// # let registration: Registration = todo!();
+    /// let status = registration.status();
+    /// if !status.registered {
+    ///     println!("not registered: {:?}", status.last_error);
+    /// }
+    /// # }
+    /// ```
+    pub fn status(&self) -> RegistrationStatus {
+        let expires = self.expires();
+        let registered = self
+            .last_registered_at
+            .is_some_and(|at| at.elapsed() < std::time::Duration::from_secs(expires as u64));
+        let public_address = self.public_address.as_ref().and_then(|addr| {
+            let port = addr.port.map(|p| *p.value()).unwrap_or(5060);
+            match addr.host {
+                rsip::Host::IpAddr(ip) => Some((ip, port)),
+                rsip::Host::Domain(_) => None,
+            }
+        });
+        RegistrationStatus {
+            registered,
+            expires,
+            public_address,
+            last_registered_at: self.last_registered_at,
+            last_error: self.last_error.clone(),
+        }
+    }
+
+    /// Follow the transport a registrar actually answered over
+    ///
+    /// Updates the negotiated transport, the local address used for the Via
+    /// of refreshes and the `transport` parameter of our Contact.
+    fn update_transport(&mut self, transport: rsip::Transport) {
+        self.negotiated_transport = Some(transport);
+        if let Some(addr) = self.last_addr.as_mut() {
+            addr.r#type = Some(transport);
+        }
+        for contact in [self.contact.as_mut(), self.last_contact.as_mut()]
+            .into_iter()
+            .flatten()
+        {
+            contact
+                .uri
+                .params
+                .retain(|p| !matches!(p, Param::Transport(_)));
+            contact.uri.params.push(Param::Transport(transport));
+        }
+    }
+
+    /// Point a sticky Contact at the discovered public address
+    fn move_contact_to_public_address(&mut self) {
+        let public_address = match self.public_address.clone() {
+            Some(addr) => addr,
+            None => return,
+        };
+        for contact in [self.contact.as_mut(), self.last_contact.as_mut()]
+            .into_iter()
+            .flatten()
+        {
+            contact.uri.host_with_port = public_address.clone();
+        }
+    }
+
+    /// Parse all Contact bindings of a REGISTER response
+    pub(super) fn parse_bindings(resp: &Response) -> Vec<rsip::typed::Contact> {
+        resp.headers
+            .iter()
+            .filter_map(|h| match h {
+                rsip::Header::Contact(contact) => Some(contact.value()),
+                _ => None,
+            })
+            .flat_map(split_header_values)
+            .filter_map(|value| match rsip::headers::Contact::new(value).typed() {
+                Ok(contact) => Some(contact),
+                Err(e) => {
+                    debug!("ignoring invalid Contact binding {}: {}", value, e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Get the `Flow-Timer` of the last successful registration
+    ///
+    /// A registrar supporting outbound (RFC 5626) may tell the client how
+    /// often, in seconds, it must send keepalives over the flow. `None` if
+    /// the last 200 OK carried no `Flow-Timer`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::registration::Registration;
+    /// # fn example() {
+    /// # let registration: Registration = todo!();
+    /// if let Some(secs) = registration.flow_timer() {
+    ///     println!("keepalive at least every {}s", secs);
+    /// }
+    /// # }
+    /// ```
+    pub fn flow_timer(&self) -> Option<u32> {
+        self.flow_timer
+    }
+
+    /// Keepalive for the registration flow
+    ///
+    /// Over connection-oriented transports (TCP, TLS, WebSocket) this is the
+    /// CRLF keepalive of RFC 5626. Over UDP, or before the transport is
+    /// known, it is [`Registration::udp_keepalive`], and `None` if that is
+    /// not set. The interval is 80% of the registrar's `Flow-Timer`, or the
+    /// keepalive's own interval when the registrar sent none.
+    pub fn keepalive(&self) -> Option<Keepalive> {
+        let reliable = self
+            .negotiated_transport
+            .is_some_and(|transport| transport != rsip::Transport::Udp);
+        self.keepalive_for(reliable)
+    }
+
+    fn keepalive_for(&self, reliable: bool) -> Option<Keepalive> {
+        let mut keepalive = if reliable {
+            Keepalive::default()
+        } else {
+            self.udp_keepalive.clone()?
+        };
+        if let Some(flow_timer) = self.flow_timer {
+            keepalive.interval = Keepalive::from_flow_timer(flow_timer).interval;
+        }
+        Some(keepalive)
+    }
+
+    /// Keep the registration flow open with keepalives
+    ///
+    /// Starts a keepalive on `connection` towards `target`: CRLF on stream
+    /// connections, [`Registration::udp_keepalive`] on UDP. Whenever a later
+    /// registration changes the `Flow-Timer`, the keepalive is restarted
+    /// with the new interval. A keepalive started earlier by this
+    /// registration is stopped.
+    ///
+    /// # Parameters
+    ///
+    /// * `connection` - Connection the registration flow uses
+    /// * `target` - Registrar or edge proxy address
+    ///
+    /// # Returns
+    ///
+    /// The handle of the running keepalive, or `None` on a UDP connection
+    /// without [`Registration::udp_keepalive`]
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::registration::Registration;
+    /// # use rsipstack::transport::{keepalive::Keepalive, SipAddr, SipConnection};
+    /// # use std::time::Duration;
+    /// # fn example(connection: SipConnection, registrar: SipAddr) {
+    /// # let mut registration: Registration = todo!();
+    /// let mut ping = Keepalive::new(Duration::from_secs(30));
+    /// ping.with_payload(b"PING".to_vec(), |data| data == b"PONG");
+    /// registration.udp_keepalive = Some(ping);
+    /// if let Some(handle) = registration.start_keepalive(connection, registrar) {
+    ///     println!("keepalive every {:?}", handle.keepalive().interval);
+    /// }
+    /// # }
+    /// ```
+    pub fn start_keepalive(
+        &mut self,
+        connection: SipConnection,
+        target: SipAddr,
+    ) -> Option<KeepaliveHandle> {
+        self.stop_keepalive();
+        let keepalive = match self.keepalive_for(connection.is_reliable()) {
+            Some(keepalive) => keepalive,
+            None => {
+                debug!("no keepalive payload for UDP flow to {}", target);
+                return None;
+            }
+        };
+        let handle = connection.start_keepalive(target, keepalive);
+        self.keepalive = Some((connection, handle.clone()));
+        Some(handle)
+    }
+
+    /// Stop the keepalive started by [`Registration::start_keepalive`]
+    pub fn stop_keepalive(&mut self) {
+        if let Some((_, handle)) = self.keepalive.take() {
+            handle.stop();
+        }
+    }
+
+    /// Handle of the running keepalive, if any
+    pub fn keepalive_handle(&self) -> Option<&KeepaliveHandle> {
+        self.keepalive.as_ref().map(|(_, handle)| handle)
+    }
+
+    /// Record the `Flow-Timer` of a 200 OK and adapt a running keepalive
+    fn update_flow_timer(&mut self, resp: &Response) {
+        let flow_timer = resp
+            .headers
+            .get_other("Flow-Timer")
+            .and_then(|v| v.trim().parse::<u32>().ok());
+        if flow_timer == self.flow_timer {
+            return;
+        }
+        debug!("registrar flow timer {:?}", flow_timer);
+        self.flow_timer = flow_timer;
+        let running = match self.keepalive.as_ref() {
+            Some((connection, handle)) if !handle.is_stopped() => {
+                Some((connection.clone(), handle.target().clone()))
+            }
+            _ => None,
+        };
+        if let Some((connection, target)) = running {
+            self.start_keepalive(connection, target);
+        }
+    }
+
+    /// Record the server time from the Date header of a response
+    pub(super) fn update_server_time(&mut self, resp: &Response) {
+        let server_time = match resp.date() {
+            Some(t) => t,
+            None => return,
+        };
+        let to_secs = |t: SystemTime| match t.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs() as i64),
+        };
+        let skew = to_secs(server_time) - to_secs(SystemTime::now());
+        debug!("registrar time {:?}, clock skew {}s", server_time, skew);
+        self.server_time = Some(server_time);
+        self.clock_skew = Some(skew);
+    }
+
     /// Get the registration expiration time
     ///
     /// Returns the expiration time in seconds for the current registration.
-    /// This value is extracted from the Contact header's expires parameter
-    /// in the last successful registration response.
+    /// This value is the expires parameter the registrar returned for our
+    /// binding in the last successful registration response, falling back
+    /// to the expires parameter of the configured Contact.
     ///
     /// # Returns
     ///
@@ -227,32 +935,201 @@ impl Registration {
     /// # }
     /// ```
     pub fn expires(&self) -> u32 {
-        self.contact
-            .as_ref()
+        let ours = self.contact.as_ref().or(self.last_contact.as_ref());
+        let granted = ours.and_then(|ours| self.find_binding(&ours.uri));
+        granted
+            .or(self.contact.as_ref())
             .and_then(|c| c.expires())
             .map(|e| e.seconds().unwrap_or(50))
             .unwrap_or(50)
     }
 
-    /// Get the first non-loopback network interface
+    /// Get the expiration the registrar granted to `contact`
+    ///
+    /// Looks `contact`, e.g. one of [`Registration::extra_contacts`], up in
+    /// the bindings of the last successful registration response.
+    ///
+    /// # Returns
+    ///
+    /// * `Some(u32)` - Seconds until the binding expires
+    /// * `None` - The registrar did not list the binding or its expiration
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::registration::Registration;
+    /// # fn example() {
+    /// # let registration: Registration = todo!();
+    /// for contact in &registration.extra_contacts {
+    ///     println!("{} expires in {:?}s", contact.uri, registration.contact_expires(contact));
+    /// }
+    /// # }
+    /// ```
+    pub fn contact_expires(&self, contact: &rsip::typed::Contact) -> Option<u32> {
+        self.find_binding(&contact.uri)?
+            .expires()
+            .and_then(|e| e.seconds().ok())
+    }
+
+    /// Find the binding of `uri` in the last successful registration response
+    ///
+    /// Registrars may add the default port or reorder URI parameters, so
+    /// bindings are matched on scheme, user, host and port only.
+    fn find_binding(&self, uri: &rsip::Uri) -> Option<&rsip::typed::Contact> {
+        self.bindings
+            .iter()
+            .find(|binding| same_binding(&binding.uri, uri))
+    }
+
+    /// Get the first non-loopback network interface
+    ///
+    /// Discovers the first available non-loopback IPv4 address of any
+    /// network interface on the system, see
+    /// [`Registration::find_local_interface`] to pick another family or
+    /// interface.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(IpAddr)` - First non-loopback IPv4 address found
+    /// * `Err(Error)` - No suitable interface found
+    pub fn get_first_non_loopback_interface() -> Result<IpAddr> {
+        Self::find_local_interface(AddrFamilyPreference::V4Only, None)
+    }
+
+    /// Find the local address to register from
+    ///
+    /// Discovers the first usable address of the system's network
+    /// interfaces. This is used to determine the local IP address for the
+    /// Contact header in registration requests. Loopback and link-local
+    /// addresses are skipped, and so are deprecated IPv6 addresses where
+    /// the system reports them (Linux).
+    ///
+    /// # Parameters
+    ///
+    /// * `preference` - Address family to pick
+    /// * `interface` - Only consider the interface with this name
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(IpAddr)` - First matching address found
+    /// * `Err(Error)` - No suitable interface found
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::registration::{AddrFamilyPreference, Registration};
+    /// # fn example() -> rsipstack::Result<()> {
+    /// let addr = Registration::find_local_interface(AddrFamilyPreference::PreferV6, Some("eth0"))?;
+    /// println!("registering from {}", addr);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn find_local_interface(
+        preference: AddrFamilyPreference,
+        interface: Option<&str>,
+    ) -> Result<IpAddr> {
+        let deprecated = deprecated_ipv6_addrs();
+        let candidates = get_if_addrs()?
+            .into_iter()
+            .filter(|i| !i.is_loopback())
+            .filter(|i| interface.is_none_or(|name| i.name == name))
+            .map(|i| i.ip())
+            .filter(|ip| !is_link_local(ip) && !deprecated.contains(ip))
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return Err(Error::Error("No interface found".to_string()));
+        }
+        let v4 = candidates.iter().find(|ip| ip.is_ipv4());
+        let v6 = candidates.iter().find(|ip| ip.is_ipv6());
+        match preference {
+            AddrFamilyPreference::V4Only => {
+                v4.ok_or(Error::Error("No IPv4 address found".to_string()))
+            }
+            AddrFamilyPreference::V6Only => {
+                v6.ok_or(Error::Error("No IPv6 address found".to_string()))
+            }
+            AddrFamilyPreference::PreferV6 => v6
+                .or(v4)
+                .ok_or(Error::Error("No interface found".to_string())),
+        }
+        .copied()
+    }
+
+    /// Port of the local socket `tx` was actually sent from
+    ///
+    /// Unlike the listen addresses, this is the port that was really bound,
+    /// which matters when the server only echoes `received` without an
+    /// `rport` value.
+    pub(super) fn local_port(tx: &Transaction) -> Option<u16> {
+        tx.connection
+            .as_ref()
+            .and_then(|connection| connection.get_local_addr().addr.port)
+            .map(|port| *port.value())
+    }
+
+    /// Parse the public address reported in the Via headers of a response
     ///
-    /// Discovers the first available non-loopback IPv4 network interface
-    /// on the system. This is used to determine the local IP address
-    /// for the Contact header in registration requests.
+    /// Looks for a Via carrying a `received` parameter and pairs it with the
+    /// `rport` value. When the server only echoed `received` (bare `rport`
+    /// or none at all), the port falls back to `local_port`, which should be
+    /// the port of the socket the request actually left from rather than
+    /// the port advertised in the Via.
     ///
     /// # Returns
     ///
-    /// * `Ok(IpAddr)` - First non-loopback IPv4 address found
-    /// * `Err(Error)` - No suitable interface found
-    fn get_first_non_loopback_interface() -> Result<IpAddr> {
-        get_if_addrs()?
+    /// * `Some(HostWithPort)` - Public address reported by the server
+    /// * `None` - No Via with a usable `received` parameter
+    pub fn public_address_from_via(
+        resp: &Response,
+        local_port: Option<u16>,
+    ) -> Option<rsip::HostWithPort> {
+        let via_headers = resp
+            .headers
             .iter()
-            .find(|i| !i.is_loopback())
-            .map(|i| match i.addr {
-                get_if_addrs::IfAddr::V4(ref addr) => Ok(std::net::IpAddr::V4(addr.ip)),
-                _ => Err(Error::Error("No IPv4 address found".to_string())),
+            .filter_map(|h| match h {
+                rsip::Header::Via(v) => Some(v),
+                _ => None,
             })
-            .unwrap_or(Err(Error::Error("No interface found".to_string())))
+            .collect::<Vec<_>>();
+
+        debug!("Found {} Via headers in {} response", via_headers.len(), resp.status_code);
+
+        for via in via_headers {
+            let typed_via = match via.typed() {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+            let mut received_ip: Option<IpAddr> = None;
+            let mut rport: Option<u16> = None;
+
+            for param in &typed_via.params {
+                match param {
+                    Param::Received(received) => {
+                        if let Ok(ip) = received.value().parse() {
+                            received_ip = Some(ip);
+                        }
+                    }
+                    Param::Other(key, Some(value)) if key.value().eq_ignore_ascii_case("rport") => {
+                        if let Ok(port) = value.value().parse::<u16>() {
+                            rport = Some(port);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            if let Some(public_ip) = received_ip {
+                let port = rport.or(local_port);
+                if rport.is_none() {
+                    info!("Via has received={} without rport, using local port {:?}", public_ip, port);
+                }
+                return Some(HostWithPort {
+                    host: public_ip.into(),
+                    port: port.map(Into::into),
+                });
+            }
+        }
+        None
     }
 
     /// Perform SIP registration with the server
@@ -277,7 +1154,8 @@ impl Registration {
     /// 2. **Request Creation** - Creates REGISTER request with proper headers
     /// 3. **Initial Send** - Sends the registration request
     /// 4. **Authentication** - Handles 401/407 challenges if credentials provided
-    /// 5. **Response Processing** - Returns final response (200 OK or error)
+    /// 5. **Interval** - Resends once with the `Min-Expires` of a 423
+    /// 6. **Response Processing** - Returns final response (200 OK or error)
     ///
     /// # Response Codes
     ///
@@ -285,7 +1163,8 @@ impl Registration {
     /// * `401 Unauthorized` - Authentication required (handled automatically)
     /// * `403 Forbidden` - Registration not allowed
     /// * `404 Not Found` - User not found
-    /// * `423 Interval Too Brief` - Requested expiration too short
+    /// * `423 Interval Too Brief` - Requested expiration too short, retried
+    ///   once with `requested_expires` raised to the registrar's `Min-Expires`
     ///
     /// # Examples
     ///
@@ -330,11 +1209,15 @@ impl Registration {
     ///         // Handle response based on status code
     ///     },
     ///     Err(Error::DnsResolutionError(msg)) => {
+    ///         // also returned when the lookup exceeds the DNS timeout
     ///         eprintln!("DNS resolution failed: {}", msg);
     ///     },
     ///     Err(Error::TransportLayerError(msg, addr)) => {
     ///         eprintln!("Network error to {}: {}", addr, msg);
     ///     },
+    ///     Err(Error::TransactionTerminated(key)) => {
+    ///         eprintln!("No final response for {}", key);
+    ///     },
     ///     Err(e) => {
     ///         eprintln!("Registration error: {}", e);
     ///     }
@@ -361,87 +1244,590 @@ impl Registration {
     /// * Determines appropriate transport protocol (UDP/TCP/TLS)
     /// * Sets up proper Via headers for response routing
     pub async fn register(&mut self, server: &String) -> Result<Response> {
-        self.last_seq += 1;
-
         let recipient = rsip::Uri::try_from(format!("sip:{}", server))?;
+        let first_addr = self.resolve_local_addr(&recipient).await?;
+        if self.server.as_ref() != Some(server) {
+            self.outbound = None;
+            self.service_route.clear();
+        }
+        self.server = Some(server.clone());
+        self.last_addr = Some(first_addr.clone());
+        let contact = self
+            .contact
+            .clone()
+            .unwrap_or_else(|| self.default_contact(&first_addr));
+        self.send_register(recipient, first_addr, RegisterContact::Binding(contact))
+            .await
+    }
 
-        let mut to = rsip::typed::To {
+    /// Remove a single binding from the registrar
+    ///
+    /// Sends a REGISTER carrying only `contact` with `expires=0`, so the
+    /// registrar drops that binding and keeps the others for the same AOR
+    /// (RFC 3261 section 10.2.2). `Contact: *` is never used here, as it
+    /// would remove every binding. Authentication challenges are answered
+    /// like in `register`, and the binding kept by this registration is
+    /// left untouched.
+    ///
+    /// # Parameters
+    ///
+    /// * `server` - Registrar to send the REGISTER to
+    /// * `contact` - URI of the binding to remove
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Response)` - Final response from the registrar
+    /// * `Err(Error)` - The request could not be sent or got no final response
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::registration::Registration;
+    /// # async fn example() -> rsipstack::Result<()> {
+    /// # let mut registration: Registration = todo!();
+    /// let stale = rsip::Uri::try_from("sip:alice@192.168.1.20:5060")?;
+    /// let response = registration
+    ///     .unregister_binding(&"sip.example.com".to_string(), &stale)
+    ///     .await?;
+    /// println!("binding removed: {}", response.status_code);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn unregister_binding(
+        &mut self,
+        server: &String,
+        contact: &rsip::Uri,
+    ) -> Result<Response> {
+        let recipient = rsip::Uri::try_from(format!("sip:{}", server))?;
+        let first_addr = match (&self.server, &self.last_addr) {
+            (Some(last_server), Some(addr)) if last_server == server => addr.clone(),
+            _ => self.resolve_local_addr(&recipient).await?,
+        };
+        let contact = rsip::typed::Contact {
             display_name: None,
-            uri: recipient.clone(),
-            params: vec![],
+            uri: contact.clone(),
+            params: vec![Param::Expires(rsip::param::Expires::new("0"))],
         };
+        self.send_register(recipient, first_addr, RegisterContact::Remove(contact))
+            .await
+    }
 
-        if let Some(cred) = &self.credential {
-            to.uri.auth = Some(rsip::auth::Auth {
-                user: cred.username.clone(),
-                password: None,
-            });
+    /// Remove our binding from the registrar
+    ///
+    /// Sends a REGISTER with our Contact and `expires=0`, e.g. when the user
+    /// agent shuts down, so the registrar stops routing calls to it right
+    /// away instead of when the binding would expire, along with the
+    /// [`Registration::extra_contacts`]. Authentication challenges are
+    /// answered like in `register`. Once the registrar accepts, the registration is reported as not registered and a
+    /// running keepalive is stopped.
+    ///
+    /// # Parameters
+    ///
+    /// * `server` - Registrar to send the REGISTER to
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Response)` - Final response from the registrar
+    /// * `Err(Error)` - The request could not be sent or got no final response
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::registration::Registration;
+    /// # async fn example() -> rsipstack::Result<()> {
+    /// # let mut registration: Registration = todo!();
+    /// let server = "sip.example.com".to_string();
+    /// registration.register(&server).await?;
+    /// // ... on shutdown
+    /// let response = registration.unregister(&server).await?;
+    /// println!("unregistered: {}", response.status_code);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn unregister(&mut self, server: &String) -> Result<Response> {
+        let recipient = rsip::Uri::try_from(format!("sip:{}", server))?;
+        let first_addr = match (&self.server, &self.last_addr) {
+            (Some(last_server), Some(addr)) if last_server == server => addr.clone(),
+            _ => self.resolve_local_addr(&recipient).await?,
+        };
+        let mut contact = self
+            .contact
+            .clone()
+            .or_else(|| self.last_contact.clone())
+            .unwrap_or_else(|| self.default_contact(&first_addr));
+        contact.params.retain(|p| !matches!(p, Param::Expires(_)));
+        contact
+            .params
+            .push(Param::Expires(rsip::param::Expires::new("0")));
+        let resp = self
+            .send_register(recipient, first_addr, RegisterContact::Unbind(contact))
+            .await?;
+        if resp.status_code == StatusCode::OK {
+            self.forget_binding();
         }
+        Ok(resp)
+    }
 
-        let form = rsip::typed::From {
-            display_name: None,
-            uri: to.uri.clone(),
-            params: vec![],
+    /// Remove every binding of our AOR from the registrar
+    ///
+    /// Like [`Registration::unregister`], but sends `Contact: *` with
+    /// `Expires: 0` (RFC 3261 section 10.2.2), which also removes the
+    /// bindings other devices registered for the same AOR.
+    ///
+    /// # Parameters
+    ///
+    /// * `server` - Registrar to send the REGISTER to
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Response)` - Final response from the registrar
+    /// * `Err(Error)` - The request could not be sent or got no final response
+    pub async fn unregister_all(&mut self, server: &String) -> Result<Response> {
+        let recipient = rsip::Uri::try_from(format!("sip:{}", server))?;
+        let first_addr = match (&self.server, &self.last_addr) {
+            (Some(last_server), Some(addr)) if last_server == server => addr.clone(),
+            _ => self.resolve_local_addr(&recipient).await?,
+        };
+        let resp = self
+            .send_register(recipient, first_addr, RegisterContact::All)
+            .await?;
+        if resp.status_code == StatusCode::OK {
+            self.forget_binding();
         }
-        .with_tag(make_tag());
+        Ok(resp)
+    }
+
+    /// Fetch the current bindings of our AOR
+    ///
+    /// Sends a REGISTER without Contact, which the registrar answers with
+    /// every binding of the AOR and changes nothing (RFC 3261 section
+    /// 10.2.3). Use it to find stale bindings, e.g. left by a crashed
+    /// instance, before registering. Authentication challenges are
+    /// answered like in `register`.
+    ///
+    /// # Parameters
+    ///
+    /// * `server` - Registrar to send the REGISTER to
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<Contact>)` - Bindings listed in the 200 OK, with their
+    ///   `expires` parameters
+    /// * `Err(Error)` - The request failed or was not answered with 200 OK
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::registration::Registration;
+    /// # async fn example() -> rsipstack::Result<()> {
+    /// # let mut registration: Registration = todo!();
+    /// let server = "sip.example.com".to_string();
+    /// for binding in registration.fetch_bindings(&server).await? {
+    ///     registration.unregister_binding(&server, &binding.uri).await?;
+    /// }
+    /// registration.register(&server).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn fetch_bindings(&mut self, server: &String) -> Result<Vec<rsip::typed::Contact>> {
+        let recipient = rsip::Uri::try_from(format!("sip:{}", server))?;
+        let first_addr = match (&self.server, &self.last_addr) {
+            (Some(last_server), Some(addr)) if last_server == server => addr.clone(),
+            _ => self.resolve_local_addr(&recipient).await?,
+        };
+        let resp = self
+            .send_register(recipient, first_addr, RegisterContact::Query)
+            .await?;
+        if resp.status_code != StatusCode::OK {
+            return Err(Error::Error(format!(
+                "binding query answered with {}",
+                resp.status_code
+            )));
+        }
+        Ok(Self::parse_bindings(&resp))
+    }
 
+    /// Drop what we know about our binding once it was removed
+    fn forget_binding(&mut self) {
+        self.last_registered_at = None;
+        self.bindings.clear();
+        self.pub_gruu = None;
+        self.service_route.clear();
+        self.outbound = None;
+        self.stop_keepalive();
+    }
+
+    /// Local address for the Via of a REGISTER sent to `recipient`
+    async fn resolve_local_addr(&self, recipient: &rsip::Uri) -> Result<SipAddr> {
         let first_addr = {
             // If we have a discovered public address, use it for Via header
-            let host_with_port = if let Some(pub_addr) = &self.public_address {
+            let (host_with_port, transport) = if let Some(pub_addr) = &self.public_address {
                 info!("Using public address for Via header: {}", pub_addr);
-                pub_addr.clone()
+                (pub_addr.clone(), None)
+            } else if let Some(local_addr) = &self.local_addr {
+                info!(
+                    "Using configured local address for Via header: {}",
+                    local_addr
+                );
+                (local_addr.addr.clone(), local_addr.r#type)
             } else {
-                HostWithPort::from(Self::get_first_non_loopback_interface()?)
+                let ip = Self::find_local_interface(self.addr_family, self.interface.as_deref())?;
+                (HostWithPort::from(ip), None)
             };
             
             let mut addr = SipAddr::from(host_with_port);
-            let context = rsip_dns::Context::initialize_from(
-                recipient.clone(),
-                rsip_dns::AsyncTrustDnsClient::new(
-                    TokioAsyncResolver::tokio(Default::default(), Default::default()).unwrap(),
-                ),
-                rsip_dns::SupportedTransports::any(),
-            )?;
-
-            let mut lookup = rsip_dns::Lookup::from(context);
-            match lookup.resolve_next().await {
-                Some(target) => {
-                    addr.r#type = Some(target.transport);
-                    addr
-                }
+            addr.r#type = match transport {
+                Some(transport) => Some(transport),
                 None => {
-                    Err(crate::Error::DnsResolutionError(format!(
-                        "DNS resolution error: {}",
-                        recipient
-                    )))
-                }?,
-            }
+                    let target = self.endpoint.transport_layer.resolve(recipient).await?;
+                    target.r#type
+                }
+            };
+            addr
+        };
+        Ok(first_addr)
+    }
+
+    /// Refresh the current registration
+    ///
+    /// Re-sends REGISTER to the server of the last [`Registration::register`]
+    /// call, reusing its Contact, local address and credential. Only the CSeq
+    /// is incremented, so neither the local interface nor the server address
+    /// is discovered again. If the server reported a new public address since,
+    /// the Contact is rebuilt from it like `register` would.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Response)` - Final response from the registrar
+    /// * `Err(Error)` - `register` was never called, or the request failed
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::registration::Registration;
+    /// # use std::time::Duration;
+    /// # async fn example() -> rsipstack::Result<()> {
+    /// # let mut registration: Registration = todo!();
+    /// registration.register(&"sip.example.com".to_string()).await?;
+    /// loop {
+    ///     let refresh = registration.expires() * 3 / 4;
+    ///     tokio::time::sleep(Duration::from_secs(refresh as u64)).await;
+    ///     registration.refresh().await?;
+    /// }
+    /// # }
+    /// ```
+    pub async fn refresh(&mut self) -> Result<Response> {
+        let (server, mut addr) = match (&self.server, &self.last_addr) {
+            (Some(server), Some(addr)) => (server.clone(), addr.clone()),
+            _ => return Err(Error::Error("refresh called before register".to_string())),
         };
+        if let Some(pub_addr) = &self.public_address {
+            addr.addr = pub_addr.clone();
+        }
+        let recipient = rsip::Uri::try_from(format!("sip:{}", server))?;
         let contact = self
             .contact
             .clone()
-            .unwrap_or_else(|| {
-                // Use public address if available, otherwise use local address
-                let contact_host_with_port = if let Some(pub_addr) = &self.public_address {
-                    info!("Using public address for initial Contact: {}", pub_addr);
-                    pub_addr.clone()
-                } else {
-                    info!("Using local address for initial Contact: {}", first_addr.addr);
-                    first_addr.clone().into()
+            .or_else(|| self.last_contact.clone())
+            .unwrap_or_else(|| self.default_contact(&addr));
+        self.send_register(recipient, addr, RegisterContact::Binding(contact))
+            .await
+    }
+
+    /// Keep the registration up in a background task
+    ///
+    /// Registers with `server`, then refreshes at 75% of the granted
+    /// expiration. A rejected or failed REGISTER is retried with
+    /// exponential backoff, from 2 seconds up to 5 minutes. When the
+    /// registrar reports a new public address the binding is replaced
+    /// right away, as in `register`.
+    ///
+    /// The task stops when `cancel_token` is cancelled, the returned
+    /// [`RefreshHandle`] is stopped or dropped, or every receiver of the
+    /// state is dropped.
+    ///
+    /// # Parameters
+    ///
+    /// * `server` - Registrar to register with
+    /// * `cancel_token` - Stops the task
+    ///
+    /// # Returns
+    ///
+    /// The handle of the task and a receiver of [`RegistrationState`] changes
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::registration::{Registration, RegistrationState};
+    /// # use tokio_util::sync::CancellationToken;
+    /// # async fn example() -> rsipstack::Result<()> {
+    /// # let registration: Registration = todo!();
+    /// let server = "sip.example.com".to_string();
+    /// let cancel_token = CancellationToken::new();
+    /// let (handle, mut state) = registration.start_refresh(server.clone(), cancel_token.clone());
+    /// while state.changed().await.is_ok() {
+    ///     if let RegistrationState::Failed { status } = &*state.borrow() {
+    ///         println!("registration failed: {:?}", status);
+    ///     }
+    /// #   break;
+    /// }
+    /// // on shutdown
+    /// let mut registration = handle.stop().await?;
+    /// registration.unregister(&server).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn start_refresh(
+        mut self,
+        server: String,
+        cancel_token: CancellationToken,
+    ) -> (RefreshHandle, watch::Receiver<RegistrationState>) {
+        let (state_tx, state_rx) = watch::channel(RegistrationState::Registering);
+        // the handle cancels its own token, not the caller's
+        let cancel_token = cancel_token.child_token();
+        let handle_token = cancel_token.clone();
+        let task = tokio::spawn(async move {
+            let mut backoff = REFRESH_BACKOFF_MIN;
+            let mut active = false;
+            loop {
+                if !active && *state_tx.borrow() != RegistrationState::Registering {
+                    state_tx.send_replace(RegistrationState::Registering);
+                }
+                let register = async {
+                    if active {
+                        self.refresh().await
+                    } else {
+                        self.register(&server).await
+                    }
+                };
+                let result = tokio::select! {
+                    _ = cancel_token.cancelled() => break,
+                    _ = state_tx.closed() => break,
+                    result = register => result,
+                };
+                let wait = match result {
+                    Ok(resp) if resp.status_code == StatusCode::OK => {
+                        let expires = self.expires();
+                        state_tx.send_replace(RegistrationState::Active { expires });
+                        active = true;
+                        backoff = REFRESH_BACKOFF_MIN;
+                        Duration::from_millis(expires as u64 * 750).max(Duration::from_secs(1))
+                    }
+                    result => {
+                        let status = match result {
+                            Ok(resp) => Some(resp.status_code),
+                            Err(e) => {
+                                warn!("registration with {} failed: {}", server, e);
+                                None
+                            }
+                        };
+                        state_tx.send_replace(RegistrationState::Failed { status });
+                        active = false;
+                        let wait = backoff;
+                        backoff = (backoff * 2).min(REFRESH_BACKOFF_MAX);
+                        wait
+                    }
                 };
-                
-                rsip::typed::Contact {
-                    display_name: None,
-                    uri: rsip::Uri {
-                        auth: to.uri.auth.clone(),
-                        scheme: Some(rsip::Scheme::Sip),
-                        host_with_port: contact_host_with_port,
-                        params: vec![],
-                        headers: vec![],
-                    },
-                    params: vec![Param::Other("ob".into(), None)], // Add outbound parameter for NAT
+                tokio::select! {
+                    _ = cancel_token.cancelled() => break,
+                    _ = state_tx.closed() => break,
+                    _ = tokio::time::sleep(wait) => {}
+                }
+            }
+            debug!("registration refresh for {} stopped", server);
+            self
+        });
+        let handle = RefreshHandle {
+            task,
+            cancel_token: handle_token,
+        };
+        (handle, state_rx)
+    }
+
+    /// Contact used when the application did not set one
+    fn default_contact(&self, local_addr: &SipAddr) -> rsip::typed::Contact {
+        // Use public address if available, otherwise use local address
+        let contact_host_with_port = if let Some(pub_addr) = &self.public_address {
+            info!("Using public address for initial Contact: {}", pub_addr);
+            pub_addr.clone()
+        } else {
+            info!(
+                "Using local address for initial Contact: {}",
+                local_addr.addr
+            );
+            local_addr.clone().into()
+        };
+        let auth = self.credential.as_ref().map(|cred| rsip::Auth {
+            user: cred.username.clone(),
+            password: None,
+        });
+
+        rsip::typed::Contact {
+            display_name: None,
+            uri: rsip::Uri {
+                auth,
+                scheme: Some(rsip::Scheme::Sip),
+                host_with_port: contact_host_with_port,
+                params: vec![],
+                headers: vec![],
+            },
+            params: vec![Param::Other("ob".into(), None)], // Add outbound parameter for NAT
+        }
+    }
+
+    /// Send a REGISTER and record its outcome for [`Registration::status`]
+    async fn send_register(
+        &mut self,
+        recipient: rsip::Uri,
+        first_addr: SipAddr,
+        contact: RegisterContact,
+    ) -> Result<Response> {
+        let is_binding = matches!(contact, RegisterContact::Binding(_));
+        let binding = match &contact {
+            RegisterContact::Binding(contact) => Some(contact.clone()),
+            _ => None,
+        };
+        self.public_address_changed = false;
+        self.stale_contact = None;
+        let mut result = self
+            .do_send_register(recipient.clone(), first_addr.clone(), contact)
+            .await;
+        // 423 Interval Too Brief: ask again, once, for the Min-Expires
+        // the registrar accepts (RFC 3261 section 10.3)
+        let min_expires = match &result {
+            Ok(resp) if resp.status_code == StatusCode::IntervalTooBrief => {
+                resp.headers.iter().find_map(|h| match h {
+                    rsip::Header::MinExpires(min) => min.value().trim().parse::<u32>().ok(),
+                    _ => None,
+                })
+            }
+            _ => None,
+        };
+        if let (Some(min_expires), Some(contact)) = (min_expires, binding) {
+            let asked = self
+                .requested_expires
+                .or_else(|| contact.expires().and_then(|e| e.seconds().ok()));
+            if asked.is_none_or(|asked| asked < min_expires) {
+                info!("registrar requires expires of at least {}", min_expires);
+                self.requested_expires = Some(min_expires);
+                result = self
+                    .do_send_register(
+                        recipient.clone(),
+                        first_addr.clone(),
+                        RegisterContact::Binding(contact),
+                    )
+                    .await;
+            }
+        }
+        // The binding just registered points at the private address, replace
+        // it right away with a Contact built from the public one
+        if std::mem::take(&mut self.public_address_changed) && result.is_ok() {
+            let mut addr = first_addr;
+            if let Some(pub_addr) = &self.public_address {
+                addr.addr = pub_addr.clone();
+            }
+            let contact = self
+                .contact
+                .clone()
+                .or_else(|| self.last_contact.clone())
+                .unwrap_or_else(|| self.default_contact(&addr));
+            info!("re-registering {} with public address", contact.uri);
+            result = self
+                .do_send_register(recipient, addr, RegisterContact::Binding(contact))
+                .await;
+            self.public_address_changed = false;
+            self.stale_contact = None;
+        }
+        match &result {
+            Ok(resp) if resp.status_code == StatusCode::OK => {
+                if is_binding {
+                    self.last_registered_at = Some(Instant::now());
                 }
+                self.last_error = None;
+            }
+            Ok(resp) => self.last_error = Some(resp.status_code.to_string()),
+            Err(e) => self.last_error = Some(e.to_string()),
+        }
+        result
+    }
+
+    async fn do_send_register(
+        &mut self,
+        recipient: rsip::Uri,
+        first_addr: SipAddr,
+        contact: RegisterContact,
+    ) -> Result<Response> {
+        self.last_seq = next_cseq(self.last_seq);
+
+        let mut to = rsip::typed::To {
+            display_name: None,
+            uri: recipient.clone(),
+            params: vec![],
+        };
+
+        if let Some(cred) = &self.credential {
+            to.uri.auth = Some(rsip::auth::Auth {
+                user: cred.username.clone(),
+                password: None,
             });
+        }
+
+        let form = rsip::typed::From {
+            display_name: self.display_name.clone(),
+            uri: to.uri.clone(),
+            params: vec![],
+        }
+        .with_tag(make_tag());
+
+        let is_binding = matches!(contact, RegisterContact::Binding(_));
+        let wildcard = matches!(contact, RegisterContact::All);
+        let extra_contacts = match contact {
+            // drop the binding at the private address in the same request
+            RegisterContact::Binding(_) => self
+                .stale_contact
+                .take()
+                .map(|mut stale| {
+                    // matched by URI, the new binding carries the same instance
+                    stale.params.retain(|p| {
+                        !matches!(p, Param::Expires(_))
+                            && !matches!(p, Param::Other(name, _)
+                                if name.value().eq_ignore_ascii_case("+sip.instance")
+                                    || name.value().eq_ignore_ascii_case("reg-id"))
+                    });
+                    stale
+                        .params
+                        .push(Param::Expires(rsip::param::Expires::new("0")));
+                    stale
+                })
+                .into_iter()
+                .chain(self.extra_contacts.iter().cloned())
+                .collect(),
+            RegisterContact::Unbind(_) => self
+                .extra_contacts
+                .iter()
+                .cloned()
+                .map(|mut extra| {
+                    extra.params.retain(|p| !matches!(p, Param::Expires(_)));
+                    extra
+                        .params
+                        .push(Param::Expires(rsip::param::Expires::new("0")));
+                    extra
+                })
+                .collect(),
+            _ => vec![],
+        };
+        let contact: Option<rsip::Header> = match contact {
+            RegisterContact::Binding(contact) => {
+                let contact = self.binding_contact(contact);
+                self.last_contact = Some(contact.clone());
+                Some(contact.into())
+            }
+            RegisterContact::Remove(contact) | RegisterContact::Unbind(contact) => {
+                Some(contact.into())
+            }
+            RegisterContact::All => Some(rsip::headers::Contact::new("*").into()),
+            RegisterContact::Query => None,
+        };
         let via = self.endpoint.get_via(Some(first_addr.clone()), None)?;
         let mut request = self.endpoint.make_request(
             rsip::Method::Register,
@@ -452,14 +1838,33 @@ impl Registration {
             self.last_seq,
         );
 
-        request.headers.unique_push(contact.into());
+        if let Some(contact) = contact {
+            request.headers.unique_push(contact);
+        }
+        for extra in extra_contacts {
+            request.headers.push(extra.into());
+        }
+        if wildcard {
+            request
+                .headers
+                .unique_push(rsip::headers::Expires::new("0").into());
+        }
         request.headers.unique_push(self.allow.clone().into());
+        if is_binding {
+            if let Some(supported) = self.register_supported() {
+                request.headers.unique_push(supported);
+            }
+        }
+        // the REGISTER is dialed to the outbound proxy, route it through there too
+        if let Some(route) = self.endpoint.outbound_route() {
+            request.headers.push(route);
+        }
 
         let key = TransactionKey::from_request(&request, TransactionRole::Client)?;
         let mut tx = Transaction::new_client(key, request, self.endpoint.clone(), None);
 
         tx.send().await?;
-        let mut auth_sent = false;
+        let mut auth = AuthRetries::new(self.endpoint.max_auth_retries);
 
         while let Some(msg) = tx.receive().await {
             match msg {
@@ -468,27 +1873,35 @@ impl Registration {
                         continue;
                     }
                     StatusCode::ProxyAuthenticationRequired | StatusCode::Unauthorized => {
-                        let received = resp.via_received();
+                        let local_port = Self::local_port(&tx);
+                        let received = Self::public_address_from_via(&resp, local_port)
+                            .or_else(|| resp.via_received());
                         if self.public_address != received {
                             info!(                                    
                                 "Updated public address from 401 response, will use in authenticated request: {:?} -> {:?}",
                                 self.public_address, received
                             );
                             self.public_address = received;
-                            self.contact = None;
+                            if self.sticky_contact {
+                                self.move_contact_to_public_address();
+                            } else {
+                                self.contact = None;
+                            }
                         }
 
-                        if auth_sent {
+                        if !auth.retry(&resp) {
                             debug!("received {} response after auth sent", resp.status_code);
                             return Ok(resp);
                         }
 
                         if let Some(cred) = &self.credential {
-                            self.last_seq += 1;
+                            self.last_seq = next_cseq(self.last_seq);
                             
                             // If we discovered a new public address, update the Contact header
                             // in the original request before authentication
-                            if let Some(pub_addr) = &self.public_address {
+                            if let Some(pub_addr) =
+                                self.public_address.as_ref().filter(|_| is_binding)
+                            {
                                 info!("Updating Contact header with public address before authentication");
                                 
                                 // Create new contact with public address
@@ -501,19 +1914,33 @@ impl Registration {
                                     None
                                 };
                                 
-                                let new_contact = rsip::typed::Contact {
-                                    display_name: None,
-                                    uri: rsip::Uri {
-                                        auth,
-                                        scheme: Some(rsip::Scheme::Sip),
-                                        host_with_port: pub_addr.clone(),
-                                        params: vec![],
-                                        headers: vec![],
+                                let sticky = self
+                                    .contact
+                                    .as_ref()
+                                    .or(self.last_contact.as_ref())
+                                    .filter(|_| self.sticky_contact);
+                                let new_contact = match sticky {
+                                    Some(contact) => {
+                                        let mut contact = contact.clone();
+                                        contact.uri.host_with_port = pub_addr.clone();
+                                        contact
+                                    }
+                                    None => rsip::typed::Contact {
+                                        display_name: self.display_name.clone(),
+                                        uri: rsip::Uri {
+                                            auth,
+                                            scheme: Some(rsip::Scheme::Sip),
+                                            host_with_port: pub_addr.clone(),
+                                            params: vec![],
+                                            headers: vec![],
+                                        },
+                                        params: vec![Param::Other("ob".into(), None)], // Add outbound parameter
                                     },
-                                    params: vec![Param::Other("ob".into(), None)], // Add outbound parameter
                                 };
                                 
                                 // Update the Contact header in the transaction's original request
+                                let new_contact = self.binding_contact(new_contact);
+                                self.last_contact = Some(new_contact.clone());
                                 tx.original.headers.unique_push(new_contact.into());
                             }
                             
@@ -521,76 +1948,75 @@ impl Registration {
                             tx = handle_client_authenticate(self.last_seq, tx, resp, cred).await?;
 
                             tx.send().await?;
-                            auth_sent = true;
                             continue;
                         } else {
                             debug!("received {} response without credential", resp.status_code);
+                            if self.endpoint.strict_auth {
+                                return Err(crate::Error::AuthenticationRequired(
+                                    resp.status_code,
+                                ));
+                            }
                             return Ok(resp);
                         }
                     }
                     StatusCode::OK => {
+                        self.update_server_time(&resp);
+                        if is_binding {
+                            self.negotiated_transport = first_addr.r#type;
+                            self.bindings = Self::parse_bindings(&resp);
+                            self.pub_gruu = self.find_pub_gruu(&resp);
+                            self.service_route = Self::parse_service_route(&resp);
+                            self.outbound = Some(resp.headers.iter().any(|h| {
+                                let tags = match h {
+                                    rsip::Header::Require(require) => require.value(),
+                                    rsip::Header::Supported(supported) => supported.value(),
+                                    _ => return false,
+                                };
+                                split_header_values(tags)
+                                    .iter()
+                                    .any(|tag| tag.eq_ignore_ascii_case("outbound"))
+                            }));
+                            self.update_flow_timer(&resp);
+                            match tx.response_transport {
+                                Some(actual) if first_addr.r#type != Some(actual) => {
+                                    warn!(
+                                        "REGISTER sent over {:?} but answered over {}, following the response",
+                                        first_addr.r#type, actual
+                                    );
+                                    self.update_transport(actual);
+                                }
+                                _ => {}
+                            }
+                        }
                         // Check if server indicated our public IP in Via header
-                        let mut _need_reregistration = false;
-                        // Get all Via headers and check each one
-                        let via_headers = resp.headers.iter()
-                            .filter_map(|h| match h {
-                                rsip::Header::Via(v) => Some(v),
-                                _ => None
-                            })
-                            .collect::<Vec<_>>();
-                        
-                        info!("Found {} Via headers in 200 OK response", via_headers.len());
-                        
-                        for (idx, via) in via_headers.iter().enumerate() {
-                            info!("Checking Via header #{} for public IP: {}", idx, via);
-                            if let Ok(typed_via) = via.typed() {
-                                let mut received_ip: Option<std::net::IpAddr> = None;
-                                let mut rport: Option<u16> = None;
-                                
-                                // Parse all Via parameters
-                                for param in &typed_via.params {
-                                    match param {
-                                        Param::Received(received) => {
-                                            if let Ok(ip) = received.value().parse() {
-                                                received_ip = Some(ip);
-                                                info!("Found received parameter: {}", ip);
-                                            }
-                                        }
-                                        Param::Other(key, Some(value)) if key.value() == "rport" => {
-                                            if let Ok(port) = value.value().parse::<u16>() {
-                                                rport = Some(port);
-                                                info!("Found rport parameter: {}", port);
-                                            }
-                                        }
-                                        _ => {}
-                                    }
+                        let local_port = Self::local_port(&tx);
+                        if let Some(new_public_addr) =
+                            Self::public_address_from_via(&resp, local_port)
+                        {
+                            info!("Server detected our public address as {}", new_public_addr);
+                            let new_public_addr = Some(new_public_addr);
+
+                            // Only update and re-register if this is new information
+                            if self.public_address != new_public_addr {
+                                self.public_address = new_public_addr;
+
+                                if self.sticky_contact {
+                                    self.move_contact_to_public_address();
+                                } else {
+                                    // Clear the stored contact so it gets regenerated with public IP
+                                    self.contact = None;
+                                    self.stale_contact =
+                                        self.last_contact.take().filter(|_| is_binding);
                                 }
-                                
-                                // If we found both received IP and rport, update our public address
-                                if let (Some(public_ip), Some(public_port)) = (received_ip, rport) {
-                                    info!("Server detected our public address as {}:{}", public_ip, public_port);
-                                    
-                                    // Store the public address
-                                    let new_public_addr = Some(HostWithPort {
-                                        host: public_ip.into(),
-                                        port: Some(public_port.into()),
-                                    });
-                                    
-                                    // Only update and re-register if this is new information
-                                    if self.public_address != new_public_addr {
-                                        self.public_address = new_public_addr;
-                                        
-                                        // Clear the stored contact so it gets regenerated with public IP
-                                        self.contact = None;
-                                        
-                                        // We need to re-register immediately with the public IP
-                                        _need_reregistration = true;
-                                        info!("Will re-register with public address");
-                                    }
+
+                                // We need to re-register immediately with the public IP
+                                if is_binding {
+                                    self.public_address_changed = true;
+                                    info!("Will re-register with public address");
                                 }
                             }
                         }
-                        
+
                         // The public address has been discovered and will be used for future requests
                         info!("registration do_request done: {:?}", resp.status_code);
                         return Ok(resp);
@@ -603,10 +2029,7 @@ impl Registration {
                 _ => break,
             }
         }
-        return Err(crate::Error::DialogError(
-            "registration transaction is already terminated".to_string(),
-            DialogId::try_from(&tx.original)?,
-        ));
+        return Err(crate::Error::TransactionTerminated(tx.key.clone()));
     }
 
     /// Create a NAT-aware Contact header with public address
@@ -672,3 +2095,83 @@ impl Registration {
         }
     }
 }
+
+/// Whether `ip` is a link-local address, unusable beyond its own link
+fn is_link_local(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => v4.is_link_local(),
+        IpAddr::V6(v6) => (v6.segments()[0] & 0xffc0) == 0xfe80,
+    }
+}
+
+/// IPv6 addresses the kernel marked deprecated, e.g. expired privacy
+/// addresses, which should not be used for new flows
+#[cfg(target_os = "linux")]
+fn deprecated_ipv6_addrs() -> Vec<IpAddr> {
+    // IFA_F_DEPRECATED of linux/if_addr.h
+    const DEPRECATED: u32 = 0x20;
+    let table = std::fs::read_to_string("/proc/net/if_inet6").unwrap_or_default();
+    table
+        .lines()
+        .filter_map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let flags = u32::from_str_radix(fields.get(4)?, 16).ok()?;
+            if flags & DEPRECATED == 0 {
+                return None;
+            }
+            let addr = u128::from_str_radix(fields.first()?, 16).ok()?;
+            Some(IpAddr::V6(std::net::Ipv6Addr::from(addr)))
+        })
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn deprecated_ipv6_addrs() -> Vec<IpAddr> {
+    vec![]
+}
+
+/// Whether two Contact URIs name the same binding, a missing port being
+/// the default one of the scheme
+fn same_binding(a: &rsip::Uri, b: &rsip::Uri) -> bool {
+    let port = |uri: &rsip::Uri| match (uri.host_with_port.port, &uri.scheme) {
+        (Some(port), _) => u16::from(port),
+        (None, Some(rsip::Scheme::Sips)) => 5061,
+        (None, _) => 5060,
+    };
+    let secure = |uri: &rsip::Uri| matches!(uri.scheme, Some(rsip::Scheme::Sips));
+    secure(a) == secure(b)
+        && a.user() == b.user()
+        && a.host_with_port.host == b.host_with_port.host
+        && port(a) == port(b)
+}
+
+/// URN of the `+sip.instance` parameter of one Contact value
+fn contact_instance(value: &str) -> Option<&str> {
+    let start = value.to_ascii_lowercase().find("+sip.instance=")? + "+sip.instance=".len();
+    let instance = value[start..].strip_prefix("\"<")?;
+    Some(&instance[..instance.find('>')?])
+}
+
+/// Extract the binding URI and `pub-gruu` of one Contact value
+fn parse_pub_gruu(value: &str) -> Option<(rsip::Uri, rsip::Uri)> {
+    let (binding, params) = value.split_once('<')?.1.split_once('>')?;
+    let binding = rsip::Uri::try_from(binding).ok()?;
+    let start = params.to_ascii_lowercase().find("pub-gruu=")? + "pub-gruu=".len();
+    let gruu = params[start..].strip_prefix('"')?;
+    let gruu = &gruu[..gruu.find('"')?];
+
+    // rsip cuts parameter values at the first colon, which would turn
+    // `gr=urn:uuid:...` into `gr=urn`, so the parameters are kept verbatim
+    let (uri, gruu_params) = gruu.split_once(';').unwrap_or((gruu, ""));
+    let mut uri = rsip::Uri::try_from(uri).ok()?;
+    uri.params.extend(
+        gruu_params
+            .split(';')
+            .filter(|param| !param.is_empty())
+            .map(|param| match param.split_once('=') {
+                Some((name, value)) => Param::Other(name.into(), Some(value.into())),
+                None => Param::Other(param.into(), None),
+            }),
+    );
+    Some((binding, uri))
+}
@@ -1,11 +1,17 @@
-use super::dialog::{Dialog, DialogInnerRef, DialogState, TerminatedReason};
+use super::authenticate::{self, Credential, DigestChallenge};
+use super::dialog::{Dialog, DialogInnerRef, DialogState, Q850Cause, SessionId, TerminatedReason};
 use super::DialogId;
 use crate::{
+    rsip_ext::{decode_user_to_user, sanitize_for_log, split_header_values, RsipHeadersExt},
     transaction::transaction::{Transaction, TransactionEvent},
     Result,
 };
-use rsip::{prelude::HeadersExt, Header, Request, SipMessage, StatusCode};
+use rsip::{
+    prelude::{HeadersExt, UntypedHeader},
+    Header, Request, SipMessage, StatusCode,
+};
 use std::sync::atomic::Ordering;
+use std::time::Duration;
 use tokio_util::sync::CancellationToken;
 use tracing::{info, trace, warn};
 
@@ -106,6 +112,21 @@ impl ServerInviteDialog {
         &self.inner.cancel_token
     }
 
+    /// Get the end-to-end session identifier (RFC 7989)
+    ///
+    /// `remote` is filled in once the peer's `Session-ID` arrives.
+    pub fn session_id(&self) -> SessionId {
+        self.inner.session_id()
+    }
+
+    /// Get the `Allow` and `Supported` headers advertising our capabilities
+    ///
+    /// Meant for in-dialog requests that are answered, such as BYE. ACK and
+    /// CANCEL never carry them.
+    pub fn capability_headers(&self) -> Vec<Header> {
+        self.inner.capability_headers()
+    }
+
     /// Get the initial INVITE request
     ///
     /// Returns a reference to the initial INVITE request that created
@@ -115,11 +136,91 @@ impl ServerInviteDialog {
         &self.inner.initial_request
     }
 
+    /// Get the User-to-User data of the initial INVITE
+    ///
+    /// Decodes the `User-to-User` header (RFC 7433) into its encoding and
+    /// payload. Returns `None` when the header is absent or malformed.
+    pub fn user_to_user(&self) -> Option<(String, Vec<u8>)> {
+        let request = &self.inner.initial_request;
+        let value = request.headers.get_other("User-to-User")?;
+        match decode_user_to_user(value) {
+            Ok(uui) => Some(uui),
+            Err(e) => {
+                info!("invalid User-to-User header: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Get the Call-IDs listed in the `In-Reply-To` header of the INVITE
+    ///
+    /// Links this call to the earlier calls it answers, e.g. a call back.
+    /// Returns an empty list when the INVITE carries no `In-Reply-To`.
+    pub fn in_reply_to(&self) -> Vec<String> {
+        self.inner
+            .initial_request
+            .headers
+            .iter()
+            .filter_map(|h| match h {
+                Header::InReplyTo(in_reply_to) => Some(in_reply_to.value()),
+                _ => None,
+            })
+            .flat_map(split_header_values)
+            .map(|call_id| call_id.to_string())
+            .collect()
+    }
+
+    /// Get the negotiated session interval
+    ///
+    /// Returns the `Session-Expires` interval in seconds agreed with the
+    /// caller (RFC 4028), or `None` when no session timer is in use.
+    pub fn session_expires(&self) -> Option<u32> {
+        *self.inner.session_expires.lock().unwrap()
+    }
+
+    /// Check whether the caller accepts a body of the given type
+    ///
+    /// Matches `content_type` against the `Accept` header of the INVITE,
+    /// honouring `type/*` and `*/*` ranges. Any type is accepted when the
+    /// INVITE carries no `Accept` header.
+    pub fn accepts(&self, content_type: &str) -> bool {
+        let accept = match self
+            .inner
+            .initial_request
+            .headers
+            .iter()
+            .find_map(|h| match h {
+                Header::Accept(accept) => Some(accept.value().to_string()),
+                _ => None,
+            }) {
+            Some(accept) => accept,
+            None => return true,
+        };
+        let media_type = |v: &str| {
+            v.split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_ascii_lowercase()
+        };
+        let content_type = media_type(content_type);
+        let (main_type, _) = content_type.split_once('/').unwrap_or((&content_type, ""));
+        accept.split(',').map(media_type).any(|range| {
+            range == "*/*" || range == content_type || range == format!("{}/*", main_type)
+        })
+    }
+
     /// Accept the incoming INVITE request
     ///
     /// Sends a 200 OK response to accept the incoming INVITE request.
     /// This establishes the dialog and transitions it to the WaitAck state,
-    /// waiting for the ACK from the client.
+    /// waiting for the ACK from the client. If no ACK arrives within the ACK
+    /// timeout (see `DialogLayer::set_ack_timeout`), the dialog terminates
+    /// with `TerminatedReason::Timeout`.
+    ///
+    /// When the body type (from `Content-Type`, `application/sdp` by default)
+    /// is not listed in the caller's `Accept` header, a 406 Not Acceptable is
+    /// sent instead and an error is returned.
     ///
     /// # Parameters
     ///
@@ -148,17 +249,60 @@ impl ServerInviteDialog {
     /// ```
     pub fn accept(&self, headers: Option<Vec<Header>>, body: Option<Vec<u8>>) -> Result<()> {
         if let Some(sender) = self.inner.tu_sender.lock().unwrap().as_ref() {
-            let resp = self.inner.make_response(
+            let mut resp = self.inner.make_response(
                 &self.inner.initial_request,
                 rsip::StatusCode::OK,
                 headers,
                 body,
             );
+            if !resp.body.is_empty() {
+                let content_type = resp
+                    .headers
+                    .iter()
+                    .find_map(|h| match h {
+                        Header::ContentType(content_type) => Some(content_type.value().to_string()),
+                        _ => None,
+                    })
+                    .unwrap_or("application/sdp".to_string());
+                if !self.accepts(&content_type) {
+                    info!(
+                        "{} is not acceptable to the caller, rejecting",
+                        content_type
+                    );
+                    let resp = self.inner.make_response(
+                        &self.inner.initial_request,
+                        StatusCode::NotAcceptable,
+                        None,
+                        None,
+                    );
+                    sender.send(TransactionEvent::Respond(resp))?;
+                    self.inner.transition(DialogState::Terminated(
+                        self.id(),
+                        TerminatedReason::UasOther(Some(StatusCode::NotAcceptable)),
+                    ))?;
+                    return Err(crate::Error::DialogError(
+                        format!("{} is not acceptable", content_type),
+                        self.id(),
+                    ));
+                }
+            }
+            self.add_session_timer_headers(&mut resp);
+            if !resp
+                .headers
+                .iter()
+                .any(|h| matches!(h, Header::Supported(_)))
+            {
+                if let Some(supported) = self.inner.endpoint_inner.supported_header() {
+                    resp.headers.push(supported);
+                }
+            }
 
             sender.send(TransactionEvent::Respond(resp.clone()))?;
+            self.inner.pending_invite.store(false, Ordering::Relaxed);
 
             self.inner
                 .transition(DialogState::WaitAck(self.id(), resp))?;
+            self.start_ack_timer();
             Ok(())
         } else {
             Err(crate::Error::DialogError(
@@ -168,6 +312,200 @@ impl ServerInviteDialog {
         }
     }
 
+    /// Tell the caller the call is queued
+    ///
+    /// Sends a 182 Queued provisional response to the pending INVITE. The
+    /// INVITE transaction stays open, so `queue` can be called again each
+    /// time the caller's position changes, until the call is finally
+    /// answered with `accept` or `reject`.
+    ///
+    /// # Parameters
+    ///
+    /// * `position` - Position in the queue, sent in the reason phrase
+    ///   (`182 Queued (position 3)`)
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - Response sent successfully
+    /// * `Err(Error)` - The INVITE was already answered or its transaction terminated
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::server_dialog::ServerInviteDialog;
+    /// # async fn example() -> rsipstack::Result<()> {
+    /// # let dialog: ServerInviteDialog = todo!();
+    /// for position in (1..=3).rev() {
+    ///     dialog.queue(Some(position))?;
+    ///     tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+    /// }
+    /// dialog.accept(None, None)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn queue(&self, position: Option<u32>) -> Result<()> {
+        let status = match position {
+            Some(position) => StatusCode::Other(182, format!("Queued (position {})", position)),
+            None => StatusCode::Queued,
+        };
+        self.provisional(status, None, None)
+    }
+
+    /// Tell the caller the callee is being alerted
+    ///
+    /// Sends a 180 Ringing provisional response to the pending INVITE.
+    /// The 100 Trying is sent automatically when the INVITE arrives; all
+    /// later provisional responses and the final response carry the same
+    /// To-tag, so `ringing`, `progress` and `progress_with_sdp` can be
+    /// mixed in any order before `accept` or `reject`.
+    ///
+    /// # Parameters
+    ///
+    /// * `headers` - Optional additional headers to include
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - Response sent successfully
+    /// * `Err(Error)` - The INVITE was already answered or its transaction terminated
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::server_dialog::ServerInviteDialog;
+    /// # fn example() -> rsipstack::Result<()> {
+    /// # let dialog: ServerInviteDialog = todo!();
+    /// # let early_media_sdp: Vec<u8> = vec![];
+    /// dialog.ringing(None)?;
+    /// dialog.progress_with_sdp(None, early_media_sdp)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn ringing(&self, headers: Option<Vec<Header>>) -> Result<()> {
+        self.provisional(StatusCode::Ringing, headers, None)
+    }
+
+    /// Send a 183 Session Progress without a body
+    ///
+    /// # Parameters
+    ///
+    /// * `headers` - Optional additional headers to include
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - Response sent successfully
+    /// * `Err(Error)` - The INVITE was already answered or its transaction terminated
+    pub fn progress(&self, headers: Option<Vec<Header>>) -> Result<()> {
+        self.provisional(StatusCode::SessionProgress, headers, None)
+    }
+
+    /// Send a 183 Session Progress with an SDP body for early media
+    ///
+    /// The body is sent as `application/sdp` unless `headers` has another
+    /// `Content-Type`.
+    ///
+    /// # Parameters
+    ///
+    /// * `headers` - Optional additional headers to include
+    /// * `sdp` - The early media SDP answer
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - Response sent successfully
+    /// * `Err(Error)` - The INVITE was already answered or its transaction terminated
+    pub fn progress_with_sdp(&self, headers: Option<Vec<Header>>, sdp: Vec<u8>) -> Result<()> {
+        let mut headers = headers.unwrap_or_default();
+        if !headers.iter().any(|h| matches!(h, Header::ContentType(_))) {
+            headers.push(Header::ContentType("application/sdp".into()));
+        }
+        self.provisional(StatusCode::SessionProgress, Some(headers), Some(sdp))
+    }
+
+    /// Send a provisional response to the pending INVITE
+    fn provisional(
+        &self,
+        status: StatusCode,
+        headers: Option<Vec<Header>>,
+        body: Option<Vec<u8>>,
+    ) -> Result<()> {
+        if matches!(
+            *self.inner.state.lock().unwrap(),
+            DialogState::WaitAck(_, _) | DialogState::Confirmed(_) | DialogState::Terminated(_, _)
+        ) {
+            return Err(crate::Error::DialogError(
+                "INVITE is already answered".to_string(),
+                self.id(),
+            ));
+        }
+        if let Some(sender) = self.inner.tu_sender.lock().unwrap().as_ref() {
+            let resp = self
+                .inner
+                .make_response(&self.inner.initial_request, status, headers, body);
+            sender
+                .send(TransactionEvent::Respond(resp))
+                .map_err(Into::into)
+        } else {
+            Err(crate::Error::DialogError(
+                "transaction is already terminated".to_string(),
+                self.id(),
+            ))
+        }
+    }
+
+    /// Give up on the ACK once the dialog's ACK timeout expires
+    ///
+    /// Terminating the INVITE transaction stops the 2xx retransmissions.
+    fn start_ack_timer(&self) {
+        let inner = self.inner.clone();
+        let timeout = *inner.ack_timeout.lock().unwrap();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = inner.cancel_token.cancelled() => return,
+                _ = tokio::time::sleep(timeout) => {}
+            }
+            let id = match &*inner.state.lock().unwrap() {
+                DialogState::WaitAck(id, _) => id.clone(),
+                _ => return,
+            };
+            info!("no ACK received within {:?}, terminating {}", timeout, id);
+            if let Some(sender) = inner.tu_sender.lock().unwrap().as_ref() {
+                sender.send(TransactionEvent::Terminate).ok();
+            }
+            inner
+                .transition(DialogState::Terminated(id, TerminatedReason::Timeout))
+                .ok();
+        });
+    }
+
+    fn add_session_timer_headers(&self, resp: &mut rsip::Response) {
+        let interval = match *self.inner.session_expires.lock().unwrap() {
+            Some(interval) => interval,
+            None => return,
+        };
+        if resp.headers.get_other("Session-Expires").is_some() {
+            return;
+        }
+        let request = &self.inner.initial_request;
+        let refresher = match request.headers.get_other("Session-Expires") {
+            Some(value) if value.contains("refresher=uas") => "uas",
+            _ => "uac",
+        };
+        resp.headers.push(Header::Other(
+            "Session-Expires".into(),
+            format!("{};refresher={}", interval, refresher),
+        ));
+        let supports_timer = request.headers.iter().any(|h| match h {
+            Header::Supported(supported) => supported
+                .value()
+                .split(',')
+                .any(|option| option.trim().eq_ignore_ascii_case("timer")),
+            _ => false,
+        });
+        if supports_timer {
+            resp.headers
+                .push(rsip::headers::Require::new("timer").into());
+        }
+    }
+
     /// Accept the incoming INVITE request with NAT-aware Contact header
     ///
     /// Sends a 200 OK response to accept the incoming INVITE request, automatically
@@ -298,9 +636,9 @@ impl ServerInviteDialog {
                 None,
                 None,
             );
-            sender
-                .send(TransactionEvent::Respond(resp))
-                .map_err(Into::into)
+            sender.send(TransactionEvent::Respond(resp))?;
+            self.inner.pending_invite.store(false, Ordering::Relaxed);
+            Ok(())
         } else {
             Err(crate::Error::DialogError(
                 "transaction is already terminated".to_string(),
@@ -309,6 +647,149 @@ impl ServerInviteDialog {
         }
     }
 
+    /// Reject the incoming INVITE because its SDP cannot be satisfied
+    ///
+    /// Sends a 488 Not Acceptable Here carrying a `Warning: 305` (incompatible
+    /// media format) with `reason` as the warn-text, then terminates the
+    /// dialog. Use it when no codec or media line of the offer is acceptable.
+    /// `reason` is kept as the dialog's [`Dialog::failure_details`].
+    ///
+    /// # Parameters
+    ///
+    /// * `reason` - Explanation sent to the caller, e.g. `"no common codec"`
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - Response sent successfully
+    /// * `Err(Error)` - Failed to send response or transaction terminated
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::server_dialog::ServerInviteDialog;
+    /// # fn example() -> rsipstack::Result<()> {
+    /// # let dialog: ServerInviteDialog = todo!();
+    /// # let answer: Vec<u8> = vec![];
+    /// let offer = String::from_utf8_lossy(&dialog.initial_request().body).to_string();
+    /// if offer.contains("PCMA/8000") {
+    ///     dialog.accept(None, Some(answer))?;
+    /// } else {
+    ///     dialog.reject_media("no common codec")?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn reject_media(&self, reason: &str) -> Result<()> {
+        let agent = match &self.inner.local_contact {
+            Some(contact) => contact.host_with_port.to_string(),
+            None => self.inner.initial_request.uri.host_with_port.to_string(),
+        };
+        let text = reason.replace('\\', "\\\\").replace('"', "\\\"");
+        let warning = rsip::headers::Warning::new(format!("305 {} \"{}\"", agent, text));
+        if let Some(sender) = self.inner.tu_sender.lock().unwrap().as_ref() {
+            let resp = self.inner.make_response(
+                &self.inner.initial_request,
+                rsip::StatusCode::NotAcceptableHere,
+                Some(vec![warning.into()]),
+                None,
+            );
+            sender.send(TransactionEvent::Respond(resp))?;
+        } else {
+            return Err(crate::Error::DialogError(
+                "transaction is already terminated".to_string(),
+                self.id(),
+            ));
+        }
+        *self.inner.failure_details.lock().unwrap() = Some(reason.to_string());
+        self.inner.transition(DialogState::Terminated(
+            self.id(),
+            TerminatedReason::UasOther(Some(StatusCode::NotAcceptableHere)),
+        ))
+    }
+
+    /// Challenge the incoming INVITE for digest credentials
+    ///
+    /// Sends a 407 Proxy Authentication Required with a `Proxy-Authenticate`
+    /// challenge for `realm`, then terminates the dialog. The caller retries
+    /// the INVITE with credentials in a new transaction, which creates a new
+    /// server dialog; check it with [`ServerInviteDialog::verify_authorization`].
+    ///
+    /// # Parameters
+    ///
+    /// * `realm` - Protection realm announced to the caller
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - Challenge sent successfully
+    /// * `Err(Error)` - Failed to send response or transaction terminated
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::server_dialog::ServerInviteDialog;
+    /// # use rsipstack::dialog::authenticate::Credential;
+    /// # fn example() -> rsipstack::Result<()> {
+    /// # let dialog: ServerInviteDialog = todo!();
+    /// let credential = Credential {
+    ///     username: "alice".to_string(),
+    ///     password: "secret123".to_string(),
+    ///     realm: None,
+    /// };
+    /// if dialog.verify_authorization("example.com", &credential) {
+    ///     dialog.accept(None, None)?;
+    /// } else {
+    ///     dialog.challenge("example.com")?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn challenge(&self, realm: &str) -> Result<()> {
+        let challenge = DigestChallenge::for_request(&self.inner.initial_request, realm)?;
+        if let Some(sender) = self.inner.tu_sender.lock().unwrap().as_ref() {
+            let resp = self.inner.make_response(
+                &self.inner.initial_request,
+                rsip::StatusCode::ProxyAuthenticationRequired,
+                Some(vec![Header::ProxyAuthenticate(
+                    rsip::headers::ProxyAuthenticate::new(challenge.to_string()),
+                )]),
+                None,
+            );
+            sender.send(TransactionEvent::Respond(resp))?;
+        } else {
+            return Err(crate::Error::DialogError(
+                "transaction is already terminated".to_string(),
+                self.id(),
+            ));
+        }
+        self.inner.transition(DialogState::Terminated(
+            self.id(),
+            TerminatedReason::ProxyAuthRequired,
+        ))
+    }
+
+    /// Username the caller authenticated as, if the INVITE has credentials
+    ///
+    /// Use it to look up the password before calling
+    /// [`ServerInviteDialog::verify_authorization`].
+    pub fn authorization_username(&self) -> Option<String> {
+        authenticate::authorization_username(&self.inner.initial_request)
+    }
+
+    /// Check the digest credentials of the INVITE
+    ///
+    /// Returns `true` if the INVITE answers a challenge sent by
+    /// [`ServerInviteDialog::challenge`] for `realm` with a valid response
+    /// for `cred`. A successful check consumes the nonce count, so it is
+    /// done once per INVITE. See [`authenticate::verify_authorization`].
+    pub fn verify_authorization(&self, realm: &str, cred: &Credential) -> bool {
+        authenticate::verify_authorization(
+            &self.inner.initial_request,
+            realm,
+            cred,
+            &self.inner.endpoint_inner.nonce_counts,
+        )
+    }
+
     /// Send a BYE request to terminate the dialog
     ///
     /// Sends a BYE request to gracefully terminate an established dialog.
@@ -332,12 +813,36 @@ impl ServerInviteDialog {
     /// # }
     /// ```
     pub async fn bye(&self) -> Result<()> {
+        self.bye_with_headers(None).await
+    }
+
+    /// Send a BYE request carrying extra headers
+    ///
+    /// Works like [`ServerInviteDialog::bye`], adding `headers` to the BYE, e.g. a
+    /// `Reason` or the dialog's [`ServerInviteDialog::capability_headers`].
+    ///
+    /// # Parameters
+    ///
+    /// * `headers` - Optional additional headers to include
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::server_dialog::ServerInviteDialog;
+    /// # async fn example() -> rsipstack::Result<()> {
+    /// # let dialog: ServerInviteDialog = todo!();
+    /// let headers = dialog.capability_headers();
+    /// dialog.bye_with_headers(Some(headers)).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn bye_with_headers(&self, headers: Option<Vec<Header>>) -> Result<()> {
         if !self.inner.is_confirmed() {
             return Ok(());
         }
         let request = self
             .inner
-            .make_request(rsip::Method::Bye, None, None, None, None, None)?;
+            .make_request(rsip::Method::Bye, None, None, None, headers, None)?;
 
         match self.inner.do_request(request).await {
             Ok(_) => {}
@@ -382,6 +887,47 @@ impl ServerInviteDialog {
         &self,
         headers: Option<Vec<rsip::Header>>,
         body: Option<Vec<u8>>,
+    ) -> Result<Option<rsip::Response>> {
+        self.reinvite_with_transport(headers, body, None).await
+    }
+
+    /// Send a re-INVITE over a specific transport
+    ///
+    /// Same as [`reinvite`](Self::reinvite), but sends this one request over
+    /// `transport` instead of the transport the dialog normally uses, e.g.
+    /// a large re-INVITE over TCP on a UDP dialog. Later requests are not
+    /// affected.
+    ///
+    /// # Parameters
+    ///
+    /// * `headers` - Optional additional headers to include
+    /// * `body` - Optional message body (typically new SDP)
+    /// * `transport` - Transport to force for this request, `None` keeps the default
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(Response))` - Response to the re-INVITE
+    /// * `Ok(None)` - Dialog not confirmed, no request sent
+    /// * `Err(Error)` - Failed to connect or send re-INVITE
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::dialog::server_dialog::ServerInviteDialog;
+    /// # async fn example() -> rsipstack::Result<()> {
+    /// # let dialog: ServerInviteDialog = todo!();
+    /// # let large_sdp = vec![];
+    /// let response = dialog
+    ///     .reinvite_with_transport(None, Some(large_sdp), Some(rsip::Transport::Tcp))
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn reinvite_with_transport(
+        &self,
+        headers: Option<Vec<rsip::Header>>,
+        body: Option<Vec<u8>>,
+        transport: Option<rsip::Transport>,
     ) -> Result<Option<rsip::Response>> {
         if !self.inner.is_confirmed() {
             return Ok(None);
@@ -389,7 +935,7 @@ impl ServerInviteDialog {
         let request =
             self.inner
                 .make_request(rsip::Method::Invite, None, None, None, headers, body)?;
-        let resp = self.inner.do_request(request.clone()).await;
+        let resp = self.inner.do_request_over(request.clone(), transport).await;
         match resp {
             Ok(Some(ref resp)) => {
                 if resp.status_code == StatusCode::OK {
@@ -513,13 +1059,17 @@ impl ServerInviteDialog {
     /// * `INFO` - Handles information exchange
     /// * `OPTIONS` - Handles capability queries
     /// * `UPDATE` - Handles session updates
+    /// * `REFER` - Accepts call transfers when enabled, see `DialogState::Refer`
+    /// * `NOTIFY` - Accepts notifications of active subscriptions, 481 otherwise
     /// * `INVITE` - Handles initial INVITE or re-INVITE
     pub async fn handle(&mut self, mut tx: Transaction) -> Result<()> {
         trace!(
-            "handle request: {:?} state:{}",
-            tx.original,
+            "handle request: {} state:{}",
+            sanitize_for_log(&tx.original.to_string()),
             self.inner.state.lock().unwrap()
         );
+        self.inner.update_session_id(&tx.original.headers);
+        self.inner.touch();
 
         let cseq = tx.original.cseq_header()?.seq()?;
         let remote_seq = self.inner.remote_seq.load(Ordering::Relaxed);
@@ -541,6 +1091,23 @@ impl ServerInviteDialog {
 
         if self.inner.is_confirmed() {
             match tx.original.method {
+                rsip::Method::Invite if self.inner.pending_invite.load(Ordering::Relaxed) => {
+                    // RFC 3261 14.2: one INVITE transaction at a time per dialog
+                    info!(
+                        "re-INVITE while another INVITE is pending {}",
+                        tx.original.uri
+                    );
+                    let retry_after =
+                        rsip::headers::RetryAfter::new((rand::random::<u32>() % 11).to_string());
+                    let resp = self.inner.make_response(
+                        &tx.original,
+                        StatusCode::ServerInternalError,
+                        Some(vec![retry_after.into()]),
+                        None,
+                    );
+                    tx.respond(resp).await?;
+                    return Ok(());
+                }
                 rsip::Method::Invite | rsip::Method::Ack => {
                     info!(
                         "invalid request received {} {}",
@@ -551,6 +1118,8 @@ impl ServerInviteDialog {
                 rsip::Method::Info => return self.handle_info(tx).await,
                 rsip::Method::Options => return self.handle_options(tx).await,
                 rsip::Method::Update => return self.handle_update(tx).await,
+                rsip::Method::Refer => return self.handle_refer(tx).await,
+                rsip::Method::Notify => return self.handle_notify(tx).await,
                 _ => {
                     info!("invalid request method: {:?}", tx.original.method);
                     tx.reply(rsip::StatusCode::MethodNotAllowed).await?;
@@ -573,6 +1142,33 @@ impl ServerInviteDialog {
                     }
                     return Ok(());
                 }
+                rsip::Method::Bye => {
+                    // The caller may hang up before its ACK reaches us
+                    let (answered, ringing) = match *self.inner.state.lock().unwrap() {
+                        DialogState::WaitAck(_, _) => (true, false),
+                        DialogState::Early(_, _) => (true, true),
+                        _ => (false, false),
+                    };
+                    if ringing {
+                        // the INVITE is still pending, it ends with the early dialog
+                        if let Some(sender) = self.inner.tu_sender.lock().unwrap().as_ref() {
+                            let resp = self.inner.make_response(
+                                &self.inner.initial_request,
+                                StatusCode::RequestTerminated,
+                                None,
+                                None,
+                            );
+                            sender.send(TransactionEvent::Respond(resp)).ok();
+                        }
+                    }
+                    if answered {
+                        return self.handle_bye(tx).await;
+                    }
+                    info!("bye before dialog established {}", tx.original.uri);
+                    tx.reply(rsip::StatusCode::CallTransactionDoesNotExist)
+                        .await?;
+                    return Ok(());
+                }
                 _ => {}
             }
         }
@@ -581,6 +1177,7 @@ impl ServerInviteDialog {
 
     async fn handle_bye(&mut self, mut tx: Transaction) -> Result<()> {
         info!("received bye {}", tx.original.uri);
+        *self.inner.hangup_cause.lock().unwrap() = Q850Cause::from_request(&tx.original);
         self.inner
             .transition(DialogState::Terminated(self.id(), TerminatedReason::UacBye))?;
         tx.reply(rsip::StatusCode::OK).await?;
@@ -595,20 +1192,24 @@ impl ServerInviteDialog {
         Ok(())
     }
 
-    async fn handle_options(&mut self, mut tx: Transaction) -> Result<()> {
+    async fn handle_options(&mut self, tx: Transaction) -> Result<()> {
         info!("received options {}", tx.original.uri);
-        self.inner
-            .transition(DialogState::Options(self.id(), tx.original.clone()))?;
-        tx.reply(rsip::StatusCode::OK).await?;
-        Ok(())
+        self.inner.handle_options(tx).await
     }
 
-    async fn handle_update(&mut self, mut tx: Transaction) -> Result<()> {
+    async fn handle_update(&mut self, tx: Transaction) -> Result<()> {
         info!("received update {}", tx.original.uri);
-        self.inner
-            .transition(DialogState::Updated(self.id(), tx.original.clone()))?;
-        tx.reply(rsip::StatusCode::OK).await?;
-        Ok(())
+        self.inner.handle_update(tx).await
+    }
+
+    async fn handle_refer(&mut self, tx: Transaction) -> Result<()> {
+        info!("received refer {}", tx.original.uri);
+        self.inner.handle_refer(tx).await
+    }
+
+    async fn handle_notify(&mut self, tx: Transaction) -> Result<()> {
+        info!("received notify {}", tx.original.uri);
+        self.inner.handle_notify(tx).await
     }
 
     async fn handle_invite(&mut self, mut tx: Transaction) -> Result<()> {
@@ -617,14 +1218,74 @@ impl ServerInviteDialog {
             .lock()
             .unwrap()
             .replace(tx.tu_sender.clone());
+        self.inner.pending_invite.store(true, Ordering::Relaxed);
 
         let handle_loop = async {
+            // The Expires header of an initial INVITE bounds how long it may ring
+            let mut setup_timeout = None;
+            if let Err(min_se) = self.inner.negotiate_session_timer(&tx.original) {
+                let resp = self.inner.make_response(
+                    &tx.original,
+                    StatusCode::SessionIntervalTooSmall,
+                    Some(vec![Header::Other("Min-SE".into(), min_se.to_string())]),
+                    None,
+                );
+                tx.respond(resp).await?;
+                if !self.inner.is_confirmed() {
+                    self.inner.transition(DialogState::Terminated(
+                        self.id(),
+                        TerminatedReason::UasOther(Some(StatusCode::SessionIntervalTooSmall)),
+                    ))?;
+                }
+                return Ok(());
+            }
             if !self.inner.is_confirmed() {
                 self.inner.transition(DialogState::Calling(self.id()))?;
-                tx.send_trying().await?;
+                if tx.last_response.is_none() {
+                    tx.send_trying().await?;
+                }
+                setup_timeout = tx
+                    .original
+                    .expires_header()
+                    .and_then(|e| e.seconds().ok())
+                    .map(|secs| Duration::from_secs(secs as u64));
             }
+            let setup_expired = async {
+                match setup_timeout {
+                    Some(timeout) => tokio::time::sleep(timeout).await,
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::pin!(setup_expired);
 
-            while let Some(msg) = tx.receive().await {
+            loop {
+                let msg = tokio::select! {
+                    msg = tx.receive() => match msg {
+                        Some(msg) => msg,
+                        None => break,
+                    },
+                    _ = &mut setup_expired, if setup_timeout.is_some() => {
+                        setup_timeout = None;
+                        let answered = tx.last_response.as_ref().is_some_and(|r| {
+                            r.status_code.kind() != rsip::StatusCodeKind::Provisional
+                        }) || matches!(
+                            *self.inner.state.lock().unwrap(),
+                            DialogState::WaitAck(_, _)
+                                | DialogState::Confirmed(_)
+                                | DialogState::Terminated(_, _)
+                        );
+                        if answered {
+                            continue;
+                        }
+                        info!("INVITE expired before it was answered {}", tx.original.uri);
+                        tx.reply(rsip::StatusCode::RequestTerminated).await?;
+                        self.inner.transition(DialogState::Terminated(
+                            self.id(),
+                            TerminatedReason::Timeout,
+                        ))?;
+                        continue;
+                    }
+                };
                 match msg {
                     SipMessage::Request(req) => match req.method {
                         rsip::Method::Ack => {
@@ -646,7 +1307,9 @@ impl ServerInviteDialog {
             }
             Ok::<(), crate::Error>(())
         };
-        match handle_loop.await {
+        let result = handle_loop.await;
+        self.inner.pending_invite.store(false, Ordering::Relaxed);
+        match result {
             Ok(_) => {
                 trace!("process done");
                 self.inner.tu_sender.lock().unwrap().take();
@@ -1,3 +1,5 @@
+mod test_authenticate;
 mod test_client_dialog;
 mod test_dialog_layer;
 mod test_dialog_states;
+mod test_registration;
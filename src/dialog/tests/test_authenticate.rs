@@ -0,0 +1,206 @@
+//! Authentication tests
+//!
+//! Tests for digest challenge parsing and response computation
+
+use crate::dialog::authenticate::{
+    compute_response, verify_authorization, Credential, DigestChallenge, NonceCounts,
+};
+use rsip::headers::auth::{Algorithm, AuthQop, Qop};
+use rsip::{headers::*, Response, StatusCode};
+
+#[test]
+fn test_parse_asterisk_challenge() -> crate::Result<()> {
+    let challenge =
+        DigestChallenge::parse(r#"Digest algorithm=MD5, realm="asterisk", nonce="1c0a3f5b""#)?;
+    assert_eq!(challenge.realm, "asterisk");
+    assert_eq!(challenge.nonce, "1c0a3f5b");
+    assert_eq!(challenge.algorithm, Some(Algorithm::Md5));
+    assert_eq!(challenge.qop, None);
+    assert_eq!(challenge.opaque, None);
+    assert!(!challenge.stale);
+    Ok(())
+}
+
+#[test]
+fn test_parse_freeswitch_challenge() -> crate::Result<()> {
+    let challenge = DigestChallenge::parse(
+        r#"Digest realm="10.0.0.1", nonce="e0e7c5a8-1bd4-4b1b-9e2c-8c5b8a4bb4f0", algorithm=MD5, qop="auth""#,
+    )?;
+    assert_eq!(challenge.realm, "10.0.0.1");
+    assert_eq!(challenge.nonce, "e0e7c5a8-1bd4-4b1b-9e2c-8c5b8a4bb4f0");
+    assert_eq!(challenge.qop, Some(Qop::Auth));
+    Ok(())
+}
+
+#[test]
+fn test_parse_kamailio_stale_challenge() -> crate::Result<()> {
+    let challenge = DigestChallenge::parse(
+        r#"Digest realm="example.com", nonce="XkZ3Rl5GRkVP1bmtZQ7Ie2m0NGhl+9K2", qop="auth-int,auth", opaque="5ccc069c403ebaf9f0171e9517f40e41", stale=TRUE"#,
+    )?;
+    assert_eq!(challenge.realm, "example.com");
+    assert_eq!(challenge.nonce, "XkZ3Rl5GRkVP1bmtZQ7Ie2m0NGhl+9K2");
+    // auth is preferred even when listed second
+    assert_eq!(challenge.qop, Some(Qop::Auth));
+    assert_eq!(
+        challenge.opaque.as_deref(),
+        Some("5ccc069c403ebaf9f0171e9517f40e41")
+    );
+    assert!(challenge.stale);
+    assert_eq!(challenge.algorithm, None);
+    Ok(())
+}
+
+#[test]
+fn test_parse_invalid_challenges() {
+    assert!(DigestChallenge::parse(r#"Basic realm="example.com""#).is_err());
+    assert!(DigestChallenge::parse(r#"Digest nonce="abc""#).is_err());
+    assert!(DigestChallenge::parse(r#"Digest realm="example.com""#).is_err());
+}
+
+#[test]
+fn test_challenge_from_response() -> crate::Result<()> {
+    let resp = Response {
+        status_code: StatusCode::ProxyAuthenticationRequired,
+        version: rsip::Version::V2,
+        headers: vec![ProxyAuthenticate::new(
+            r#"Digest realm="proxy.example.com", nonce="4b1d", qop="auth""#,
+        )
+        .into()]
+        .into(),
+        body: vec![],
+    };
+    let (challenge, is_proxy) = DigestChallenge::from_response(&resp)?;
+    assert!(is_proxy);
+    assert_eq!(challenge.realm, "proxy.example.com");
+
+    let resp = Response {
+        status_code: StatusCode::Unauthorized,
+        version: rsip::Version::V2,
+        headers: vec![WwwAuthenticate::new(r#"Digest realm="example.com", nonce="4b1d""#).into()]
+            .into(),
+        body: vec![],
+    };
+    let (_, is_proxy) = DigestChallenge::from_response(&resp)?;
+    assert!(!is_proxy);
+    Ok(())
+}
+
+#[test]
+fn test_compute_response() -> crate::Result<()> {
+    // RFC 2069 style, no qop
+    let challenge =
+        DigestChallenge::parse(r#"Digest algorithm=MD5, realm="asterisk", nonce="1c0a3f5b""#)?;
+    let cred = Credential {
+        username: "alice".to_string(),
+        password: "secret123".to_string(),
+        realm: None,
+    };
+    let uri = rsip::Uri::try_from("sip:pbx.example.com")?;
+    let response = compute_response(&challenge, &cred, &rsip::Method::Register, &uri, None);
+    assert_eq!(response, "23344e3f14e57bbc117072404cd5833a");
+
+    // qop=auth with cnonce and nonce count
+    let challenge = DigestChallenge::parse(
+        r#"Digest realm="example.com", nonce="84f1c1ae6cbe5ua4", qop="auth""#,
+    )?;
+    let cred = Credential {
+        username: "bob".to_string(),
+        password: "hunter2".to_string(),
+        realm: None,
+    };
+    let uri = rsip::Uri::try_from("sip:carol@example.com")?;
+    let qop = AuthQop::Auth {
+        cnonce: "0a4f113b".to_string(),
+        nc: 1,
+    };
+    let response = compute_response(&challenge, &cred, &rsip::Method::Invite, &uri, Some(&qop));
+    assert_eq!(response, "bebe42621296356449405299f450a9bf");
+    Ok(())
+}
+
+fn signed_request(
+    challenge: &DigestChallenge,
+    cred: &Credential,
+    uri: &str,
+    nc: u8,
+) -> rsip::Request {
+    let uri = rsip::Uri::try_from(uri).unwrap();
+    let qop = AuthQop::Auth {
+        cnonce: "0a4f113b".to_string(),
+        nc,
+    };
+    let response = compute_response(challenge, cred, &rsip::Method::Invite, &uri, Some(&qop));
+    let auth = rsip::typed::Authorization {
+        scheme: rsip::headers::auth::Scheme::Digest,
+        username: cred.username.clone(),
+        realm: challenge.realm.clone(),
+        nonce: challenge.nonce.clone(),
+        uri,
+        response,
+        algorithm: Some(Algorithm::Md5),
+        opaque: None,
+        qop: Some(qop),
+    };
+    rsip::Request {
+        method: rsip::Method::Invite,
+        uri: rsip::Uri::try_from("sip:bob@example.com").unwrap(),
+        headers: vec![CallId::new("verify-call").into(), auth.into()].into(),
+        version: rsip::Version::V2,
+        body: vec![],
+    }
+}
+
+#[test]
+fn test_verify_authorization_uri_and_nonce_count() -> crate::Result<()> {
+    let cred = Credential {
+        username: "alice".to_string(),
+        password: "secret123".to_string(),
+        realm: None,
+    };
+    let unsigned = signed_request(
+        &DigestChallenge::parse(r#"Digest realm="example.com", nonce="x""#)?,
+        &cred,
+        "sip:bob@example.com",
+        1,
+    );
+    let challenge = DigestChallenge::for_request(&unsigned, "example.com")?;
+    let counts = NonceCounts::default();
+
+    // the digest must be computed for the Request-URI
+    let other_uri = signed_request(&challenge, &cred, "sip:carol@example.com", 1);
+    assert!(!verify_authorization(
+        &other_uri,
+        "example.com",
+        &cred,
+        &counts
+    ));
+
+    let first = signed_request(&challenge, &cred, "sip:bob@example.com", 1);
+    assert!(verify_authorization(&first, "example.com", &cred, &counts));
+    // a replay with the same nonce count is rejected
+    assert!(!verify_authorization(&first, "example.com", &cred, &counts));
+
+    let second = signed_request(&challenge, &cred, "sip:bob@example.com", 2);
+    assert!(verify_authorization(&second, "example.com", &cred, &counts));
+    assert!(!verify_authorization(&first, "example.com", &cred, &counts));
+    Ok(())
+}
+
+#[test]
+fn test_nonce_counts_are_bounded() {
+    use crate::dialog::authenticate::MAX_NONCE_COUNTS;
+
+    let counts = NonceCounts::default();
+    assert!(counts.is_empty());
+    assert!(counts.accept("first", 1));
+    assert!(!counts.accept("first", 1));
+
+    for i in 0..MAX_NONCE_COUNTS {
+        assert!(counts.accept(&format!("nonce-{}", i), 1));
+    }
+    assert_eq!(counts.len(), MAX_NONCE_COUNTS);
+
+    // the oldest nonce was forgotten to make room
+    assert!(counts.accept("first", 1));
+    assert_eq!(counts.len(), MAX_NONCE_COUNTS);
+}
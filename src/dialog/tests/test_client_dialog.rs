@@ -7,7 +7,10 @@ use crate::dialog::{
     dialog::{DialogInner, DialogState, TerminatedReason},
     DialogId,
 };
-use crate::transaction::{endpoint::EndpointBuilder, key::TransactionRole};
+use crate::transaction::{
+    endpoint::EndpointBuilder,
+    key::{TransactionKey, TransactionRole},
+};
 use crate::transport::TransportLayer;
 use rsip::{headers::*, Request, Response, StatusCode, Uri};
 use std::sync::Arc;
@@ -268,3 +271,1621 @@ async fn test_client_dialog_termination_scenarios() -> crate::Result<()> {
 
     Ok(())
 }
+
+fn create_forked_invite(target: &str, branch: &str) -> Request {
+    Request {
+        method: rsip::Method::Invite,
+        uri: Uri::try_from(format!("sip:bob@{}", target).as_str()).unwrap(),
+        headers: vec![
+            Via::new(&format!("SIP/2.0/UDP 127.0.0.1:5060;branch={}", branch)).into(),
+            CSeq::new("1 INVITE").into(),
+            From::new("Alice <sip:alice@example.com>;tag=alice-tag").into(),
+            To::new("Bob <sip:bob@example.com>").into(),
+            CallId::new("test-call-fork").into(),
+            Contact::new("<sip:alice@alice.example.com:5060>").into(),
+            MaxForwards::new("70").into(),
+        ]
+        .into(),
+        version: rsip::Version::V2,
+        body: vec![],
+    }
+}
+
+fn create_fork_response(invite: &Request, status_code: StatusCode, to_tag: &str) -> Response {
+    let mut headers: Vec<rsip::Header> = invite
+        .headers
+        .iter()
+        .filter(|h| !matches!(h, rsip::Header::To(_)))
+        .cloned()
+        .collect();
+    headers.push(To::new(&format!("Bob <sip:bob@example.com>;tag={}", to_tag)).into());
+    Response {
+        status_code,
+        version: rsip::Version::V2,
+        headers: headers.into(),
+        body: vec![],
+    }
+}
+
+#[tokio::test]
+async fn test_client_dialog_cancel_other_forks() -> crate::Result<()> {
+    use crate::dialog::dialog_layer::DialogLayer;
+    use rsip::prelude::HeadersExt;
+
+    // Late answers of other forks only reach the transaction over UDP, on
+    // reliable transports it ends with the ACK
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    let udp = crate::transport::udp::UdpConnection::create_connection(
+        "127.0.0.1:0".parse().unwrap(),
+        None,
+    )
+    .await?;
+    let alice_addr = udp.get_addr().addr.to_string();
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let peer_addr = peer.local_addr()?.to_string();
+
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+    let opt = crate::dialog::invitation::InviteOption {
+        caller: Uri::try_from(format!("sip:alice@{}", alice_addr).as_str())?,
+        callee: Uri::try_from(format!("sip:bob@{}", peer_addr).as_str())?,
+        contact: Uri::try_from(format!("sip:alice@{}", alice_addr).as_str())?,
+        ..Default::default()
+    };
+    let (state_sender, _) = unbounded_channel();
+    let call = tokio::spawn(async move { dialog_layer.do_invite(opt, state_sender).await });
+
+    let invite = next_udp_request(&peer, rsip::Method::Invite)
+        .await
+        .expect("INVITE");
+    let (bob, alice) = (&peer, alice_addr.as_str());
+    let deliver = move |msg: rsip::SipMessage| async move {
+        bob.send_to(msg.to_string().as_bytes(), alice)
+            .await
+            .unwrap();
+    };
+    let answer = |to_tag: &str| {
+        let mut ok = create_fork_response(&invite, StatusCode::OK, to_tag);
+        ok.headers
+            .unique_push(Contact::new(&format!("<sip:bob@{}>", peer_addr)).into());
+        ok
+    };
+    let to_tag = |req: &Request| {
+        req.to_header()
+            .unwrap()
+            .tag()
+            .unwrap()
+            .map(|tag| tag.value().to_string())
+    };
+
+    // A forking proxy relays two ringing branches on the same INVITE transaction
+    deliver(create_fork_response(&invite, StatusCode::Ringing, "fork-a").into()).await;
+    deliver(create_fork_response(&invite, StatusCode::Ringing, "fork-b").into()).await;
+    deliver(answer("fork-a").into()).await;
+
+    let ack = next_udp_request(&peer, rsip::Method::Ack)
+        .await
+        .expect("ACK");
+    assert_eq!(to_tag(&ack).as_deref(), Some("fork-a"));
+    let (dialog, _) = call.await.unwrap()?;
+    assert!(dialog.inner.is_confirmed());
+
+    // The still ringing fork is cancelled through the shared INVITE
+    let cancel = next_udp_request(&peer, rsip::Method::Cancel)
+        .await
+        .expect("CANCEL");
+    assert_eq!(
+        cancel.via_header()?.value().to_string(),
+        invite.via_header()?.value().to_string()
+    );
+    deliver(
+        Response {
+            status_code: StatusCode::OK,
+            version: rsip::Version::V2,
+            headers: cancel.headers.clone(),
+            body: vec![],
+        }
+        .into(),
+    )
+    .await;
+
+    // The other fork answers anyway: it is acknowledged and hung up
+    deliver(answer("fork-b").into()).await;
+    let late_ack = next_udp_request(&peer, rsip::Method::Ack)
+        .await
+        .expect("late ACK");
+    assert_eq!(to_tag(&late_ack).as_deref(), Some("fork-b"));
+    let bye = next_udp_request(&peer, rsip::Method::Bye)
+        .await
+        .expect("BYE");
+    assert_eq!(to_tag(&bye).as_deref(), Some("fork-b"));
+
+    assert!(dialog.inner.is_confirmed());
+    let branches = dialog.early_branches();
+    assert_eq!(branches.len(), 1);
+    assert_eq!(branches[0].to_tag, "fork-a");
+    token.cancel();
+    Ok(())
+}
+
+/// Next request of `method` a UDP peer receives, skipping retransmissions
+/// of earlier requests
+async fn next_udp_request(peer: &tokio::net::UdpSocket, method: rsip::Method) -> Option<Request> {
+    let mut buf = vec![0u8; 4096];
+    loop {
+        let (len, _) =
+            tokio::time::timeout(std::time::Duration::from_secs(2), peer.recv_from(&mut buf))
+                .await
+                .ok()?
+                .ok()?;
+        match rsip::SipMessage::try_from(std::str::from_utf8(&buf[..len]).ok()?) {
+            Ok(rsip::SipMessage::Request(req)) if req.method == method => return Some(req),
+            _ => continue,
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_client_dialog_request_transaction_terminated() -> crate::Result<()> {
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    let udp = crate::transport::udp::UdpConnection::create_connection(
+        "127.0.0.1:0".parse().unwrap(),
+        None,
+    )
+    .await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .build();
+
+    let peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let peer_addr = peer.local_addr()?.to_string();
+
+    let dialog_id = DialogId {
+        call_id: "test-call-fork".to_string(),
+        from_tag: "alice-tag".to_string(),
+        to_tag: "bob-tag".to_string(),
+    };
+    let (state_sender, _) = unbounded_channel();
+    let dialog_inner = DialogInner::new(
+        TransactionRole::Client,
+        dialog_id.clone(),
+        create_forked_invite(&peer_addr, "z9hG4bKterm"),
+        endpoint.inner.clone(),
+        state_sender,
+        None,
+        Some(Uri::try_from("sip:alice@alice.example.com:5060").unwrap()),
+    )?;
+    dialog_inner.transition(DialogState::Confirmed(dialog_id))?;
+    let client_dialog = ClientInviteDialog {
+        inner: Arc::new(dialog_inner),
+    };
+
+    let dialog = client_dialog.clone();
+    let info = tokio::spawn(async move { dialog.info(None, None).await });
+
+    let mut buf = vec![0u8; 2048];
+    let (len, _) =
+        tokio::time::timeout(std::time::Duration::from_secs(5), peer.recv_from(&mut buf))
+            .await
+            .expect("INFO was not sent")?;
+    let msg = rsip::SipMessage::try_from(std::str::from_utf8(&buf[..len]).unwrap())?;
+    let request = match msg {
+        rsip::SipMessage::Request(req) => req,
+        _ => panic!("expected a request"),
+    };
+    let key = TransactionKey::from_request(&request, TransactionRole::Client)?;
+
+    // Terminate the transaction before any response arrives
+    let tu_sender = endpoint
+        .inner
+        .transactions
+        .lock()
+        .unwrap()
+        .get(&key)
+        .cloned()
+        .expect("transaction is attached");
+    tu_sender
+        .send(crate::transaction::transaction::TransactionEvent::Terminate)
+        .unwrap();
+
+    let result = info.await.unwrap();
+    assert_eq!(result, Err(crate::Error::TransactionTerminated(key)));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_dialog_reinvite_forced_tcp() -> crate::Result<()> {
+    use rsip::prelude::{HeadersExt, ToTypedHeader};
+    use tokio::io::AsyncReadExt;
+
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    let udp = crate::transport::udp::UdpConnection::create_connection(
+        "127.0.0.1:0".parse().unwrap(),
+        None,
+    )
+    .await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .build();
+
+    // The peer listens on the same port over TCP and UDP
+    let tcp_peer = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let peer_addr = tcp_peer.local_addr()?;
+    let udp_peer = tokio::net::UdpSocket::bind(peer_addr).await?;
+
+    let dialog_id = DialogId {
+        call_id: "test-call-fork".to_string(),
+        from_tag: "alice-tag".to_string(),
+        to_tag: "bob-tag".to_string(),
+    };
+    let (state_sender, _) = unbounded_channel();
+    let dialog_inner = DialogInner::new(
+        TransactionRole::Client,
+        dialog_id.clone(),
+        create_forked_invite(&peer_addr.to_string(), "z9hG4bKtcp"),
+        endpoint.inner.clone(),
+        state_sender,
+        None,
+        Some(Uri::try_from("sip:alice@alice.example.com:5060").unwrap()),
+    )?;
+    dialog_inner.transition(DialogState::Confirmed(dialog_id))?;
+    let client_dialog = ClientInviteDialog {
+        inner: Arc::new(dialog_inner),
+    };
+
+    let dialog = client_dialog.clone();
+    tokio::spawn(async move {
+        dialog
+            .reinvite_with_transport(None, None, Some(rsip::Transport::Tcp))
+            .await
+    });
+
+    let (mut stream, _) =
+        tokio::time::timeout(std::time::Duration::from_secs(5), tcp_peer.accept())
+            .await
+            .expect("re-INVITE did not dial TCP")?;
+    let mut data = Vec::new();
+    let mut buf = vec![0u8; 2048];
+    while !data.ends_with(b"\r\n\r\n") {
+        let len = tokio::time::timeout(std::time::Duration::from_secs(5), stream.read(&mut buf))
+            .await
+            .expect("re-INVITE was not sent over TCP")?;
+        assert!(len > 0, "TCP stream closed");
+        data.extend_from_slice(&buf[..len]);
+    }
+    let reinvite = match rsip::SipMessage::try_from(std::str::from_utf8(&data).unwrap())? {
+        rsip::SipMessage::Request(req) => req,
+        _ => panic!("expected a request"),
+    };
+    assert_eq!(reinvite.method, rsip::Method::Invite);
+    assert_eq!(
+        reinvite.via_header()?.typed()?.transport,
+        rsip::Transport::Tcp
+    );
+
+    // The dialog default is untouched: the next request goes over UDP
+    let dialog = client_dialog.clone();
+    tokio::spawn(async move { dialog.info(None, None).await });
+
+    let (len, _) = tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        udp_peer.recv_from(&mut buf),
+    )
+    .await
+    .expect("INFO was not sent over UDP")?;
+    let info = match rsip::SipMessage::try_from(std::str::from_utf8(&buf[..len]).unwrap())? {
+        rsip::SipMessage::Request(req) => req,
+        _ => panic!("expected a request"),
+    };
+    assert_eq!(info.method, rsip::Method::Info);
+    assert_eq!(info.via_header()?.typed()?.transport, rsip::Transport::Udp);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_dialog_discards_mismatched_to_tag() -> crate::Result<()> {
+    use crate::transaction::transaction::TransactionEvent;
+
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    let udp = crate::transport::udp::UdpConnection::create_connection(
+        "127.0.0.1:0".parse().unwrap(),
+        None,
+    )
+    .await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .build();
+
+    let peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let peer_addr = peer.local_addr()?.to_string();
+
+    let dialog_id = DialogId {
+        call_id: "test-call-fork".to_string(),
+        from_tag: "alice-tag".to_string(),
+        to_tag: "bob-tag".to_string(),
+    };
+    let (state_sender, mut state_receiver) = unbounded_channel();
+    let dialog_inner = DialogInner::new(
+        TransactionRole::Client,
+        dialog_id.clone(),
+        create_forked_invite(&peer_addr, "z9hG4bKtotag"),
+        endpoint.inner.clone(),
+        state_sender,
+        None,
+        Some(Uri::try_from("sip:alice@alice.example.com:5060").unwrap()),
+    )?;
+    dialog_inner.transition(DialogState::Confirmed(dialog_id.clone()))?;
+    dialog_inner.update_remote_tag("bob-tag")?;
+    let client_dialog = ClientInviteDialog {
+        inner: Arc::new(dialog_inner),
+    };
+    while state_receiver.try_recv().is_ok() {}
+
+    let dialog = client_dialog.clone();
+    let info = tokio::spawn(async move { dialog.info(None, None).await });
+
+    let mut buf = vec![0u8; 2048];
+    let (len, _) =
+        tokio::time::timeout(std::time::Duration::from_secs(5), peer.recv_from(&mut buf))
+            .await
+            .expect("INFO was not sent")?;
+    let request = match rsip::SipMessage::try_from(std::str::from_utf8(&buf[..len]).unwrap())? {
+        rsip::SipMessage::Request(req) => req,
+        _ => panic!("expected a request"),
+    };
+    let key = TransactionKey::from_request(&request, TransactionRole::Client)?;
+    let tu_sender = endpoint
+        .inner
+        .transactions
+        .lock()
+        .unwrap()
+        .get(&key)
+        .cloned()
+        .expect("transaction is attached");
+
+    // Responses carrying someone else's To tag must not reach the dialog
+    for status in [StatusCode::Ringing, StatusCode::OK] {
+        let resp = create_fork_response(&request, status, "mallory-tag");
+        tu_sender
+            .send(TransactionEvent::Received(
+                rsip::SipMessage::Response(resp),
+                None,
+            ))
+            .unwrap();
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    tu_sender.send(TransactionEvent::Terminate).ok();
+
+    let result = info.await.unwrap();
+    assert_eq!(result, Err(crate::Error::TransactionTerminated(key)));
+    assert!(client_dialog.inner.is_confirmed());
+    assert!(state_receiver.try_recv().is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_dialog_reacks_retransmitted_2xx() -> crate::Result<()> {
+    use crate::dialog::dialog_layer::DialogLayer;
+    use crate::transport::{channel::ChannelConnection, SipAddr, TransportEvent};
+
+    let alice_addr = SipAddr::new(
+        rsip::Transport::Udp,
+        rsip::HostWithPort::try_from("127.0.0.1:15160")?,
+    );
+    let bob_addr = SipAddr::new(
+        rsip::Transport::Udp,
+        rsip::HostWithPort::try_from("127.0.0.1:15170")?,
+    );
+    let (incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, alice_addr)
+            .await?
+            .into();
+
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    tl.add_transport(conn.clone());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+    let opt = crate::dialog::invitation::InviteOption {
+        caller: Uri::try_from("sip:alice@127.0.0.1:15160")?,
+        callee: Uri::try_from("sip:bob@127.0.0.1:15170")?,
+        contact: Uri::try_from("sip:alice@127.0.0.1:15160")?,
+        ..Default::default()
+    };
+    let (state_sender, _) = unbounded_channel();
+    let call = tokio::spawn(async move { dialog_layer.do_invite(opt, state_sender).await });
+
+    let invite = next_request(&mut outgoing_rx).await.expect("INVITE");
+    assert_eq!(invite.method, rsip::Method::Invite);
+    let mut ok = create_fork_response(&invite, StatusCode::OK, "bob-tag");
+    ok.headers
+        .unique_push(Contact::new("<sip:bob@127.0.0.1:15170>").into());
+    let deliver = |resp: Response| {
+        incoming_tx
+            .send(TransportEvent::Incoming(
+                resp.into(),
+                conn.clone(),
+                bob_addr.clone(),
+            ))
+            .unwrap()
+    };
+
+    deliver(ok.clone());
+    let ack = next_request(&mut outgoing_rx).await.expect("ACK");
+    assert_eq!(ack.method, rsip::Method::Ack);
+    let (dialog, _) = call.await.unwrap()?;
+    assert!(dialog.inner.is_confirmed());
+
+    // The UAS did not see the ACK and retransmits its 2xx
+    deliver(ok);
+    let re_ack = next_request(&mut outgoing_rx)
+        .await
+        .expect("ACK was not re-sent");
+    assert_eq!(re_ack.method, rsip::Method::Ack);
+    assert_eq!(re_ack.to_string(), ack.to_string());
+    assert!(dialog.inner.is_confirmed());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_finished_invite_reacks_only_its_dialog() -> crate::Result<()> {
+    use crate::transport::{channel::ChannelConnection, SipAddr, TransportEvent};
+
+    let alice_addr = SipAddr::new(
+        rsip::Transport::Udp,
+        rsip::HostWithPort::try_from("127.0.0.1:15360")?,
+    );
+    let bob_addr = SipAddr::new(
+        rsip::Transport::Udp,
+        rsip::HostWithPort::try_from("127.0.0.1:15370")?,
+    );
+    let (incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, alice_addr)
+            .await?
+            .into();
+
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    tl.add_transport(conn.clone());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    // an INVITE answered by fork-a, whose transaction ended after the ACK
+    let invite = create_forked_invite("127.0.0.1:15370", "z9hG4bKreack");
+    let mut ack = invite.clone();
+    ack.method = rsip::Method::Ack;
+    ack.headers.retain(|h| !matches!(h, rsip::Header::To(_)));
+    ack.headers
+        .push(To::new("Bob <sip:bob@example.com>;tag=fork-a").into());
+    let key = TransactionKey::from_request(&invite, TransactionRole::Client)?;
+    endpoint
+        .inner
+        .detach_transaction(&key, Some(ack.clone().into()));
+
+    let deliver = |resp: Response| {
+        incoming_tx
+            .send(TransportEvent::Incoming(
+                resp.into(),
+                conn.clone(),
+                bob_addr.clone(),
+            ))
+            .unwrap()
+    };
+
+    // a 2xx of another fork is not acknowledged by our ACK
+    deliver(create_fork_response(&invite, StatusCode::OK, "fork-b"));
+    assert!(next_request(&mut outgoing_rx).await.is_none());
+
+    deliver(create_fork_response(&invite, StatusCode::OK, "fork-a"));
+    let re_ack = next_request(&mut outgoing_rx)
+        .await
+        .expect("ACK was not re-sent");
+    assert_eq!(re_ack.to_string(), ack.to_string());
+    Ok(())
+}
+
+/// Next request written to a channel connection, if any arrives in time
+async fn next_request(
+    rx: &mut tokio::sync::mpsc::UnboundedReceiver<crate::transport::TransportEvent>,
+) -> Option<Request> {
+    use crate::transport::TransportEvent;
+    loop {
+        match tokio::time::timeout(std::time::Duration::from_secs(2), rx.recv()).await {
+            Ok(Some(TransportEvent::Incoming(rsip::SipMessage::Request(req), _, _))) => {
+                return Some(req)
+            }
+            Ok(Some(_)) => continue,
+            _ => return None,
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_client_dialog_early_media_then_final_answer() -> crate::Result<()> {
+    use crate::dialog::dialog::DialogState;
+    use crate::dialog::dialog_layer::DialogLayer;
+    use crate::transport::{channel::ChannelConnection, SipAddr, TransportEvent};
+
+    let alice_addr = SipAddr::new(
+        rsip::Transport::Udp,
+        rsip::HostWithPort::try_from("127.0.0.1:15180")?,
+    );
+    let bob_addr = SipAddr::new(
+        rsip::Transport::Udp,
+        rsip::HostWithPort::try_from("127.0.0.1:15190")?,
+    );
+    let (incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, alice_addr)
+            .await?
+            .into();
+
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    tl.add_transport(conn.clone());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+    let opt = crate::dialog::invitation::InviteOption {
+        caller: Uri::try_from("sip:alice@127.0.0.1:15180")?,
+        callee: Uri::try_from("sip:bob@127.0.0.1:15190")?,
+        content_type: Some("application/sdp".to_string()),
+        offer: Some(b"v=0\r\ns=offer\r\n".to_vec()),
+        contact: Uri::try_from("sip:alice@127.0.0.1:15180")?,
+        ..Default::default()
+    };
+    let (state_sender, mut state_receiver) = unbounded_channel();
+    let call = tokio::spawn(async move { dialog_layer.do_invite(opt, state_sender).await });
+
+    let invite = next_request(&mut outgoing_rx).await.expect("INVITE");
+    let early_sdp = b"v=0\r\ns=early\r\n".to_vec();
+    let final_sdp = b"v=0\r\ns=final\r\n".to_vec();
+    let mut progress = create_fork_response(&invite, StatusCode::SessionProgress, "bob-tag");
+    progress.body = early_sdp.clone();
+    let mut ok = create_fork_response(&invite, StatusCode::OK, "bob-tag");
+    ok.headers
+        .unique_push(Contact::new("<sip:bob@127.0.0.1:15190>").into());
+    ok.body = final_sdp.clone();
+    for resp in [progress, ok] {
+        incoming_tx
+            .send(TransportEvent::Incoming(
+                resp.into(),
+                conn.clone(),
+                bob_addr.clone(),
+            ))
+            .unwrap();
+    }
+
+    next_request(&mut outgoing_rx).await.expect("ACK");
+    let (dialog, resp) = call.await.unwrap()?;
+    assert_eq!(resp.expect("final response").body, final_sdp);
+    assert_eq!(dialog.early_media_sdp(), Some(early_sdp.clone()));
+    assert_eq!(dialog.remote_sdp(), Some(final_sdp));
+
+    // the early media SDP is delivered before the dialog is confirmed
+    let mut saw_early = false;
+    while let Ok(state) = state_receiver.try_recv() {
+        match state {
+            DialogState::Early(_, resp) => {
+                assert_eq!(resp.body, early_sdp);
+                saw_early = true;
+            }
+            DialogState::Confirmed(_) => {
+                assert!(saw_early, "confirmed before early media was delivered");
+                return Ok(());
+            }
+            _ => {}
+        }
+    }
+    panic!("dialog was never confirmed");
+}
+
+#[tokio::test]
+async fn test_client_dialog_ack_carries_record_route() -> crate::Result<()> {
+    use crate::dialog::dialog_layer::DialogLayer;
+    use crate::transport::{channel::ChannelConnection, SipAddr, TransportEvent};
+    use rsip::prelude::UntypedHeader;
+
+    let alice_addr = SipAddr::new(
+        rsip::Transport::Udp,
+        rsip::HostWithPort::try_from("127.0.0.1:15200")?,
+    );
+    let proxy_addr = SipAddr::new(
+        rsip::Transport::Udp,
+        rsip::HostWithPort::try_from("127.0.0.1:15210")?,
+    );
+    let (incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, alice_addr)
+            .await?
+            .into();
+
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    tl.add_transport(conn.clone());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+    let opt = crate::dialog::invitation::InviteOption {
+        caller: Uri::try_from("sip:alice@127.0.0.1:15200")?,
+        callee: Uri::try_from("sip:bob@127.0.0.1:15210")?,
+        contact: Uri::try_from("sip:alice@127.0.0.1:15200")?,
+        ..Default::default()
+    };
+    let (state_sender, _) = unbounded_channel();
+    let call = tokio::spawn(async move { dialog_layer.do_invite(opt, state_sender).await });
+
+    let invite = next_request(&mut outgoing_rx).await.expect("INVITE");
+    let mut ok = create_fork_response(&invite, StatusCode::OK, "bob-tag");
+    ok.headers
+        .unique_push(Contact::new("<sip:bob@192.0.2.10:5060>").into());
+    // Record-Route as seen by the UAC: the proxy nearest to the UAS comes first
+    ok.headers
+        .push(RecordRoute::new("<sip:p2.example.com;lr>").into());
+    ok.headers
+        .push(RecordRoute::new("<sip:p1.example.com;lr>").into());
+    incoming_tx
+        .send(TransportEvent::Incoming(
+            ok.into(),
+            conn.clone(),
+            proxy_addr,
+        ))
+        .unwrap();
+
+    let ack = next_request(&mut outgoing_rx).await.expect("ACK");
+    assert_eq!(ack.method, rsip::Method::Ack);
+    let routes: Vec<String> = ack
+        .headers
+        .iter()
+        .filter_map(|h| match h {
+            rsip::Header::Route(r) => Some(r.value().to_string()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        routes,
+        vec!["<sip:p1.example.com;lr>", "<sip:p2.example.com;lr>"]
+    );
+
+    let (dialog, _) = call.await.unwrap()?;
+    assert_eq!(dialog.inner.route_set.lock().unwrap().len(), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dialog_initial_request_accessor() -> crate::Result<()> {
+    use crate::dialog::dialog::Dialog;
+
+    let endpoint = create_test_endpoint().await?;
+    let (state_sender, _) = unbounded_channel();
+    let invite_req = create_invite_request("alice-tag", "", "initial-call-id");
+    let dialog_inner = DialogInner::new(
+        TransactionRole::Client,
+        DialogId {
+            call_id: "initial-call-id".to_string(),
+            from_tag: "alice-tag".to_string(),
+            to_tag: "".to_string(),
+        },
+        invite_req.clone(),
+        endpoint.inner.clone(),
+        state_sender,
+        None,
+        Some(Uri::try_from("sip:alice@alice.example.com:5060").unwrap()),
+    )?;
+    let dialog = Dialog::ClientInvite(ClientInviteDialog {
+        inner: Arc::new(dialog_inner),
+    });
+
+    let initial = dialog.initial_request();
+    assert_eq!(initial.method, rsip::Method::Invite);
+    assert_eq!(initial.to_string(), invite_req.to_string());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_dialog_2xx_without_to_tag() -> crate::Result<()> {
+    use crate::dialog::dialog::{DialogState, TerminatedReason};
+    use crate::dialog::dialog_layer::DialogLayer;
+    use crate::transport::{channel::ChannelConnection, SipAddr, TransportEvent};
+    use rsip::prelude::HeadersExt;
+
+    let alice_addr = SipAddr::new(
+        rsip::Transport::Udp,
+        rsip::HostWithPort::try_from("127.0.0.1:15220")?,
+    );
+    let bob_addr = SipAddr::new(
+        rsip::Transport::Udp,
+        rsip::HostWithPort::try_from("127.0.0.1:15230")?,
+    );
+    let (incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, alice_addr)
+            .await?
+            .into();
+
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    tl.add_transport(conn.clone());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let dialog_layer = Arc::new(DialogLayer::new(endpoint.inner.clone()));
+    let opt = crate::dialog::invitation::InviteOption {
+        caller: Uri::try_from("sip:alice@127.0.0.1:15220")?,
+        callee: Uri::try_from("sip:bob@127.0.0.1:15230")?,
+        contact: Uri::try_from("sip:alice@127.0.0.1:15220")?,
+        ..Default::default()
+    };
+    let (state_sender, mut state_receiver) = unbounded_channel();
+    let layer = dialog_layer.clone();
+    let call = tokio::spawn(async move { layer.do_invite(opt, state_sender).await });
+
+    let invite = next_request(&mut outgoing_rx).await.expect("INVITE");
+    let mut ok = create_fork_response(&invite, StatusCode::OK, "unused");
+    // a non-compliant UAS echoes the To header without adding its tag
+    ok.headers.unique_push(invite.to_header()?.clone().into());
+    incoming_tx
+        .send(TransportEvent::Incoming(ok.into(), conn.clone(), bob_addr))
+        .unwrap();
+
+    let ack = next_request(&mut outgoing_rx).await.expect("ACK");
+    assert_eq!(ack.method, rsip::Method::Ack);
+    let result = call.await.expect("do_invite panicked");
+    assert!(
+        matches!(result, Err(crate::Error::DialogError(ref msg, _)) if msg.contains("without To tag"))
+    );
+    assert_eq!(dialog_layer.len(), 0);
+
+    let mut terminated = false;
+    while let Ok(state) = state_receiver.try_recv() {
+        if let DialogState::Terminated(_, reason) = state {
+            assert!(matches!(
+                reason,
+                TerminatedReason::UasOther(Some(StatusCode::OK))
+            ));
+            terminated = true;
+        }
+    }
+    assert!(terminated);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_dialog_terminated_with_warning() -> crate::Result<()> {
+    use crate::dialog::dialog::{DialogState, TerminatedReason};
+    use crate::dialog::dialog_layer::DialogLayer;
+    use crate::transport::{channel::ChannelConnection, SipAddr, TransportEvent};
+
+    let alice_addr = SipAddr::new(
+        rsip::Transport::Udp,
+        rsip::HostWithPort::try_from("127.0.0.1:15240")?,
+    );
+    let bob_addr = SipAddr::new(
+        rsip::Transport::Udp,
+        rsip::HostWithPort::try_from("127.0.0.1:15250")?,
+    );
+    let (incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, alice_addr)
+            .await?
+            .into();
+
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    tl.add_transport(conn.clone());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let dialog_layer = Arc::new(DialogLayer::new(endpoint.inner.clone()));
+    let opt = crate::dialog::invitation::InviteOption {
+        caller: Uri::try_from("sip:alice@127.0.0.1:15240")?,
+        callee: Uri::try_from("sip:bob@127.0.0.1:15250")?,
+        contact: Uri::try_from("sip:alice@127.0.0.1:15240")?,
+        ..Default::default()
+    };
+    let (state_sender, mut state_receiver) = unbounded_channel();
+    let layer = dialog_layer.clone();
+    let call = tokio::spawn(async move { layer.do_invite(opt, state_sender).await });
+
+    let invite = next_request(&mut outgoing_rx).await.expect("INVITE");
+    let dialog = dialog_layer
+        .get_dialog(&DialogId::try_from(&invite)?)
+        .expect("dialog created for the INVITE");
+    let mut decline = create_fork_response(&invite, StatusCode::Decline, "bob-tag");
+    decline
+        .headers
+        .push(Warning::new(r#"399 gw.example.com "Rejected by call screening""#).into());
+    decline
+        .headers
+        .push(ErrorInfo::new("<sip:screening-announcement@gw.example.com>").into());
+    incoming_tx
+        .send(TransportEvent::Incoming(
+            decline.into(),
+            conn.clone(),
+            bob_addr,
+        ))
+        .unwrap();
+
+    assert!(call.await.unwrap().is_err());
+    loop {
+        match tokio::time::timeout(std::time::Duration::from_secs(2), state_receiver.recv())
+            .await
+            .expect("dialog was not terminated")
+        {
+            Some(DialogState::Terminated(_, TerminatedReason::UasOther(status))) => {
+                assert_eq!(status, Some(StatusCode::Decline));
+                break;
+            }
+            Some(_) => continue,
+            None => panic!("state channel closed"),
+        }
+    }
+    assert_eq!(
+        dialog.failure_details().as_deref(),
+        Some("Rejected by call screening; <sip:screening-announcement@gw.example.com>")
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dialog_probe() -> crate::Result<()> {
+    use crate::dialog::dialog::Dialog;
+    use crate::transaction::transaction::TransactionEvent;
+
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    let udp = crate::transport::udp::UdpConnection::create_connection(
+        "127.0.0.1:0".parse().unwrap(),
+        None,
+    )
+    .await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .build();
+
+    let peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let peer_addr = peer.local_addr()?.to_string();
+
+    let dialog_id = DialogId {
+        call_id: "test-call-probe".to_string(),
+        from_tag: "alice-tag".to_string(),
+        to_tag: "bob-tag".to_string(),
+    };
+    let (state_sender, mut state_receiver) = unbounded_channel();
+    let dialog_inner = DialogInner::new(
+        TransactionRole::Client,
+        dialog_id.clone(),
+        create_forked_invite(&peer_addr, "z9hG4bKprobe"),
+        endpoint.inner.clone(),
+        state_sender,
+        None,
+        Some(Uri::try_from("sip:alice@alice.example.com:5060").unwrap()),
+    )?;
+    dialog_inner.transition(DialogState::Confirmed(dialog_id.clone()))?;
+    dialog_inner.update_remote_tag("bob-tag")?;
+    let dialog = Dialog::ClientInvite(ClientInviteDialog {
+        inner: Arc::new(dialog_inner),
+    });
+    while state_receiver.try_recv().is_ok() {}
+
+    for status in [StatusCode::OK, StatusCode::CallTransactionDoesNotExist] {
+        let probing = dialog.clone();
+        let probe = tokio::spawn(async move { probing.probe().await });
+
+        let mut buf = vec![0u8; 2048];
+        let (len, _) =
+            tokio::time::timeout(std::time::Duration::from_secs(5), peer.recv_from(&mut buf))
+                .await
+                .expect("OPTIONS was not sent")?;
+        let request = match rsip::SipMessage::try_from(std::str::from_utf8(&buf[..len]).unwrap())? {
+            rsip::SipMessage::Request(req) => req,
+            _ => panic!("expected a request"),
+        };
+        assert_eq!(request.method, rsip::Method::Options);
+        assert!(request.body.is_empty());
+
+        let key = TransactionKey::from_request(&request, TransactionRole::Client)?;
+        let tu_sender = endpoint
+            .inner
+            .transactions
+            .lock()
+            .unwrap()
+            .get(&key)
+            .cloned()
+            .expect("transaction is attached");
+        let resp = create_fork_response(&request, status.clone(), "bob-tag");
+        tu_sender
+            .send(TransactionEvent::Received(
+                rsip::SipMessage::Response(resp),
+                None,
+            ))
+            .unwrap();
+
+        assert_eq!(probe.await.unwrap()?, status);
+    }
+
+    assert!(dialog.inner().state.lock().unwrap().is_terminated());
+    assert!(matches!(
+        state_receiver.try_recv(),
+        Ok(DialogState::Terminated(
+            _,
+            TerminatedReason::UasOther(Some(StatusCode::CallTransactionDoesNotExist))
+        ))
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bye_challenged_by_proxy() -> crate::Result<()> {
+    use crate::dialog::authenticate::Credential;
+    use crate::transaction::transaction::TransactionEvent;
+
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    let udp = crate::transport::udp::UdpConnection::create_connection(
+        "127.0.0.1:0".parse().unwrap(),
+        None,
+    )
+    .await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .build();
+
+    let peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let peer_addr = peer.local_addr()?.to_string();
+
+    let recv_bye = || async {
+        let mut buf = vec![0u8; 2048];
+        let (len, _) =
+            tokio::time::timeout(std::time::Duration::from_secs(5), peer.recv_from(&mut buf))
+                .await
+                .expect("BYE was not sent")?;
+        match rsip::SipMessage::try_from(std::str::from_utf8(&buf[..len]).unwrap())? {
+            rsip::SipMessage::Request(req) => crate::Result::Ok(req),
+            _ => panic!("expected a request"),
+        }
+    };
+    let reply = |request: &Request, status: StatusCode| {
+        let key = TransactionKey::from_request(request, TransactionRole::Client).unwrap();
+        let tu_sender = endpoint
+            .inner
+            .transactions
+            .lock()
+            .unwrap()
+            .get(&key)
+            .cloned()
+            .expect("transaction is attached");
+        let mut resp = create_fork_response(request, status, "bob-tag");
+        resp.headers
+            .push(WwwAuthenticate::new(r#"Digest realm="example.com", nonce="4b1d""#).into());
+        tu_sender
+            .send(TransactionEvent::Received(
+                rsip::SipMessage::Response(resp),
+                None,
+            ))
+            .unwrap();
+    };
+
+    let credentials = [
+        None,
+        Some(Credential {
+            username: "alice".to_string(),
+            password: "secret".to_string(),
+            realm: None,
+        }),
+    ];
+    for (i, credential) in credentials.into_iter().enumerate() {
+        let with_credential = credential.is_some();
+        let dialog_id = DialogId {
+            call_id: format!("test-call-bye-auth-{}", i),
+            from_tag: "alice-tag".to_string(),
+            to_tag: "bob-tag".to_string(),
+        };
+        let (state_sender, mut state_receiver) = unbounded_channel();
+        let dialog_inner = DialogInner::new(
+            TransactionRole::Client,
+            dialog_id.clone(),
+            create_forked_invite(&peer_addr, &format!("z9hG4bKbyeauth{}", i)),
+            endpoint.inner.clone(),
+            state_sender,
+            credential,
+            Some(Uri::try_from("sip:alice@alice.example.com:5060").unwrap()),
+        )?;
+        dialog_inner.transition(DialogState::Confirmed(dialog_id.clone()))?;
+        dialog_inner.update_remote_tag("bob-tag")?;
+        let dialog = ClientInviteDialog {
+            inner: Arc::new(dialog_inner),
+        };
+        while state_receiver.try_recv().is_ok() {}
+
+        let hanging_up = dialog.clone();
+        let bye = tokio::spawn(async move { hanging_up.bye().await });
+
+        let request = recv_bye().await?;
+        assert_eq!(request.method, rsip::Method::Bye);
+        reply(&request, StatusCode::Unauthorized);
+
+        if with_credential {
+            let retry = recv_bye().await?;
+            assert_eq!(retry.method, rsip::Method::Bye);
+            assert!(retry
+                .headers
+                .iter()
+                .any(|h| matches!(h, rsip::Header::Authorization(_))));
+            reply(&retry, StatusCode::OK);
+        }
+
+        tokio::time::timeout(std::time::Duration::from_secs(5), bye)
+            .await
+            .expect("BYE did not complete")
+            .unwrap()?;
+
+        assert!(dialog.inner.state.lock().unwrap().is_terminated());
+        assert!(matches!(
+            state_receiver.try_recv(),
+            Ok(DialogState::Terminated(_, TerminatedReason::UacBye))
+        ));
+        assert!(state_receiver.try_recv().is_err());
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cancel_pending_invites() -> crate::Result<()> {
+    use crate::dialog::{dialog::Dialog, dialog_layer::DialogLayer};
+    use crate::transaction::transaction::TransactionEvent;
+    use rsip::prelude::{HeadersExt, UntypedHeader};
+
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    let udp = crate::transport::udp::UdpConnection::create_connection(
+        "127.0.0.1:0".parse().unwrap(),
+        None,
+    )
+    .await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .build();
+    let dialog_layer = Arc::new(DialogLayer::new(endpoint.inner.clone()));
+
+    let peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let peer_addr = peer.local_addr()?.to_string();
+
+    let mut dialogs = vec![];
+    for (to_tag, branch) in [
+        ("ringing-tag", "z9hG4bKringing"),
+        ("bob-tag", "z9hG4bKanswered"),
+    ] {
+        let dialog_id = DialogId {
+            call_id: "test-call-fork".to_string(),
+            from_tag: "alice-tag".to_string(),
+            to_tag: to_tag.to_string(),
+        };
+        let invite = create_forked_invite(&peer_addr, branch);
+        let (state_sender, _state_receiver) = unbounded_channel();
+        let dialog_inner = DialogInner::new(
+            TransactionRole::Client,
+            dialog_id.clone(),
+            invite.clone(),
+            endpoint.inner.clone(),
+            state_sender,
+            None,
+            Some(Uri::try_from("sip:alice@alice.example.com:5060").unwrap()),
+        )?;
+        let ringing = create_fork_response(&invite, StatusCode::Ringing, to_tag);
+        dialog_inner.transition(DialogState::Early(dialog_id.clone(), ringing))?;
+        if to_tag == "bob-tag" {
+            dialog_inner.transition(DialogState::Confirmed(dialog_id.clone()))?;
+        }
+        let dialog = ClientInviteDialog {
+            inner: Arc::new(dialog_inner),
+        };
+        dialog_layer.insert_dialog(dialog_id, Dialog::ClientInvite(dialog.clone()))?;
+        dialogs.push(dialog);
+    }
+
+    let layer = dialog_layer.clone();
+    let cancelling = tokio::spawn(async move { layer.cancel_pending_invites().await });
+
+    let mut buf = vec![0u8; 2048];
+    let (len, _) =
+        tokio::time::timeout(std::time::Duration::from_secs(5), peer.recv_from(&mut buf))
+            .await
+            .expect("CANCEL was not sent")?;
+    let request = match rsip::SipMessage::try_from(std::str::from_utf8(&buf[..len]).unwrap())? {
+        rsip::SipMessage::Request(req) => req,
+        _ => panic!("expected a request"),
+    };
+    assert_eq!(request.method, rsip::Method::Cancel);
+    assert!(request
+        .via_header()?
+        .value()
+        .contains("branch=z9hG4bKringing"));
+
+    let key = TransactionKey::from_request(&request, TransactionRole::Client)?;
+    let tu_sender = endpoint
+        .inner
+        .transactions
+        .lock()
+        .unwrap()
+        .get(&key)
+        .cloned()
+        .expect("transaction is attached");
+    tu_sender
+        .send(TransactionEvent::Received(
+            rsip::SipMessage::Response(create_fork_response(
+                &request,
+                StatusCode::OK,
+                "ringing-tag",
+            )),
+            None,
+        ))
+        .unwrap();
+    tokio::time::timeout(std::time::Duration::from_secs(5), cancelling)
+        .await
+        .expect("cancel_pending_invites did not complete")
+        .unwrap()?;
+
+    // the answered call got nothing
+    assert!(tokio::time::timeout(
+        std::time::Duration::from_millis(200),
+        peer.recv_from(&mut buf)
+    )
+    .await
+    .is_err());
+    assert!(dialogs[1].inner.is_confirmed());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_dialog_discards_mismatched_call_id() -> crate::Result<()> {
+    use crate::dialog::dialog_layer::DialogLayer;
+    use crate::transport::{channel::ChannelConnection, SipAddr, TransportEvent};
+
+    let alice_addr = SipAddr::new(
+        rsip::Transport::Udp,
+        rsip::HostWithPort::try_from("127.0.0.1:15260")?,
+    );
+    let bob_addr = SipAddr::new(
+        rsip::Transport::Udp,
+        rsip::HostWithPort::try_from("127.0.0.1:15270")?,
+    );
+    let (incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, alice_addr)
+            .await?
+            .into();
+
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    tl.add_transport(conn.clone());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let dialog_layer = Arc::new(DialogLayer::new(endpoint.inner.clone()));
+    let opt = crate::dialog::invitation::InviteOption {
+        caller: Uri::try_from("sip:alice@127.0.0.1:15260")?,
+        callee: Uri::try_from("sip:bob@127.0.0.1:15270")?,
+        contact: Uri::try_from("sip:alice@127.0.0.1:15260")?,
+        ..Default::default()
+    };
+    let (state_sender, mut state_receiver) = unbounded_channel();
+    let layer = dialog_layer.clone();
+    let call = tokio::spawn(async move { layer.do_invite(opt, state_sender).await });
+
+    let invite = next_request(&mut outgoing_rx).await.expect("INVITE");
+    let mut ok = create_fork_response(&invite, StatusCode::OK, "bob-tag");
+    ok.headers
+        .unique_push(CallId::new("rewritten-by-broken-server").into());
+    incoming_tx
+        .send(TransportEvent::Incoming(ok.into(), conn.clone(), bob_addr))
+        .unwrap();
+
+    // the 200 OK is not acknowledged and does not confirm the dialog
+    assert!(next_request(&mut outgoing_rx).await.is_none());
+    while let Ok(state) = state_receiver.try_recv() {
+        assert!(!state.is_confirmed(), "dialog confirmed by {}", state);
+    }
+    assert!(!call.is_finished());
+    call.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cancel_after_connection_reset() -> crate::Result<()> {
+    use crate::transaction::transaction::Transaction;
+    use rsip::prelude::{HeadersExt, ToTypedHeader};
+    use tokio::io::AsyncReadExt;
+
+    async fn read_request(stream: &mut tokio::net::TcpStream) -> crate::Result<Request> {
+        let mut data = Vec::new();
+        let mut buf = vec![0u8; 2048];
+        while !data.ends_with(b"\r\n\r\n") {
+            let len =
+                tokio::time::timeout(std::time::Duration::from_secs(5), stream.read(&mut buf))
+                    .await
+                    .expect("request was not sent")?;
+            assert!(len > 0, "TCP stream closed");
+            data.extend_from_slice(&buf[..len]);
+        }
+        match rsip::SipMessage::try_from(std::str::from_utf8(&data).unwrap())? {
+            rsip::SipMessage::Request(req) => Ok(req),
+            _ => panic!("expected a request"),
+        }
+    }
+
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .build();
+    let peer = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let peer_addr = peer.local_addr()?;
+
+    let mut invite = create_forked_invite(&format!("{};transport=tcp", peer_addr), "z9hG4bKreset");
+    invite
+        .headers
+        .unique_push(Via::new("SIP/2.0/TCP 127.0.0.1:5060;branch=z9hG4bKreset;rport").into());
+    let dialog_id = DialogId {
+        call_id: "test-call-fork".to_string(),
+        from_tag: "alice-tag".to_string(),
+        to_tag: "".to_string(),
+    };
+    let (state_sender, _) = unbounded_channel();
+    let dialog_inner = DialogInner::new(
+        TransactionRole::Client,
+        dialog_id,
+        invite.clone(),
+        endpoint.inner.clone(),
+        state_sender,
+        None,
+        Some(Uri::try_from("sip:alice@alice.example.com:5060").unwrap()),
+    )?;
+    let client_dialog = ClientInviteDialog {
+        inner: Arc::new(dialog_inner),
+    };
+
+    let key = TransactionKey::from_request(&invite, TransactionRole::Client)?;
+    let mut tx = Transaction::new_client(key, invite.clone(), endpoint.inner.clone(), None);
+    tx.send().await?;
+    let (mut stream, _) = peer.accept().await?;
+    let sent = read_request(&mut stream).await?;
+    assert_eq!(sent.method, rsip::Method::Invite);
+
+    // the peer resets the connection before answering
+    drop(stream);
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    let dialog = client_dialog.clone();
+    tokio::spawn(async move { dialog.cancel().await });
+
+    let (mut stream, _) = tokio::time::timeout(std::time::Duration::from_secs(5), peer.accept())
+        .await
+        .expect("CANCEL did not dial a new connection")?;
+    let cancel = read_request(&mut stream).await?;
+    assert_eq!(cancel.method, rsip::Method::Cancel);
+    assert_eq!(
+        cancel.via_header()?.typed()?.branch(),
+        sent.via_header()?.typed()?.branch()
+    );
+    assert_eq!(
+        cancel
+            .headers
+            .iter()
+            .filter(|h| matches!(h, rsip::Header::Via(_)))
+            .count(),
+        1
+    );
+    let cseq = cancel.cseq_header()?.typed()?;
+    assert_eq!(cseq.method, rsip::Method::Cancel);
+    assert_eq!(cseq.seq, sent.cseq_header()?.seq()?);
+    assert_eq!(cancel.uri, sent.uri);
+    assert_eq!(cancel.call_id_header()?, sent.call_id_header()?);
+    assert!(cancel.contact_header().is_err());
+    token.cancel();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_dialog_session_id() -> crate::Result<()> {
+    use crate::dialog::dialog::SessionId;
+    use crate::dialog::dialog_layer::DialogLayer;
+    use crate::rsip_ext::RsipHeadersExt;
+    use crate::transport::{channel::ChannelConnection, SipAddr, TransportEvent};
+
+    let alice_addr = SipAddr::new(
+        rsip::Transport::Udp,
+        rsip::HostWithPort::try_from("127.0.0.1:15280")?,
+    );
+    let bob_addr = SipAddr::new(
+        rsip::Transport::Udp,
+        rsip::HostWithPort::try_from("127.0.0.1:15290")?,
+    );
+    let (incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, alice_addr)
+            .await?
+            .into();
+
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    tl.add_transport(conn.clone());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let dialog_layer = Arc::new(DialogLayer::new(endpoint.inner.clone()));
+    let opt = crate::dialog::invitation::InviteOption {
+        caller: Uri::try_from("sip:alice@127.0.0.1:15280")?,
+        callee: Uri::try_from("sip:bob@127.0.0.1:15290")?,
+        contact: Uri::try_from("sip:alice@127.0.0.1:15280")?,
+        ..Default::default()
+    };
+    let (state_sender, _state_receiver) = unbounded_channel();
+    let layer = dialog_layer.clone();
+    let call = tokio::spawn(async move { layer.do_invite(opt, state_sender).await });
+
+    let invite = next_request(&mut outgoing_rx).await.expect("INVITE");
+    let sent = invite
+        .headers
+        .get_other("Session-ID")
+        .expect("Session-ID on the INVITE");
+    let local = SessionId::parse(sent).expect("valid Session-ID");
+    assert_eq!(local.local.len(), 32);
+    assert!(local.remote.is_none());
+    assert!(sent.ends_with(";remote=00000000000000000000000000000000"));
+
+    let bob = "47755a9de7794ba387653f2099600ef2";
+    let mut ok = create_fork_response(&invite, StatusCode::OK, "bob-tag");
+    ok.headers.retain(|h| !matches!(h, rsip::Header::Other(..)));
+    ok.headers.push(rsip::Header::Other(
+        "Session-ID".into(),
+        format!("{};remote={}", bob, local.local),
+    ));
+    incoming_tx
+        .send(TransportEvent::Incoming(ok.into(), conn.clone(), bob_addr))
+        .unwrap();
+
+    let (dialog, _) = tokio::time::timeout(std::time::Duration::from_secs(5), call)
+        .await
+        .expect("INVITE completed")
+        .unwrap()?;
+    let session_id = dialog.session_id();
+    assert_eq!(session_id.local, local.local);
+    assert_eq!(session_id.remote.as_deref(), Some(bob));
+
+    // later requests of the dialog carry both halves
+    let ack = next_request(&mut outgoing_rx).await.expect("ACK");
+    assert_eq!(ack.method, rsip::Method::Ack);
+    assert_eq!(
+        ack.headers.get_other("Session-ID"),
+        Some(format!("{};remote={}", local.local, bob).as_str())
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_dialog_route_set_from_2xx_only() -> crate::Result<()> {
+    use crate::dialog::dialog_layer::DialogLayer;
+    use rsip::prelude::{HeadersExt, UntypedHeader};
+
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    let udp = crate::transport::udp::UdpConnection::create_connection(
+        "127.0.0.1:0".parse().unwrap(),
+        None,
+    )
+    .await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let bob = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let bob_addr = bob.local_addr()?.to_string();
+    let proxy = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let proxy_addr = proxy.local_addr()?.to_string();
+
+    async fn recv_request(
+        socket: &tokio::net::UdpSocket,
+    ) -> crate::Result<(Request, std::net::SocketAddr)> {
+        let mut buf = vec![0u8; 4096];
+        let (len, from) =
+            tokio::time::timeout(std::time::Duration::from_secs(5), socket.recv_from(&mut buf))
+                .await
+                .expect("request was not sent")?;
+        Ok((Request::try_from(&buf[..len])?, from))
+    }
+
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+    let opt = crate::dialog::invitation::InviteOption {
+        caller: Uri::try_from("sip:alice@127.0.0.1")?,
+        callee: Uri::try_from(format!("sip:bob@{}", bob_addr))?,
+        contact: Uri::try_from("sip:alice@127.0.0.1")?,
+        ..Default::default()
+    };
+    let (state_sender, _) = unbounded_channel();
+    let call = tokio::spawn(async move { dialog_layer.do_invite(opt, state_sender).await });
+
+    // the INVITE has no Record-Route, only the 200 OK carries one
+    let (invite, from) = recv_request(&bob).await?;
+    assert!(!invite
+        .headers
+        .iter()
+        .any(|h| matches!(h, rsip::Header::RecordRoute(_))));
+    let mut ok = create_fork_response(&invite, StatusCode::OK, "bob-tag");
+    ok.headers
+        .unique_push(Contact::new(format!("<sip:bob@{}>", bob_addr)).into());
+    ok.headers
+        .push(RecordRoute::new(format!("<sip:{};lr>", proxy_addr)).into());
+    ok.headers.unique_push(ContentLength::from(0u32).into());
+    bob.send_to(ok.to_string().as_bytes(), from).await?;
+
+    let (dialog, _) = call.await.unwrap()?;
+    let route_set = dialog.inner.route_set.lock().unwrap().clone();
+    assert_eq!(route_set.len(), 1);
+    assert_eq!(route_set[0].value(), format!("<sip:{};lr>", proxy_addr));
+
+    // the BYE goes to the recorded proxy, addressed to Bob's Contact
+    let hanging_up = dialog.clone();
+    let bye = tokio::spawn(async move { hanging_up.bye().await });
+    let (request, from) = loop {
+        let (request, from) = recv_request(&proxy).await?;
+        if request.method == rsip::Method::Bye {
+            break (request, from);
+        }
+    };
+    assert_eq!(request.uri.to_string(), format!("sip:bob@{}", bob_addr));
+    assert_eq!(
+        request.route_header().map(|r| r.value().to_string()),
+        Some(format!("<sip:{};lr>", proxy_addr))
+    );
+    let resp = create_fork_response(&request, StatusCode::OK, "bob-tag");
+    proxy.send_to(resp.to_string().as_bytes(), from).await?;
+    tokio::time::timeout(std::time::Duration::from_secs(5), bye)
+        .await
+        .expect("BYE did not complete")
+        .unwrap()?;
+    token.cancel();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_invite_preloads_service_route() -> crate::Result<()> {
+    use crate::dialog::dialog_layer::DialogLayer;
+    use rsip::prelude::UntypedHeader;
+
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    let udp = crate::transport::udp::UdpConnection::create_connection(
+        "127.0.0.1:0".parse().unwrap(),
+        None,
+    )
+    .await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let edge = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let edge_addr = edge.local_addr()?.to_string();
+
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+    dialog_layer.set_service_route(vec![
+        Route::new(format!("<sip:{};lr>", edge_addr)),
+        Route::new("<sip:core.example.com;lr>"),
+    ]);
+    let opt = crate::dialog::invitation::InviteOption {
+        caller: Uri::try_from("sip:alice@127.0.0.1")?,
+        callee: Uri::try_from("sip:bob@example.com")?,
+        contact: Uri::try_from("sip:alice@127.0.0.1")?,
+        ..Default::default()
+    };
+    let (state_sender, _) = unbounded_channel();
+    let call = tokio::spawn(async move { dialog_layer.do_invite(opt, state_sender).await });
+
+    // the INVITE goes to the first Route, still addressed to Bob
+    let mut buf = vec![0u8; 4096];
+    let (len, _) =
+        tokio::time::timeout(std::time::Duration::from_secs(5), edge.recv_from(&mut buf))
+            .await
+            .expect("INVITE was not sent to the edge proxy")?;
+    let invite = Request::try_from(&buf[..len])?;
+    assert_eq!(invite.method, rsip::Method::Invite);
+    assert_eq!(invite.uri.to_string(), "sip:bob@example.com");
+    let routes = invite
+        .headers
+        .iter()
+        .filter_map(|h| match h {
+            rsip::Header::Route(route) => Some(route.value().to_string()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        routes,
+        vec![
+            format!("<sip:{};lr>", edge_addr),
+            "<sip:core.example.com;lr>".to_string(),
+        ]
+    );
+    call.abort();
+    token.cancel();
+    Ok(())
+}
@@ -9,7 +9,11 @@ use crate::transaction::{
     transaction::Transaction,
 };
 use crate::transport::{channel::ChannelConnection, SipAddr, TransportLayer};
-use rsip::{headers::*, Request};
+use rsip::{
+    headers::*,
+    prelude::{HeadersExt, UntypedHeader},
+    Request,
+};
 use tokio::sync::mpsc::unbounded_channel;
 use tokio_util::sync::CancellationToken;
 
@@ -399,3 +403,2374 @@ async fn test_dialog_error_cases() -> crate::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_invite_branch_carries_trace_token() -> crate::Result<()> {
+    let endpoint = create_test_endpoint().await?;
+    endpoint
+        .inner
+        .transport_layer
+        .add_transport(create_mock_connection().await?);
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+
+    let opt = crate::dialog::invitation::InviteOption {
+        caller: rsip::Uri::try_from("sip:alice@example.com")?,
+        callee: rsip::Uri::try_from("sip:bob@example.com")?,
+        contact: rsip::Uri::try_from("sip:alice@127.0.0.1:5060")?,
+        branch_token: Some("trace-4bf92f3577b34da6".to_string()),
+        ..Default::default()
+    };
+    let request = dialog_layer.make_invite_request(&opt)?;
+    let branch = request
+        .via_header()?
+        .params()?
+        .into_iter()
+        .find_map(|p| match p {
+            rsip::Param::Branch(b) => Some(b.to_string()),
+            _ => None,
+        })
+        .expect("branch param");
+    assert!(branch.starts_with("z9hG4bK"));
+    assert!(branch.ends_with("-trace-4bf92f3577b34da6"));
+
+    // Two INVITEs with the same token still get distinct branches
+    let other = dialog_layer.make_invite_request(&opt)?;
+    assert_ne!(
+        request.via_header()?.to_string(),
+        other.via_header()?.to_string()
+    );
+    Ok(())
+}
+
+fn create_update_request(call_id: &str, to_tag: &str, body: Vec<u8>) -> Request {
+    Request {
+        method: rsip::Method::Update,
+        uri: rsip::Uri::try_from("sip:bob@bob.example.com:5060").unwrap(),
+        headers: vec![
+            Via::new("SIP/2.0/UDP alice.example.com:5060;branch=z9hG4bKupd").into(),
+            CSeq::new("2 UPDATE").into(),
+            From::new("Alice <sip:alice@example.com>;tag=alice-tag-123").into(),
+            To::new(&format!("Bob <sip:bob@example.com>;tag={}", to_tag)).into(),
+            CallId::new(call_id).into(),
+            Contact::new("<sip:alice@10.0.0.9:5070>").into(),
+            MaxForwards::new("70").into(),
+        ]
+        .into(),
+        version: rsip::Version::V2,
+        body,
+    }
+}
+
+#[tokio::test]
+async fn test_respond_update_with_answer() -> crate::Result<()> {
+    use crate::dialog::dialog::{Dialog, DialogState};
+    use crate::transport::TransportEvent;
+
+    let token = CancellationToken::new();
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(TransportLayer::new(token.child_token()))
+        .with_manual_update(true)
+        .build();
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+
+    let addr = SipAddr::from("127.0.0.1:5060".parse::<std::net::SocketAddr>().unwrap());
+    let (_incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, addr)
+            .await?
+            .into();
+
+    let invite_req = create_invite_request("alice-tag-123", "", "call-id-update", "z9hG4bKinv");
+    let key = TransactionKey::from_request(&invite_req, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, invite_req, endpoint.inner.clone(), Some(conn.clone()));
+    let (state_sender, mut state_receiver) = unbounded_channel();
+    let dialog = dialog_layer.get_or_create_server_invite(
+        &tx,
+        state_sender,
+        None,
+        Some(rsip::Uri::try_from("sip:bob@bob.example.com:5060")?),
+    )?;
+    let id = dialog.id();
+    dialog
+        .inner
+        .transition(DialogState::Confirmed(id.clone()))?;
+
+    let offer = b"v=0\r\no=alice 2890844526 2890844528 IN IP4 10.0.0.9\r\n".to_vec();
+    let update = create_update_request("call-id-update", &id.to_tag, offer.clone());
+    let key = TransactionKey::from_request(&update, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, update.clone(), endpoint.inner.clone(), Some(conn));
+    Dialog::ServerInvite(dialog.clone()).handle(tx).await?;
+
+    // The offer is surfaced to the application instead of being answered blindly
+    let mut surfaced = None;
+    while let Ok(state) = state_receiver.try_recv() {
+        if let DialogState::Updated(_, req) = state {
+            surfaced = Some(req);
+        }
+    }
+    let surfaced = surfaced.expect("UPDATE surfaced as DialogState::Updated");
+    assert_eq!(surfaced.body, offer);
+    assert!(outgoing_rx.try_recv().is_err());
+
+    let answer = b"v=0\r\no=bob 2808844564 2808844565 IN IP4 10.0.0.1\r\n".to_vec();
+    Dialog::ServerInvite(dialog.clone())
+        .respond_update(&surfaced, Some(answer.clone()))
+        .await?;
+
+    let resp = match outgoing_rx.recv().await {
+        Some(TransportEvent::Incoming(rsip::SipMessage::Response(resp), _, _)) => resp,
+        _ => panic!("expected a response to the UPDATE"),
+    };
+    assert_eq!(resp.status_code, rsip::StatusCode::OK);
+    assert_eq!(resp.body, answer);
+    assert!(resp.headers.iter().any(|h| matches!(
+        h,
+        rsip::Header::ContentType(ct) if ct.value() == "application/sdp"
+    )));
+    assert_eq!(
+        dialog.inner.remote_uri.lock().unwrap().to_string(),
+        "sip:alice@10.0.0.9:5070"
+    );
+
+    // Nothing left to answer
+    assert!(Dialog::ServerInvite(dialog)
+        .respond_update(&surfaced, None)
+        .await
+        .is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_update_offer_answered_immediately_by_default() -> crate::Result<()> {
+    use crate::dialog::dialog::{Dialog, DialogState};
+    use crate::transport::TransportEvent;
+
+    let endpoint = create_test_endpoint().await?;
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+
+    let addr = SipAddr::from("127.0.0.1:5060".parse::<std::net::SocketAddr>().unwrap());
+    let (_incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, addr)
+            .await?
+            .into();
+
+    let invite_req =
+        create_invite_request("alice-tag-123", "", "call-id-update-auto", "z9hG4bKinv");
+    let key = TransactionKey::from_request(&invite_req, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, invite_req, endpoint.inner.clone(), Some(conn.clone()));
+    let (state_sender, mut state_receiver) = unbounded_channel();
+    let dialog = dialog_layer.get_or_create_server_invite(
+        &tx,
+        state_sender,
+        None,
+        Some(rsip::Uri::try_from("sip:bob@bob.example.com:5060")?),
+    )?;
+    let id = dialog.id();
+    dialog
+        .inner
+        .transition(DialogState::Confirmed(id.clone()))?;
+
+    let offer = b"v=0\r\no=alice 2890844526 2890844528 IN IP4 10.0.0.9\r\n".to_vec();
+    let update = create_update_request("call-id-update-auto", &id.to_tag, offer.clone());
+    let key = TransactionKey::from_request(&update, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, update.clone(), endpoint.inner.clone(), Some(conn));
+    Dialog::ServerInvite(dialog.clone()).handle(tx).await?;
+
+    // answered without waiting for the application
+    let resp = match outgoing_rx.try_recv() {
+        Ok(TransportEvent::Incoming(rsip::SipMessage::Response(resp), _, _)) => resp,
+        _ => panic!("UPDATE not answered right away"),
+    };
+    assert_eq!(resp.status_code, rsip::StatusCode::OK);
+    let mut surfaced = None;
+    while let Ok(state) = state_receiver.try_recv() {
+        if let DialogState::Updated(_, req) = state {
+            surfaced = Some(req);
+        }
+    }
+    let surfaced = surfaced.expect("UPDATE surfaced as DialogState::Updated");
+    assert_eq!(surfaced.body, offer);
+    assert!(Dialog::ServerInvite(dialog)
+        .respond_update(&surfaced, None)
+        .await
+        .is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_max_dialogs_rejects_second_invite() -> crate::Result<()> {
+    let endpoint = create_test_endpoint().await?;
+    endpoint
+        .inner
+        .transport_layer
+        .add_transport(create_mock_connection().await?);
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+    dialog_layer.set_max_dialogs(1);
+    assert_eq!(dialog_layer.max_dialogs(), Some(1));
+
+    let addr = SipAddr::from("127.0.0.1:5060".parse::<std::net::SocketAddr>().unwrap());
+    let (_incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let mock_conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, addr)
+            .await?
+            .into();
+    let first = create_invite_request("alice-tag-1", "", "call-id-cap-1", "z9hG4bKcap1");
+    let key = TransactionKey::from_request(&first, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, first, endpoint.inner.clone(), Some(mock_conn.clone()));
+    let (state_sender, _) = unbounded_channel();
+    dialog_layer.get_or_create_server_invite(&tx, state_sender, None, None)?;
+    assert_eq!(dialog_layer.len(), 1);
+
+    // Inbound INVITE over the cap is refused with 503 and Retry-After
+    let second = create_invite_request("alice-tag-2", "", "call-id-cap-2", "z9hG4bKcap2");
+    let key = TransactionKey::from_request(&second, TransactionRole::Server)?;
+    let mut tx = Transaction::new_server(key, second, endpoint.inner.clone(), Some(mock_conn));
+    let (state_sender, _) = unbounded_channel();
+    let result = dialog_layer.get_or_create_server_invite(&tx, state_sender, None, None);
+    assert!(matches!(result, Err(crate::Error::DialogError(_, _))));
+    assert_eq!(dialog_layer.len(), 1);
+    tokio::time::timeout(std::time::Duration::from_millis(100), tx.receive())
+        .await
+        .ok();
+    let resp = match outgoing_rx.try_recv() {
+        Ok(crate::transport::TransportEvent::Incoming(rsip::SipMessage::Response(resp), _, _)) => {
+            resp
+        }
+        _ => panic!("no response to the refused INVITE"),
+    };
+    assert_eq!(resp.status_code, rsip::StatusCode::ServiceUnavailable);
+    assert!(resp
+        .headers
+        .iter()
+        .any(|h| matches!(h, rsip::Header::RetryAfter(_))));
+
+    // Outbound INVITE over the cap is refused before anything is sent
+    let opt = crate::dialog::invitation::InviteOption {
+        caller: rsip::Uri::try_from("sip:alice@example.com")?,
+        callee: rsip::Uri::try_from("sip:bob@example.com")?,
+        contact: rsip::Uri::try_from("sip:alice@127.0.0.1:5060")?,
+        ..Default::default()
+    };
+    let (state_sender, _) = unbounded_channel();
+    let result = dialog_layer.do_invite(opt, state_sender).await;
+    assert!(matches!(result, Err(crate::Error::DialogError(_, _))));
+    assert_eq!(dialog_layer.len(), 1);
+
+    // Lifting the cap accepts new dialogs again
+    dialog_layer.set_max_dialogs(0);
+    assert_eq!(dialog_layer.max_dialogs(), None);
+    let (state_sender, _) = unbounded_channel();
+    dialog_layer.get_or_create_server_invite(&tx, state_sender, None, None)?;
+    assert_eq!(dialog_layer.len(), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dialog_id_collision_reported() -> crate::Result<()> {
+    use crate::dialog::dialog::{Dialog, DialogState, TerminatedReason};
+
+    let endpoint = create_test_endpoint().await?;
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+    let mock_conn = create_mock_connection().await?;
+
+    let mut dialogs = vec![];
+    for (tag, call_id, branch) in [
+        ("alice-tag-1", "call-id-collide-1", "z9hG4bKcol1"),
+        ("alice-tag-2", "call-id-collide-2", "z9hG4bKcol2"),
+    ] {
+        let invite = create_invite_request(tag, "", call_id, branch);
+        let key = TransactionKey::from_request(&invite, TransactionRole::Server)?;
+        let tx =
+            Transaction::new_server(key, invite, endpoint.inner.clone(), Some(mock_conn.clone()));
+        let (state_sender, _) = unbounded_channel();
+        dialogs.push(dialog_layer.get_or_create_server_invite(&tx, state_sender, None, None)?);
+    }
+    let (active, other) = (dialogs[0].clone(), dialogs[1].clone());
+
+    // Another dialog under the id of a live one is refused, the live one stays
+    let result = dialog_layer.insert_dialog(active.id(), Dialog::ServerInvite(other.clone()));
+    assert!(
+        matches!(result, Err(crate::Error::DialogError(ref msg, _)) if msg.contains("collision"))
+    );
+    match dialog_layer.get_dialog(&active.id()) {
+        Some(Dialog::ServerInvite(d)) => assert_eq!(d.id(), active.id()),
+        _ => panic!("active dialog was clobbered"),
+    }
+
+    // Re-inserting the same dialog is fine
+    dialog_layer.insert_dialog(active.id(), Dialog::ServerInvite(active.clone()))?;
+
+    // A terminated dialog may be replaced
+    active.inner.transition(DialogState::Terminated(
+        active.id(),
+        TerminatedReason::UacBye,
+    ))?;
+    dialog_layer.insert_dialog(active.id(), Dialog::ServerInvite(other))?;
+    assert_eq!(dialog_layer.len(), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_server_invite_expires_unanswered() -> crate::Result<()> {
+    use crate::dialog::dialog::{Dialog, DialogState, TerminatedReason};
+    use crate::transport::TransportEvent;
+
+    let endpoint = create_test_endpoint().await?;
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+
+    let addr = SipAddr::from("127.0.0.1:5060".parse::<std::net::SocketAddr>().unwrap());
+    let (_incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, addr)
+            .await?
+            .into();
+
+    let mut invite_req =
+        create_invite_request("alice-tag-123", "", "call-id-expires", "z9hG4bKexp");
+    invite_req.headers.push(rsip::Header::Expires(1.into()));
+    let key = TransactionKey::from_request(&invite_req, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, invite_req, endpoint.inner.clone(), Some(conn));
+    let (state_sender, mut state_receiver) = unbounded_channel();
+    let dialog = dialog_layer.get_or_create_server_invite(&tx, state_sender, None, None)?;
+    tokio::spawn(async move { Dialog::ServerInvite(dialog).handle(tx).await });
+
+    // Nobody answers: 100 Trying, then 487 once the INVITE's Expires elapses
+    let mut statuses = vec![];
+    while statuses.last() != Some(&rsip::StatusCode::RequestTerminated) {
+        match tokio::time::timeout(std::time::Duration::from_secs(3), outgoing_rx.recv()).await {
+            Ok(Some(TransportEvent::Incoming(rsip::SipMessage::Response(resp), _, _))) => {
+                statuses.push(resp.status_code)
+            }
+            Ok(_) => continue,
+            Err(_) => panic!("INVITE did not expire, sent {:?}", statuses),
+        }
+    }
+    assert_eq!(statuses.first(), Some(&rsip::StatusCode::Trying));
+
+    let mut terminated = false;
+    while let Ok(state) = state_receiver.try_recv() {
+        if let DialogState::Terminated(_, reason) = state {
+            assert!(matches!(reason, TerminatedReason::Timeout));
+            terminated = true;
+        }
+    }
+    assert!(terminated);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_server_invite_session_interval_too_small() -> crate::Result<()> {
+    use crate::dialog::dialog::{Dialog, DialogState, TerminatedReason};
+    use crate::rsip_ext::RsipHeadersExt;
+    use crate::transport::TransportEvent;
+
+    let endpoint = create_test_endpoint().await?;
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+    dialog_layer.set_min_se(120);
+    assert_eq!(dialog_layer.min_se(), 120);
+
+    let addr = SipAddr::from("127.0.0.1:5060".parse::<std::net::SocketAddr>().unwrap());
+    let (_incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, addr)
+            .await?
+            .into();
+
+    let mut invite_req =
+        create_invite_request("alice-tag-123", "", "call-id-min-se", "z9hG4bKminse");
+    invite_req
+        .headers
+        .push(rsip::Header::Other("Session-Expires".into(), "90".into()));
+    let key = TransactionKey::from_request(&invite_req, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, invite_req, endpoint.inner.clone(), Some(conn));
+    let (state_sender, mut state_receiver) = unbounded_channel();
+    let dialog = dialog_layer.get_or_create_server_invite(&tx, state_sender, None, None)?;
+    tokio::spawn(async move { Dialog::ServerInvite(dialog).handle(tx).await });
+
+    let resp = loop {
+        match tokio::time::timeout(std::time::Duration::from_secs(3), outgoing_rx.recv()).await {
+            Ok(Some(TransportEvent::Incoming(rsip::SipMessage::Response(resp), _, _))) => {
+                break resp
+            }
+            Ok(_) => continue,
+            Err(_) => panic!("no response to INVITE"),
+        }
+    };
+    assert_eq!(resp.status_code, rsip::StatusCode::SessionIntervalTooSmall);
+    assert_eq!(resp.headers.get_other("Min-SE"), Some("120"));
+
+    let reason = loop {
+        match tokio::time::timeout(std::time::Duration::from_secs(1), state_receiver.recv()).await {
+            Ok(Some(DialogState::Terminated(_, reason))) => break reason,
+            Ok(Some(_)) => continue,
+            _ => panic!("dialog not terminated"),
+        }
+    };
+    assert!(matches!(
+        reason,
+        TerminatedReason::UasOther(Some(rsip::StatusCode::SessionIntervalTooSmall))
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_server_contact_follows_inbound_address() -> crate::Result<()> {
+    use crate::dialog::dialog::Dialog;
+    use crate::transport::TransportEvent;
+
+    let endpoint = create_test_endpoint().await?;
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+
+    // Two listen addresses, the INVITE arrives on the TCP one
+    let udp_addr = SipAddr::new(
+        rsip::Transport::Udp,
+        rsip::HostWithPort::try_from("127.0.0.1:5060")?,
+    );
+    let tcp_addr = SipAddr::new(
+        rsip::Transport::Tcp,
+        rsip::HostWithPort::try_from("10.0.0.2:5070")?,
+    );
+    let (_udp_incoming_tx, udp_incoming_rx) = unbounded_channel();
+    let (udp_outgoing_tx, _udp_outgoing_rx) = unbounded_channel();
+    let udp_conn =
+        ChannelConnection::create_connection(udp_incoming_rx, udp_outgoing_tx, udp_addr).await?;
+    let (_tcp_incoming_tx, tcp_incoming_rx) = unbounded_channel();
+    let (tcp_outgoing_tx, mut tcp_outgoing_rx) = unbounded_channel();
+    let tcp_conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(tcp_incoming_rx, tcp_outgoing_tx, tcp_addr)
+            .await?
+            .into();
+    endpoint
+        .inner
+        .transport_layer
+        .add_transport(udp_conn.into());
+    endpoint
+        .inner
+        .transport_layer
+        .add_transport(tcp_conn.clone());
+
+    let invite_req =
+        create_invite_request("alice-tag-123", "", "call-id-contact", "z9hG4bKcontact");
+    let key = TransactionKey::from_request(&invite_req, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, invite_req, endpoint.inner.clone(), Some(tcp_conn));
+    let (state_sender, _state_receiver) = unbounded_channel();
+    let dialog = dialog_layer.get_or_create_server_invite(
+        &tx,
+        state_sender,
+        None,
+        Some(rsip::Uri::try_from("sip:bob@127.0.0.1:5060")?),
+    )?;
+    let mut handler = Dialog::ServerInvite(dialog.clone());
+    tokio::spawn(async move { handler.handle(tx).await });
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    dialog.accept(None, None)?;
+
+    let resp = loop {
+        match tokio::time::timeout(std::time::Duration::from_secs(3), tcp_outgoing_rx.recv()).await
+        {
+            Ok(Some(TransportEvent::Incoming(rsip::SipMessage::Response(resp), _, _)))
+                if resp.status_code == rsip::StatusCode::OK =>
+            {
+                break resp
+            }
+            Ok(_) => continue,
+            Err(_) => panic!("no 200 OK sent"),
+        }
+    };
+    let contact = resp.contact_header()?.uri()?;
+    assert_eq!(contact.host_with_port.to_string(), "10.0.0.2:5070");
+    assert!(contact
+        .params
+        .contains(&rsip::Param::Transport(rsip::Transport::Tcp)));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_server_bye_before_ack() -> crate::Result<()> {
+    use crate::dialog::dialog::{Dialog, DialogState, TerminatedReason};
+    use crate::transport::TransportEvent;
+
+    let endpoint = create_test_endpoint().await?;
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+
+    let addr = SipAddr::from("127.0.0.1:5060".parse::<std::net::SocketAddr>().unwrap());
+    let (_incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, addr)
+            .await?
+            .into();
+
+    let invite_req = create_invite_request("alice-tag-123", "", "call-id-early-bye", "z9hG4bKeb1");
+    let key = TransactionKey::from_request(&invite_req, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, invite_req, endpoint.inner.clone(), Some(conn.clone()));
+    let (state_sender, mut state_receiver) = unbounded_channel();
+    let dialog = dialog_layer.get_or_create_server_invite(&tx, state_sender, None, None)?;
+    let mut handler = Dialog::ServerInvite(dialog.clone());
+    tokio::spawn(async move { handler.handle(tx).await });
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    dialog.accept(None, None)?;
+    assert!(matches!(
+        *dialog.inner.state.lock().unwrap(),
+        DialogState::WaitAck(_, _)
+    ));
+
+    // BYE right after the 200 OK, the ACK never arrives
+    let mut bye_req = create_invite_request(
+        "alice-tag-123",
+        &dialog.id().to_tag,
+        "call-id-early-bye",
+        "z9hG4bKeb2",
+    );
+    bye_req.method = rsip::Method::Bye;
+    bye_req.body.clear();
+    for header in bye_req.headers.iter_mut() {
+        if let rsip::Header::CSeq(cseq) = header {
+            *cseq = CSeq::new("2 BYE");
+        }
+    }
+    let key = TransactionKey::from_request(&bye_req, TransactionRole::Server)?;
+    let bye_tx = Transaction::new_server(key, bye_req, endpoint.inner.clone(), Some(conn));
+    let mut matched = dialog_layer
+        .match_dialog(&bye_tx.original)
+        .expect("BYE matches the dialog");
+    matched.handle(bye_tx).await?;
+
+    let bye_resp = loop {
+        match tokio::time::timeout(std::time::Duration::from_secs(3), outgoing_rx.recv()).await {
+            Ok(Some(TransportEvent::Incoming(rsip::SipMessage::Response(resp), _, _)))
+                if resp.cseq_header()?.method()? == rsip::Method::Bye =>
+            {
+                break resp
+            }
+            Ok(_) => continue,
+            Err(_) => panic!("BYE was not answered"),
+        }
+    };
+    assert_eq!(bye_resp.status_code, rsip::StatusCode::OK);
+
+    let reason = loop {
+        match tokio::time::timeout(std::time::Duration::from_secs(1), state_receiver.recv()).await {
+            Ok(Some(DialogState::Terminated(_, reason))) => break reason,
+            Ok(Some(_)) => continue,
+            _ => panic!("dialog not terminated"),
+        }
+    };
+    assert!(matches!(reason, TerminatedReason::UacBye));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_server_bye_while_ringing() -> crate::Result<()> {
+    use crate::dialog::dialog::{Dialog, DialogState};
+    use crate::transport::TransportEvent;
+
+    let endpoint = create_test_endpoint().await?;
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+
+    let addr = SipAddr::from("127.0.0.1:5060".parse::<std::net::SocketAddr>().unwrap());
+    let (_incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, addr)
+            .await?
+            .into();
+
+    let invite_req =
+        create_invite_request("alice-tag-123", "", "call-id-ringing-bye", "z9hG4bKrb1");
+    let key = TransactionKey::from_request(&invite_req, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, invite_req, endpoint.inner.clone(), Some(conn.clone()));
+    let (state_sender, _state_receiver) = unbounded_channel();
+    let dialog = dialog_layer.get_or_create_server_invite(&tx, state_sender, None, None)?;
+    let mut handler = Dialog::ServerInvite(dialog.clone());
+    tokio::spawn(async move { handler.handle(tx).await });
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    dialog.ringing(None)?;
+    assert!(matches!(
+        *dialog.inner.state.lock().unwrap(),
+        DialogState::Early(_, _)
+    ));
+
+    // the caller hangs up the early dialog with a BYE instead of a CANCEL
+    let mut bye_req = create_invite_request(
+        "alice-tag-123",
+        &dialog.id().to_tag,
+        "call-id-ringing-bye",
+        "z9hG4bKrb2",
+    );
+    bye_req.method = rsip::Method::Bye;
+    bye_req.body.clear();
+    for header in bye_req.headers.iter_mut() {
+        if let rsip::Header::CSeq(cseq) = header {
+            *cseq = CSeq::new("2 BYE");
+        }
+    }
+    let key = TransactionKey::from_request(&bye_req, TransactionRole::Server)?;
+    let bye_tx = Transaction::new_server(key, bye_req, endpoint.inner.clone(), Some(conn));
+    let mut matched = dialog_layer
+        .match_dialog(&bye_tx.original)
+        .expect("BYE matches the dialog");
+    matched.handle(bye_tx).await?;
+
+    let mut invite_status = None;
+    let mut bye_status = None;
+    while invite_status.is_none() || bye_status.is_none() {
+        match tokio::time::timeout(std::time::Duration::from_secs(3), outgoing_rx.recv()).await {
+            Ok(Some(TransportEvent::Incoming(rsip::SipMessage::Response(resp), _, _))) => {
+                match resp.cseq_header()?.method()? {
+                    rsip::Method::Invite
+                        if resp.status_code.kind() != rsip::StatusCodeKind::Provisional =>
+                    {
+                        invite_status = Some(resp.status_code)
+                    }
+                    rsip::Method::Bye => bye_status = Some(resp.status_code),
+                    _ => {}
+                }
+            }
+            Ok(_) => continue,
+            Err(_) => panic!("INVITE or BYE was not answered"),
+        }
+    }
+    assert_eq!(invite_status, Some(rsip::StatusCode::RequestTerminated));
+    assert_eq!(bye_status, Some(rsip::StatusCode::OK));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_invite_user_to_user_roundtrip() -> crate::Result<()> {
+    let endpoint = create_test_endpoint().await?;
+    endpoint
+        .inner
+        .transport_layer
+        .add_transport(create_mock_connection().await?);
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+
+    let uui = vec![0x56, 0xa3, 0x90, 0xf3, 0xd2, 0xb7, 0x31, 0x00, 0x23, 0xa2];
+    let opt = crate::dialog::invitation::InviteOption {
+        caller: rsip::Uri::try_from("sip:alice@example.com")?,
+        callee: rsip::Uri::try_from("sip:bob@example.com")?,
+        contact: rsip::Uri::try_from("sip:alice@127.0.0.1:5060")?,
+        headers: Some(vec![rsip::Header::Other(
+            "X-Queue".into(),
+            "support".into(),
+        )]),
+        user_to_user: Some(("hex".to_string(), uui.clone())),
+        ..Default::default()
+    };
+    let request = dialog_layer.make_invite_request(&opt)?;
+    assert!(request
+        .to_string()
+        .contains("User-to-User: 56a390f3d2b7310023a2;encoding=hex\r\n"));
+    assert!(request.to_string().contains("X-Queue: support\r\n"));
+
+    // Parse it back on the answering side, through the wire format
+    let request = Request::try_from(request.to_string().as_str())?;
+    let key = TransactionKey::from_request(&request, TransactionRole::Server)?;
+    let tx = Transaction::new_server(
+        key,
+        request,
+        endpoint.inner.clone(),
+        Some(create_mock_connection().await?),
+    );
+    let (state_sender, _) = unbounded_channel();
+    let dialog = dialog_layer.get_or_create_server_invite(&tx, state_sender, None, None)?;
+    assert_eq!(dialog.user_to_user(), Some(("hex".to_string(), uui)));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_invite_user_phone() -> crate::Result<()> {
+    use rsip::prelude::ToTypedHeader;
+
+    let endpoint = create_test_endpoint().await?;
+    endpoint
+        .inner
+        .transport_layer
+        .add_transport(create_mock_connection().await?);
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+
+    let mut opt = crate::dialog::invitation::InviteOption {
+        caller: rsip::Uri::try_from("sip:alice@example.com")?,
+        callee: rsip::Uri::try_from("sip:+1-555-123-4567@gw.example.com")?,
+        contact: rsip::Uri::try_from("sip:alice@127.0.0.1:5060")?,
+        ..Default::default()
+    };
+    opt.with_user_phone();
+    let request = dialog_layer.make_invite_request(&opt)?;
+    assert_eq!(
+        request.uri.to_string(),
+        "sip:+15551234567@gw.example.com;user=phone"
+    );
+    let to = request.to_header()?.typed()?;
+    assert_eq!(to.uri, request.uri);
+    assert!(request
+        .to_string()
+        .contains("To: <sip:+15551234567@gw.example.com;user=phone>\r\n"));
+
+    // Survives the wire format
+    let parsed = Request::try_from(request.to_string().as_str())?;
+    assert!(parsed
+        .uri
+        .params
+        .contains(&rsip::Param::User(rsip::param::User::new("phone"))));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_loopback_call_setup_and_teardown() -> crate::Result<()> {
+    use crate::dialog::dialog::{DialogState, TerminatedReason};
+
+    let alice_addr = SipAddr::new(
+        rsip::Transport::Udp,
+        rsip::HostWithPort::try_from("127.0.0.1:15060")?,
+    );
+    let bob_addr = SipAddr::new(
+        rsip::Transport::Udp,
+        rsip::HostWithPort::try_from("127.0.0.1:15070")?,
+    );
+    let (alice_conn, bob_conn) = ChannelConnection::create_pair(alice_addr, bob_addr);
+
+    let make_endpoint = |conn: ChannelConnection, ua: &str| {
+        let tl = TransportLayer::new(CancellationToken::new());
+        tl.add_transport(conn.into());
+        EndpointBuilder::new()
+            .with_user_agent(ua)
+            .with_transport_layer(tl)
+            .build()
+    };
+    let alice = make_endpoint(alice_conn, "alice");
+    let bob = make_endpoint(bob_conn, "bob");
+    let mut bob_incoming = bob.incoming_transactions();
+    for endpoint in [&alice, &bob] {
+        let inner = endpoint.inner.clone();
+        tokio::spawn(async move { inner.serve().await });
+    }
+
+    // Bob creates a dialog for new INVITEs and routes in-dialog requests to it
+    let bob_layer = DialogLayer::new(bob.inner.clone());
+    let (bob_state_sender, mut bob_states) = unbounded_channel();
+    let (bob_dialog_tx, mut bob_dialog_rx) = unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(tx) = bob_incoming.recv().await {
+            if let Some(mut dialog) = bob_layer.match_dialog(&tx.original) {
+                tokio::spawn(async move { dialog.handle(tx).await });
+                continue;
+            }
+            if tx.original.method != rsip::Method::Invite {
+                continue;
+            }
+            let dialog = bob_layer
+                .get_or_create_server_invite(
+                    &tx,
+                    bob_state_sender.clone(),
+                    None,
+                    Some(rsip::Uri::try_from("sip:bob@127.0.0.1:15070").unwrap()),
+                )
+                .expect("server dialog");
+            bob_dialog_tx.send(dialog.clone()).ok();
+            let mut handler = dialog;
+            tokio::spawn(async move { handler.handle(tx).await });
+        }
+    });
+
+    let alice_layer = DialogLayer::new(alice.inner.clone());
+    let opt = crate::dialog::invitation::InviteOption {
+        caller: rsip::Uri::try_from("sip:alice@127.0.0.1:15060")?,
+        callee: rsip::Uri::try_from("sip:bob@127.0.0.1:15070")?,
+        offer: Some(b"v=0\r\n".to_vec()),
+        contact: rsip::Uri::try_from("sip:alice@127.0.0.1:15060")?,
+        ..Default::default()
+    };
+    let (alice_state_sender, _alice_states) = unbounded_channel();
+    let invite = alice_layer.do_invite(opt, alice_state_sender);
+
+    // Bob answers as soon as his dialog has picked up the INVITE
+    let answer = async {
+        let dialog = bob_dialog_rx.recv().await.expect("bob dialog");
+        wait_state(&mut bob_states, |s| matches!(s, DialogState::Calling(_))).await;
+        dialog
+            .accept(None, Some(b"v=0\r\n".to_vec()))
+            .expect("accept");
+        dialog
+    };
+    let (invite_result, bob_dialog) = tokio::join!(invite, answer);
+    let (alice_dialog, resp) = invite_result?;
+    assert_eq!(
+        resp.expect("final response").status_code,
+        rsip::StatusCode::OK
+    );
+    wait_state(&mut bob_states, |s| matches!(s, DialogState::Confirmed(_))).await;
+
+    // Alice hangs up
+    alice_dialog.bye().await?;
+    match wait_state(&mut bob_states, |s| {
+        matches!(s, DialogState::Terminated(_, _))
+    })
+    .await
+    {
+        DialogState::Terminated(id, reason) => {
+            assert_eq!(id, bob_dialog.id());
+            assert!(matches!(reason, TerminatedReason::UacBye));
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+/// Wait for the first dialog state matching `expected`
+async fn wait_state(
+    states: &mut tokio::sync::mpsc::UnboundedReceiver<crate::dialog::dialog::DialogState>,
+    expected: fn(&crate::dialog::dialog::DialogState) -> bool,
+) -> crate::dialog::dialog::DialogState {
+    loop {
+        let state = tokio::time::timeout(std::time::Duration::from_secs(5), states.recv())
+            .await
+            .expect("timed out waiting for dialog state")
+            .expect("dialog state channel closed");
+        if expected(&state) {
+            return state;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_invite_replaces_header() -> crate::Result<()> {
+    use crate::rsip_ext::RsipHeadersExt;
+
+    let endpoint = create_test_endpoint().await?;
+    endpoint
+        .inner
+        .transport_layer
+        .add_transport(create_mock_connection().await?);
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+
+    let replaced = DialogId {
+        call_id: "consult-call@example.com".to_string(),
+        from_tag: "transferor-tag".to_string(),
+        to_tag: "target-tag".to_string(),
+    };
+    let mut opt = crate::dialog::invitation::InviteOption {
+        caller: rsip::Uri::try_from("sip:bob@example.com")?,
+        callee: rsip::Uri::try_from("sip:carol@example.com")?,
+        contact: rsip::Uri::try_from("sip:bob@127.0.0.1:5060")?,
+        headers: Some(vec![rsip::headers::Require::new("100rel").into()]),
+        replaces: Some(replaced),
+        ..Default::default()
+    };
+    let request = dialog_layer.make_invite_request(&opt)?;
+    // the to-tag names the callee's side of the replaced dialog
+    assert_eq!(
+        request.headers.get_other("Replaces"),
+        Some("consult-call@example.com;to-tag=target-tag;from-tag=transferor-tag")
+    );
+    assert!(request
+        .to_string()
+        .contains("Require: 100rel, replaces\r\n"));
+
+    opt.headers = None;
+    let parsed = Request::try_from(dialog_layer.make_invite_request(&opt)?.to_string().as_str())?;
+    assert!(parsed.to_string().contains("Require: replaces\r\n"));
+    assert!(parsed.headers.get_other("Replaces").is_some());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tcp_invite_gets_tcp_contact_with_multiple_listeners() -> crate::Result<()> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let endpoint = create_test_endpoint().await?;
+    let host = rsip::HostWithPort::try_from("127.0.0.1:0")?;
+    let udp_addr = endpoint
+        .listen(SipAddr::new(rsip::Transport::Udp, host.clone()))
+        .await?;
+    let tcp_addr = endpoint
+        .listen(SipAddr::new(rsip::Transport::Tcp, host))
+        .await?;
+    assert_eq!(endpoint.get_addrs().len(), 2);
+
+    let mut incoming = endpoint.incoming_transactions();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+    let contact = rsip::Uri::try_from(format!("sip:bob@{}", udp_addr.addr).as_str())?;
+    let (state_sender, mut states) = unbounded_channel();
+    let (dialog_tx, mut dialog_rx) = unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(tx) = incoming.recv().await {
+            if tx.original.method != rsip::Method::Invite {
+                continue;
+            }
+            let mut dialog = dialog_layer
+                .get_or_create_server_invite(&tx, state_sender.clone(), None, Some(contact.clone()))
+                .expect("server dialog");
+            dialog_tx.send(dialog.clone()).ok();
+            tokio::spawn(async move { dialog.handle(tx).await });
+        }
+    });
+
+    let mut invite = create_invite_request("alice-tag-tcp", "", "call-id-tcp", "z9hG4bKtcp");
+    invite
+        .headers
+        .unique_push(Via::new("SIP/2.0/TCP 127.0.0.1:5099;branch=z9hG4bKtcp").into());
+    invite
+        .headers
+        .unique_push(To::new("Bob <sip:bob@example.com>").into());
+    invite
+        .headers
+        .unique_push(ContentLength::from(invite.body.len() as u32).into());
+    let mut stream = tokio::net::TcpStream::connect(tcp_addr.get_socketaddr()?).await?;
+    stream.write_all(invite.to_string().as_bytes()).await?;
+
+    let dialog = tokio::time::timeout(std::time::Duration::from_secs(5), dialog_rx.recv())
+        .await
+        .expect("INVITE not received over TCP")
+        .expect("dialog");
+    wait_state(&mut states, |s| {
+        matches!(s, crate::dialog::dialog::DialogState::Calling(_))
+    })
+    .await;
+    dialog.accept(None, None)?;
+
+    let mut received = String::new();
+    let mut buf = vec![0u8; 4096];
+    let ok = loop {
+        if let Some(start) = received.find("SIP/2.0 200") {
+            if received[start..].contains("\r\n\r\n") {
+                break rsip::Response::try_from(&received[start..])?;
+            }
+        }
+        let len = tokio::time::timeout(std::time::Duration::from_secs(5), stream.read(&mut buf))
+            .await
+            .expect("no 200 OK over TCP")?;
+        assert!(len > 0, "connection closed before 200 OK");
+        received.push_str(&String::from_utf8_lossy(&buf[..len]));
+    };
+
+    let contact = ok.contact_header()?.uri()?;
+    assert_eq!(contact.host_with_port, tcp_addr.addr);
+    assert!(contact
+        .params
+        .contains(&rsip::Param::Transport(rsip::Transport::Tcp)));
+    Ok(())
+}
+
+fn create_options_request(call_id: &str, to_tag: &str) -> Request {
+    Request {
+        method: rsip::Method::Options,
+        uri: rsip::Uri::try_from("sip:bob@127.0.0.1:5060").unwrap(),
+        headers: vec![
+            Via::new("SIP/2.0/UDP alice.example.com:5060;branch=z9hG4bKopt2").into(),
+            CSeq::new("2 OPTIONS").into(),
+            From::new("Alice <sip:alice@example.com>;tag=alice-tag-opt").into(),
+            To::new(&format!("Bob <sip:bob@example.com>;tag={}", to_tag)).into(),
+            CallId::new(call_id).into(),
+            MaxForwards::new("70").into(),
+        ]
+        .into(),
+        version: rsip::Version::V2,
+        body: vec![],
+    }
+}
+
+#[tokio::test]
+async fn test_in_dialog_options_answered() -> crate::Result<()> {
+    use crate::dialog::dialog::{Dialog, DialogState};
+    use crate::transport::TransportEvent;
+
+    let token = CancellationToken::new();
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(TransportLayer::new(token.child_token()))
+        .with_manual_options(true)
+        .build();
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+    let addr = SipAddr::from("127.0.0.1:5060".parse::<std::net::SocketAddr>().unwrap());
+    let (_incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, addr)
+            .await?
+            .into();
+
+    let invite = create_invite_request("alice-tag-opt", "", "call-id-options", "z9hG4bKopt1");
+    let key = TransactionKey::from_request(&invite, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, invite, endpoint.inner.clone(), Some(conn.clone()));
+    let (state_sender, mut states) = unbounded_channel();
+    let server = dialog_layer.get_or_create_server_invite(
+        &tx,
+        state_sender,
+        None,
+        Some(rsip::Uri::try_from("sip:bob@127.0.0.1:5060")?),
+    )?;
+    server
+        .inner
+        .transition(DialogState::Confirmed(server.id()))?;
+    let mut dialog = Dialog::ServerInvite(server.clone());
+
+    let options = create_options_request("call-id-options", &server.id().to_tag);
+    let key = TransactionKey::from_request(&options, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, options, endpoint.inner.clone(), Some(conn));
+    dialog.handle(tx).await?;
+
+    let request = match wait_state(&mut states, |s| matches!(s, DialogState::Options(_, _))).await {
+        DialogState::Options(_, request) => request,
+        _ => unreachable!(),
+    };
+    dialog.respond_options(&request).await?;
+
+    let resp =
+        match tokio::time::timeout(std::time::Duration::from_secs(1), outgoing_rx.recv()).await {
+            Ok(Some(TransportEvent::Incoming(rsip::SipMessage::Response(resp), _, _))) => resp,
+            _ => panic!("no response to OPTIONS"),
+        };
+    assert_eq!(resp.status_code, rsip::StatusCode::OK);
+    let allow = resp
+        .headers
+        .iter()
+        .find_map(|h| match h {
+            rsip::Header::Allow(allow) => Some(allow.value().to_string()),
+            _ => None,
+        })
+        .expect("Allow header");
+    assert!(allow.contains("OPTIONS"));
+    assert!(allow.contains("BYE"));
+    assert!(resp
+        .headers
+        .iter()
+        .any(|h| matches!(h, rsip::Header::Accept(_))));
+    assert!(server.inner.is_confirmed());
+
+    // a second answer has nothing left to respond to
+    assert!(dialog.respond_options(&request).await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_in_dialog_options_answered_immediately_by_default() -> crate::Result<()> {
+    use crate::dialog::dialog::{Dialog, DialogState};
+    use crate::transport::TransportEvent;
+
+    let endpoint = create_test_endpoint().await?;
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+    let addr = SipAddr::from("127.0.0.1:5060".parse::<std::net::SocketAddr>().unwrap());
+    let (_incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, addr)
+            .await?
+            .into();
+
+    let invite = create_invite_request("alice-tag-opt", "", "call-id-options-auto", "z9hG4bKopt1");
+    let key = TransactionKey::from_request(&invite, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, invite, endpoint.inner.clone(), Some(conn.clone()));
+    let (state_sender, mut states) = unbounded_channel();
+    let server = dialog_layer.get_or_create_server_invite(
+        &tx,
+        state_sender,
+        None,
+        Some(rsip::Uri::try_from("sip:bob@127.0.0.1:5060")?),
+    )?;
+    server
+        .inner
+        .transition(DialogState::Confirmed(server.id()))?;
+    let mut dialog = Dialog::ServerInvite(server.clone());
+
+    let options = create_options_request("call-id-options-auto", &server.id().to_tag);
+    let key = TransactionKey::from_request(&options, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, options, endpoint.inner.clone(), Some(conn));
+    dialog.handle(tx).await?;
+
+    // answered without waiting for the application
+    let resp = match outgoing_rx.try_recv() {
+        Ok(TransportEvent::Incoming(rsip::SipMessage::Response(resp), _, _)) => resp,
+        _ => panic!("OPTIONS not answered right away"),
+    };
+    assert_eq!(resp.status_code, rsip::StatusCode::OK);
+    let request = match wait_state(&mut states, |s| matches!(s, DialogState::Options(_, _))).await {
+        DialogState::Options(_, request) => request,
+        _ => unreachable!(),
+    };
+    assert!(dialog.respond_options(&request).await.is_err());
+    Ok(())
+}
+
+fn create_refer_request(call_id: &str, to_tag: &str, seq: u32, refer_sub: Option<&str>) -> Request {
+    let mut headers: Vec<rsip::Header> = vec![
+        Via::new(&format!(
+            "SIP/2.0/UDP alice.example.com:5060;branch=z9hG4bKrefer{}",
+            seq
+        ))
+        .into(),
+        CSeq::new(&format!("{} REFER", seq)).into(),
+        From::new("Alice <sip:alice@example.com>;tag=alice-tag-refer").into(),
+        To::new(&format!("Bob <sip:bob@example.com>;tag={}", to_tag)).into(),
+        CallId::new(call_id).into(),
+        MaxForwards::new("70").into(),
+        rsip::Header::Other("Refer-To".into(), "<sip:carol@example.com>".into()),
+    ];
+    if let Some(refer_sub) = refer_sub {
+        headers.push(rsip::Header::Other("Refer-Sub".into(), refer_sub.into()));
+        headers.push(Supported::new("norefersub").into());
+    }
+    Request {
+        method: rsip::Method::Refer,
+        uri: rsip::Uri::try_from("sip:bob@127.0.0.1:5060").unwrap(),
+        headers: headers.into(),
+        version: rsip::Version::V2,
+        body: vec![],
+    }
+}
+
+#[tokio::test]
+async fn test_refer_with_norefersub_creates_no_subscription() -> crate::Result<()> {
+    use crate::dialog::dialog::{Dialog, DialogState};
+    use crate::rsip_ext::RsipHeadersExt;
+    use crate::transport::TransportEvent;
+
+    let token = CancellationToken::new();
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(TransportLayer::new(token.child_token()))
+        .with_supported(vec!["replaces".to_string(), "norefersub".to_string()])
+        .with_accept_refer(true)
+        .build();
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+    let addr = SipAddr::from("127.0.0.1:5060".parse::<std::net::SocketAddr>().unwrap());
+    let (_incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, addr)
+            .await?
+            .into();
+
+    let invite = create_invite_request("alice-tag-refer", "", "call-id-refer", "z9hG4bKrefer0");
+    let key = TransactionKey::from_request(&invite, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, invite, endpoint.inner.clone(), Some(conn.clone()));
+    let (state_sender, mut states) = unbounded_channel();
+    let server = dialog_layer.get_or_create_server_invite(
+        &tx,
+        state_sender,
+        None,
+        Some(rsip::Uri::try_from("sip:bob@127.0.0.1:5060")?),
+    )?;
+    server
+        .inner
+        .transition(DialogState::Confirmed(server.id()))?;
+    let mut dialog = Dialog::ServerInvite(server.clone());
+
+    let refer = create_refer_request("call-id-refer", &server.id().to_tag, 2, Some("false"));
+    let key = TransactionKey::from_request(&refer, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, refer, endpoint.inner.clone(), Some(conn.clone()));
+    dialog.handle(tx).await?;
+
+    let resp =
+        match tokio::time::timeout(std::time::Duration::from_secs(1), outgoing_rx.recv()).await {
+            Ok(Some(TransportEvent::Incoming(rsip::SipMessage::Response(resp), _, _))) => resp,
+            _ => panic!("no response to REFER"),
+        };
+    assert_eq!(resp.status_code.code(), 202);
+    assert_eq!(resp.headers.get_other("Refer-Sub"), Some("false"));
+    wait_state(&mut states, |s| matches!(s, DialogState::Refer(_, _))).await;
+    assert!(dialog.refer_subscriptions().is_empty());
+    assert!(dialog.notify_refer(2, rsip::StatusCode::OK).await.is_err());
+
+    // without Refer-Sub the REFER creates the implicit subscription
+    let refer = create_refer_request("call-id-refer", &server.id().to_tag, 3, None);
+    let key = TransactionKey::from_request(&refer, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, refer, endpoint.inner.clone(), Some(conn));
+    dialog.handle(tx).await?;
+
+    let resp =
+        match tokio::time::timeout(std::time::Duration::from_secs(1), outgoing_rx.recv()).await {
+            Ok(Some(TransportEvent::Incoming(rsip::SipMessage::Response(resp), _, _))) => resp,
+            _ => panic!("no response to REFER"),
+        };
+    assert_eq!(resp.status_code.code(), 202);
+    assert!(resp.headers.get_other("Refer-Sub").is_none());
+    let subscriptions = dialog.refer_subscriptions();
+    assert_eq!(subscriptions.len(), 1);
+    assert_eq!(subscriptions[0].id, 3);
+    assert_eq!(subscriptions[0].refer_to, "<sip:carol@example.com>");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_refer_rejected_unless_enabled() -> crate::Result<()> {
+    use crate::dialog::dialog::{Dialog, DialogState};
+    use crate::transport::TransportEvent;
+
+    let endpoint = create_test_endpoint().await?;
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+    let addr = SipAddr::from("127.0.0.1:5060".parse::<std::net::SocketAddr>().unwrap());
+    let (_incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, addr)
+            .await?
+            .into();
+
+    let invite = create_invite_request("alice-tag-refer", "", "call-id-refer-405", "z9hG4bKrefer0");
+    let key = TransactionKey::from_request(&invite, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, invite, endpoint.inner.clone(), Some(conn.clone()));
+    let (state_sender, _states) = unbounded_channel();
+    let server = dialog_layer.get_or_create_server_invite(
+        &tx,
+        state_sender,
+        None,
+        Some(rsip::Uri::try_from("sip:bob@127.0.0.1:5060")?),
+    )?;
+    server
+        .inner
+        .transition(DialogState::Confirmed(server.id()))?;
+    let mut dialog = Dialog::ServerInvite(server.clone());
+
+    let refer = create_refer_request("call-id-refer-405", &server.id().to_tag, 2, None);
+    let key = TransactionKey::from_request(&refer, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, refer, endpoint.inner.clone(), Some(conn));
+    dialog.handle(tx).await?;
+
+    let resp =
+        match tokio::time::timeout(std::time::Duration::from_secs(1), outgoing_rx.recv()).await {
+            Ok(Some(TransportEvent::Incoming(rsip::SipMessage::Response(resp), _, _))) => resp,
+            _ => panic!("no response to REFER"),
+        };
+    assert_eq!(resp.status_code, rsip::StatusCode::MethodNotAllowed);
+    assert!(dialog.refer_subscriptions().is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_server_dialog_ack_timeout() -> crate::Result<()> {
+    use crate::dialog::dialog::{Dialog, DialogState, TerminatedReason};
+
+    let endpoint = create_test_endpoint().await?;
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+    assert_eq!(dialog_layer.ack_timeout(), endpoint.inner.t1x64);
+    dialog_layer.set_ack_timeout(std::time::Duration::from_millis(200));
+
+    let addr = SipAddr::from("127.0.0.1:5060".parse::<std::net::SocketAddr>().unwrap());
+    let (_incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, _outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, addr)
+            .await?
+            .into();
+
+    let invite_req =
+        create_invite_request("alice-tag-ack", "", "call-id-ack-timeout", "z9hG4bKat1");
+    let key = TransactionKey::from_request(&invite_req, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, invite_req, endpoint.inner.clone(), Some(conn));
+    let (state_sender, mut states) = unbounded_channel();
+    let dialog = dialog_layer.get_or_create_server_invite(&tx, state_sender, None, None)?;
+    let mut handler = Dialog::ServerInvite(dialog.clone());
+    let handle = tokio::spawn(async move { handler.handle(tx).await });
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    dialog.accept(None, None)?;
+
+    // the ACK never arrives
+    let started = std::time::Instant::now();
+    let state = tokio::time::timeout(
+        std::time::Duration::from_secs(2),
+        wait_state(&mut states, |s| matches!(s, DialogState::Terminated(_, _))),
+    )
+    .await
+    .expect("dialog did not time out");
+    assert!(matches!(
+        state,
+        DialogState::Terminated(_, TerminatedReason::Timeout)
+    ));
+    assert!(started.elapsed() >= std::time::Duration::from_millis(200));
+
+    // the INVITE transaction is gone, so the 2xx is no longer retransmitted
+    tokio::time::timeout(std::time::Duration::from_secs(1), handle)
+        .await
+        .expect("INVITE transaction still running")
+        .expect("handler panicked")?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_notify_without_subscription_rejected() -> crate::Result<()> {
+    use crate::dialog::dialog::{Dialog, DialogState, EventSubscription};
+    use crate::transport::TransportEvent;
+
+    let endpoint = create_test_endpoint().await?;
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+    let addr = SipAddr::from("127.0.0.1:5060".parse::<std::net::SocketAddr>().unwrap());
+    let (_incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, addr)
+            .await?
+            .into();
+
+    let invite = create_invite_request("alice-tag-notify", "", "call-id-notify", "z9hG4bKntf0");
+    let key = TransactionKey::from_request(&invite, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, invite, endpoint.inner.clone(), Some(conn.clone()));
+    let (state_sender, mut states) = unbounded_channel();
+    let server = dialog_layer.get_or_create_server_invite(
+        &tx,
+        state_sender,
+        None,
+        Some(rsip::Uri::try_from("sip:bob@127.0.0.1:5060")?),
+    )?;
+    server
+        .inner
+        .transition(DialogState::Confirmed(server.id()))?;
+    let mut dialog = Dialog::ServerInvite(server.clone());
+    assert!(dialog.subscriptions().is_empty());
+    while states.try_recv().is_ok() {}
+
+    let notify = Request {
+        method: rsip::Method::Notify,
+        uri: rsip::Uri::try_from("sip:bob@127.0.0.1:5060")?,
+        headers: vec![
+            Via::new("SIP/2.0/UDP alice.example.com:5060;branch=z9hG4bKntf1").into(),
+            CSeq::new("2 NOTIFY").into(),
+            From::new("Alice <sip:alice@example.com>;tag=alice-tag-notify").into(),
+            To::new(&format!(
+                "Bob <sip:bob@example.com>;tag={}",
+                server.id().to_tag
+            ))
+            .into(),
+            CallId::new("call-id-notify").into(),
+            MaxForwards::new("70").into(),
+            Event::new("refer;id=42").into(),
+            SubscriptionState::new("active;expires=60").into(),
+        ]
+        .into(),
+        version: rsip::Version::V2,
+        body: vec![],
+    };
+    let key = TransactionKey::from_request(&notify, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, notify, endpoint.inner.clone(), Some(conn));
+    dialog.handle(tx).await?;
+
+    let resp =
+        match tokio::time::timeout(std::time::Duration::from_secs(1), outgoing_rx.recv()).await {
+            Ok(Some(TransportEvent::Incoming(rsip::SipMessage::Response(resp), _, _))) => resp,
+            _ => panic!("no response to NOTIFY"),
+        };
+    assert_eq!(
+        resp.status_code,
+        rsip::StatusCode::CallTransactionDoesNotExist
+    );
+    assert!(states.try_recv().is_err());
+    assert!(server.inner.is_confirmed());
+
+    assert_eq!(
+        EventSubscription::from_event("Refer ; id=42"),
+        EventSubscription {
+            event: "refer".to_string(),
+            id: Some("42".to_string()),
+        }
+    );
+    assert_eq!(EventSubscription::from_event("presence").id, None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_notify_without_id_matches_oldest_subscription() -> crate::Result<()> {
+    use crate::dialog::dialog::{Dialog, DialogState, EventSubscription};
+    use crate::transport::TransportEvent;
+
+    let endpoint = create_test_endpoint().await?;
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+    let addr = SipAddr::from("127.0.0.1:5060".parse::<std::net::SocketAddr>().unwrap());
+    let (_incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, addr)
+            .await?
+            .into();
+
+    let invite = create_invite_request("alice-tag-noid", "", "call-id-noid", "z9hG4bKnid0");
+    let key = TransactionKey::from_request(&invite, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, invite, endpoint.inner.clone(), Some(conn.clone()));
+    let (state_sender, mut states) = unbounded_channel();
+    let server = dialog_layer.get_or_create_server_invite(
+        &tx,
+        state_sender,
+        None,
+        Some(rsip::Uri::try_from("sip:bob@127.0.0.1:5060")?),
+    )?;
+    server
+        .inner
+        .transition(DialogState::Confirmed(server.id()))?;
+    // two REFERs were accepted, with CSeq 2 and 3
+    for id in ["2", "3"] {
+        server
+            .inner
+            .subscriptions
+            .lock()
+            .unwrap()
+            .push(EventSubscription {
+                event: "refer".to_string(),
+                id: Some(id.to_string()),
+            });
+    }
+    let mut dialog = Dialog::ServerInvite(server.clone());
+    while states.try_recv().is_ok() {}
+
+    // the notifier leaves out the id of the first REFER
+    let notify = Request {
+        method: rsip::Method::Notify,
+        uri: rsip::Uri::try_from("sip:bob@127.0.0.1:5060")?,
+        headers: vec![
+            Via::new("SIP/2.0/UDP alice.example.com:5060;branch=z9hG4bKnid1").into(),
+            CSeq::new("2 NOTIFY").into(),
+            From::new("Alice <sip:alice@example.com>;tag=alice-tag-noid").into(),
+            To::new(&format!(
+                "Bob <sip:bob@example.com>;tag={}",
+                server.id().to_tag
+            ))
+            .into(),
+            CallId::new("call-id-noid").into(),
+            MaxForwards::new("70").into(),
+            Event::new("refer").into(),
+            SubscriptionState::new("terminated;reason=noresource").into(),
+        ]
+        .into(),
+        version: rsip::Version::V2,
+        body: vec![],
+    };
+    let key = TransactionKey::from_request(&notify, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, notify, endpoint.inner.clone(), Some(conn));
+    dialog.handle(tx).await?;
+
+    let resp =
+        match tokio::time::timeout(std::time::Duration::from_secs(1), outgoing_rx.recv()).await {
+            Ok(Some(TransportEvent::Incoming(rsip::SipMessage::Response(resp), _, _))) => resp,
+            _ => panic!("no response to NOTIFY"),
+        };
+    assert_eq!(resp.status_code, rsip::StatusCode::OK);
+    assert!(matches!(states.try_recv(), Ok(DialogState::Notify(_, _))));
+    // the oldest subscription ended, the other one is still active
+    assert_eq!(
+        dialog.subscriptions(),
+        vec![EventSubscription {
+            event: "refer".to_string(),
+            id: Some("3".to_string()),
+        }]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_accept_header_negotiation() -> crate::Result<()> {
+    use crate::dialog::dialog::{Dialog, DialogState, TerminatedReason};
+    use crate::transport::TransportEvent;
+
+    let endpoint = create_test_endpoint().await?;
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+
+    let opt = crate::dialog::invitation::InviteOption {
+        caller: rsip::Uri::try_from("sip:alice@example.com")?,
+        callee: rsip::Uri::try_from("sip:bob@example.com")?,
+        contact: rsip::Uri::try_from("sip:alice@127.0.0.1:5060")?,
+        accept: vec!["application/sdp".to_string()],
+        ..Default::default()
+    };
+    let request = dialog_layer.make_invite_request(&opt)?;
+    assert!(request.to_string().contains("Accept: application/sdp\r\n"));
+
+    let addr = SipAddr::from("127.0.0.1:5060".parse::<std::net::SocketAddr>().unwrap());
+    let (_incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, addr)
+            .await?
+            .into();
+
+    let mut invite_req =
+        create_invite_request("alice-tag-123", "", "call-id-accept", "z9hG4bKaccept");
+    invite_req
+        .headers
+        .push(Accept::new("application/sdp").into());
+    let key = TransactionKey::from_request(&invite_req, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, invite_req, endpoint.inner.clone(), Some(conn));
+    let (state_sender, mut state_receiver) = unbounded_channel();
+    let dialog = dialog_layer.get_or_create_server_invite(&tx, state_sender, None, None)?;
+    let mut handler = Dialog::ServerInvite(dialog.clone());
+    tokio::spawn(async move { handler.handle(tx).await });
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+    assert!(dialog.accepts("application/sdp"));
+    assert!(!dialog.accepts("application/json"));
+    assert!(dialog
+        .accept(
+            Some(vec![ContentType::new("application/json").into()]),
+            Some(b"{}".to_vec()),
+        )
+        .is_err());
+
+    let resp = loop {
+        match tokio::time::timeout(std::time::Duration::from_secs(3), outgoing_rx.recv()).await {
+            Ok(Some(TransportEvent::Incoming(rsip::SipMessage::Response(resp), _, _)))
+                if resp.status_code != rsip::StatusCode::Trying =>
+            {
+                break resp
+            }
+            Ok(_) => continue,
+            Err(_) => panic!("no final response sent"),
+        }
+    };
+    assert_eq!(resp.status_code, rsip::StatusCode::NotAcceptable);
+    let state = wait_state(&mut state_receiver, |s| {
+        matches!(s, DialogState::Terminated(_, _))
+    })
+    .await;
+    assert!(matches!(
+        state,
+        DialogState::Terminated(
+            _,
+            TerminatedReason::UasOther(Some(rsip::StatusCode::NotAcceptable))
+        )
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_invite_subject_and_priority() -> crate::Result<()> {
+    use rsip::typed::Priority;
+
+    let endpoint = create_test_endpoint().await?;
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+    let mut opt = crate::dialog::invitation::InviteOption {
+        caller: rsip::Uri::try_from("sip:alice@example.com")?,
+        callee: rsip::Uri::try_from("sip:bob@example.com")?,
+        contact: rsip::Uri::try_from("sip:alice@127.0.0.1:5060")?,
+        subject: Some("Project review".to_string()),
+        priority: Some(Priority::Other("whenever".to_string())),
+        ..Default::default()
+    };
+    assert!(dialog_layer.make_invite_request(&opt).is_err());
+
+    for (priority, token) in [
+        (Priority::Emergency, "emergency"),
+        (Priority::Urgent, "urgent"),
+        (Priority::Normal, "normal"),
+        (Priority::NonUrgent, "non-urgent"),
+    ] {
+        opt.priority = Some(priority);
+        let request = dialog_layer.make_invite_request(&opt)?.to_string();
+        assert!(request.contains(&format!("Priority: {}\r\n", token)));
+        assert!(request.contains("Subject: Project review\r\n"));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_invite_in_reply_to() -> crate::Result<()> {
+    let endpoint = create_test_endpoint().await?;
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+    let opt = crate::dialog::invitation::InviteOption {
+        caller: rsip::Uri::try_from("sip:alice@example.com")?,
+        callee: rsip::Uri::try_from("sip:bob@example.com")?,
+        contact: rsip::Uri::try_from("sip:alice@127.0.0.1:5060")?,
+        in_reply_to: vec![
+            "70710@saturn.bell-tel.com".to_string(),
+            "17320@saturn.bell-tel.com".to_string(),
+        ],
+        ..Default::default()
+    };
+    let request = dialog_layer.make_invite_request(&opt)?;
+    assert!(request
+        .to_string()
+        .contains("In-Reply-To: 70710@saturn.bell-tel.com, 17320@saturn.bell-tel.com\r\n"));
+
+    // the UAS reads back both Call-IDs
+    let key = TransactionKey::from_request(&request, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, request, endpoint.inner.clone(), None);
+    let (state_sender, _) = unbounded_channel();
+    let dialog = dialog_layer.get_or_create_server_invite(&tx, state_sender, None, None)?;
+    assert_eq!(
+        dialog.in_reply_to(),
+        vec!["70710@saturn.bell-tel.com", "17320@saturn.bell-tel.com"]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_server_dialog_queue() -> crate::Result<()> {
+    use crate::dialog::dialog::Dialog;
+    use crate::transport::TransportEvent;
+
+    let endpoint = create_test_endpoint().await?;
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+
+    let addr = SipAddr::from("127.0.0.1:5060".parse::<std::net::SocketAddr>().unwrap());
+    let (_incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, addr)
+            .await?
+            .into();
+
+    let invite_req = create_invite_request("alice-tag-123", "", "call-id-queue", "z9hG4bKqueue");
+    let key = TransactionKey::from_request(&invite_req, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, invite_req, endpoint.inner.clone(), Some(conn));
+    let (state_sender, _state_receiver) = unbounded_channel();
+    let dialog = dialog_layer.get_or_create_server_invite(&tx, state_sender, None, None)?;
+    let mut handler = Dialog::ServerInvite(dialog.clone());
+    tokio::spawn(async move { handler.handle(tx).await });
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+    async fn next_response(
+        rx: &mut tokio::sync::mpsc::UnboundedReceiver<TransportEvent>,
+    ) -> rsip::Response {
+        loop {
+            match tokio::time::timeout(std::time::Duration::from_secs(3), rx.recv()).await {
+                Ok(Some(TransportEvent::Incoming(rsip::SipMessage::Response(resp), _, _)))
+                    if resp.status_code != rsip::StatusCode::Trying =>
+                {
+                    break resp
+                }
+                Ok(_) => continue,
+                Err(_) => panic!("no response sent"),
+            }
+        }
+    }
+
+    dialog.queue(None)?;
+    let resp = next_response(&mut outgoing_rx).await;
+    assert_eq!(resp.status_code.code(), 182);
+    assert!(resp.to_string().starts_with("SIP/2.0 182 Queued\r\n"));
+
+    for position in [3, 2, 1] {
+        dialog.queue(Some(position))?;
+        let resp = next_response(&mut outgoing_rx).await;
+        assert_eq!(resp.status_code.code(), 182);
+        assert!(resp
+            .to_string()
+            .starts_with(&format!("SIP/2.0 182 Queued (position {})\r\n", position)));
+    }
+
+    // the INVITE is still pending and can be answered
+    assert!(!dialog.inner.state.lock().unwrap().is_terminated());
+    dialog.accept(None, None)?;
+    assert_eq!(
+        next_response(&mut outgoing_rx).await.status_code,
+        rsip::StatusCode::OK
+    );
+    assert!(dialog.queue(Some(1)).is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_server_dialog_reject_media() -> crate::Result<()> {
+    use crate::dialog::dialog::{Dialog, DialogState, TerminatedReason};
+    use crate::rsip_ext::RsipResponseExt;
+    use crate::transport::TransportEvent;
+
+    let endpoint = create_test_endpoint().await?;
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+
+    let addr = SipAddr::from("127.0.0.1:5060".parse::<std::net::SocketAddr>().unwrap());
+    let (_incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, addr)
+            .await?
+            .into();
+
+    let invite_req = create_invite_request("alice-tag-123", "", "call-id-488", "z9hG4bK488");
+    let key = TransactionKey::from_request(&invite_req, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, invite_req, endpoint.inner.clone(), Some(conn));
+    let (state_sender, mut state_receiver) = unbounded_channel();
+    let dialog = dialog_layer.get_or_create_server_invite(&tx, state_sender, None, None)?;
+    let mut handler = Dialog::ServerInvite(dialog.clone());
+    tokio::spawn(async move { handler.handle(tx).await });
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+    dialog.reject_media("no common codec, \"G729\" only")?;
+    let resp = loop {
+        match tokio::time::timeout(std::time::Duration::from_secs(3), outgoing_rx.recv()).await {
+            Ok(Some(TransportEvent::Incoming(rsip::SipMessage::Response(resp), _, _)))
+                if resp.status_code != rsip::StatusCode::Trying =>
+            {
+                break resp
+            }
+            Ok(_) => continue,
+            Err(_) => panic!("no response sent"),
+        }
+    };
+    assert_eq!(resp.status_code, rsip::StatusCode::NotAcceptableHere);
+    let warning = resp
+        .headers
+        .iter()
+        .find_map(|h| match h {
+            rsip::Header::Warning(w) => Some(w.value().to_string()),
+            _ => None,
+        })
+        .expect("Warning header");
+    assert!(warning.starts_with("305 "));
+    assert_eq!(
+        resp.diagnostics().as_deref(),
+        Some(r#"no common codec, \"G729\" only"#)
+    );
+
+    let mut terminated = None;
+    while let Ok(state) = state_receiver.try_recv() {
+        if let DialogState::Terminated(_, reason) = state {
+            terminated = Some(reason);
+        }
+    }
+    assert!(matches!(
+        terminated,
+        Some(TerminatedReason::UasOther(Some(
+            rsip::StatusCode::NotAcceptableHere
+        )))
+    ));
+    assert_eq!(
+        Dialog::ServerInvite(dialog).failure_details().as_deref(),
+        Some("no common codec, \"G729\" only")
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dialog_idle_timeout_sends_bye() -> crate::Result<()> {
+    use crate::dialog::dialog::{Dialog, DialogState, TerminatedReason};
+    use std::time::Duration;
+
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    let udp = crate::transport::udp::UdpConnection::create_connection(
+        "127.0.0.1:0".parse().unwrap(),
+        None,
+    )
+    .await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+    assert_eq!(dialog_layer.idle_timeout(), None);
+    dialog_layer.set_idle_timeout(Some(Duration::from_millis(300)));
+
+    let peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let mut invite_req = create_invite_request("alice-tag-idle", "", "call-id-idle", "z9hG4bKidle");
+    invite_req
+        .headers
+        .unique_push(Contact::new(format!("<sip:alice@{}>", peer.local_addr()?)).into());
+    let key = TransactionKey::from_request(&invite_req, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, invite_req, endpoint.inner.clone(), None);
+    let (state_sender, mut states) = unbounded_channel();
+    let dialog = dialog_layer.get_or_create_server_invite(&tx, state_sender, None, None)?;
+    dialog
+        .inner
+        .transition(DialogState::Confirmed(dialog.id()))?;
+    let started = tokio::time::Instant::now();
+
+    // activity restarts the countdown
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    Dialog::ServerInvite(dialog.clone()).touch();
+
+    let mut buf = vec![0u8; 2048];
+    let (len, from) = tokio::time::timeout(Duration::from_secs(2), peer.recv_from(&mut buf))
+        .await
+        .expect("no BYE after inactivity")?;
+    let bye = rsip::Request::try_from(&buf[..len])?;
+    assert_eq!(bye.method, rsip::Method::Bye);
+    assert!(started.elapsed() >= Duration::from_millis(500));
+
+    let headers: rsip::Headers = bye
+        .headers
+        .iter()
+        .filter(|h| {
+            matches!(
+                h,
+                rsip::Header::Via(_)
+                    | rsip::Header::From(_)
+                    | rsip::Header::To(_)
+                    | rsip::Header::CallId(_)
+                    | rsip::Header::CSeq(_)
+            )
+        })
+        .cloned()
+        .collect::<Vec<_>>()
+        .into();
+    let ok = rsip::Response {
+        status_code: rsip::StatusCode::OK,
+        version: rsip::Version::V2,
+        headers,
+        body: vec![],
+    };
+    peer.send_to(ok.to_string().as_bytes(), from).await?;
+
+    let state = wait_state(&mut states, |s| matches!(s, DialogState::Terminated(_, _))).await;
+    assert!(matches!(
+        state,
+        DialogState::Terminated(_, TerminatedReason::Timeout)
+    ));
+    token.cancel();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_accept_invite_creates_server_dialog() -> crate::Result<()> {
+    use crate::transport::TransportEvent;
+
+    let endpoint = create_test_endpoint().await?;
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+
+    let addr = SipAddr::from("127.0.0.1:5060".parse::<std::net::SocketAddr>().unwrap());
+    let (_incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, addr)
+            .await?
+            .into();
+
+    let mut invite_req =
+        create_invite_request("alice-tag-123", "", "call-id-accept", "z9hG4bKaccept");
+    invite_req
+        .headers
+        .unique_push(To::new("Bob <sip:bob@example.com>").into());
+    invite_req
+        .headers
+        .push(RecordRoute::new("<sip:p1.example.com;lr>").into());
+    invite_req
+        .headers
+        .push(RecordRoute::new("<sip:p2.example.com;lr>").into());
+    let key = TransactionKey::from_request(&invite_req, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, invite_req.clone(), endpoint.inner.clone(), Some(conn));
+
+    // a request that is not the transaction's INVITE is refused
+    let other = create_invite_request("alice-tag-123", "", "call-id-other", "z9hG4bKother");
+    let other_key = TransactionKey::from_request(&other, TransactionRole::Server)?;
+    let other_tx = Transaction::new_server(other_key, other.clone(), endpoint.inner.clone(), None);
+    let (state_sender, _state_receiver) = unbounded_channel();
+    assert!(dialog_layer
+        .accept_invite(invite_req.clone(), other_tx, state_sender, None, None)
+        .is_err());
+    assert_eq!(dialog_layer.len(), 0);
+
+    let (state_sender, _state_receiver) = unbounded_channel();
+    let dialog = dialog_layer.accept_invite(invite_req, tx, state_sender, None, None)?;
+    let id = dialog.id();
+    assert_eq!(id.call_id, "call-id-accept");
+    assert_eq!(id.from_tag, "alice-tag-123");
+    assert!(!id.to_tag.is_empty());
+    assert!(dialog_layer.get_dialog(&id).is_some());
+
+    let routes = dialog
+        .inner
+        .route_set
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|r| r.value().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        routes,
+        vec!["<sip:p1.example.com;lr>", "<sip:p2.example.com;lr>"]
+    );
+
+    // ready to answer without calling handle first
+    dialog.accept(None, None)?;
+    let resp = loop {
+        match tokio::time::timeout(std::time::Duration::from_secs(3), outgoing_rx.recv()).await {
+            Ok(Some(TransportEvent::Incoming(rsip::SipMessage::Response(resp), _, _)))
+                if resp.status_code == rsip::StatusCode::OK =>
+            {
+                break resp
+            }
+            Ok(_) => continue,
+            Err(_) => panic!("no 200 OK sent"),
+        }
+    };
+    assert_eq!(
+        resp.to_header()?.tag()?.map(|t| t.value().to_string()),
+        Some(id.to_tag.clone())
+    );
+    let record_routes = resp
+        .headers
+        .iter()
+        .filter(|h| matches!(h, rsip::Header::RecordRoute(_)))
+        .count();
+    assert_eq!(record_routes, 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_invite_contact_user_from_credential() -> crate::Result<()> {
+    use rsip::prelude::ToTypedHeader;
+
+    let endpoint = create_test_endpoint().await?;
+    endpoint
+        .inner
+        .transport_layer
+        .add_transport(create_mock_connection().await?);
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+
+    let mut opt = crate::dialog::invitation::InviteOption {
+        caller: rsip::Uri::try_from("sip:alice@example.com")?,
+        callee: rsip::Uri::try_from("sip:bob@example.com")?,
+        contact: rsip::Uri::try_from("sip:127.0.0.1:5060")?,
+        credential: Some(crate::dialog::authenticate::Credential {
+            username: "1001".to_string(),
+            password: "secret".to_string(),
+        }),
+        ..Default::default()
+    };
+    let contact_user = |request: &Request| -> crate::Result<Option<String>> {
+        Ok(request
+            .contact_header()?
+            .typed()?
+            .uri
+            .auth
+            .map(|auth| auth.user))
+    };
+
+    let request = dialog_layer.make_invite_request(&opt)?;
+    assert_eq!(contact_user(&request)?.as_deref(), Some("1001"));
+    assert_eq!(
+        request
+            .contact_header()?
+            .typed()?
+            .uri
+            .host_with_port
+            .to_string(),
+        "127.0.0.1:5060"
+    );
+
+    // without credential the caller's user part is used
+    opt.credential = None;
+    let request = dialog_layer.make_invite_request(&opt)?;
+    assert_eq!(contact_user(&request)?.as_deref(), Some("alice"));
+
+    // an explicit user part is kept
+    opt.contact = rsip::Uri::try_from("sip:desk@127.0.0.1:5060")?;
+    let request = dialog_layer.make_invite_request(&opt)?;
+    assert_eq!(contact_user(&request)?.as_deref(), Some("desk"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bye_reason_q850_cause() -> crate::Result<()> {
+    use crate::dialog::dialog::{DialogState, Q850Cause, TerminatedReason};
+
+    let endpoint = create_test_endpoint().await?;
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+    let conn = create_mock_connection().await?;
+
+    let invite_req = create_invite_request("alice-tag-123", "", "call-id-q850", "z9hG4bKq850");
+    let key = TransactionKey::from_request(&invite_req, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, invite_req, endpoint.inner.clone(), Some(conn.clone()));
+    let (state_sender, mut state_receiver) = unbounded_channel();
+    let dialog = dialog_layer.get_or_create_server_invite(&tx, state_sender, None, None)?;
+    dialog
+        .inner
+        .transition(DialogState::Confirmed(dialog.id()))?;
+
+    // the network tears the call down because of congestion
+    let mut bye_req = create_invite_request(
+        "alice-tag-123",
+        &dialog.id().to_tag,
+        "call-id-q850",
+        "z9hG4bKq850bye",
+    );
+    bye_req.method = rsip::Method::Bye;
+    bye_req.body.clear();
+    for header in bye_req.headers.iter_mut() {
+        if let rsip::Header::CSeq(cseq) = header {
+            *cseq = CSeq::new("2 BYE");
+        }
+    }
+    bye_req.headers.push(rsip::Header::Other(
+        "Reason".into(),
+        r#"SIP;cause=200;text="OK", Q.850;cause=34;text="No circuit available""#.into(),
+    ));
+    let key = TransactionKey::from_request(&bye_req, TransactionRole::Server)?;
+    let bye_tx = Transaction::new_server(key, bye_req, endpoint.inner.clone(), Some(conn));
+    let mut matched = dialog_layer
+        .match_dialog(&bye_tx.original)
+        .expect("BYE matches the dialog");
+    matched.handle(bye_tx).await?;
+
+    let reason = loop {
+        match tokio::time::timeout(std::time::Duration::from_secs(1), state_receiver.recv()).await {
+            Ok(Some(DialogState::Terminated(_, reason))) => break reason,
+            Ok(Some(_)) => continue,
+            _ => panic!("dialog not terminated"),
+        }
+    };
+    assert!(matches!(reason, TerminatedReason::UacBye));
+    let cause = matched.hangup_cause().expect("Q.850 cause of the BYE");
+    assert_eq!(cause, Q850Cause::Congestion);
+    assert_eq!(cause.code(), 34);
+    assert_eq!(Q850Cause::from(16), Q850Cause::NormalClearing);
+    assert_eq!(Q850Cause::from(99), Q850Cause::Other(99));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cseq_wraps_within_rfc_range() -> crate::Result<()> {
+    use crate::transaction::MAX_CSEQ;
+
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(TransportLayer::new(CancellationToken::new()))
+        .with_initial_cseq(MAX_CSEQ - 1)
+        .build();
+    endpoint
+        .inner
+        .transport_layer
+        .add_transport(create_mock_connection().await?);
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+
+    let opt = crate::dialog::invitation::InviteOption {
+        caller: rsip::Uri::try_from("sip:alice@example.com")?,
+        callee: rsip::Uri::try_from("sip:bob@example.com")?,
+        contact: rsip::Uri::try_from("sip:alice@127.0.0.1:5060")?,
+        ..Default::default()
+    };
+    let seqs = (0..3)
+        .map(|_| {
+            dialog_layer
+                .make_invite_request(&opt)
+                .and_then(|r| Ok(r.cseq_header()?.seq()?))
+        })
+        .collect::<crate::Result<Vec<_>>>()?;
+    assert_eq!(seqs, vec![MAX_CSEQ, 1, 2]);
+
+    // in-dialog requests of a server dialog start at the configured CSeq
+    let invite_req = create_invite_request("alice-tag-123", "", "call-id-cseq", "z9hG4bKcseq");
+    let key = TransactionKey::from_request(&invite_req, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, invite_req, endpoint.inner.clone(), None);
+    let (state_sender, _) = unbounded_channel();
+    let dialog = dialog_layer.get_or_create_server_invite(&tx, state_sender, None, None)?;
+    assert_eq!(dialog.inner.get_local_seq(), MAX_CSEQ - 1);
+    assert_eq!(dialog.inner.increment_local_seq(), MAX_CSEQ);
+    assert_eq!(dialog.inner.increment_local_seq(), 1);
+    assert!(dialog.inner.get_local_seq() <= MAX_CSEQ);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_server_dialog_challenge_and_verify() -> crate::Result<()> {
+    use crate::dialog::authenticate::{handle_client_authenticate, Credential};
+    use crate::dialog::dialog::{Dialog, DialogState, TerminatedReason};
+    use crate::transport::TransportEvent;
+
+    let endpoint = create_test_endpoint().await?;
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+
+    let addr = SipAddr::from("127.0.0.1:5060".parse::<std::net::SocketAddr>().unwrap());
+    let (_incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, addr)
+            .await?
+            .into();
+
+    let invite_req = create_invite_request("alice-tag-407", "", "call-id-407", "z9hG4bK407a");
+    let key = TransactionKey::from_request(&invite_req, TransactionRole::Server)?;
+    let tx = Transaction::new_server(
+        key,
+        invite_req.clone(),
+        endpoint.inner.clone(),
+        Some(conn.clone()),
+    );
+    let (state_sender, mut state_receiver) = unbounded_channel();
+    let dialog = dialog_layer.get_or_create_server_invite(&tx, state_sender, None, None)?;
+    assert_eq!(dialog.authorization_username(), None);
+    let mut handler = Dialog::ServerInvite(dialog.clone());
+    tokio::spawn(async move { handler.handle(tx).await });
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+    dialog.challenge("example.com")?;
+    let challenge = loop {
+        match tokio::time::timeout(std::time::Duration::from_secs(3), outgoing_rx.recv()).await {
+            Ok(Some(TransportEvent::Incoming(rsip::SipMessage::Response(resp), _, _)))
+                if resp.status_code != rsip::StatusCode::Trying =>
+            {
+                break resp
+            }
+            Ok(_) => continue,
+            Err(_) => panic!("no challenge sent"),
+        }
+    };
+    assert_eq!(
+        challenge.status_code,
+        rsip::StatusCode::ProxyAuthenticationRequired
+    );
+    let mut terminated = None;
+    while let Ok(state) = state_receiver.try_recv() {
+        if let DialogState::Terminated(_, reason) = state {
+            terminated = Some(reason);
+        }
+    }
+    assert!(matches!(
+        terminated,
+        Some(TerminatedReason::ProxyAuthRequired)
+    ));
+
+    // the caller retries with credentials in a new transaction
+    let credential = Credential {
+        username: "alice".to_string(),
+        password: "secret123".to_string(),
+        realm: None,
+    };
+    let client_key = TransactionKey::from_request(&invite_req, TransactionRole::Client)?;
+    let client_tx = Transaction::new_client(client_key, invite_req, endpoint.inner.clone(), None);
+    let mut retry = handle_client_authenticate(2, client_tx, challenge, &credential)
+        .await?
+        .original;
+    retry
+        .headers
+        .unique_push(Via::new("SIP/2.0/UDP alice.example.com:5060;branch=z9hG4bK407b").into());
+
+    let key = TransactionKey::from_request(&retry, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, retry, endpoint.inner.clone(), Some(conn));
+    let (state_sender, _state_receiver) = unbounded_channel();
+    let dialog = dialog_layer.get_or_create_server_invite(&tx, state_sender, None, None)?;
+    assert_eq!(dialog.authorization_username().as_deref(), Some("alice"));
+    assert!(dialog.verify_authorization("example.com", &credential));
+    assert!(!dialog.verify_authorization("other.com", &credential));
+    let wrong = Credential {
+        password: "wrong".to_string(),
+        ..credential.clone()
+    };
+    assert!(!dialog.verify_authorization("example.com", &wrong));
+
+    let mut handler = Dialog::ServerInvite(dialog.clone());
+    tokio::spawn(async move { handler.handle(tx).await });
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    dialog.accept(None, None)?;
+    let resp = loop {
+        match tokio::time::timeout(std::time::Duration::from_secs(3), outgoing_rx.recv()).await {
+            Ok(Some(TransportEvent::Incoming(rsip::SipMessage::Response(resp), _, _)))
+                if resp.status_code == rsip::StatusCode::OK =>
+            {
+                break resp
+            }
+            Ok(_) => continue,
+            Err(_) => panic!("authenticated INVITE not accepted"),
+        }
+    };
+    assert_eq!(resp.cseq_header()?.seq()?, 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_server_dialog_ringing_then_early_media() -> crate::Result<()> {
+    use crate::dialog::dialog::Dialog;
+    use crate::transport::TransportEvent;
+    use rsip::prelude::{HeadersExt, ToTypedHeader};
+
+    let endpoint = create_test_endpoint().await?;
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+
+    let addr = SipAddr::from("127.0.0.1:5060".parse::<std::net::SocketAddr>().unwrap());
+    let (_incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, addr)
+            .await?
+            .into();
+
+    let invite_req = create_invite_request("alice-tag-123", "", "call-id-early", "z9hG4bKearly");
+    let key = TransactionKey::from_request(&invite_req, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, invite_req, endpoint.inner.clone(), Some(conn));
+    let (state_sender, _state_receiver) = unbounded_channel();
+    let dialog = dialog_layer.get_or_create_server_invite(&tx, state_sender, None, None)?;
+    let mut handler = Dialog::ServerInvite(dialog.clone());
+    tokio::spawn(async move { handler.handle(tx).await });
+
+    async fn next_response(
+        rx: &mut tokio::sync::mpsc::UnboundedReceiver<TransportEvent>,
+    ) -> rsip::Response {
+        loop {
+            match tokio::time::timeout(std::time::Duration::from_secs(3), rx.recv()).await {
+                Ok(Some(TransportEvent::Incoming(rsip::SipMessage::Response(resp), _, _))) => {
+                    break resp
+                }
+                Ok(_) => continue,
+                Err(_) => panic!("no response sent"),
+            }
+        }
+    }
+    let to_tag = |resp: &rsip::Response| -> crate::Result<Option<String>> {
+        Ok(resp.to_header()?.typed()?.tag().map(|tag| tag.to_string()))
+    };
+
+    let trying = next_response(&mut outgoing_rx).await;
+    assert_eq!(trying.status_code, rsip::StatusCode::Trying);
+
+    dialog.ringing(None)?;
+    let ringing = next_response(&mut outgoing_rx).await;
+    assert_eq!(ringing.status_code, rsip::StatusCode::Ringing);
+    let tag = to_tag(&ringing)?.expect("180 carries a To-tag");
+    assert_eq!(tag, dialog.id().to_tag);
+    if let Some(trying_tag) = to_tag(&trying)? {
+        assert_eq!(trying_tag, tag);
+    }
+
+    let sdp = b"v=0\r\no=- 1 1 IN IP4 10.0.0.1\r\ns=-\r\nc=IN IP4 10.0.0.1\r\nt=0 0\r\nm=audio 5000 RTP/AVP 0\r\n".to_vec();
+    dialog.progress_with_sdp(None, sdp.clone())?;
+    let progress = next_response(&mut outgoing_rx).await;
+    assert_eq!(progress.status_code, rsip::StatusCode::SessionProgress);
+    assert_eq!(progress.body, sdp);
+    assert!(progress
+        .to_string()
+        .contains("Content-Type: application/sdp\r\n"));
+    assert_eq!(to_tag(&progress)?, Some(tag.clone()));
+
+    dialog.accept(None, None)?;
+    let ok = next_response(&mut outgoing_rx).await;
+    assert_eq!(ok.status_code, rsip::StatusCode::OK);
+    assert_eq!(to_tag(&ok)?, Some(tag));
+    assert!(dialog.ringing(None).is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_server_dialog_reinvite_while_pending() -> crate::Result<()> {
+    use crate::dialog::dialog::{Dialog, DialogState};
+    use crate::transport::TransportEvent;
+
+    let endpoint = create_test_endpoint().await?;
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+
+    let addr = SipAddr::from("127.0.0.1:5060".parse::<std::net::SocketAddr>().unwrap());
+    let (_incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, addr)
+            .await?
+            .into();
+
+    let invite_req = create_invite_request("alice-tag-123", "", "call-id-glare", "z9hG4bKglare");
+    let key = TransactionKey::from_request(&invite_req, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, invite_req, endpoint.inner.clone(), Some(conn.clone()));
+    let (state_sender, _state_receiver) = unbounded_channel();
+    let dialog = dialog_layer.get_or_create_server_invite(&tx, state_sender, None, None)?;
+    dialog
+        .inner
+        .transition(DialogState::Confirmed(dialog.id()))?;
+    let to_tag = dialog.id().to_tag;
+
+    let reinvite = |seq: u32, branch: &str| {
+        let mut request = create_invite_request("alice-tag-123", &to_tag, "call-id-glare", branch);
+        request
+            .headers
+            .unique_push(CSeq::new(format!("{} INVITE", seq)).into());
+        let key = TransactionKey::from_request(&request, TransactionRole::Server).unwrap();
+        Transaction::new_server(key, request, endpoint.inner.clone(), Some(conn.clone()))
+    };
+
+    // the first re-INVITE is left unanswered
+    let mut handler = Dialog::ServerInvite(dialog.clone());
+    let first = reinvite(2, "z9hG4bKglare2");
+    tokio::spawn(async move { handler.handle(first).await });
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+    let mut handler = Dialog::ServerInvite(dialog.clone());
+    handler.handle(reinvite(3, "z9hG4bKglare3")).await?;
+    let resp = loop {
+        match tokio::time::timeout(std::time::Duration::from_secs(3), outgoing_rx.recv()).await {
+            Ok(Some(TransportEvent::Incoming(rsip::SipMessage::Response(resp), _, _))) => {
+                break resp
+            }
+            Ok(_) => continue,
+            Err(_) => panic!("no response sent"),
+        }
+    };
+    assert_eq!(resp.status_code, rsip::StatusCode::ServerInternalError);
+    assert_eq!(resp.cseq_header()?.seq()?, 3);
+    let retry_after = resp
+        .headers
+        .iter()
+        .find_map(|h| match h {
+            rsip::Header::RetryAfter(retry_after) => Some(retry_after.value().to_string()),
+            _ => None,
+        })
+        .expect("500 carries Retry-After");
+    assert!(retry_after.parse::<u32>().unwrap() <= 10);
+
+    // answering the first re-INVITE clears the guard
+    dialog.accept(None, None)?;
+    assert!(!dialog
+        .inner
+        .pending_invite
+        .load(std::sync::atomic::Ordering::Relaxed));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_restore_dialog_from_snapshot() -> crate::Result<()> {
+    use crate::dialog::dialog::DialogState;
+
+    let endpoint = create_test_endpoint().await?;
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+
+    let mut invite_req =
+        create_invite_request("alice-tag-123", "", "call-id-failover", "z9hG4bKfailover");
+    invite_req
+        .headers
+        .push(RecordRoute::new("<sip:p1.example.com;lr>").into());
+    invite_req
+        .headers
+        .push(RecordRoute::new("<sip:p2.example.com;lr>").into());
+    let key = TransactionKey::from_request(&invite_req, TransactionRole::Server)?;
+    let tx = Transaction::new_server(key, invite_req, endpoint.inner.clone(), None);
+    let (state_sender, _) = unbounded_channel();
+    let dialog = dialog_layer.get_or_create_server_invite(&tx, state_sender, None, None)?;
+    dialog
+        .inner
+        .transition(DialogState::Confirmed(dialog.id()))?;
+    let snapshot = crate::dialog::dialog::Dialog::ServerInvite(dialog.clone()).serialize_state();
+    assert_eq!(snapshot.role, TransactionRole::Server);
+    assert_eq!(snapshot.remote_uri, "sip:alice@alice.example.com:5060");
+    assert_eq!(
+        snapshot.route_set,
+        vec!["<sip:p1.example.com;lr>", "<sip:p2.example.com;lr>"]
+    );
+
+    // another instance takes over the call
+    let standby = create_test_endpoint().await?;
+    let standby_layer = DialogLayer::new(standby.inner.clone());
+    let (state_sender, _) = unbounded_channel();
+    let restored = standby_layer.restore_dialog(snapshot.clone(), state_sender, None)?;
+    assert_eq!(restored.id(), dialog.id());
+    assert!(restored.inner().state.lock().unwrap().is_confirmed());
+    assert!(standby_layer.get_dialog(&dialog.id()).is_some());
+
+    let bye = restored
+        .inner()
+        .make_request(rsip::Method::Bye, None, None, None, None, None)?;
+    assert_eq!(bye.uri.to_string(), "sip:alice@alice.example.com:5060");
+    assert_eq!(bye.cseq_header()?.seq()?, snapshot.local_seq + 1);
+    let routes = bye
+        .headers
+        .iter()
+        .filter_map(|h| match h {
+            rsip::Header::Route(route) => Some(route.value().to_string()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(routes, snapshot.route_set);
+    assert_eq!(bye.call_id_header()?.value(), "call-id-failover");
+    assert!(bye
+        .from_header()?
+        .value()
+        .contains(&format!("tag={}", dialog.id().to_tag)));
+    assert!(bye.to_header()?.value().contains("tag=alice-tag-123"));
+    Ok(())
+}
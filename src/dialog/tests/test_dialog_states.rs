@@ -429,3 +429,327 @@ async fn test_dialog_id_creation() -> crate::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_dialog_id_matches_either_perspective() {
+    let uac = DialogId {
+        call_id: "call-id-match".to_string(),
+        from_tag: "alice-tag".to_string(),
+        to_tag: "bob-tag".to_string(),
+    };
+    let uas = DialogId {
+        call_id: "call-id-match".to_string(),
+        from_tag: "bob-tag".to_string(),
+        to_tag: "alice-tag".to_string(),
+    };
+    assert!(uac.matches(&uac));
+    assert!(uac.matches(&uas));
+    assert!(uas.matches(&uac));
+
+    let other_call = DialogId {
+        call_id: "call-id-other".to_string(),
+        ..uas.clone()
+    };
+    assert!(!uac.matches(&other_call));
+
+    let other_tag = DialogId {
+        to_tag: "carol-tag".to_string(),
+        ..uas.clone()
+    };
+    assert!(!uac.matches(&other_tag));
+
+    let same_tags = DialogId {
+        call_id: "call-id-match".to_string(),
+        from_tag: "alice-tag".to_string(),
+        to_tag: "alice-tag".to_string(),
+    };
+    assert!(!uac.matches(&same_tags));
+}
+
+#[test]
+fn test_dialog_id_from_response_requires_to_tag() {
+    let mut response = create_response(StatusCode::Trying, "alice-tag-123", "", "call-id-456");
+    response
+        .headers
+        .retain(|h| !matches!(h, rsip::Header::To(_)));
+    response
+        .headers
+        .push(To::new("Bob <sip:bob@example.com>").into());
+    assert!(DialogId::try_from(&response).is_err());
+}
+
+#[test]
+fn test_dialog_id_display_from_str_round_trip() {
+    use std::str::FromStr;
+
+    let ids = [
+        ("a84b4c76-e667-4710-8b9a-1c2d3e4f5a6b@restsend.com", "alicetag123", "bobtag789"),
+        ("call-id-early", "alicetag", ""),
+        ("plain", "1928301774", "a6c85cf"),
+        ("odd-call-id", "", ""),
+    ];
+    for (call_id, from_tag, to_tag) in ids {
+        let id = DialogId {
+            call_id: call_id.to_string(),
+            from_tag: from_tag.to_string(),
+            to_tag: to_tag.to_string(),
+        };
+        assert_eq!(DialogId::from_str(&id.to_string()).unwrap(), id);
+    }
+
+    assert!(DialogId::from_str("").is_err());
+    assert!(DialogId::from_str("callidonly").is_err());
+    assert!(DialogId::from_str("-alice-bob").is_err());
+}
+
+#[tokio::test]
+async fn test_make_request_stacks_extra_vias() -> crate::Result<()> {
+    let endpoint = create_test_endpoint().await?;
+    let (state_sender, _state_receiver) = unbounded_channel();
+
+    let dialog_id = DialogId {
+        call_id: "test-call-id-vias".to_string(),
+        from_tag: "alice-tag".to_string(),
+        to_tag: "bob-tag".to_string(),
+    };
+    let invite_req = create_invite_request("alice-tag", "bob-tag", "test-call-id-vias");
+    let dialog_inner = DialogInner::new(
+        TransactionRole::Client,
+        dialog_id,
+        invite_req,
+        endpoint.inner.clone(),
+        state_sender,
+        None,
+        Some(rsip::Uri::try_from("sip:alice@alice.example.com:5060")?),
+    )?;
+
+    let local_addr = crate::transport::SipAddr {
+        r#type: Some(rsip::transport::Transport::Udp),
+        addr: rsip::HostWithPort::try_from("192.0.2.10:5060")?,
+    };
+    let request = dialog_inner.make_request(
+        rsip::Method::Info,
+        None,
+        Some(local_addr),
+        None,
+        Some(vec![
+            rsip::Header::ContentType("application/dtmf-relay".into()),
+            Via::new("SIP/2.0/UDP proxy1.example.com:5060;branch=z9hG4bKproxy1").into(),
+            Via::new("SIP/2.0/UDP proxy2.example.com:5060;branch=z9hG4bKproxy2").into(),
+        ]),
+        None,
+    )?;
+
+    let vias = request
+        .headers
+        .iter()
+        .filter_map(|h| match h {
+            rsip::Header::Via(via) => Some(via.to_string()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(vias.len(), 3);
+    assert!(vias[0].contains("192.0.2.10:5060"));
+    assert!(vias[1].contains("proxy1.example.com"));
+    assert!(vias[2].contains("proxy2.example.com"));
+    assert!(matches!(request.headers.iter().next(), Some(rsip::Header::Via(_))));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_make_request_capability_headers() -> crate::Result<()> {
+    use rsip::prelude::UntypedHeader;
+
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_supported(vec!["timer".to_string(), "replaces".to_string()])
+        .build();
+    let (state_sender, _state_receiver) = unbounded_channel();
+    let dialog_id = DialogId {
+        call_id: "test-call-id-caps".to_string(),
+        from_tag: "alice-tag".to_string(),
+        to_tag: "bob-tag".to_string(),
+    };
+    let dialog_inner = DialogInner::new(
+        TransactionRole::Client,
+        dialog_id,
+        create_invite_request("alice-tag", "bob-tag", "test-call-id-caps"),
+        endpoint.inner.clone(),
+        state_sender,
+        None,
+        Some(rsip::Uri::try_from("sip:alice@alice.example.com:5060")?),
+    )?;
+
+    let bye = dialog_inner.make_request(
+        rsip::Method::Bye,
+        None,
+        None,
+        None,
+        Some(dialog_inner.capability_headers()),
+        None,
+    )?;
+    let supported = bye
+        .headers
+        .iter()
+        .find_map(|h| match h {
+            rsip::Header::Supported(s) => Some(s.value().to_string()),
+            _ => None,
+        })
+        .expect("BYE carries Supported");
+    assert_eq!(supported, "timer, replaces");
+    let allow = bye
+        .headers
+        .iter()
+        .find_map(|h| match h {
+            rsip::Header::Allow(a) => Some(a.value().to_string()),
+            _ => None,
+        })
+        .expect("BYE carries Allow");
+    assert!(allow.contains("BYE"));
+
+    // ACK is built without them, even when asked to
+    let ack = dialog_inner.make_request(
+        rsip::Method::Ack,
+        Some(1),
+        None,
+        None,
+        Some(dialog_inner.capability_headers()),
+        None,
+    )?;
+    assert!(!ack
+        .headers
+        .iter()
+        .any(|h| matches!(h, rsip::Header::Allow(_) | rsip::Header::Supported(_))));
+    let ack = rsip::Request::try_from(ack.to_string().as_str())?;
+    assert_eq!(ack.method, rsip::Method::Ack);
+    assert!(ack.to_string().contains("CSeq: 1 ACK\r\n"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_make_request_bodiless_content_length() -> crate::Result<()> {
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .build();
+    let (state_sender, _state_receiver) = unbounded_channel();
+    let dialog_id = DialogId {
+        call_id: "test-call-id-length".to_string(),
+        from_tag: "alice-tag".to_string(),
+        to_tag: "bob-tag".to_string(),
+    };
+    let dialog_inner = DialogInner::new(
+        TransactionRole::Client,
+        dialog_id,
+        create_invite_request("alice-tag", "bob-tag", "test-call-id-length"),
+        endpoint.inner.clone(),
+        state_sender,
+        None,
+        Some(rsip::Uri::try_from("sip:alice@alice.example.com:5060")?),
+    )?;
+
+    for method in [rsip::Method::Options, rsip::Method::Bye] {
+        let request = dialog_inner.make_request(method, None, None, None, None, None)?;
+        let lengths = request
+            .headers
+            .iter()
+            .filter_map(|h| match h {
+                rsip::Header::ContentLength(len) => len.length().ok(),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(lengths, vec![0]);
+        assert!(request.to_string().contains("Content-Length: 0\r\n"));
+    }
+
+    // a body gets its real length
+    let info = dialog_inner.make_request(
+        rsip::Method::Info,
+        None,
+        None,
+        None,
+        None,
+        Some(b"Signal=1\r\n".to_vec()),
+    )?;
+    assert!(info.to_string().contains("Content-Length: 10\r\n"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dialog_state_predicates() -> crate::Result<()> {
+    let dialog_id = DialogId {
+        call_id: "test-call-id-predicates".to_string(),
+        from_tag: "alice-tag".to_string(),
+        to_tag: "bob-tag".to_string(),
+    };
+    let request = create_invite_request("alice-tag", "bob-tag", "test-call-id-predicates");
+    let ringing = create_response(
+        StatusCode::Ringing,
+        "alice-tag",
+        "bob-tag",
+        "test-call-id-predicates",
+    );
+
+    // (state, is_early, is_confirmed/is_established, is_terminated)
+    let states = vec![
+        (DialogState::Calling(dialog_id.clone()), false, false, false),
+        (DialogState::Trying(dialog_id.clone()), false, false, false),
+        (
+            DialogState::Early(dialog_id.clone(), ringing.clone()),
+            true,
+            false,
+            false,
+        ),
+        (
+            DialogState::WaitAck(dialog_id.clone(), ringing),
+            false,
+            false,
+            false,
+        ),
+        (DialogState::Confirmed(dialog_id.clone()), false, true, false),
+        (
+            DialogState::Updated(dialog_id.clone(), request.clone()),
+            false,
+            false,
+            false,
+        ),
+        (
+            DialogState::Notify(dialog_id.clone(), request.clone()),
+            false,
+            false,
+            false,
+        ),
+        (
+            DialogState::Info(dialog_id.clone(), request.clone()),
+            false,
+            false,
+            false,
+        ),
+        (
+            DialogState::Options(dialog_id.clone(), request),
+            false,
+            false,
+            false,
+        ),
+        (
+            DialogState::Terminated(dialog_id.clone(), TerminatedReason::UacBye),
+            false,
+            false,
+            true,
+        ),
+    ];
+
+    for (state, early, established, terminated) in states {
+        assert_eq!(state.is_early(), early, "is_early for {}", state);
+        assert_eq!(state.is_confirmed(), established, "is_confirmed for {}", state);
+        assert_eq!(state.is_established(), established, "is_established for {}", state);
+        assert_eq!(state.is_terminated(), terminated, "is_terminated for {}", state);
+        assert_eq!(state.dialog_id(), &dialog_id);
+    }
+    Ok(())
+}
@@ -0,0 +1,2065 @@
+//! Registration tests
+//!
+//! Tests for public address discovery from REGISTER responses
+
+use crate::dialog::registration::Registration;
+use crate::transaction::{
+    endpoint::EndpointBuilder,
+    key::{TransactionKey, TransactionRole},
+    transaction::Transaction,
+};
+use crate::transport::{udp::UdpConnection, TransportLayer};
+use rsip::headers::*;
+use tokio_util::sync::CancellationToken;
+
+#[tokio::test]
+async fn test_received_without_rport_uses_bound_port() -> crate::Result<()> {
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    // Advertise an external port that differs from the one actually bound
+    let udp = UdpConnection::create_connection(
+        "127.0.0.1:0".parse().unwrap(),
+        Some("127.0.0.1:5999".parse().unwrap()),
+    )
+    .await?;
+    let bound_port = udp.get_local_addr().addr.port.map(|p| *p.value());
+    tl.add_transport(udp.into());
+
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .build();
+
+    let peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let peer_addr = peer.local_addr()?;
+
+    let via = endpoint.inner.get_via(None, None)?;
+    let from = rsip::typed::From {
+        display_name: None,
+        uri: rsip::Uri::try_from("sip:alice@example.com").unwrap(),
+        params: vec![],
+    };
+    let to = rsip::typed::To {
+        display_name: None,
+        uri: rsip::Uri::try_from("sip:alice@example.com").unwrap(),
+        params: vec![],
+    };
+    let req_uri = rsip::Uri::try_from(format!("sip:{}", peer_addr).as_str()).unwrap();
+    let request = endpoint
+        .inner
+        .make_request(rsip::Method::Register, req_uri, via, from, to, 1);
+
+    let key = TransactionKey::from_request(&request, TransactionRole::Client)?;
+    let mut tx = Transaction::new_client(key, request, endpoint.inner.clone(), None);
+    assert!(Registration::local_port(&tx).is_none());
+    tx.send().await?;
+
+    // the port comes from the transaction's own socket, not a shared slot
+    let local_port = Registration::local_port(&tx);
+    assert_eq!(local_port, bound_port);
+
+    let response = rsip::Response {
+        status_code: rsip::StatusCode::OK,
+        version: rsip::Version::V2,
+        headers: vec![Via::new(
+            "SIP/2.0/UDP 127.0.0.1:5999;branch=z9hG4bKtest;received=198.51.100.7;rport",
+        )
+        .into()]
+        .into(),
+        body: vec![],
+    };
+    let public = Registration::public_address_from_via(&response, local_port)
+        .expect("public address from received");
+    assert_eq!(public.host.to_string(), "198.51.100.7");
+    assert_eq!(public.port.map(|p| *p.value()), bound_port);
+
+    // An explicit rport value always wins over the local port
+    let response = rsip::Response {
+        status_code: rsip::StatusCode::OK,
+        version: rsip::Version::V2,
+        headers: vec![Via::new(
+            "SIP/2.0/UDP 127.0.0.1:5999;branch=z9hG4bKtest;received=198.51.100.7;rport=40000",
+        )
+        .into()]
+        .into(),
+        body: vec![],
+    };
+    let public = Registration::public_address_from_via(&response, bound_port).unwrap();
+    assert_eq!(public.port.map(|p| *p.value()), Some(40000));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_registration_server_time_and_clock_skew() -> crate::Result<()> {
+    let token = CancellationToken::new();
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(TransportLayer::new(token.child_token()))
+        .build();
+    let mut registration = Registration::new(endpoint.inner.clone(), None);
+    assert!(registration.server_time().is_none());
+    assert!(registration.clock_skew().is_none());
+
+    let response = rsip::Response {
+        status_code: rsip::StatusCode::OK,
+        version: rsip::Version::V2,
+        headers: vec![
+            Via::new("SIP/2.0/UDP 127.0.0.1:5060;branch=z9hG4bKtest").into(),
+            Date::new("Sat, 13 Nov 2010 23:29:00 GMT").into(),
+        ]
+        .into(),
+        body: vec![],
+    };
+    registration.update_server_time(&response);
+
+    let server_time = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1289690940);
+    assert_eq!(registration.server_time(), Some(server_time));
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let skew = registration.clock_skew().expect("clock skew computed");
+    assert!((skew - (1289690940 - now)).abs() <= 1);
+    assert!(skew < 0);
+    Ok(())
+}
+
+/// Mock registrar answering the first REGISTER with a 401 challenge
+async fn challenge_once(registrar: &tokio::net::UdpSocket) -> crate::Result<()> {
+    let mut buf = vec![0u8; 4096];
+    let (len, from) = registrar.recv_from(&mut buf).await?;
+    let request = rsip::Request::try_from(&buf[..len])?;
+    let mut headers: rsip::Headers = request
+        .headers
+        .iter()
+        .filter(|h| {
+            matches!(
+                h,
+                rsip::Header::Via(_)
+                    | rsip::Header::From(_)
+                    | rsip::Header::To(_)
+                    | rsip::Header::CallId(_)
+                    | rsip::Header::CSeq(_)
+            )
+        })
+        .cloned()
+        .collect::<Vec<_>>()
+        .into();
+    headers.push(
+        WwwAuthenticate::new(r#"Digest realm="example.com", nonce="abc123", algorithm=MD5"#).into(),
+    );
+    headers.push(ContentLength::from(0u32).into());
+    let response = rsip::Response {
+        status_code: rsip::StatusCode::Unauthorized,
+        version: rsip::Version::V2,
+        headers,
+        body: vec![],
+    };
+    registrar
+        .send_to(response.to_string().as_bytes(), from)
+        .await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_register_without_credential_strict_auth() -> crate::Result<()> {
+    let token = CancellationToken::new();
+    let registrar = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let registrar_addr = registrar.local_addr()?.to_string();
+
+    let mut statuses = vec![];
+    for strict_auth in [false, true] {
+        let tl = TransportLayer::new(token.child_token());
+        let udp = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None).await?;
+        tl.add_transport(udp.into());
+        let endpoint = EndpointBuilder::new()
+            .with_user_agent("rsipstack-test")
+            .with_transport_layer(tl)
+            .with_cancel_token(token.child_token())
+            .with_strict_auth(strict_auth)
+            .build();
+        let inner = endpoint.inner.clone();
+        tokio::spawn(async move { inner.serve().await });
+
+        let mut registration = Registration::new(endpoint.inner.clone(), None);
+        let (result, served) = tokio::join!(
+            registration.register(&registrar_addr),
+            challenge_once(&registrar)
+        );
+        served?;
+        statuses.push(result);
+    }
+
+    // Default: the challenge is handed back to the caller
+    assert!(matches!(
+        statuses[0],
+        Ok(ref resp) if resp.status_code == rsip::StatusCode::Unauthorized
+    ));
+    // Strict: a missing credential is an error
+    assert_eq!(
+        statuses[1],
+        Err(crate::Error::AuthenticationRequired(
+            rsip::StatusCode::Unauthorized
+        ))
+    );
+    token.cancel();
+    Ok(())
+}
+
+/// Mock registrar accepting one REGISTER, returning the request it saw
+async fn accept_once(registrar: &tokio::net::UdpSocket) -> crate::Result<rsip::Request> {
+    let mut buf = vec![0u8; 4096];
+    let (len, from) = registrar.recv_from(&mut buf).await?;
+    let request = rsip::Request::try_from(&buf[..len])?;
+    registrar
+        .send_to(ok_response(&request).to_string().as_bytes(), from)
+        .await?;
+    Ok(request)
+}
+
+/// 200 OK to a REGISTER, echoing its Contact
+fn ok_response(request: &rsip::Request) -> rsip::Response {
+    let mut headers: rsip::Headers = request
+        .headers
+        .iter()
+        .filter(|h| {
+            matches!(
+                h,
+                rsip::Header::Via(_)
+                    | rsip::Header::From(_)
+                    | rsip::Header::To(_)
+                    | rsip::Header::CallId(_)
+                    | rsip::Header::CSeq(_)
+                    | rsip::Header::Contact(_)
+            )
+        })
+        .cloned()
+        .collect::<Vec<_>>()
+        .into();
+    headers.push(ContentLength::from(0u32).into());
+    rsip::Response {
+        status_code: rsip::StatusCode::OK,
+        version: rsip::Version::V2,
+        headers,
+        body: vec![],
+    }
+}
+
+/// 200 OK to a REGISTER reporting the public address 198.51.100.7:40000
+fn nat_ok_response(request: &rsip::Request) -> rsip::Response {
+    use rsip::prelude::UntypedHeader;
+
+    let mut response = ok_response(request);
+    for header in response.headers.iter_mut() {
+        if let rsip::Header::Via(via) = header {
+            *via = Via::new(
+                via.value()
+                    .replace(";rport", ";received=198.51.100.7;rport=40000"),
+            );
+        }
+    }
+    response
+}
+
+#[tokio::test]
+async fn test_reregister_on_public_address_change() -> crate::Result<()> {
+    use rsip::prelude::{HeadersExt, ToTypedHeader};
+
+    let token = CancellationToken::new();
+    let registrar = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let registrar_addr = registrar.local_addr()?.to_string();
+
+    let tl = TransportLayer::new(token.child_token());
+    let udp = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None).await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let mut registration = Registration::new(endpoint.inner.clone(), None);
+    let mock = async {
+        let mut buf = vec![0u8; 4096];
+        let (len, from) = registrar.recv_from(&mut buf).await?;
+        let first = rsip::Request::try_from(&buf[..len])?;
+        registrar
+            .send_to(nat_ok_response(&first).to_string().as_bytes(), from)
+            .await?;
+        let (len, from) = registrar.recv_from(&mut buf).await?;
+        let second = rsip::Request::try_from(&buf[..len])?;
+        registrar
+            .send_to(nat_ok_response(&second).to_string().as_bytes(), from)
+            .await?;
+        crate::Result::Ok((first, second))
+    };
+    let (result, requests) = tokio::join!(registration.register(&registrar_addr), mock);
+    let (first, second) = requests?;
+    assert_eq!(result?.status_code, rsip::StatusCode::OK);
+
+    let contact_host = |request: &rsip::Request| -> crate::Result<String> {
+        Ok(request
+            .contact_header()?
+            .typed()?
+            .uri
+            .host_with_port
+            .to_string())
+    };
+    assert_ne!(contact_host(&first)?, "198.51.100.7:40000");
+    assert_eq!(contact_host(&second)?, "198.51.100.7:40000");
+    // the private binding is removed by the same re-REGISTER
+    let contacts = second
+        .headers
+        .iter()
+        .filter_map(|h| match h {
+            rsip::Header::Contact(contact) => contact.typed().ok(),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(contacts.len(), 2);
+    assert_eq!(
+        contacts[1].uri.host_with_port.to_string(),
+        contact_host(&first)?
+    );
+    assert_eq!(
+        contacts[1].expires().and_then(|e| e.seconds().ok()),
+        Some(0)
+    );
+    assert_eq!(
+        second.cseq_header()?.seq()?,
+        first.cseq_header()?.seq()? + 1
+    );
+    assert_eq!(
+        registration
+            .discovered_public_address()
+            .map(|a| a.to_string()),
+        Some("198.51.100.7:40000".to_string())
+    );
+    token.cancel();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sticky_contact_survives_public_address_change() -> crate::Result<()> {
+    use rsip::prelude::{HeadersExt, ToTypedHeader};
+
+    let token = CancellationToken::new();
+    let registrar = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let registrar_addr = registrar.local_addr()?.to_string();
+
+    let tl = TransportLayer::new(token.child_token());
+    let udp = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None).await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let mut registration = Registration::new(endpoint.inner.clone(), None);
+    registration.sticky_contact = true;
+    registration.contact = Some(rsip::typed::Contact {
+        display_name: Some("Alice Desk".to_string()),
+        uri: rsip::Uri::try_from("sip:alice@192.0.2.10:5060;gr=urn:uuid:f81d4fae")?,
+        params: vec![rsip::Param::Other(
+            "+sip.instance".into(),
+            Some("\"<urn:uuid:f81d4fae>\"".into()),
+        )],
+    });
+    let mock = async {
+        let mut buf = vec![0u8; 4096];
+        let mut requests = vec![];
+        for _ in 0..2 {
+            let (len, from) = registrar.recv_from(&mut buf).await?;
+            let request = rsip::Request::try_from(&buf[..len])?;
+            registrar
+                .send_to(nat_ok_response(&request).to_string().as_bytes(), from)
+                .await?;
+            requests.push(request);
+        }
+        crate::Result::Ok(requests)
+    };
+    let (result, requests) = tokio::join!(registration.register(&registrar_addr), mock);
+    let requests = requests?;
+    assert_eq!(result?.status_code, rsip::StatusCode::OK);
+
+    // the re-REGISTER only moves the Contact to the public address
+    let contact = requests[1].contact_header()?.typed()?;
+    assert_eq!(contact.display_name.as_deref(), Some("Alice Desk"));
+    assert_eq!(
+        contact.uri.auth.as_ref().map(|a| a.user.as_str()),
+        Some("alice")
+    );
+    assert_eq!(contact.uri.host_with_port.to_string(), "198.51.100.7:40000");
+    assert_eq!(contact.uri.params, registration.contact.as_ref().unwrap().uri.params);
+    assert!(contact.to_string().contains("+sip.instance"));
+    assert!(contact.uri.to_string().contains("gr=urn:uuid:f81d4fae"));
+
+    let stored = registration.contact.as_ref().expect("contact kept");
+    assert_eq!(stored.uri.host_with_port.to_string(), "198.51.100.7:40000");
+    assert!(stored.to_string().contains("+sip.instance"));
+    token.cancel();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_registration_refresh_reuses_contact() -> crate::Result<()> {
+    use rsip::prelude::HeadersExt;
+
+    let token = CancellationToken::new();
+    let registrar = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let registrar_addr = registrar.local_addr()?.to_string();
+
+    let tl = TransportLayer::new(token.child_token());
+    let udp = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None).await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let mut registration = Registration::new(endpoint.inner.clone(), None);
+    assert!(registration.refresh().await.is_err());
+
+    let (result, first) = tokio::join!(
+        registration.register(&registrar_addr),
+        accept_once(&registrar)
+    );
+    assert_eq!(result?.status_code, rsip::StatusCode::OK);
+    let (result, second) = tokio::join!(registration.refresh(), accept_once(&registrar));
+    assert_eq!(result?.status_code, rsip::StatusCode::OK);
+    let (first, second) = (first?, second?);
+
+    assert_eq!(
+        first.contact_header()?.to_string(),
+        second.contact_header()?.to_string()
+    );
+    assert_eq!(first.uri, second.uri);
+    assert_eq!(
+        second.cseq_header()?.seq()?,
+        first.cseq_header()?.seq()? + 1
+    );
+    token.cancel();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_unregister_single_binding() -> crate::Result<()> {
+    use rsip::prelude::{HeadersExt, ToTypedHeader, UntypedHeader};
+
+    let token = CancellationToken::new();
+    let registrar = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let registrar_addr = registrar.local_addr()?.to_string();
+
+    let tl = TransportLayer::new(token.child_token());
+    let udp = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None).await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let desk = rsip::Uri::try_from("sip:alice@192.0.2.10:5060")?;
+    let laptop = rsip::Uri::try_from("sip:alice@192.0.2.20:5060")?;
+    let mut registration = Registration::new(endpoint.inner.clone(), None);
+    registration.contact = Some(rsip::typed::Contact {
+        display_name: None,
+        uri: desk.clone(),
+        params: vec![],
+    });
+
+    let (result, _) = tokio::join!(
+        registration.register(&registrar_addr),
+        accept_once(&registrar)
+    );
+    assert_eq!(result?.status_code, rsip::StatusCode::OK);
+
+    let (result, request) = tokio::join!(
+        registration.unregister_binding(&registrar_addr, &laptop),
+        accept_once(&registrar)
+    );
+    assert_eq!(result?.status_code, rsip::StatusCode::OK);
+    let request = request?;
+    let contacts = request
+        .headers
+        .iter()
+        .filter(|h| matches!(h, rsip::Header::Contact(_)))
+        .count();
+    assert_eq!(contacts, 1);
+    let contact = request.contact_header()?.typed()?;
+    assert_eq!(contact.uri, laptop);
+    assert_eq!(contact.expires().and_then(|e| e.seconds().ok()), Some(0));
+    assert!(!request.contact_header()?.value().contains('*'));
+
+    // the registration keeps refreshing its own binding
+    let (result, request) = tokio::join!(registration.refresh(), accept_once(&registrar));
+    assert_eq!(result?.status_code, rsip::StatusCode::OK);
+    assert_eq!(request?.contact_header()?.typed()?.uri, desk);
+    token.cancel();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_register_through_outbound_proxy() -> crate::Result<()> {
+    use crate::transport::SipAddr;
+    use rsip::prelude::{HeadersExt, UntypedHeader};
+
+    let token = CancellationToken::new();
+    let registrar = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let registrar_addr = registrar.local_addr()?.to_string();
+    let proxy = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let proxy_addr = SipAddr::from(proxy.local_addr()?);
+
+    let mut tl = TransportLayer::new(token.child_token());
+    let udp = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None).await?;
+    tl.add_transport(udp.into());
+    tl.outbound = Some(SipAddr {
+        r#type: Some(rsip::Transport::Udp),
+        ..proxy_addr.clone()
+    });
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let mut registration = Registration::new(endpoint.inner.clone(), None);
+    let (result, request) = tokio::join!(
+        registration.register(&registrar_addr),
+        accept_once(&proxy)
+    );
+    assert_eq!(result?.status_code, rsip::StatusCode::OK);
+    let request = request?;
+    assert_eq!(request.uri.host_with_port.to_string(), registrar_addr);
+    let route = request.route_header().expect("Route header").value();
+    assert!(route.contains(&proxy_addr.addr.to_string()));
+    assert!(route.contains(";lr"));
+
+    // nothing reached the registrar directly
+    let mut buf = vec![0u8; 4096];
+    assert!(tokio::time::timeout(
+        std::time::Duration::from_millis(200),
+        registrar.recv_from(&mut buf)
+    )
+    .await
+    .is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_register_negotiated_transport() -> crate::Result<()> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let token = CancellationToken::new();
+    let registrar = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let registrar_addr = format!("{};transport=tcp", registrar.local_addr()?);
+
+    let tl = TransportLayer::new(token.child_token());
+    let udp = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None).await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let mock = async {
+        let (mut stream, _) = registrar.accept().await?;
+        let mut buf = Vec::new();
+        let mut chunk = [0u8; 4096];
+        while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+            let n = stream.read(&mut chunk).await?;
+            if n == 0 {
+                break;
+            }
+            buf.extend_from_slice(&chunk[..n]);
+        }
+        let request = rsip::Request::try_from(buf.as_slice())?;
+        stream
+            .write_all(ok_response(&request).to_string().as_bytes())
+            .await?;
+        crate::Result::Ok(stream)
+    };
+
+    let mut registration = Registration::new(endpoint.inner.clone(), None);
+    assert!(registration.negotiated_transport().is_none());
+    let (result, stream) = tokio::join!(registration.register(&registrar_addr), mock);
+    let _stream = stream?;
+    assert_eq!(result?.status_code, rsip::StatusCode::OK);
+    assert_eq!(
+        registration.negotiated_transport(),
+        Some(rsip::Transport::Tcp)
+    );
+    token.cancel();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_register_comma_separated_bindings() -> crate::Result<()> {
+    let token = CancellationToken::new();
+    let registrar = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let registrar_addr = registrar.local_addr()?.to_string();
+
+    let tl = TransportLayer::new(token.child_token());
+    let udp = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None).await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let desk = rsip::Uri::try_from("sip:alice@192.0.2.10:5060")?;
+    let mut registration = Registration::new(endpoint.inner.clone(), None);
+    registration.contact = Some(rsip::typed::Contact {
+        display_name: None,
+        uri: desk.clone(),
+        params: vec![],
+    });
+
+    // the registrar lists both bindings of the AOR in one Contact header
+    let mock = async {
+        let mut buf = vec![0u8; 4096];
+        let (len, from) = registrar.recv_from(&mut buf).await?;
+        let request = rsip::Request::try_from(&buf[..len])?;
+        let mut response = ok_response(&request);
+        response
+            .headers
+            .retain(|h| !matches!(h, rsip::Header::Contact(_)));
+        response.headers.push(
+            Contact::new(
+                "<sip:alice@192.0.2.20:5060>;expires=3600, <sip:alice@192.0.2.10:5060>;expires=1800",
+            )
+            .into(),
+        );
+        registrar
+            .send_to(response.to_string().as_bytes(), from)
+            .await?;
+        crate::Result::Ok(())
+    };
+    let (result, mock) = tokio::join!(registration.register(&registrar_addr), mock);
+    mock?;
+    assert_eq!(result?.status_code, rsip::StatusCode::OK);
+
+    let bindings = registration.bindings();
+    assert_eq!(bindings.len(), 2);
+    assert_eq!(
+        bindings[0].uri.host_with_port.to_string(),
+        "192.0.2.20:5060"
+    );
+    assert_eq!(bindings[1].uri, desk);
+    assert_eq!(registration.expires(), 1800);
+    token.cancel();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_register_flow_timer_adapts_keepalive() -> crate::Result<()> {
+    use crate::transport::{
+        keepalive::{Keepalive, DEFAULT_KEEPALIVE_INTERVAL},
+        SipAddr, SipConnection,
+    };
+    use std::time::Duration;
+
+    let token = CancellationToken::new();
+    let registrar = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let registrar_addr = registrar.local_addr()?.to_string();
+    // keepalives go to an edge proxy so they do not reach the mock registrar
+    let edge = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+
+    let tl = TransportLayer::new(token.child_token());
+    let udp: SipConnection = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None)
+        .await?
+        .into();
+    tl.add_transport(udp.clone());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let mut registration = Registration::new(endpoint.inner.clone(), None);
+    // no CRLF pings over UDP: the flow needs a payload of its own
+    assert!(registration.keepalive().is_none());
+    assert!(registration
+        .start_keepalive(udp.clone(), SipAddr::from(edge.local_addr()?))
+        .is_none());
+
+    let mut ping = Keepalive::default();
+    ping.with_payload(b"PING".to_vec(), |data| data == b"PONG");
+    registration.udp_keepalive = Some(ping);
+    let first = registration
+        .start_keepalive(udp, SipAddr::from(edge.local_addr()?))
+        .expect("keepalive started");
+    assert_eq!(first.keepalive().interval, DEFAULT_KEEPALIVE_INTERVAL);
+    assert_eq!(first.keepalive().payload, b"PING");
+
+    let mock = async {
+        let mut buf = vec![0u8; 4096];
+        let (len, from) = registrar.recv_from(&mut buf).await?;
+        let request = rsip::Request::try_from(&buf[..len])?;
+        let mut response = ok_response(&request);
+        response
+            .headers
+            .push(rsip::Header::Other("Flow-Timer".into(), "25".into()));
+        registrar
+            .send_to(response.to_string().as_bytes(), from)
+            .await?;
+        crate::Result::Ok(())
+    };
+    let (result, mock) = tokio::join!(registration.register(&registrar_addr), mock);
+    mock?;
+    assert_eq!(result?.status_code, rsip::StatusCode::OK);
+
+    assert_eq!(registration.flow_timer(), Some(25));
+    assert_eq!(
+        registration.keepalive().map(|keepalive| keepalive.interval),
+        Some(Duration::from_secs(20))
+    );
+    // the running keepalive was restarted with the registrar's interval
+    assert!(first.is_stopped());
+    let current = registration
+        .keepalive_handle()
+        .cloned()
+        .expect("keepalive running");
+    assert!(!current.is_stopped());
+    assert_eq!(current.keepalive().interval, Duration::from_secs(20));
+    assert_eq!(current.target(), first.target());
+
+    registration.stop_keepalive();
+    assert!(current.is_stopped());
+    token.cancel();
+    Ok(())
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_registration_refresh_with_paused_clock() -> crate::Result<()> {
+    use crate::transport::{channel::ChannelConnection, SipAddr, TransportEvent};
+    use rsip::prelude::HeadersExt;
+    use std::time::Duration;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    let local_addr = SipAddr::new(
+        rsip::Transport::Udp,
+        rsip::HostWithPort::try_from("127.0.0.1:15300")?,
+    );
+    let registrar_addr = SipAddr::new(
+        rsip::Transport::Udp,
+        rsip::HostWithPort::try_from("127.0.0.1:15310")?,
+    );
+    let (incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, local_addr)
+            .await?
+            .into();
+
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    tl.add_transport(conn.clone());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    // registrar answering every REGISTER with 200 OK
+    let (seen_tx, mut seen_rx) = unbounded_channel();
+    let from = registrar_addr.clone();
+    tokio::spawn(async move {
+        while let Some(event) = outgoing_rx.recv().await {
+            if let TransportEvent::Incoming(rsip::SipMessage::Request(request), _, _) = event {
+                let response = ok_response(&request);
+                incoming_tx
+                    .send(TransportEvent::Incoming(
+                        response.into(),
+                        conn.clone(),
+                        from.clone(),
+                    ))
+                    .ok();
+                seen_tx.send(request).ok();
+            }
+        }
+    });
+
+    let mut registration = Registration::new(endpoint.inner.clone(), None);
+    registration.contact = Some(rsip::typed::Contact {
+        display_name: None,
+        uri: rsip::Uri::try_from("sip:alice@127.0.0.1:15300")?,
+        params: vec![rsip::Param::Expires(rsip::param::Expires::new("3600"))],
+    });
+    let response = registration
+        .register(&registrar_addr.addr.to_string())
+        .await?;
+    assert_eq!(response.status_code, rsip::StatusCode::OK);
+    let first = seen_rx.recv().await.expect("initial REGISTER");
+    assert_eq!(registration.expires(), 3600);
+
+    // the usual refresh loop: re-register at 75% of the granted expires
+    let refresher = tokio::spawn(async move {
+        let refresh = registration.expires() as u64 * 3 / 4;
+        tokio::time::sleep(Duration::from_secs(refresh)).await;
+        registration.refresh().await
+    });
+
+    tokio::time::advance(Duration::from_secs(2699)).await;
+    assert!(seen_rx.try_recv().is_err(), "refreshed too early");
+
+    tokio::time::advance(Duration::from_secs(1)).await;
+    let refreshed = seen_rx.recv().await.expect("refresh REGISTER");
+    assert_eq!(
+        refreshed.cseq_header()?.seq()?,
+        first.cseq_header()?.seq()? + 1
+    );
+    assert_eq!(refresher.await.unwrap()?.status_code, rsip::StatusCode::OK);
+    token.cancel();
+    Ok(())
+}
+
+#[derive(Clone, Default)]
+struct LogBuffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl std::io::Write for LogBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_registration_follows_response_transport() -> crate::Result<()> {
+    use crate::transport::{channel::ChannelConnection, SipAddr, TransportEvent};
+    use tokio::sync::mpsc::unbounded_channel;
+
+    let logs = LogBuffer::default();
+    let writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(move || writer.clone())
+        .with_ansi(false)
+        .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let local_addr = SipAddr::new(
+        rsip::Transport::Udp,
+        rsip::HostWithPort::try_from("127.0.0.1:15320")?,
+    );
+    let registrar_addr = SipAddr::new(
+        rsip::Transport::Udp,
+        rsip::HostWithPort::try_from("127.0.0.1:15330")?,
+    );
+    let (incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+    let conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, local_addr)
+            .await?
+            .into();
+
+    // the registrar answers over a TCP connection, e.g. after a failover
+    let (_unused_tx, unused_rx) = unbounded_channel();
+    let (tcp_out_tx, _tcp_out_rx) = unbounded_channel();
+    let tcp_addr = SipAddr::new(
+        rsip::Transport::Tcp,
+        rsip::HostWithPort::try_from("127.0.0.1:15320")?,
+    );
+    let tcp_conn: crate::transport::SipConnection =
+        ChannelConnection::create_connection(unused_rx, tcp_out_tx, tcp_addr)
+            .await?
+            .into();
+
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    tl.add_transport(conn.clone());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let from = registrar_addr.clone();
+    tokio::spawn(async move {
+        while let Some(event) = outgoing_rx.recv().await {
+            if let TransportEvent::Incoming(rsip::SipMessage::Request(request), _, _) = event {
+                incoming_tx
+                    .send(TransportEvent::Incoming(
+                        ok_response(&request).into(),
+                        tcp_conn.clone(),
+                        from.clone(),
+                    ))
+                    .ok();
+            }
+        }
+    });
+
+    let mut registration = Registration::new(endpoint.inner.clone(), None);
+    registration.contact = Some(rsip::typed::Contact {
+        display_name: None,
+        uri: rsip::Uri::try_from("sip:alice@127.0.0.1:15320")?,
+        params: vec![rsip::Param::Expires(rsip::param::Expires::new("3600"))],
+    });
+    let response = registration
+        .register(&registrar_addr.addr.to_string())
+        .await?;
+    assert_eq!(response.status_code, rsip::StatusCode::OK);
+
+    assert_eq!(
+        registration.negotiated_transport(),
+        Some(rsip::Transport::Tcp)
+    );
+    let contact = registration.contact.as_ref().expect("contact");
+    assert!(contact
+        .uri
+        .params
+        .contains(&rsip::Param::Transport(rsip::Transport::Tcp)));
+
+    let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+    assert!(logs.contains("answered over TCP"), "{}", logs);
+    token.cancel();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_registration_status_snapshot() -> crate::Result<()> {
+    let token = CancellationToken::new();
+    let registrar = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let registrar_addr = registrar.local_addr()?.to_string();
+
+    let tl = TransportLayer::new(token.child_token());
+    let udp = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None).await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let mut registration = Registration::new(endpoint.inner.clone(), None);
+    registration.contact = Some(rsip::typed::Contact {
+        display_name: None,
+        uri: rsip::Uri::try_from("sip:alice@192.0.2.10:5060")?,
+        params: vec![],
+    });
+    let status = registration.status();
+    assert!(!status.registered);
+    assert!(status.last_registered_at.is_none());
+    assert!(status.last_error.is_none());
+
+    // 200 OK reporting our public address in the Via, to the REGISTER and
+    // to the re-REGISTER with the public Contact that follows it
+    let mock = async {
+        for _ in 0..2 {
+            let mut buf = vec![0u8; 4096];
+            let (len, from) = registrar.recv_from(&mut buf).await?;
+            let request = rsip::Request::try_from(&buf[..len])?;
+            registrar
+                .send_to(nat_ok_response(&request).to_string().as_bytes(), from)
+                .await?;
+        }
+        crate::Result::Ok(())
+    };
+    let (result, mock) = tokio::join!(registration.register(&registrar_addr), mock);
+    mock?;
+    assert_eq!(result?.status_code, rsip::StatusCode::OK);
+
+    let status = registration.status();
+    assert!(status.registered);
+    assert_eq!(status.expires, registration.expires());
+    assert_eq!(
+        status.public_address,
+        Some(("198.51.100.7".parse().unwrap(), 40000))
+    );
+    assert!(status.last_registered_at.is_some());
+    assert!(status.last_error.is_none());
+
+    // a rejected refresh is reported, the binding stays valid until it expires
+    let reject = async {
+        let mut buf = vec![0u8; 4096];
+        let (len, from) = registrar.recv_from(&mut buf).await?;
+        let request = rsip::Request::try_from(&buf[..len])?;
+        let mut response = ok_response(&request);
+        response.status_code = rsip::StatusCode::Forbidden;
+        registrar
+            .send_to(response.to_string().as_bytes(), from)
+            .await?;
+        crate::Result::Ok(())
+    };
+    let (result, reject) = tokio::join!(registration.refresh(), reject);
+    reject?;
+    assert_eq!(result?.status_code, rsip::StatusCode::Forbidden);
+    let status = registration.status();
+    assert!(status.registered);
+    assert_eq!(status.last_error.as_deref(), Some("403 Forbidden"));
+    token.cancel();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_pub_gruu_becomes_invite_contact() -> crate::Result<()> {
+    use crate::dialog::dialog_layer::DialogLayer;
+    use rsip::prelude::{HeadersExt, UntypedHeader};
+
+    let token = CancellationToken::new();
+    let registrar = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let registrar_addr = registrar.local_addr()?.to_string();
+
+    let tl = TransportLayer::new(token.child_token());
+    let udp = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None).await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let mut registration = Registration::new(endpoint.inner.clone(), None);
+    registration.contact = Some(rsip::typed::Contact {
+        display_name: None,
+        uri: rsip::Uri::try_from("sip:alice@192.0.2.10:5060")?,
+        params: vec![],
+    });
+
+    // another device of the AOR is listed first with its own GRUU
+    let mock = async {
+        let mut buf = vec![0u8; 4096];
+        let (len, from) = registrar.recv_from(&mut buf).await?;
+        let request = rsip::Request::try_from(&buf[..len])?;
+        let mut response = ok_response(&request);
+        response
+            .headers
+            .retain(|h| !matches!(h, rsip::Header::Contact(_)));
+        response.headers.push(
+            Contact::new(
+                "<sip:alice@192.0.2.20:5060>;expires=3600;pub-gruu=\"sip:alice@example.com;gr=urn:uuid:0000\"",
+            )
+            .into(),
+        );
+        response.headers.push(
+            Contact::new(
+                "<sip:alice@192.0.2.10:5060>;expires=1800;+sip.instance=\"<urn:uuid:f81d4fae-7dec-11d0-a765-00a0c91e6bf6>\";pub-gruu=\"sip:alice@example.com;gr=urn:uuid:f81d4fae-7dec-11d0-a765-00a0c91e6bf6\"",
+            )
+            .into(),
+        );
+        registrar
+            .send_to(response.to_string().as_bytes(), from)
+            .await?;
+        crate::Result::Ok(())
+    };
+    let (result, mock) = tokio::join!(registration.register(&registrar_addr), mock);
+    mock?;
+    assert_eq!(result?.status_code, rsip::StatusCode::OK);
+
+    let gruu = "sip:alice@example.com;gr=urn:uuid:f81d4fae-7dec-11d0-a765-00a0c91e6bf6";
+    assert_eq!(
+        registration
+            .pub_gruu()
+            .map(|uri| uri.to_string())
+            .as_deref(),
+        Some(gruu)
+    );
+
+    let dialog_layer = DialogLayer::new(endpoint.inner.clone());
+    dialog_layer.set_default_contact(registration.pub_gruu().cloned());
+    let opt = crate::dialog::invitation::InviteOption {
+        caller: rsip::Uri::try_from("sip:alice@example.com")?,
+        callee: rsip::Uri::try_from("sip:bob@example.com")?,
+        contact: rsip::Uri::try_from("sip:alice@192.0.2.10:5060")?,
+        ..Default::default()
+    };
+    let request = dialog_layer.make_invite_request(&opt)?;
+    assert_eq!(request.contact_header()?.value(), format!("<{}>", gruu));
+
+    // without a default contact the option's contact is used again
+    dialog_layer.set_default_contact(None);
+    let request = dialog_layer.make_invite_request(&opt)?;
+    assert_eq!(
+        request.contact_header()?.value(),
+        "<sip:alice@192.0.2.10:5060>"
+    );
+    token.cancel();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_pub_gruu_only_from_our_binding() -> crate::Result<()> {
+    let token = CancellationToken::new();
+    let registrar = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let registrar_addr = registrar.local_addr()?.to_string();
+
+    let tl = TransportLayer::new(token.child_token());
+    let udp = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None).await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let mut registration = Registration::new(endpoint.inner.clone(), None);
+    registration.contact = Some(rsip::typed::Contact {
+        display_name: None,
+        uri: rsip::Uri::try_from("sip:alice@192.0.2.10:5060")?,
+        params: vec![],
+    });
+    let registrar = &registrar;
+    let respond_with = |binding: &'static str| async move {
+        let mut buf = vec![0u8; 4096];
+        let (len, from) = registrar.recv_from(&mut buf).await?;
+        let request = rsip::Request::try_from(&buf[..len])?;
+        let mut response = ok_response(&request);
+        response
+            .headers
+            .retain(|h| !matches!(h, rsip::Header::Contact(_)));
+        response.headers.push(Contact::new(binding).into());
+        registrar
+            .send_to(response.to_string().as_bytes(), from)
+            .await?;
+        crate::Result::Ok(())
+    };
+
+    // only another device of the AOR has a GRUU
+    let (result, mock) = tokio::join!(
+        registration.register(&registrar_addr),
+        respond_with(
+            "<sip:alice@192.0.2.20:5060>;expires=3600;pub-gruu=\"sip:alice@example.com;gr=urn:uuid:0000\""
+        )
+    );
+    mock?;
+    assert_eq!(result?.status_code, rsip::StatusCode::OK);
+    assert!(registration.pub_gruu().is_none());
+
+    // the registrar rewrote our binding, it is still ours by its instance
+    registration
+        .set_instance_id(uuid::Uuid::parse_str("f81d4fae-7dec-11d0-a765-00a0c91e6bf6").unwrap());
+    let (result, mock) = tokio::join!(
+        registration.register(&registrar_addr),
+        respond_with(
+            "<sip:alice@198.51.100.7:40000>;expires=3600;+sip.instance=\"<urn:uuid:f81d4fae-7dec-11d0-a765-00a0c91e6bf6>\";pub-gruu=\"sip:alice@example.com;gr=urn:uuid:f81d4fae-7dec-11d0-a765-00a0c91e6bf6\""
+        )
+    );
+    mock?;
+    assert_eq!(result?.status_code, rsip::StatusCode::OK);
+    assert_eq!(
+        registration
+            .pub_gruu()
+            .map(|uri| uri.to_string())
+            .as_deref(),
+        Some("sip:alice@example.com;gr=urn:uuid:f81d4fae-7dec-11d0-a765-00a0c91e6bf6")
+    );
+    token.cancel();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_register_proxy_then_registrar_challenge() -> crate::Result<()> {
+    use rsip::prelude::ToTypedHeader;
+
+    let token = CancellationToken::new();
+    let registrar = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let registrar_addr = registrar.local_addr()?.to_string();
+
+    let tl = TransportLayer::new(token.child_token());
+    let udp = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None).await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let credential = crate::dialog::authenticate::Credential {
+        username: "alice".to_string(),
+        password: "secret123".to_string(),
+        realm: None,
+    };
+    let mut registration = Registration::new(endpoint.inner.clone(), Some(credential));
+
+    // the outbound proxy challenges first, then the registrar behind it
+    let mock = async {
+        let mut buf = vec![0u8; 4096];
+        let mut requests = vec![];
+        let challenges: [Option<rsip::Header>; 3] = [
+            Some(ProxyAuthenticate::new(r#"Digest realm="proxy.example.com", nonce="p1""#).into()),
+            Some(WwwAuthenticate::new(r#"Digest realm="example.com", nonce="r1""#).into()),
+            None,
+        ];
+        for challenge in challenges {
+            let (len, from) = registrar.recv_from(&mut buf).await?;
+            let request = rsip::Request::try_from(&buf[..len])?;
+            let mut response = ok_response(&request);
+            if let Some(challenge) = challenge {
+                response.status_code = match challenge {
+                    rsip::Header::ProxyAuthenticate(_) => {
+                        rsip::StatusCode::ProxyAuthenticationRequired
+                    }
+                    _ => rsip::StatusCode::Unauthorized,
+                };
+                response
+                    .headers
+                    .retain(|h| !matches!(h, rsip::Header::Contact(_)));
+                response.headers.push(challenge);
+            }
+            registrar
+                .send_to(response.to_string().as_bytes(), from)
+                .await?;
+            requests.push(request);
+        }
+        crate::Result::Ok(requests)
+    };
+    let (result, requests) = tokio::join!(registration.register(&registrar_addr), mock);
+    assert_eq!(result?.status_code, rsip::StatusCode::OK);
+
+    let requests = requests?;
+    let realms = |request: &rsip::Request| {
+        request
+            .headers
+            .iter()
+            .filter_map(|h| match h {
+                rsip::Header::ProxyAuthorization(auth) => {
+                    auth.typed().ok().map(|auth| auth.0.realm)
+                }
+                rsip::Header::Authorization(auth) => auth.typed().ok().map(|auth| auth.realm),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    assert!(realms(&requests[0]).is_empty());
+    assert_eq!(realms(&requests[1]), vec!["proxy.example.com"]);
+    // the registrar retry still answers the proxy
+    assert_eq!(
+        realms(&requests[2]),
+        vec!["proxy.example.com", "example.com"]
+    );
+    token.cancel();
+    Ok(())
+}
+
+/// Resolver that answers only after `delay`, recording the lookup order
+struct SlowResolver {
+    delay: std::time::Duration,
+    orders: std::sync::Mutex<Vec<crate::transport::dns::DnsLookupOrder>>,
+}
+
+#[async_trait::async_trait]
+impl crate::transport::dns::DnsResolver for SlowResolver {
+    async fn resolve(
+        &self,
+        _uri: &rsip::Uri,
+        order: crate::transport::dns::DnsLookupOrder,
+    ) -> crate::Result<crate::transport::SipAddr> {
+        self.orders.lock().unwrap().push(order);
+        tokio::time::sleep(self.delay).await;
+        Ok(crate::transport::SipAddr::from(
+            "127.0.0.1:5060".parse::<std::net::SocketAddr>().unwrap(),
+        ))
+    }
+}
+
+#[tokio::test]
+async fn test_register_dns_timeout() -> crate::Result<()> {
+    use crate::transport::dns::{DnsConfig, DnsLookupOrder};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let token = CancellationToken::new();
+    let resolver = Arc::new(SlowResolver {
+        delay: Duration::from_secs(10),
+        orders: Default::default(),
+    });
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(TransportLayer::new(token.child_token()))
+        .with_cancel_token(token.child_token())
+        .with_dns_config(DnsConfig {
+            timeout: Duration::from_millis(200),
+            order: DnsLookupOrder::Srv,
+            resolver: Some(resolver.clone()),
+        })
+        .build();
+
+    let mut registration = Registration::new(endpoint.inner.clone(), None);
+    let started = std::time::Instant::now();
+    let result = registration.register(&"sip.example.com".to_string()).await;
+    assert!(matches!(result, Err(crate::Error::DnsResolutionError(_))));
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(*resolver.orders.lock().unwrap(), vec![DnsLookupOrder::Srv]);
+    token.cancel();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_refresh_keeps_negotiated_outbound() -> crate::Result<()> {
+    use rsip::prelude::{HeadersExt, ToTypedHeader, UntypedHeader};
+
+    let has_ob = |request: &rsip::Request| -> crate::Result<bool> {
+        Ok(request
+            .contact_header()?
+            .typed()?
+            .params
+            .iter()
+            .any(|p| matches!(p, rsip::Param::Other(name, None) if name.to_string() == "ob")))
+    };
+
+    for supported in [false, true] {
+        let token = CancellationToken::new();
+        let registrar = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+        let registrar_addr = registrar.local_addr()?.to_string();
+
+        let tl = TransportLayer::new(token.child_token());
+        let udp = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None).await?;
+        tl.add_transport(udp.into());
+        let endpoint = EndpointBuilder::new()
+            .with_user_agent("rsipstack-test")
+            .with_transport_layer(tl)
+            .with_cancel_token(token.child_token())
+            .build();
+        let inner = endpoint.inner.clone();
+        tokio::spawn(async move { inner.serve().await });
+
+        let mut registration = Registration::new(endpoint.inner.clone(), None);
+        assert!(!registration.is_refresh());
+        let mock = async {
+            let mut buf = vec![0u8; 4096];
+            let (len, from) = registrar.recv_from(&mut buf).await?;
+            let request = rsip::Request::try_from(&buf[..len])?;
+            let mut response = ok_response(&request);
+            if supported {
+                response.headers.push(Require::new("outbound").into());
+            }
+            registrar
+                .send_to(response.to_string().as_bytes(), from)
+                .await?;
+            crate::Result::Ok(request)
+        };
+        let (result, initial) = tokio::join!(registration.register(&registrar_addr), mock);
+        assert_eq!(result?.status_code, rsip::StatusCode::OK);
+        assert!(has_ob(&initial?)?);
+        assert!(registration.is_refresh());
+        assert_eq!(registration.outbound_supported(), Some(supported));
+
+        let mock = async {
+            let mut buf = vec![0u8; 4096];
+            let (len, from) = registrar.recv_from(&mut buf).await?;
+            let request = rsip::Request::try_from(&buf[..len])?;
+            registrar
+                .send_to(ok_response(&request).to_string().as_bytes(), from)
+                .await?;
+            crate::Result::Ok(request)
+        };
+        let (result, refresh) = tokio::join!(registration.refresh(), mock);
+        assert_eq!(result?.status_code, rsip::StatusCode::OK);
+        assert_eq!(has_ob(&refresh?)?, supported);
+        token.cancel();
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_register_display_name() -> crate::Result<()> {
+    use rsip::prelude::{HeadersExt, ToTypedHeader};
+
+    let token = CancellationToken::new();
+    let registrar = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let registrar_addr = registrar.local_addr()?.to_string();
+
+    let tl = TransportLayer::new(token.child_token());
+    let udp = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None).await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let mut registration = Registration::new(endpoint.inner.clone(), None);
+    registration.display_name = Some("Alice Desk".to_string());
+    let mock = async {
+        let mut buf = vec![0u8; 4096];
+        let (len, from) = registrar.recv_from(&mut buf).await?;
+        let request = rsip::Request::try_from(&buf[..len])?;
+        registrar
+            .send_to(ok_response(&request).to_string().as_bytes(), from)
+            .await?;
+        crate::Result::Ok(request)
+    };
+    let (result, request) = tokio::join!(registration.register(&registrar_addr), mock);
+    assert_eq!(result?.status_code, rsip::StatusCode::OK);
+    let request = request?;
+    assert_eq!(
+        request.from_header()?.typed()?.display_name.as_deref(),
+        Some("Alice Desk")
+    );
+    assert_eq!(
+        request.contact_header()?.typed()?.display_name.as_deref(),
+        Some("Alice Desk")
+    );
+    token.cancel();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_unregister() -> crate::Result<()> {
+    use rsip::prelude::{HeadersExt, ToTypedHeader, UntypedHeader};
+
+    let token = CancellationToken::new();
+    let registrar = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let registrar_addr = registrar.local_addr()?.to_string();
+
+    let tl = TransportLayer::new(token.child_token());
+    let udp = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None).await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let accept = || async {
+        let mut buf = vec![0u8; 4096];
+        let (len, from) = registrar.recv_from(&mut buf).await?;
+        let request = rsip::Request::try_from(&buf[..len])?;
+        registrar
+            .send_to(ok_response(&request).to_string().as_bytes(), from)
+            .await?;
+        crate::Result::Ok(request)
+    };
+
+    let mut registration = Registration::new(endpoint.inner.clone(), None);
+    let (result, register) = tokio::join!(registration.register(&registrar_addr), accept());
+    assert_eq!(result?.status_code, rsip::StatusCode::OK);
+    assert!(registration.status().registered);
+    let bound = register?.contact_header()?.typed()?.uri;
+
+    // our own binding is removed with expires=0
+    let (result, unregister) = tokio::join!(registration.unregister(&registrar_addr), accept());
+    assert_eq!(result?.status_code, rsip::StatusCode::OK);
+    let contact = unregister?.contact_header()?.typed()?;
+    assert_eq!(contact.uri, bound);
+    assert_eq!(contact.expires().and_then(|e| e.seconds().ok()), Some(0));
+    assert!(!registration.status().registered);
+
+    // or every binding of the AOR with Contact: *
+    let (result, unregister_all) =
+        tokio::join!(registration.unregister_all(&registrar_addr), accept());
+    assert_eq!(result?.status_code, rsip::StatusCode::OK);
+    let unregister_all = unregister_all?;
+    assert_eq!(unregister_all.contact_header()?.value(), "*");
+    assert!(unregister_all.to_string().contains("\r\nExpires: 0\r\n"));
+    token.cancel();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_start_refresh() -> crate::Result<()> {
+    use crate::dialog::registration::RegistrationState;
+    use rsip::prelude::{HeadersExt, UntypedHeader};
+
+    let token = CancellationToken::new();
+    let registrar = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let registrar_addr = registrar.local_addr()?.to_string();
+
+    let tl = TransportLayer::new(token.child_token());
+    let udp = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None).await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let registration = Registration::new(endpoint.inner.clone(), None);
+    let refresh_token = CancellationToken::new();
+    let (handle, mut state) =
+        registration.start_refresh(registrar_addr.clone(), refresh_token.clone());
+
+    async fn next_state(
+        state: &mut tokio::sync::watch::Receiver<RegistrationState>,
+    ) -> RegistrationState {
+        loop {
+            tokio::time::timeout(std::time::Duration::from_secs(5), state.changed())
+                .await
+                .expect("state did not change")
+                .expect("refresh task stopped");
+            let current = state.borrow_and_update().clone();
+            if current != RegistrationState::Registering {
+                return current;
+            }
+        }
+    }
+
+    let mut buf = vec![0u8; 4096];
+
+    // the binding is granted for 2 seconds
+    let (len, from) = registrar.recv_from(&mut buf).await?;
+    let request = rsip::Request::try_from(&buf[..len])?;
+    let mut ok = ok_response(&request);
+    let contact = format!("{};expires=2", request.contact_header()?.value());
+    ok.headers.unique_push(Contact::new(contact).into());
+    registrar.send_to(ok.to_string().as_bytes(), from).await?;
+    assert_eq!(
+        next_state(&mut state).await,
+        RegistrationState::Active { expires: 2 }
+    );
+
+    // and refreshed before it expires, a rejection is reported
+    let (len, from) = tokio::time::timeout(
+        std::time::Duration::from_secs(2),
+        registrar.recv_from(&mut buf),
+    )
+    .await
+    .expect("no refresh before expiry")?;
+    let refresh = rsip::Request::try_from(&buf[..len])?;
+    assert_eq!(refresh.method, rsip::Method::Register);
+    let mut unavailable = ok_response(&refresh);
+    unavailable.status_code = rsip::StatusCode::ServiceUnavailable;
+    registrar
+        .send_to(unavailable.to_string().as_bytes(), from)
+        .await?;
+    assert_eq!(
+        next_state(&mut state).await,
+        RegistrationState::Failed {
+            status: Some(rsip::StatusCode::ServiceUnavailable)
+        }
+    );
+
+    let registration = tokio::time::timeout(std::time::Duration::from_secs(1), handle.stop())
+        .await
+        .expect("refresh task did not stop")?;
+    assert!(registration.status().last_error.is_some());
+    assert!(!refresh_token.is_cancelled());
+
+    // dropping the handle stops the task too
+    let (handle, mut state) = registration.start_refresh(registrar_addr, refresh_token.clone());
+    accept_once(&registrar).await?;
+    assert!(matches!(
+        next_state(&mut state).await,
+        RegistrationState::Active { .. }
+    ));
+    drop(handle);
+    tokio::time::timeout(std::time::Duration::from_secs(1), state.changed())
+        .await
+        .expect("refresh task did not stop")
+        .expect_err("state sender dropped");
+    token.cancel();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_register_requested_expires() -> crate::Result<()> {
+    use rsip::prelude::{HeadersExt, ToTypedHeader, UntypedHeader};
+
+    let token = CancellationToken::new();
+    let registrar = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let registrar_addr = registrar.local_addr()?.to_string();
+
+    let tl = TransportLayer::new(token.child_token());
+    let udp = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None).await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let mut registration = Registration::new(endpoint.inner.clone(), None);
+    registration.requested_expires = Some(120);
+    let mock = async {
+        let mut buf = vec![0u8; 4096];
+        let (len, from) = registrar.recv_from(&mut buf).await?;
+        let request = rsip::Request::try_from(&buf[..len])?;
+        // the registrar grants less than asked for
+        let mut ok = ok_response(&request);
+        let granted = request
+            .contact_header()?
+            .value()
+            .replace("expires=120", "expires=60");
+        ok.headers.unique_push(Contact::new(granted).into());
+        registrar.send_to(ok.to_string().as_bytes(), from).await?;
+        crate::Result::Ok(request)
+    };
+    let (result, request) = tokio::join!(registration.register(&registrar_addr), mock);
+    assert_eq!(result?.status_code, rsip::StatusCode::OK);
+    let contact = request?.contact_header()?.typed()?;
+    assert_eq!(contact.expires().and_then(|e| e.seconds().ok()), Some(120));
+    assert_eq!(registration.expires(), 60);
+    token.cancel();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_register_interval_too_brief() -> crate::Result<()> {
+    use rsip::prelude::{HeadersExt, ToTypedHeader, UntypedHeader};
+
+    let token = CancellationToken::new();
+    let registrar = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let registrar_addr = registrar.local_addr()?.to_string();
+
+    let tl = TransportLayer::new(token.child_token());
+    let udp = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None).await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let mut registration = Registration::new(endpoint.inner.clone(), None);
+    registration.requested_expires = Some(30);
+    let too_brief = |request: &rsip::Request| {
+        let mut resp = ok_response(request);
+        resp.status_code = rsip::StatusCode::IntervalTooBrief;
+        resp.headers.push(MinExpires::new("3600").into());
+        resp
+    };
+    let mock = async {
+        let mut buf = vec![0u8; 4096];
+        let (len, from) = registrar.recv_from(&mut buf).await?;
+        let first = rsip::Request::try_from(&buf[..len])?;
+        registrar
+            .send_to(too_brief(&first).to_string().as_bytes(), from)
+            .await?;
+        let (len, from) = registrar.recv_from(&mut buf).await?;
+        let second = rsip::Request::try_from(&buf[..len])?;
+        registrar
+            .send_to(ok_response(&second).to_string().as_bytes(), from)
+            .await?;
+        crate::Result::Ok((first, second))
+    };
+    let (result, requests) = tokio::join!(registration.register(&registrar_addr), mock);
+    assert_eq!(result?.status_code, rsip::StatusCode::OK);
+    let (first, second) = requests?;
+    let expires = |request: &rsip::Request| -> crate::Result<Option<u32>> {
+        Ok(request
+            .contact_header()?
+            .typed()?
+            .expires()
+            .and_then(|e| e.seconds().ok()))
+    };
+    assert_eq!(expires(&first)?, Some(30));
+    assert_eq!(expires(&second)?, Some(3600));
+    assert_eq!(registration.requested_expires, Some(3600));
+
+    // a registrar insisting on 423 gets it back after one retry
+    let mock = async {
+        let mut buf = vec![0u8; 4096];
+        for _ in 0..2 {
+            let (len, from) = registrar.recv_from(&mut buf).await?;
+            let request = rsip::Request::try_from(&buf[..len])?;
+            registrar
+                .send_to(too_brief(&request).to_string().as_bytes(), from)
+                .await?;
+        }
+        crate::Result::Ok(())
+    };
+    registration.requested_expires = Some(30);
+    let (result, served) = tokio::join!(registration.register(&registrar_addr), mock);
+    served?;
+    assert_eq!(result?.status_code, rsip::StatusCode::IntervalTooBrief);
+    token.cancel();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_register_outbound_instance() -> crate::Result<()> {
+    use rsip::prelude::{HeadersExt, UntypedHeader};
+
+    let uuid = uuid::Uuid::parse_str("f81d4fae-7dec-11d0-a765-00a0c91e6bf6").unwrap();
+    let instance = "+sip.instance=\"<urn:uuid:f81d4fae-7dec-11d0-a765-00a0c91e6bf6>\"";
+    let supports_outbound = |request: &rsip::Request| {
+        request.headers.iter().any(|h| match h {
+            rsip::Header::Supported(supported) => supported.value().contains("outbound"),
+            _ => false,
+        })
+    };
+
+    for supported in [true, false] {
+        let token = CancellationToken::new();
+        let registrar = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+        let registrar_addr = registrar.local_addr()?.to_string();
+
+        let tl = TransportLayer::new(token.child_token());
+        let udp = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None).await?;
+        tl.add_transport(udp.into());
+        let endpoint = EndpointBuilder::new()
+            .with_user_agent("rsipstack-test")
+            .with_transport_layer(tl)
+            .with_cancel_token(token.child_token())
+            .build();
+        let inner = endpoint.inner.clone();
+        tokio::spawn(async move { inner.serve().await });
+
+        let mut registration = Registration::new(endpoint.inner.clone(), None);
+        registration.set_instance_id(uuid);
+        registration.reg_id = 2;
+        assert_eq!(
+            registration.instance_id(),
+            Some("urn:uuid:f81d4fae-7dec-11d0-a765-00a0c91e6bf6")
+        );
+        let mock = async {
+            let mut buf = vec![0u8; 4096];
+            let (len, from) = registrar.recv_from(&mut buf).await?;
+            let request = rsip::Request::try_from(&buf[..len])?;
+            let mut response = ok_response(&request);
+            // some registrars confirm with Supported instead of Require
+            if supported {
+                response.headers.push(Supported::new("outbound").into());
+            }
+            registrar
+                .send_to(response.to_string().as_bytes(), from)
+                .await?;
+            let (len, from) = registrar.recv_from(&mut buf).await?;
+            let refresh = rsip::Request::try_from(&buf[..len])?;
+            registrar
+                .send_to(ok_response(&refresh).to_string().as_bytes(), from)
+                .await?;
+            crate::Result::Ok((request, refresh))
+        };
+        let register = async {
+            registration.register(&registrar_addr).await?;
+            registration.refresh().await
+        };
+        let (result, requests) = tokio::join!(register, mock);
+        assert_eq!(result?.status_code, rsip::StatusCode::OK);
+        let (initial, refresh) = requests?;
+        assert_eq!(registration.outbound_supported(), Some(supported));
+
+        let contact = initial.contact_header()?.value().to_string();
+        assert!(contact.contains(instance), "{}", contact);
+        assert!(contact.contains(";reg-id=2"), "{}", contact);
+        assert!(contact.contains(";ob"), "{}", contact);
+        assert!(supports_outbound(&initial));
+
+        // without outbound the refresh keeps the instance for GRUU only
+        let contact = refresh.contact_header()?.value().to_string();
+        assert!(contact.contains(instance), "{}", contact);
+        assert_eq!(contact.contains(";reg-id=2"), supported, "{}", contact);
+        assert_eq!(supports_outbound(&refresh), supported);
+        token.cancel();
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_register_service_route() -> crate::Result<()> {
+    use rsip::prelude::UntypedHeader;
+
+    let token = CancellationToken::new();
+    let registrar = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let registrar_addr = registrar.local_addr()?.to_string();
+
+    let tl = TransportLayer::new(token.child_token());
+    let udp = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None).await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let mut registration = Registration::new(endpoint.inner.clone(), None);
+    let mock = async {
+        let mut buf = vec![0u8; 4096];
+        let (len, from) = registrar.recv_from(&mut buf).await?;
+        let request = rsip::Request::try_from(&buf[..len])?;
+        let mut ok = ok_response(&request);
+        ok.headers.push(rsip::Header::Other(
+            "Service-Route".into(),
+            "<sip:edge.example.com;lr>, <sip:core.example.com;lr>".into(),
+        ));
+        ok.headers.push(rsip::Header::Other(
+            "Service-Route".into(),
+            "<sip:orig@scscf.example.com;lr>".into(),
+        ));
+        registrar.send_to(ok.to_string().as_bytes(), from).await?;
+
+        // the next 200 OK has none, so the route is dropped
+        let (len, from) = registrar.recv_from(&mut buf).await?;
+        let request = rsip::Request::try_from(&buf[..len])?;
+        registrar
+            .send_to(ok_response(&request).to_string().as_bytes(), from)
+            .await?;
+        crate::Result::Ok(())
+    };
+    let register = async {
+        registration.register(&registrar_addr).await?;
+        let route = registration
+            .service_route()
+            .iter()
+            .map(|route| route.value().to_string())
+            .collect::<Vec<_>>();
+        registration.refresh().await?;
+        crate::Result::Ok(route)
+    };
+    let (route, served) = tokio::join!(register, mock);
+    served?;
+    assert_eq!(
+        route?,
+        vec![
+            "<sip:edge.example.com;lr>",
+            "<sip:core.example.com;lr>",
+            "<sip:orig@scscf.example.com;lr>",
+        ]
+    );
+    assert!(registration.service_route().is_empty());
+    token.cancel();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_register_extra_contacts() -> crate::Result<()> {
+    use rsip::prelude::UntypedHeader;
+
+    let token = CancellationToken::new();
+    let registrar = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let registrar_addr = registrar.local_addr()?.to_string();
+
+    let tl = TransportLayer::new(token.child_token());
+    let udp = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None).await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let contacts = |request: &rsip::Request| {
+        request
+            .headers
+            .iter()
+            .filter_map(|h| match h {
+                rsip::Header::Contact(contact) => Some(contact.value().to_string()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    let softphone = rsip::typed::Contact {
+        display_name: None,
+        uri: rsip::Uri::try_from("sip:alice@192.0.2.30")?,
+        params: vec![rsip::Param::Expires(rsip::param::Expires::new("600"))],
+    };
+    let mut registration = Registration::new(endpoint.inner.clone(), None);
+    registration.requested_expires = Some(3600);
+    registration.extra_contacts = vec![softphone.clone()];
+    let mock = async {
+        let mut buf = vec![0u8; 4096];
+        let (len, from) = registrar.recv_from(&mut buf).await?;
+        let request = rsip::Request::try_from(&buf[..len])?;
+        let sent = contacts(&request);
+        // each binding gets its own expiry, the softphone with the default port
+        let mut ok = ok_response(&request);
+        ok.headers
+            .retain(|h| !matches!(h, rsip::Header::Contact(_)));
+        ok.headers
+            .push(Contact::new(sent[0].replace("3600", "1800")).into());
+        ok.headers
+            .push(Contact::new("<sip:alice@192.0.2.30:5060>;expires=300").into());
+        registrar.send_to(ok.to_string().as_bytes(), from).await?;
+
+        let (len, from) = registrar.recv_from(&mut buf).await?;
+        let unregister = rsip::Request::try_from(&buf[..len])?;
+        registrar
+            .send_to(ok_response(&unregister).to_string().as_bytes(), from)
+            .await?;
+        crate::Result::Ok((sent, contacts(&unregister)))
+    };
+    let requests = async {
+        let resp = registration.register(&registrar_addr).await?;
+        assert_eq!(resp.status_code, rsip::StatusCode::OK);
+        assert_eq!(registration.bindings().len(), 2);
+        assert_eq!(registration.expires(), 1800);
+        assert_eq!(registration.contact_expires(&softphone), Some(300));
+        registration.unregister(&registrar_addr).await
+    };
+    let (result, sent) = tokio::join!(requests, mock);
+    assert_eq!(result?.status_code, rsip::StatusCode::OK);
+    let (registered, unregistered) = sent?;
+    assert_eq!(registered.len(), 2);
+    assert!(registered[0].contains("expires=3600"), "{:?}", registered);
+    assert_eq!(registered[1], "<sip:alice@192.0.2.30>;expires=600");
+    assert_eq!(unregistered.len(), 2);
+    assert!(unregistered.iter().all(|c| c.ends_with("expires=0")));
+    token.cancel();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fetch_bindings() -> crate::Result<()> {
+    let token = CancellationToken::new();
+    let registrar = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let registrar_addr = registrar.local_addr()?.to_string();
+
+    let tl = TransportLayer::new(token.child_token());
+    let udp = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None).await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let mut registration = Registration::new(endpoint.inner.clone(), None);
+    let mock = async {
+        let mut buf = vec![0u8; 4096];
+        let (len, from) = registrar.recv_from(&mut buf).await?;
+        let request = rsip::Request::try_from(&buf[..len])?;
+        let mut ok = ok_response(&request);
+        ok.headers.push(
+            Contact::new(
+                "<sip:alice@192.0.2.10:5060>;expires=1200, <sip:alice@192.0.2.20:5060>;expires=30",
+            )
+            .into(),
+        );
+        registrar.send_to(ok.to_string().as_bytes(), from).await?;
+        crate::Result::Ok(request)
+    };
+    let (bindings, request) = tokio::join!(registration.fetch_bindings(&registrar_addr), mock);
+    // a query carries no Contact and leaves our registration alone
+    let request = request?;
+    assert_eq!(request.method, rsip::Method::Register);
+    assert!(!request
+        .headers
+        .iter()
+        .any(|h| matches!(h, rsip::Header::Contact(_))));
+    let bindings = bindings?;
+    assert_eq!(bindings.len(), 2);
+    assert_eq!(bindings[0].uri.to_string(), "sip:alice@192.0.2.10:5060");
+    assert_eq!(
+        bindings[1].expires().and_then(|e| e.seconds().ok()),
+        Some(30)
+    );
+    assert!(!registration.status().registered);
+
+    let mock = async {
+        let mut buf = vec![0u8; 4096];
+        let (len, from) = registrar.recv_from(&mut buf).await?;
+        let request = rsip::Request::try_from(&buf[..len])?;
+        let mut resp = ok_response(&request);
+        resp.status_code = rsip::StatusCode::Forbidden;
+        registrar.send_to(resp.to_string().as_bytes(), from).await?;
+        crate::Result::Ok(())
+    };
+    let (bindings, served) = tokio::join!(registration.fetch_bindings(&registrar_addr), mock);
+    served?;
+    assert!(bindings.is_err());
+    token.cancel();
+    Ok(())
+}
+
+#[test]
+fn test_local_interface_preference() {
+    use crate::dialog::registration::AddrFamilyPreference;
+
+    assert!(Registration::find_local_interface(
+        AddrFamilyPreference::PreferV6,
+        Some("no-such-interface0"),
+    )
+    .is_err());
+
+    // whatever the host has, only usable addresses of the family come back
+    if let Ok(ip) = Registration::find_local_interface(AddrFamilyPreference::V4Only, None) {
+        assert!(ip.is_ipv4() && !ip.is_loopback());
+    }
+    if let Ok(ip) = Registration::get_first_non_loopback_interface() {
+        assert!(ip.is_ipv4() && !ip.is_loopback());
+    }
+    if let Ok(ip) = Registration::find_local_interface(AddrFamilyPreference::V6Only, None) {
+        let std::net::IpAddr::V6(v6) = ip else {
+            panic!("IPv4 address {} for V6Only", ip);
+        };
+        assert!(!v6.is_loopback());
+        assert_ne!(v6.segments()[0] & 0xffc0, 0xfe80, "link-local {}", v6);
+    }
+}
+
+#[tokio::test]
+async fn test_register_explicit_local_addr() -> crate::Result<()> {
+    use rsip::prelude::{HeadersExt, ToTypedHeader};
+
+    let token = CancellationToken::new();
+    let registrar = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let registrar_addr = registrar.local_addr()?.to_string();
+
+    let tl = TransportLayer::new(token.child_token());
+    let udp = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None).await?;
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token.child_token())
+        .build();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let mut registration = Registration::new(endpoint.inner.clone(), None);
+    registration.local_addr = Some(crate::transport::SipAddr {
+        r#type: Some(rsip::Transport::Udp),
+        addr: rsip::HostWithPort::try_from("192.0.2.55:5070")?,
+    });
+    let mock = async {
+        let mut buf = vec![0u8; 4096];
+        let (len, from) = registrar.recv_from(&mut buf).await?;
+        let request = rsip::Request::try_from(&buf[..len])?;
+        registrar
+            .send_to(ok_response(&request).to_string().as_bytes(), from)
+            .await?;
+        crate::Result::Ok(request)
+    };
+    let (result, request) = tokio::join!(registration.register(&registrar_addr), mock);
+    assert_eq!(result?.status_code, rsip::StatusCode::OK);
+    let request = request?;
+    let via = request.via_header()?.typed()?;
+    assert_eq!(via.uri.host_with_port.to_string(), "192.0.2.55:5070");
+    let contact = request.contact_header()?.typed()?;
+    assert_eq!(contact.uri.host_with_port.to_string(), "192.0.2.55:5070");
+    token.cancel();
+    Ok(())
+}
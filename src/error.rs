@@ -8,8 +8,10 @@ pub enum Error {
     DnsResolutionError(String),
     TransportLayerError(String, SipAddr),
     TransactionError(String, TransactionKey),
+    TransactionTerminated(TransactionKey),
     EndpointError(String),
     DialogError(String, DialogId),
+    AuthenticationRequired(rsip::StatusCode),
     Keepalive,
     Error(String),
 }
@@ -23,8 +25,14 @@ impl std::fmt::Display for Error {
                 write!(f, "Transport layer error: {}: {}", e, addr)
             }
             Error::TransactionError(e, key) => write!(f, "Transaction error: {}: {}", e, key),
+            Error::TransactionTerminated(key) => {
+                write!(f, "Transaction terminated without a final response: {}", key)
+            }
             Error::EndpointError(e) => write!(f, "Endpoint error: {}", e),
             Error::DialogError(e, id) => write!(f, "Dialog error: {}: {}", e, id),
+            Error::AuthenticationRequired(status) => {
+                write!(f, "Authentication required but no credential: {}", status)
+            }
             Error::Keepalive => write!(f, "Keepalive message"),
             Error::Error(e) => write!(f, "Error: {}", e),
         }
@@ -38,8 +46,14 @@ impl Into<JsValue> for Error {
             Error::SipMessageError(e) => e.into(),
             Error::TransportLayerError(e, _) => e.into(),
             Error::TransactionError(e, key) => format!("{}: {}", e, key.to_string()).into(),
+            Error::TransactionTerminated(key) => {
+                format!("transaction terminated: {}", key.to_string()).into()
+            }
             Error::EndpointError(e) => e.into(),
             Error::DialogError(e, id) => format!("{}: {}", e, id.to_string()).into(),
+            Error::AuthenticationRequired(status) => {
+                format!("authentication required: {}", status).into()
+            }
             Error::Keepalive => "Keepalive message".into(),
             Error::Error(e) => e.into(),
         }
@@ -89,8 +89,7 @@
 //!     contact: rsip::Uri::try_from("sip:alice@myhost.com:5060")?,
 //!     content_type: Some("application/sdp".to_string()),
 //!     offer: Some(sdp_body),
-//!     credential: None,
-//!     headers: None,
+//!     ..Default::default()
 //! };
 //!
 //! let (dialog, response) = dialog_layer.do_invite(invite_option, state_sender).await?;
@@ -238,5 +237,7 @@ pub mod transaction;
 pub mod transport;
 pub use transaction::EndpointBuilder;
 pub mod rsip_ext;
+#[cfg(feature = "sdp")]
+pub mod sdp;
 
 const USER_AGENT: &str = "rsipstack/0.1";
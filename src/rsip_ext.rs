@@ -1,9 +1,16 @@
-use rsip::{message::HasHeaders, prelude::HeadersExt};
+use rsip::{
+    message::HasHeaders,
+    prelude::{HeadersExt, UntypedHeader},
+};
 
 use crate::transport::SipConnection;
+use std::sync::atomic::{AtomicBool, Ordering};
+
 pub trait RsipResponseExt {
     fn reason_phrase(&self) -> Option<&str>;
     fn via_received(&self) -> Option<rsip::HostWithPort>;
+    fn date(&self) -> Option<std::time::SystemTime>;
+    fn diagnostics(&self) -> Option<String>;
 }
 
 impl RsipResponseExt for rsip::Response {
@@ -26,10 +33,105 @@ impl RsipResponseExt for rsip::Response {
         let via = self.via_header().ok()?;
         SipConnection::parse_target_from_via(via).ok()
     }
+    /// Parse the Date header
+    ///
+    /// Returns `None` when the response has no Date header or it is not a
+    /// valid SIP-date (RFC 1123 in GMT).
+    fn date(&self) -> Option<std::time::SystemTime> {
+        self.headers().iter().find_map(|h| match h {
+            rsip::Header::Date(date) => parse_sip_date(date.value()),
+            _ => None,
+        })
+    }
+    /// Collect the diagnostic text of a failure response
+    ///
+    /// Joins the text of every `Warning` header (the quoted warn-text, or the
+    /// whole value if it has none) and every `Error-Info` value with `; `.
+    /// Returns `None` when the response carries neither header.
+    fn diagnostics(&self) -> Option<String> {
+        let mut details = vec![];
+        for header in self.headers().iter() {
+            match header {
+                rsip::Header::Warning(warning) => {
+                    let value = warning.value().trim();
+                    let text = match (value.find('"'), value.rfind('"')) {
+                        (Some(start), Some(end)) if end > start => &value[start + 1..end],
+                        _ => value,
+                    };
+                    details.push(text.to_string());
+                }
+                rsip::Header::ErrorInfo(info) => details.push(info.value().trim().to_string()),
+                _ => {}
+            }
+        }
+        if details.is_empty() {
+            None
+        } else {
+            Some(details.join("; "))
+        }
+    }
+}
+
+/// Parse a SIP-date such as `Sat, 13 Nov 2010 23:29:00 GMT`
+///
+/// RFC 3261 restricts the Date header to the RFC 1123 format and always GMT.
+/// A leading `Date:` header name is ignored.
+pub fn parse_sip_date(value: &str) -> Option<std::time::SystemTime> {
+    let value = value.trim();
+    let value = match value.split_once(':') {
+        Some((name, rest)) if name.eq_ignore_ascii_case("date") => rest.trim(),
+        _ => value,
+    };
+    let value = value.split_once(',').map(|(_, rest)| rest).unwrap_or(value);
+    let mut parts = value.split_whitespace();
+    let day: u32 = parts.next()?.parse().ok()?;
+    let month = match parts.next()?.to_ascii_lowercase().as_str() {
+        "jan" => 1,
+        "feb" => 2,
+        "mar" => 3,
+        "apr" => 4,
+        "may" => 5,
+        "jun" => 6,
+        "jul" => 7,
+        "aug" => 8,
+        "sep" => 9,
+        "oct" => 10,
+        "nov" => 11,
+        "dec" => 12,
+        _ => return None,
+    };
+    let year: i64 = parts.next()?.parse().ok()?;
+    let mut time = parts.next()?.split(':');
+    let hour: u64 = time.next()?.parse().ok()?;
+    let minute: u64 = time.next()?.parse().ok()?;
+    let second: u64 = time.next()?.parse().ok()?;
+    if !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    match parts.next() {
+        Some(zone) if zone.eq_ignore_ascii_case("GMT") || zone.eq_ignore_ascii_case("UTC") => {}
+        _ => return None,
+    }
+
+    // days since 1970-01-01, from Howard Hinnant's days_from_civil
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    if days < 0 {
+        return None;
+    }
+    let secs = days as u64 * 86400 + hour * 3600 + minute * 60 + second;
+    Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs))
 }
 
 pub trait RsipHeadersExt {
     fn push_front(&mut self, header: rsip::Header);
+    /// Value of the first extension header with the given name (case-insensitive)
+    fn get_other(&self, name: &str) -> Option<&str>;
 }
 
 impl RsipHeadersExt for rsip::Headers {
@@ -38,6 +140,15 @@ impl RsipHeadersExt for rsip::Headers {
         headers.insert(0, header);
         *self = headers.into();
     }
+
+    fn get_other(&self, name: &str) -> Option<&str> {
+        self.iter().find_map(|h| match h {
+            rsip::Header::Other(key, value) if key.eq_ignore_ascii_case(name) => {
+                Some(value.as_str())
+            }
+            _ => None,
+        })
+    }
 }
 
 #[macro_export]
@@ -76,6 +187,249 @@ pub fn extract_uri_from_contact(line: &str) -> crate::Result<rsip::Uri> {
     }
 }
 
+/// Split a header value holding several comma-separated entries
+///
+/// Commas inside `<...>` or a quoted string are part of the entry, so
+/// `"Alice, A." <sip:a@x>, <sip:b@y>` yields two entries. Each entry is
+/// trimmed and empty entries are skipped.
+pub fn split_header_values(value: &str) -> Vec<&str> {
+    let mut values = Vec::new();
+    let mut start = 0;
+    let mut in_quotes = false;
+    let mut in_angle = false;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' if in_quotes => escaped = true,
+            '"' if !in_angle => in_quotes = !in_quotes,
+            '<' if !in_quotes => in_angle = true,
+            '>' if !in_quotes => in_angle = false,
+            ',' if !in_quotes && !in_angle => {
+                values.push(value[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    values.push(value[start..].trim());
+    values.retain(|v| !v.is_empty());
+    values
+}
+
+/// Format a `User-to-User` header value (RFC 7433)
+///
+/// The payload is encoded as `hex` or `base64` and tagged with the matching
+/// `encoding` parameter, e.g. `56a390f3d2b7310023a2;encoding=hex`.
+pub fn encode_user_to_user(encoding: &str, data: &[u8]) -> crate::Result<String> {
+    let payload = if encoding.eq_ignore_ascii_case("hex") {
+        data.iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+    } else if encoding.eq_ignore_ascii_case("base64") {
+        encode_base64(data)
+    } else {
+        return Err(crate::Error::Error(format!(
+            "unsupported User-to-User encoding: {}",
+            encoding
+        )));
+    };
+    Ok(format!(
+        "{};encoding={}",
+        payload,
+        encoding.to_ascii_lowercase()
+    ))
+}
+
+/// Decode a `User-to-User` header value into its encoding and payload
+///
+/// Without an `encoding` parameter the payload is taken as `hex`, the
+/// RFC 7433 default. Other parameters such as `purpose` are ignored.
+pub fn decode_user_to_user(value: &str) -> crate::Result<(String, Vec<u8>)> {
+    let mut parts = value.split(';');
+    let payload = parts.next().unwrap_or_default().trim();
+    let encoding = parts
+        .filter_map(|p| p.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("encoding"))
+        .map(|(_, v)| v.trim().to_ascii_lowercase())
+        .unwrap_or_else(|| "hex".to_string());
+    let invalid = || crate::Error::Error(format!("invalid User-to-User payload: {}", value));
+    let data = match encoding.as_str() {
+        "hex" => {
+            if payload.len() % 2 != 0 {
+                return Err(invalid());
+            }
+            (0..payload.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(payload.get(i..i + 2)?, 16).ok())
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(invalid)?
+        }
+        "base64" => decode_base64(payload).ok_or_else(invalid)?,
+        _ => {
+            return Err(crate::Error::Error(format!(
+                "unsupported User-to-User encoding: {}",
+                encoding
+            )))
+        }
+    };
+    Ok((encoding, data))
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn encode_base64(data: &[u8]) -> String {
+    let mut out = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | ((*b as u32) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim_end_matches('=');
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let mut n = 0u32;
+    let mut bits = 0;
+    for c in text.bytes() {
+        let v = BASE64_ALPHABET.iter().position(|a| *a == c)? as u32;
+        n = (n << 6) | v;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((n >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+static LOG_REDACT_USER_PARTS: AtomicBool = AtomicBool::new(false);
+
+/// Also hide the user part of `From`/`To` URIs in logged messages
+///
+/// Off by default. The setting is process wide and applies to every
+/// message the stack logs; use [`sanitize_for_log_with`] to choose per
+/// call. Credentials in `Authorization` and `Proxy-Authorization` are
+/// always redacted by [`sanitize_for_log`].
+pub fn set_log_redact_user_parts(enabled: bool) {
+    LOG_REDACT_USER_PARTS.store(enabled, Ordering::Relaxed);
+}
+
+/// Redact credentials from a SIP message before it is logged
+///
+/// The digest `response` of `Authorization` and `Proxy-Authorization` is
+/// replaced, and credentials of any other scheme are dropped entirely.
+/// With [`set_log_redact_user_parts`] enabled, the user parts of `From`
+/// and `To` are hidden as well.
+///
+/// # Examples
+///
+/// ```rust
+/// use rsipstack::rsip_ext::sanitize_for_log;
+///
+/// let msg = "REGISTER sip:example.com SIP/2.0\r\n\
+///            Authorization: Digest username=\"alice\", response=\"6629fae4\"\r\n\r\n";
+/// let logged = sanitize_for_log(msg);
+/// assert!(!logged.contains("6629fae4"));
+/// ```
+pub fn sanitize_for_log(message: &str) -> String {
+    sanitize_for_log_with(message, LOG_REDACT_USER_PARTS.load(Ordering::Relaxed))
+}
+
+/// Redact a SIP message before it is logged, choosing whether to hide users
+///
+/// Same as [`sanitize_for_log`], with `redact_user_parts` used instead of
+/// the setting of [`set_log_redact_user_parts`].
+///
+/// # Examples
+///
+/// ```rust
+/// use rsipstack::rsip_ext::sanitize_for_log_with;
+///
+/// let msg = "MESSAGE sip:bob@example.com SIP/2.0\r\n\
+///            From: <sip:alice@example.com>;tag=1\r\n\r\n";
+/// assert!(sanitize_for_log_with(msg, true).contains("<sip:***@example.com>"));
+/// assert!(sanitize_for_log_with(msg, false).contains("<sip:alice@example.com>"));
+/// ```
+pub fn sanitize_for_log_with(message: &str, redact_user_parts: bool) -> String {
+    message
+        .split_inclusive('\n')
+        .map(|line| sanitize_log_line(line, redact_user_parts))
+        .collect()
+}
+
+fn sanitize_log_line(line: &str, redact_users: bool) -> String {
+    let (name, value) = match line.split_once(':') {
+        Some(parts) => parts,
+        None => return line.to_string(),
+    };
+    let name_trimmed = name.trim();
+    let is_header = |names: &[&str]| names.iter().any(|n| name_trimmed.eq_ignore_ascii_case(n));
+    // keep the line ending untouched
+    let body = value.trim_end();
+    let ending = &value[body.len()..];
+    if is_header(&["Authorization", "Proxy-Authorization"]) {
+        return format!("{}:{}{}", name, redact_credentials(body), ending);
+    }
+    if redact_users && is_header(&["From", "To", "f", "t"]) {
+        return format!("{}:{}{}", name, redact_user_part(body), ending);
+    }
+    line.to_string()
+}
+
+fn redact_credentials(value: &str) -> String {
+    let trimmed = value.trim_start();
+    let indent = &value[..value.len() - trimmed.len()];
+    let scheme = trimmed.split_whitespace().next().unwrap_or_default();
+    if !scheme.eq_ignore_ascii_case("digest") {
+        return format!("{}{} <redacted>", indent, scheme);
+    }
+    let start = match value.to_ascii_lowercase().find("response=") {
+        Some(pos) => pos + "response=".len(),
+        None => return value.to_string(),
+    };
+    let rest = &value[start..];
+    let end = match rest.strip_prefix('"') {
+        Some(quoted) => quoted.find('"').map(|i| i + 2).unwrap_or(rest.len()),
+        None => rest.find(',').unwrap_or(rest.len()),
+    };
+    format!("{}\"<redacted>\"{}", &value[..start], &rest[end..])
+}
+
+fn redact_user_part(value: &str) -> String {
+    let lower = value.to_ascii_lowercase();
+    let start = match ["sips:", "sip:", "tel:"]
+        .iter()
+        .filter_map(|scheme| lower.find(scheme).map(|pos| pos + scheme.len()))
+        .min()
+    {
+        Some(start) => start,
+        None => return value.to_string(),
+    };
+    let rest = &value[start..];
+    let uri_end = rest.find(['>', ';', ' ']).unwrap_or(rest.len());
+    let end = match rest[..uri_end].find('@') {
+        Some(at) => at,
+        None if lower[..start].ends_with("tel:") => uri_end,
+        None => return value.to_string(),
+    };
+    format!("{}***{}", &value[..start], &rest[end..])
+}
+
 #[test]
 fn test_rsip_headers_ext() {
     use rsip::{Header, Headers};
@@ -101,3 +455,82 @@ fn test_rsip_headers_ext() {
         ]
     );
 }
+
+#[test]
+fn test_split_header_values() {
+    assert_eq!(
+        split_header_values("<sip:a@x;lr>;expires=60, <sip:b@y>;expires=30"),
+        vec!["<sip:a@x;lr>;expires=60", "<sip:b@y>;expires=30"]
+    );
+    assert_eq!(
+        split_header_values("\"Doe, John\" <sip:j@x>,sip:k@y"),
+        vec!["\"Doe, John\" <sip:j@x>", "sip:k@y"]
+    );
+    assert_eq!(split_header_values("sip:a@x"), vec!["sip:a@x"]);
+    assert!(split_header_values(" ").is_empty());
+}
+
+#[test]
+fn test_parse_sip_date() {
+    use std::time::{Duration, UNIX_EPOCH};
+    assert_eq!(
+        parse_sip_date("Sat, 13 Nov 2010 23:29:00 GMT"),
+        Some(UNIX_EPOCH + Duration::from_secs(1289690940))
+    );
+    assert_eq!(
+        parse_sip_date("Date: Fri, 28 Feb 2025 08:05:09 GMT"),
+        Some(UNIX_EPOCH + Duration::from_secs(1740729909))
+    );
+    assert_eq!(parse_sip_date("Sat, 13 Nov 2010 23:29:00 PST"), None);
+    assert_eq!(parse_sip_date("yesterday"), None);
+}
+
+#[test]
+fn test_user_to_user_encoding() {
+    let data = b"\x56\xa3\x90\xf3\xd2\xb7\x31\x00\x23\xa2";
+    let value = encode_user_to_user("hex", data).unwrap();
+    assert_eq!(value, "56a390f3d2b7310023a2;encoding=hex");
+    assert_eq!(
+        decode_user_to_user(&value).unwrap(),
+        ("hex".to_string(), data.to_vec())
+    );
+
+    let value = encode_user_to_user("base64", b"call-123").unwrap();
+    assert_eq!(value, "Y2FsbC0xMjM=;encoding=base64");
+    assert_eq!(
+        decode_user_to_user(&value).unwrap(),
+        ("base64".to_string(), b"call-123".to_vec())
+    );
+
+    // hex is the default encoding
+    assert_eq!(
+        decode_user_to_user("00ff;purpose=isdn-uui").unwrap().1,
+        vec![0, 255]
+    );
+    assert!(decode_user_to_user("0g").is_err());
+    assert!(encode_user_to_user("ascii", b"x").is_err());
+}
+
+#[test]
+fn test_sanitize_for_log() {
+    let msg = "REGISTER sip:example.com SIP/2.0\r\n\
+               From: \"Alice\" <sip:alice@example.com>;tag=abc\r\n\
+               To: <sip:alice@example.com>\r\n\
+               Authorization: Digest username=\"alice\", realm=\"example.com\", \
+               nonce=\"abc\", uri=\"sip:example.com\", response=\"6629fae49393a05397450978507c4ef1\", \
+               algorithm=MD5\r\n\
+               Proxy-Authorization: Basic YWxpY2U6c2VjcmV0\r\n\
+               Content-Length: 0\r\n\r\n";
+    let logged = sanitize_for_log_with(msg, false);
+    assert!(!logged.contains("6629fae49393a05397450978507c4ef1"));
+    assert!(logged.contains("response=\"<redacted>\", algorithm=MD5\r\n"));
+    assert!(!logged.contains("YWxpY2U6c2VjcmV0"));
+    assert!(logged.contains("Proxy-Authorization: Basic <redacted>\r\n"));
+    assert!(logged.contains("From: \"Alice\" <sip:alice@example.com>;tag=abc\r\n"));
+    assert!(logged.ends_with("Content-Length: 0\r\n\r\n"));
+
+    let logged = sanitize_for_log_with(msg, true);
+    assert!(logged.contains("From: \"Alice\" <sip:***@example.com>;tag=abc\r\n"));
+    assert!(logged.contains("To: <sip:***@example.com>\r\n"));
+    assert!(logged.starts_with("REGISTER sip:example.com SIP/2.0\r\n"));
+}
@@ -0,0 +1,313 @@
+//! Minimal SDP for plain audio calls
+//!
+//! rsipstack does not handle media, but almost every basic call needs the
+//! same G.711 offer/answer. This module builds RFC 4566 SDP for a single
+//! RTP/AVP audio stream, suitable for `InviteOption::offer` or the body of
+//! `ServerInviteDialog::accept`. It is not a media stack: no ICE, SRTP,
+//! video or renegotiation.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use rsipstack::sdp::{build_audio_offer, AudioCodec};
+//!
+//! let offer = build_audio_offer(
+//!     "192.168.1.100".parse().unwrap(),
+//!     4000,
+//!     &[AudioCodec::Pcmu, AudioCodec::Pcma, AudioCodec::TelephoneEvent],
+//! );
+//! assert!(String::from_utf8(offer)
+//!     .unwrap()
+//!     .contains("m=audio 4000 RTP/AVP 0 8 101\r\n"));
+//! ```
+use crate::{Error, Result};
+use std::net::IpAddr;
+
+/// Audio payloads supported by the SDP helpers
+///
+/// * `Pcmu` - G.711 µ-law, static payload type 0
+/// * `Pcma` - G.711 A-law, static payload type 8
+/// * `TelephoneEvent` - RFC 4733 DTMF events, dynamic payload type 101
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AudioCodec {
+    Pcmu,
+    Pcma,
+    TelephoneEvent,
+}
+
+impl AudioCodec {
+    /// Payload type used in an offer
+    pub fn payload_type(&self) -> u8 {
+        match self {
+            AudioCodec::Pcmu => 0,
+            AudioCodec::Pcma => 8,
+            AudioCodec::TelephoneEvent => 101,
+        }
+    }
+
+    /// Encoding name as it appears in `a=rtpmap`
+    pub fn encoding_name(&self) -> &'static str {
+        match self {
+            AudioCodec::Pcmu => "PCMU",
+            AudioCodec::Pcma => "PCMA",
+            AudioCodec::TelephoneEvent => "telephone-event",
+        }
+    }
+}
+
+/// Build an SDP offer for one audio stream
+///
+/// # Parameters
+///
+/// * `local_ip` - Address RTP is received on, used for `o=` and `c=`
+/// * `port` - RTP port
+/// * `codecs` - Payloads in order of preference
+///
+/// # Returns
+///
+/// The SDP body with CRLF line endings
+pub fn build_audio_offer(local_ip: IpAddr, port: u16, codecs: &[AudioCodec]) -> Vec<u8> {
+    let payloads = codecs
+        .iter()
+        .map(|codec| (codec.payload_type(), *codec))
+        .collect::<Vec<_>>();
+    build_audio_sdp(local_ip, port, "RTP/AVP", &payloads, "sendrecv", &[])
+}
+
+/// Build an SDP answer to an audio offer
+///
+/// Keeps the offered payloads that are also in `codecs`, in the order of
+/// the offer and with the payload types the offerer chose. The direction
+/// is mirrored, so `sendonly` is answered with `recvonly`.
+///
+/// Connection-oriented media (RFC 4145), e.g. `TCP/RTP/AVP` or MSRP
+/// relays, keeps its transport: the answer uses the offered protocol,
+/// takes the `a=setup` role the offerer left (`actpass` and `passive`
+/// become `active`, `active` becomes `passive`) and repeats
+/// `a=connection`.
+///
+/// # Parameters
+///
+/// * `local_ip` - Address RTP is received on, used for `o=` and `c=`
+/// * `port` - RTP port
+/// * `offer` - The received SDP offer
+/// * `codecs` - Payloads we accept
+///
+/// # Returns
+///
+/// * `Ok(Vec<u8>)` - The SDP answer
+/// * `Err(Error)` - The offer has no audio stream or no codec in common
+pub fn build_audio_answer(
+    local_ip: IpAddr,
+    port: u16,
+    offer: &[u8],
+    codecs: &[AudioCodec],
+) -> Result<Vec<u8>> {
+    let offer = std::str::from_utf8(offer)
+        .map_err(|e| Error::Error(format!("invalid SDP offer: {}", e)))?;
+    let media = offer
+        .lines()
+        .find_map(|line| line.strip_prefix("m=audio "))
+        .ok_or(Error::Error("no audio stream in SDP offer".to_string()))?;
+    let proto = media.split_whitespace().nth(1).unwrap_or("RTP/AVP");
+    let offered = media
+        .split_whitespace()
+        .skip(2)
+        .filter_map(|pt| pt.parse::<u8>().ok())
+        .collect::<Vec<_>>();
+
+    let rtpmap = |pt: u8| {
+        let prefix = format!("a=rtpmap:{} ", pt);
+        offer
+            .lines()
+            .find_map(|line| line.strip_prefix(prefix.as_str()))
+            .and_then(|map| map.split('/').next())
+            .map(|name| name.trim().to_string())
+    };
+    let payloads = offered
+        .into_iter()
+        .filter_map(|pt| {
+            let codec = match rtpmap(pt) {
+                Some(name) => codecs
+                    .iter()
+                    .find(|c| c.encoding_name().eq_ignore_ascii_case(&name)),
+                // static payload types may come without rtpmap
+                None => codecs.iter().find(|c| c.payload_type() == pt && pt < 96),
+            }?;
+            Some((pt, *codec))
+        })
+        .collect::<Vec<_>>();
+    if !payloads
+        .iter()
+        .any(|(_, codec)| *codec != AudioCodec::TelephoneEvent)
+    {
+        return Err(Error::Error("no common audio codec".to_string()));
+    }
+
+    let direction = offer
+        .lines()
+        .find_map(|line| match line.trim() {
+            "a=sendonly" => Some("recvonly"),
+            "a=recvonly" => Some("sendonly"),
+            "a=inactive" => Some("inactive"),
+            _ => None,
+        })
+        .unwrap_or("sendrecv");
+
+    let attribute = |name: &str| {
+        offer
+            .lines()
+            .find_map(|line| line.trim().strip_prefix(name))
+            .map(str::trim)
+    };
+    let mut attributes = vec![];
+    if let Some(setup) = attribute("a=setup:") {
+        let setup = match setup {
+            "active" => "passive",
+            "holdconn" => "holdconn",
+            _ => "active",
+        };
+        attributes.push(format!("a=setup:{}", setup));
+    }
+    if let Some(connection) = attribute("a=connection:") {
+        attributes.push(format!("a=connection:{}", connection));
+    }
+    Ok(build_audio_sdp(
+        local_ip,
+        port,
+        proto,
+        &payloads,
+        direction,
+        &attributes,
+    ))
+}
+
+fn build_audio_sdp(
+    local_ip: IpAddr,
+    port: u16,
+    proto: &str,
+    payloads: &[(u8, AudioCodec)],
+    direction: &str,
+    attributes: &[String],
+) -> Vec<u8> {
+    let addr_type = match local_ip {
+        IpAddr::V4(_) => "IP4",
+        IpAddr::V6(_) => "IP6",
+    };
+    let session_id = rand::random::<u32>();
+    let formats = payloads
+        .iter()
+        .map(|(pt, _)| pt.to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let mut sdp = format!(
+        "v=0\r\n\
+         o=- {session_id} {session_id} IN {addr_type} {local_ip}\r\n\
+         s=-\r\n\
+         c=IN {addr_type} {local_ip}\r\n\
+         t=0 0\r\n\
+         m=audio {port} {proto} {formats}\r\n"
+    );
+    for (pt, codec) in payloads {
+        sdp.push_str(&format!(
+            "a=rtpmap:{} {}/8000\r\n",
+            pt,
+            codec.encoding_name()
+        ));
+        if *codec == AudioCodec::TelephoneEvent {
+            sdp.push_str(&format!("a=fmtp:{} 0-16\r\n", pt));
+        }
+    }
+    sdp.push_str("a=ptime:20\r\n");
+    sdp.push_str(&format!("a={}\r\n", direction));
+    for attribute in attributes {
+        sdp.push_str(attribute);
+        sdp.push_str("\r\n");
+    }
+    sdp.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audio_offer_answer() {
+        let offer = build_audio_offer(
+            "192.168.1.100".parse().unwrap(),
+            4000,
+            &[
+                AudioCodec::Pcmu,
+                AudioCodec::Pcma,
+                AudioCodec::TelephoneEvent,
+            ],
+        );
+        let text = String::from_utf8(offer.clone()).unwrap();
+        let parsed = sdp_rs::SessionDescription::try_from(text.as_str()).expect("offer parses");
+        let media = &parsed.media_descriptions[0].media;
+        assert_eq!(media.port, 4000);
+        assert_eq!(media.fmt, "0 8 101");
+        assert!(text.contains("m=audio 4000 RTP/AVP 0 8 101\r\n"));
+
+        // the answer keeps the offer's order and drops what we do not support
+        let answer = build_audio_answer(
+            "10.0.0.1".parse().unwrap(),
+            5000,
+            &offer,
+            &[AudioCodec::Pcma, AudioCodec::TelephoneEvent],
+        )
+        .expect("answer");
+        let answer = String::from_utf8(answer).unwrap();
+        sdp_rs::SessionDescription::try_from(answer.as_str()).expect("answer parses");
+        assert!(answer.contains("c=IN IP4 10.0.0.1\r\n"));
+        assert!(answer.contains("m=audio 5000 RTP/AVP 8 101\r\n"));
+        assert!(answer.contains("a=sendrecv\r\n"));
+
+        assert!(build_audio_answer(
+            "10.0.0.1".parse().unwrap(),
+            5000,
+            &offer,
+            &[AudioCodec::TelephoneEvent],
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_audio_answer_keeps_tcp_setup() {
+        let offer = "v=0\r\n\
+                     o=- 1 1 IN IP4 192.168.1.100\r\n\
+                     s=-\r\n\
+                     c=IN IP4 192.168.1.100\r\n\
+                     t=0 0\r\n\
+                     m=audio 9 TCP/RTP/AVP 0\r\n\
+                     a=rtpmap:0 PCMU/8000\r\n\
+                     a=setup:actpass\r\n\
+                     a=connection:new\r\n";
+        let answer = build_audio_answer(
+            "10.0.0.1".parse().unwrap(),
+            5000,
+            offer.as_bytes(),
+            &[AudioCodec::Pcmu],
+        )
+        .expect("answer");
+        let answer = String::from_utf8(answer).unwrap();
+        sdp_rs::SessionDescription::try_from(answer.as_str()).expect("answer parses");
+        assert!(answer.contains("m=audio 5000 TCP/RTP/AVP 0\r\n"));
+        assert!(answer.contains("a=setup:active\r\n"));
+        assert!(answer.contains("a=connection:new\r\n"));
+
+        // an active offerer gets a passive answerer
+        let offer = offer.replace("a=setup:actpass", "a=setup:active");
+        let answer = build_audio_answer(
+            "10.0.0.1".parse().unwrap(),
+            5000,
+            offer.as_bytes(),
+            &[AudioCodec::Pcmu],
+        )
+        .expect("answer");
+        assert!(String::from_utf8(answer)
+            .unwrap()
+            .contains("a=setup:passive\r\n"));
+    }
+}
@@ -6,26 +6,70 @@ use super::{
     SipConnection, TransactionReceiver, TransactionSender, TransactionTimer,
 };
 use crate::{
+    dialog::authenticate::NonceCounts,
+    rsip_ext::sanitize_for_log,
     transport::{
-        connection::{TransportReceiver, TransportSender},
+        connection::{MalformedMessage, TransportReceiver, TransportSender},
+        dns::DnsConfig,
         SipAddr, TransportEvent, TransportLayer,
     },
     Error, Result, USER_AGENT,
 };
-use rsip::SipMessage;
+use rsip::{
+    prelude::{HeadersExt, UntypedHeader},
+    SipMessage,
+};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     sync::{Arc, Mutex},
-    time::{Duration, Instant},
+    time::Duration,
 };
 use tokio::{
     select,
-    sync::mpsc::{error, unbounded_channel},
-    time::sleep,
+    sync::mpsc::{error, unbounded_channel, UnboundedReceiver, UnboundedSender},
+    time::{sleep, Instant},
 };
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, info, trace, warn};
 
+/// Largest number of requests remembered for merge detection, the oldest
+/// are forgotten first when more arrive within 64*T1
+pub(crate) const MAX_SEEN_REQUESTS: usize = 8192;
+
+/// Out-of-dialog requests received in the last 64*T1, for merge detection
+///
+/// Requests are queued in arrival order, so expired ones are dropped from
+/// the front without scanning the map.
+#[derive(Default)]
+pub(crate) struct SeenRequests {
+    keys: HashMap<String, TransactionKey>,
+    order: VecDeque<(Instant, String)>,
+}
+
+impl SeenRequests {
+    /// Whether `id` was seen with another transaction key, remembering it
+    /// with `key` otherwise
+    pub(crate) fn check(&mut self, id: String, key: &TransactionKey, lifetime: Duration) -> bool {
+        let now = Instant::now();
+        while let Some((at, _)) = self.order.front() {
+            if now.duration_since(*at) < lifetime && self.order.len() < MAX_SEEN_REQUESTS {
+                break;
+            }
+            if let Some((_, expired)) = self.order.pop_front() {
+                self.keys.remove(&expired);
+            }
+        }
+        match self.keys.get(&id) {
+            Some(seen_key) => seen_key != key,
+            None => {
+                self.keys.insert(id.clone(), key.clone());
+                self.order.push_back((now, id));
+                false
+            }
+        }
+    }
+}
+
 /// SIP Endpoint Core Implementation
 ///
 /// `EndpointInner` is the core implementation of a SIP endpoint that manages
@@ -43,17 +87,32 @@ use tracing::{debug, info, trace, warn};
 /// # Fields
 ///
 /// * `allows` - List of supported SIP methods
+/// * `supported` - Option tags advertised in `Supported` headers
 /// * `user_agent` - User-Agent header value for outgoing messages
 /// * `timers` - Timer management system for SIP timers
 /// * `transport_layer` - Transport layer for network communication
 /// * `finished_transactions` - Cache of completed transactions
 /// * `transactions` - Active transaction senders
 /// * `incoming_sender` - Channel for incoming transaction notifications
+/// * `malformed_sender` - Channel for inbound messages that failed to parse, off by default
 /// * `cancel_token` - Cancellation token for graceful shutdown
 /// * `timer_interval` - Interval for timer processing
 /// * `transport_tx` - Transport event sender
 /// * `transport_rx` - Transport event receiver
 /// * `t1`, `t4`, `t1x64` - SIP timer values as per RFC 3261
+/// * `auto_trying` - Send 100 Trying as soon as an INVITE server transaction is created
+/// * `strict_auth` - Fail with `Error::AuthenticationRequired` on a 401/407 when no credential is set
+/// * `max_auth_retries` - Authenticated retries sent for one request before a challenge is returned
+/// * `merged_request_detection` - Answer forks of an already received request with 482
+/// * `reack_2xx` - Re-send the ACK for 2xx retransmissions for 64*T1 after the INVITE completed
+/// * `accept_refer` - Accept in-dialog REFERs with 202 instead of answering 405
+/// * `manual_options` - Leave in-dialog OPTIONS to `Dialog::respond_options` instead of answering at once
+/// * `manual_update` - Leave UPDATE offers to `Dialog::respond_update` instead of answering at once
+/// * `seen_requests` - Recent out-of-dialog requests by From-tag, Call-ID and CSeq, for merge detection
+/// * `nonce_counts` - Digest nonce counts accepted by `ServerInviteDialog::verify_authorization`
+/// * `via_host_override` - Host advertised as the Via `sent-by` instead of the local address
+/// * `max_body_size` - Largest inbound message body accepted, unlimited when `None`
+/// * `dns` - DNS timeout, lookup order and resolver applied to the transport layer
 ///
 /// # Timer Values
 ///
@@ -62,12 +121,14 @@ use tracing::{debug, info, trace, warn};
 /// * `t1x64` - Maximum retransmission timeout (default 32s)
 pub struct EndpointInner {
     pub allows: Vec<rsip::Method>,
+    pub supported: Vec<String>,
     pub user_agent: String,
     pub timers: Timer<TransactionTimer>,
     pub transport_layer: TransportLayer,
     pub finished_transactions: Mutex<HashMap<TransactionKey, Option<SipMessage>>>,
     pub transactions: Mutex<HashMap<TransactionKey, TransactionEventSender>>,
     incoming_sender: Mutex<Option<TransactionSender>>,
+    malformed_sender: Mutex<Option<UnboundedSender<MalformedMessage>>>,
     cancel_token: CancellationToken,
     timer_interval: Duration,
 
@@ -77,9 +138,61 @@ pub struct EndpointInner {
     pub t1: Duration,
     pub t4: Duration,
     pub t1x64: Duration,
+    pub auto_trying: bool,
+    pub strict_auth: bool,
+    pub max_auth_retries: u32,
+    pub merged_request_detection: bool,
+    pub reack_2xx: bool,
+    pub accept_refer: bool,
+    pub manual_options: bool,
+    pub manual_update: bool,
+    seen_requests: Mutex<SeenRequests>,
+    pub nonce_counts: NonceCounts,
+    pub via_host_override: Option<rsip::HostWithPort>,
+    pub max_body_size: Option<usize>,
+    pub initial_cseq: Option<u32>,
 }
 pub type EndpointInnerRef = Arc<EndpointInner>;
 
+/// Protocol behaviour of an endpoint
+///
+/// Set through the [`EndpointBuilder`] `with_*` methods, which document
+/// each option; the defaults are the builder's.
+#[derive(Debug, Clone)]
+pub struct EndpointOptions {
+    pub supported: Vec<String>,
+    pub auto_trying: bool,
+    pub strict_auth: bool,
+    pub max_auth_retries: u32,
+    pub merged_request_detection: bool,
+    pub reack_2xx: bool,
+    pub accept_refer: bool,
+    pub manual_options: bool,
+    pub manual_update: bool,
+    pub via_host_override: Option<rsip::HostWithPort>,
+    pub max_body_size: Option<usize>,
+    pub initial_cseq: Option<u32>,
+}
+
+impl Default for EndpointOptions {
+    fn default() -> Self {
+        EndpointOptions {
+            supported: Vec::new(),
+            auto_trying: true,
+            strict_auth: false,
+            max_auth_retries: super::DEFAULT_MAX_AUTH_RETRIES,
+            merged_request_detection: false,
+            reack_2xx: true,
+            accept_refer: false,
+            manual_options: false,
+            manual_update: false,
+            via_host_override: None,
+            max_body_size: None,
+            initial_cseq: None,
+        }
+    }
+}
+
 /// SIP Endpoint Builder
 ///
 /// `EndpointBuilder` provides a fluent interface for constructing SIP endpoints
@@ -104,6 +217,8 @@ pub struct EndpointBuilder {
     transport_layer: Option<TransportLayer>,
     cancel_token: Option<CancellationToken>,
     timer_interval: Option<Duration>,
+    options: EndpointOptions,
+    dns: Option<DnsConfig>,
 }
 
 /// SIP Endpoint
@@ -168,10 +283,12 @@ impl EndpointInner {
         cancel_token: CancellationToken,
         timer_interval: Option<Duration>,
         allows: Vec<rsip::Method>,
+        options: EndpointOptions,
     ) -> Arc<Self> {
         let (transport_tx, transport_rx) = unbounded_channel();
         Arc::new(EndpointInner {
             allows,
+            supported: options.supported,
             user_agent,
             timers: Timer::new(),
             transport_layer,
@@ -182,12 +299,35 @@ impl EndpointInner {
             transport_rx: Mutex::new(Some(transport_rx)),
             cancel_token,
             incoming_sender: Mutex::new(None),
+            malformed_sender: Mutex::new(None),
             t1: Duration::from_millis(500),
             t4: Duration::from_secs(4),
             t1x64: Duration::from_millis(64 * 500),
+            auto_trying: options.auto_trying,
+            strict_auth: options.strict_auth,
+            max_auth_retries: options.max_auth_retries,
+            merged_request_detection: options.merged_request_detection,
+            reack_2xx: options.reack_2xx,
+            accept_refer: options.accept_refer,
+            manual_options: options.manual_options,
+            manual_update: options.manual_update,
+            seen_requests: Mutex::new(SeenRequests::default()),
+            nonce_counts: NonceCounts::default(),
+            via_host_override: options.via_host_override,
+            max_body_size: options.max_body_size,
+            initial_cseq: options.initial_cseq,
         })
     }
 
+    /// First CSeq of a new dialog or registration
+    ///
+    /// Random unless a fixed value was set with
+    /// [`EndpointBuilder::with_initial_cseq`].
+    pub fn make_cseq(&self) -> u32 {
+        self.initial_cseq
+            .unwrap_or_else(super::generate_random_cseq)
+    }
+
     pub async fn serve(self: &Arc<Self>) -> Result<()> {
         select! {
             _ = self.cancel_token.cancelled() => {
@@ -217,7 +357,7 @@ impl EndpointInner {
         while let Some(event) = transport_rx.recv().await {
             match event {
                 TransportEvent::Incoming(msg, connection, from) => {
-                    match self.on_received_message(msg, connection).await {
+                    match self.on_received_message(msg, connection, from.clone()).await {
                         Ok(()) => {}
                         Err(e) => {
                             warn!("on_received_message error:{} {:?}", from, e);
@@ -230,6 +370,11 @@ impl EndpointInner {
                 TransportEvent::Closed(t) => {
                     trace!("connection closed {} ", t);
                 }
+                TransportEvent::Malformed(malformed) => {
+                    if let Some(sender) = self.malformed_sender.lock().unwrap().as_ref() {
+                        sender.send(malformed).ok();
+                    }
+                }
             }
         }
         Ok(())
@@ -269,12 +414,43 @@ impl EndpointInner {
         *self.incoming_sender.lock().unwrap() = sender;
     }
 
+    pub fn attach_malformed_sender(&self, sender: Option<UnboundedSender<MalformedMessage>>) {
+        *self.malformed_sender.lock().unwrap() = sender;
+    }
+
     // receive message from transport layer
     pub async fn on_received_message(
         self: &Arc<Self>,
         msg: SipMessage,
         connection: SipConnection,
+        source: SipAddr,
     ) -> Result<()> {
+        if self.exceeds_max_body_size(&msg) {
+            match msg {
+                SipMessage::Request(request) => {
+                    info!(
+                        "{} from {} exceeds max body size {:?}",
+                        request.method, source, self.max_body_size
+                    );
+                    if request.method != rsip::Method::Ack {
+                        let resp = self.make_response(
+                            &request,
+                            rsip::StatusCode::RequestEntityTooLarge,
+                            None,
+                        );
+                        connection.send(resp.into(), None).await?;
+                    }
+                }
+                SipMessage::Response(resp) => {
+                    info!(
+                        "dropping {} response from {} exceeding max body size {:?}",
+                        resp.status_code, source, self.max_body_size
+                    );
+                }
+            }
+            return Ok(());
+        }
+
         let mut key = match &msg {
             SipMessage::Request(req) => {
                 TransactionKey::from_request(req, super::key::TransactionRole::Server)?
@@ -294,6 +470,15 @@ impl EndpointInner {
             .flatten();
 
         if let Some(last_message) = last_message {
+            if let (SipMessage::Request(ack), SipMessage::Response(resp)) = (&last_message, &msg) {
+                if !self.is_acked_response(ack, resp) {
+                    debug!(
+                        "not re-sending ACK for {} {}, it belongs to another dialog",
+                        key, resp.status_code
+                    );
+                    return Ok(());
+                }
+            }
             connection.send(last_message, None).await?;
             return Ok(());
         }
@@ -310,7 +495,11 @@ impl EndpointInner {
         let request = match msg {
             SipMessage::Request(req) => req,
             SipMessage::Response(resp) => {
-                debug!("the transaction is not exist {} {}", key, resp);
+                debug!(
+                    "the transaction is not exist {} {}",
+                    key,
+                    sanitize_for_log(&resp.to_string())
+                );
                 return Ok(());
             }
         };
@@ -325,6 +514,9 @@ impl EndpointInner {
                     .collect::<Vec<_>>()
                     .join(", ");
                 resp.headers.unique_push(rsip::Header::Allow(allowed_methods.into()));
+                if let Some(supported) = self.supported_header() {
+                    resp.headers.unique_push(supported);
+                }
                 resp.headers.unique_push(rsip::Header::ContentLength(0.into()));
                 resp
             } else {
@@ -343,13 +535,29 @@ impl EndpointInner {
             ));
         }
 
+        if self.is_merged_request(&request, &key) {
+            info!(
+                "{} from {} is a merged request, answering 482",
+                request.method, source
+            );
+            let resp = self.make_response(&request, rsip::StatusCode::LoopDetected, None);
+            connection.send(resp.into(), None).await?;
+            return Ok(());
+        }
+
         if matches!(request.method, rsip::Method::Ack | rsip::Method::Cancel) {
             key =
                 TransactionKey::from_ack_or_cancel(&request, super::key::TransactionRole::Server)?;
         }
 
-        let tx =
+        let mut tx =
             Transaction::new_server(key.clone(), request.clone(), self.clone(), Some(connection));
+        tx.source.replace(source);
+
+        // Stop INVITE retransmissions before the TU has decided anything (RFC 3261 17.2.1)
+        if self.auto_trying && request.method == rsip::Method::Invite {
+            tx.send_trying().await?;
+        }
 
         self.incoming_sender
             .lock()
@@ -359,6 +567,60 @@ impl EndpointInner {
         return Ok(());
     }
 
+    /// Check whether a new request was already received on another transaction
+    ///
+    /// Remembers out-of-dialog requests for 64*T1 by From-tag, Call-ID and
+    /// CSeq, at most `MAX_SEEN_REQUESTS` of them; the same request arriving
+    /// with another transaction key is a merged fork (RFC 3261 8.2.2.2).
+    fn is_merged_request(&self, request: &rsip::Request, key: &TransactionKey) -> bool {
+        if !self.merged_request_detection
+            || matches!(request.method, rsip::Method::Ack | rsip::Method::Cancel)
+        {
+            return false;
+        }
+        let has_to_tag = request
+            .to_header()
+            .and_then(|to| to.tag())
+            .is_ok_and(|tag| tag.is_some_and(|tag| !tag.value().is_empty()));
+        if has_to_tag {
+            return false;
+        }
+        let (from_tag, call_id, cseq) = match (
+            request.from_header().and_then(|from| from.tag()),
+            request.call_id_header(),
+            request.cseq_header(),
+        ) {
+            (Ok(Some(from_tag)), Ok(call_id), Ok(cseq)) => (from_tag, call_id, cseq),
+            _ => return false,
+        };
+        let id = format!("{}:{}:{}", from_tag.value(), call_id.value(), cseq.value());
+
+        self.seen_requests
+            .lock()
+            .unwrap()
+            .check(id, key, self.t1x64)
+    }
+
+    /// Check the declared and actual body size against `max_body_size`
+    fn exceeds_max_body_size(&self, msg: &SipMessage) -> bool {
+        let max_body_size = match self.max_body_size {
+            Some(max_body_size) => max_body_size,
+            None => return false,
+        };
+        let headers = match msg {
+            SipMessage::Request(req) => &req.headers,
+            SipMessage::Response(resp) => &resp.headers,
+        };
+        let declared = headers
+            .iter()
+            .find_map(|h| match h {
+                rsip::Header::ContentLength(len) => len.length().ok(),
+                _ => None,
+            })
+            .unwrap_or_default() as usize;
+        declared.max(msg.body().len()) > max_body_size
+    }
+
     pub fn attach_transaction(&self, key: &TransactionKey, tu_sender: TransactionEventSender) {
         trace!("attach_transaction {}", key);
         self.transactions
@@ -372,14 +634,18 @@ impl EndpointInner {
         self.transactions.lock().unwrap().remove(key);
 
         if let Some(msg) = last_message {
-            let timer_k_duration = if msg.is_request() {
-                self.t4
-            } else {
-                self.t1x64
+            let linger = match &msg {
+                // a UAS retransmits its 2xx for 64*T1, and each copy must be
+                // ACKed again (RFC 3261 13.2.2.4)
+                SipMessage::Request(req) if req.method == rsip::Method::Ack && self.reack_2xx => {
+                    self.t1x64
+                }
+                SipMessage::Request(_) => self.t4,
+                SipMessage::Response(_) => self.t1x64,
             };
 
             self.timers.timeout(
-                timer_k_duration,
+                linger,
                 TransactionTimer::TimerCleanup(key.clone()), // maybe use TimerK ???
             );
 
@@ -390,10 +656,49 @@ impl EndpointInner {
         }
     }
 
+    /// Whether `ack` answers `resp`, a response of a finished INVITE
+    ///
+    /// The response must carry the To tag of the ACK: a 2xx of another fork
+    /// is a different dialog that the ACK does not acknowledge. 2xx are
+    /// only re-ACKed when enabled with [`EndpointBuilder::with_reack_2xx`].
+    fn is_acked_response(&self, ack: &rsip::Request, resp: &rsip::Response) -> bool {
+        if resp.status_code.kind() == rsip::StatusCodeKind::Successful && !self.reack_2xx {
+            return false;
+        }
+        let ack_tag = ack.to_header().and_then(|to| to.tag()).ok().flatten();
+        let resp_tag = resp.to_header().and_then(|to| to.tag()).ok().flatten();
+        ack_tag.is_some() && ack_tag == resp_tag
+    }
+
     pub fn get_addrs(&self) -> Vec<SipAddr> {
         self.transport_layer.get_addrs()
     }
 
+    /// Check whether `option` is one of the configured `Supported` option tags
+    pub fn is_supported(&self, option: &str) -> bool {
+        self.supported
+            .iter()
+            .any(|tag| tag.eq_ignore_ascii_case(option))
+    }
+
+    /// `Supported` header listing the configured option tags, if any
+    pub fn supported_header(&self) -> Option<rsip::Header> {
+        if self.supported.is_empty() {
+            return None;
+        }
+        Some(rsip::Header::Supported(self.supported.join(", ").into()))
+    }
+
+    /// Loose `Route` to the transport layer's outbound proxy, if one is set
+    pub fn outbound_route(&self) -> Option<rsip::Header> {
+        let proxy = self.transport_layer.outbound.clone()?;
+        let route = rsip::typed::Route(rsip::UriWithParamsList(vec![rsip::UriWithParams {
+            uri: proxy.into(),
+            params: vec![rsip::Param::Other("lr".into(), None)].into(),
+        }]));
+        Some(rsip::Header::Route(route.into()))
+    }
+
     pub fn get_record_route(&self) -> Result<rsip::typed::RecordRoute> {
         let first_addr = self
             .transport_layer
@@ -440,6 +745,12 @@ impl EndpointInner {
             }
         }
 
+        // The sent-by may advertise a VIP or FQDN, the request still leaves
+        // through the local socket
+        if let Some(host) = self.via_host_override.as_ref() {
+            first_addr.addr = host.clone();
+        }
+
         let via = rsip::typed::Via {
             version: rsip::Version::V2,
             transport: first_addr.r#type.unwrap_or_default(),
@@ -462,6 +773,8 @@ impl EndpointBuilder {
             transport_layer: None,
             cancel_token: None,
             timer_interval: None,
+            options: EndpointOptions::default(),
+            dns: None,
         }
     }
 
@@ -488,6 +801,255 @@ impl EndpointBuilder {
         self.allows = allows;
         self
     }
+    /// Set the option tags advertised in `Supported` headers
+    ///
+    /// The tags are sent on outgoing INVITEs, on 2xx answers to inbound
+    /// INVITEs and on answers to in-dialog OPTIONS. Some of them also change
+    /// how the stack behaves, e.g. `norefersub` lets a REFER carrying
+    /// `Refer-Sub: false` be accepted without an implicit subscription.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rsipstack::EndpointBuilder;
+    ///
+    /// let endpoint = EndpointBuilder::new()
+    ///     .with_supported(vec!["replaces".to_string(), "norefersub".to_string()])
+    ///     .build();
+    /// assert!(endpoint.inner.is_supported("norefersub"));
+    /// ```
+    pub fn with_supported(&mut self, supported: Vec<String>) -> &mut Self {
+        self.options.supported = supported;
+        self
+    }
+    /// Control the automatic 100 Trying on inbound INVITE
+    ///
+    /// Enabled by default: the endpoint answers every new INVITE server
+    /// transaction with 100 Trying before handing it to the application.
+    /// Proxies that want to decide themselves when (or whether) to send it
+    /// can turn this off and call `Transaction::send_trying`.
+    pub fn with_auto_trying(&mut self, auto_trying: bool) -> &mut Self {
+        self.options.auto_trying = auto_trying;
+        self
+    }
+    /// Treat a challenge without a configured credential as an error
+    ///
+    /// By default, when a 401/407 arrives and no credential was given,
+    /// `Registration::register` returns the challenge response and dialog
+    /// requests terminate the dialog. With strict auth enabled they return
+    /// `Error::AuthenticationRequired` instead, so the missing credential
+    /// surfaces as a programming error.
+    pub fn with_strict_auth(&mut self, strict_auth: bool) -> &mut Self {
+        self.options.strict_auth = strict_auth;
+        self
+    }
+    /// Limit the authenticated retries of one request
+    ///
+    /// A request may be challenged more than once, e.g. by an outbound
+    /// proxy and then by the registrar, or again with `stale=true` once a
+    /// nonce expired. Each challenge is answered until `max_auth_retries`
+    /// retries were sent; a new challenge for a realm already answered
+    /// without `stale=true` means the credentials were rejected and ends the
+    /// retries early. Defaults to [`super::DEFAULT_MAX_AUTH_RETRIES`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rsipstack::EndpointBuilder;
+    ///
+    /// let endpoint = EndpointBuilder::new().with_max_auth_retries(5).build();
+    /// assert_eq!(endpoint.inner.max_auth_retries, 5);
+    /// ```
+    pub fn with_max_auth_retries(&mut self, max_auth_retries: u32) -> &mut Self {
+        self.options.max_auth_retries = max_auth_retries;
+        self
+    }
+    /// Control merged request detection (RFC 3261 8.2.2.2)
+    ///
+    /// Disabled by default. When enabled, a request without To-tag that has
+    /// the From-tag, Call-ID and CSeq of a request received shortly before
+    /// on another transaction is a fork that reached this UAS twice, and is
+    /// answered with 482 Loop Detected. Only UAs should turn it on; proxies
+    /// must forward such requests.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rsipstack::EndpointBuilder;
+    ///
+    /// let endpoint = EndpointBuilder::new()
+    ///     .with_merged_request_detection(true)
+    ///     .build();
+    /// assert!(endpoint.inner.merged_request_detection);
+    /// ```
+    pub fn with_merged_request_detection(&mut self, enabled: bool) -> &mut Self {
+        self.options.merged_request_detection = enabled;
+        self
+    }
+    /// Control re-ACKing of retransmitted 2xx responses (RFC 3261 13.2.2.4)
+    ///
+    /// Enabled by default: a UAS that did not see the ACK retransmits its
+    /// 2xx for 64*T1, and each copy is answered with the same ACK again, as
+    /// long as its To tag is the one of the acknowledged dialog. When
+    /// disabled, 2xx retransmissions are not answered once the INVITE
+    /// transaction ended.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rsipstack::EndpointBuilder;
+    ///
+    /// let endpoint = EndpointBuilder::new().with_reack_2xx(false).build();
+    /// assert!(!endpoint.inner.reack_2xx);
+    /// ```
+    pub fn with_reack_2xx(&mut self, enabled: bool) -> &mut Self {
+        self.options.reack_2xx = enabled;
+        self
+    }
+    /// Accept call transfers with in-dialog REFER (RFC 3515)
+    ///
+    /// Disabled by default, and a REFER is answered with 405 Method Not
+    /// Allowed. When enabled, REFER is listed in `Allow`, a REFER is
+    /// answered with 202 Accepted and surfaced as `DialogState::Refer`, and
+    /// its progress is reported with `Dialog::notify_refer`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rsipstack::EndpointBuilder;
+    ///
+    /// let endpoint = EndpointBuilder::new().with_accept_refer(true).build();
+    /// assert!(endpoint.inner.accept_refer);
+    /// ```
+    pub fn with_accept_refer(&mut self, enabled: bool) -> &mut Self {
+        self.options.accept_refer = enabled;
+        self
+    }
+    /// Let the application answer in-dialog OPTIONS
+    ///
+    /// Disabled by default, and an in-dialog OPTIONS is answered with 200 OK
+    /// as soon as it is surfaced as `DialogState::Options`. When enabled, the
+    /// request stays pending until the application calls
+    /// `Dialog::respond_options`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rsipstack::EndpointBuilder;
+    ///
+    /// let endpoint = EndpointBuilder::new().with_manual_options(true).build();
+    /// assert!(endpoint.inner.manual_options);
+    /// ```
+    pub fn with_manual_options(&mut self, enabled: bool) -> &mut Self {
+        self.options.manual_options = enabled;
+        self
+    }
+    /// Let the application answer UPDATE offers
+    ///
+    /// Disabled by default, and an in-dialog UPDATE is answered with 200 OK
+    /// as soon as it is surfaced as `DialogState::Updated`. When enabled, an
+    /// UPDATE carrying an SDP offer stays pending until the application
+    /// answers it with `Dialog::respond_update`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rsipstack::EndpointBuilder;
+    ///
+    /// let endpoint = EndpointBuilder::new().with_manual_update(true).build();
+    /// assert!(endpoint.inner.manual_update);
+    /// ```
+    pub fn with_manual_update(&mut self, enabled: bool) -> &mut Self {
+        self.options.manual_update = enabled;
+        self
+    }
+    /// Advertise a fixed host in the Via `sent-by`
+    ///
+    /// Behind some NATs and load balancers the topmost Via must name an
+    /// FQDN or VIP rather than the address the socket is bound to. Requests
+    /// are still sent from the local socket to their real destination.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rsipstack::EndpointBuilder;
+    ///
+    /// let endpoint = EndpointBuilder::new()
+    ///     .with_via_host_override(rsip::HostWithPort::try_from("sip.example.com:5060").unwrap())
+    ///     .build();
+    /// ```
+    pub fn with_via_host_override(&mut self, host: rsip::HostWithPort) -> &mut Self {
+        self.options.via_host_override = Some(host);
+        self
+    }
+    /// Limit the size of inbound message bodies
+    ///
+    /// Messages whose `Content-Length` or body exceeds `max_body_size` bytes
+    /// are not handed to any transaction: requests are answered with
+    /// 413 Request Entity Too Large, responses are dropped. The limit is
+    /// also set on the transport layer, so UDP and TCP connections stop
+    /// reading such a body into memory once its headers are in. Unlimited
+    /// by default.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rsipstack::EndpointBuilder;
+    ///
+    /// let endpoint = EndpointBuilder::new()
+    ///     .with_max_body_size(16 * 1024)
+    ///     .build();
+    /// ```
+    pub fn with_max_body_size(&mut self, max_body_size: usize) -> &mut Self {
+        self.options.max_body_size = Some(max_body_size);
+        self
+    }
+    /// Start every dialog and registration at a fixed CSeq
+    ///
+    /// CSeq numbers are random by default. A fixed start makes requests
+    /// deterministic, e.g. in tests. Values above [`super::MAX_CSEQ`] are
+    /// lowered to it.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rsipstack::EndpointBuilder;
+    ///
+    /// let endpoint = EndpointBuilder::new().with_initial_cseq(1).build();
+    /// assert_eq!(endpoint.inner.make_cseq(), 1);
+    /// ```
+    pub fn with_initial_cseq(&mut self, cseq: u32) -> &mut Self {
+        self.options.initial_cseq = Some(cseq.min(super::MAX_CSEQ));
+        self
+    }
+    /// Bound and order the DNS lookups locating servers
+    ///
+    /// Applied to the transport layer when the endpoint is built. A lookup
+    /// taking longer than the timeout, e.g. on an unresponsive resolver,
+    /// fails with `Error::DnsResolutionError`. Networks whose resolvers do
+    /// not answer NAPTR queries can skip them with [`DnsLookupOrder::Srv`].
+    ///
+    /// [`DnsLookupOrder::Srv`]: crate::transport::dns::DnsLookupOrder::Srv
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rsipstack::transport::dns::{DnsConfig, DnsLookupOrder};
+    /// use rsipstack::EndpointBuilder;
+    /// use std::time::Duration;
+    ///
+    /// let endpoint = EndpointBuilder::new()
+    ///     .with_dns_config(DnsConfig {
+    ///         timeout: Duration::from_secs(2),
+    ///         order: DnsLookupOrder::Srv,
+    ///         resolver: None,
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn with_dns_config(&mut self, dns: DnsConfig) -> &mut Self {
+        self.dns = Some(dns);
+        self
+    }
     pub fn build(&mut self) -> Endpoint {
         let cancel_token = self.cancel_token.take().unwrap_or_default();
 
@@ -495,6 +1057,12 @@ impl EndpointBuilder {
             .transport_layer
             .take()
             .unwrap_or(TransportLayer::new(cancel_token.child_token()));
+        if let Some(dns) = self.dns.take() {
+            transport_layer.set_dns_config(dns);
+        }
+        if self.options.max_body_size.is_some() {
+            transport_layer.set_max_body_size(self.options.max_body_size);
+        }
 
         let allows = self.allows.to_owned();
         let user_agent = self.user_agent.to_owned();
@@ -506,6 +1074,7 @@ impl EndpointBuilder {
             cancel_token,
             timer_interval,
             allows,
+            self.options.clone(),
         );
 
         Endpoint { inner: core }
@@ -539,7 +1108,69 @@ impl Endpoint {
         rx
     }
 
+    /// Surface inbound messages that fail to parse
+    ///
+    /// By default unparseable messages are logged and dropped. Once this is
+    /// called, each one is delivered with its raw bytes, the parse error and
+    /// the source address, which helps to debug interop with non-compliant
+    /// devices.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::EndpointBuilder;
+    /// # async fn example() {
+    /// let endpoint = EndpointBuilder::new().build();
+    /// let mut malformed = endpoint.malformed_messages();
+    /// while let Some(message) = malformed.recv().await {
+    ///     println!("{} sent garbage: {}", message.source, message.error);
+    /// }
+    /// # }
+    /// ```
+    pub fn malformed_messages(&self) -> UnboundedReceiver<MalformedMessage> {
+        let (tx, rx) = unbounded_channel();
+        self.inner.attach_malformed_sender(Some(tx));
+        rx
+    }
+
     pub fn get_addrs(&self) -> Vec<SipAddr> {
         self.inner.transport_layer.get_addrs()
     }
+
+    /// Listen on an additional local transport
+    ///
+    /// Binds a listener for the transport type of `addr` and delivers what
+    /// it receives to this endpoint. Call it for each transport the UA should
+    /// accept requests on, before [`Endpoint::serve`]. Requests remember the
+    /// connection they arrived on, so responses and the Contact of server
+    /// dialogs use the matching transport and address.
+    ///
+    /// # Parameters
+    ///
+    /// * `addr` - Local address and transport to bind, port 0 picks a free port
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(SipAddr)` - The bound address
+    /// * `Err(Error)` - Binding failed or the transport is not supported
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::transaction::endpoint::Endpoint;
+    /// # use rsipstack::transport::SipAddr;
+    /// # async fn example(endpoint: Endpoint) -> rsipstack::Result<()> {
+    /// let host = rsip::HostWithPort::try_from("0.0.0.0:5060")?;
+    /// endpoint.listen(SipAddr::new(rsip::Transport::Udp, host.clone())).await?;
+    /// endpoint.listen(SipAddr::new(rsip::Transport::Tcp, host)).await?;
+    /// endpoint.serve().await;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn listen(&self, addr: SipAddr) -> Result<SipAddr> {
+        self.inner
+            .transport_layer
+            .add_listener(addr, self.inner.transport_tx.clone())
+            .await
+    }
 }
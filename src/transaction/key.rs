@@ -47,6 +47,12 @@ impl TransactionKey {
         Self::build_key(role, via, method, cseq, from_tag, call_id)
     }
 
+    /// Build the transaction key of a request
+    ///
+    /// A stateful proxy keys the inbound request with
+    /// `TransactionRole::Server` and the forwarded copy, whose top Via
+    /// carries a [`TransactionKey::proxy_branch`], with
+    /// `TransactionRole::Client`.
     pub fn from_request(req: &Request, role: TransactionRole) -> Result<Self> {
         let via = req.via_header()?.typed()?;
         let mut method = req.method().clone();
@@ -77,6 +83,41 @@ impl TransactionKey {
         Self::build_key(role, via, method, cseq.seq()?, from_tag, call_id)
     }
 
+    /// Derive the branch of a forwarded request from the inbound branch
+    ///
+    /// RFC 3261 §16.6 lets a stateful proxy compute the branch of its own Via
+    /// from the inbound request. Each fork of the request needs a branch of
+    /// its own, so the fork index is part of the hash. The result is
+    /// deterministic, so a CANCEL or a retransmission of the inbound request
+    /// maps to the same client transaction of each fork, and it keeps the
+    /// `z9hG4bK` magic cookie.
+    ///
+    /// # Parameters
+    ///
+    /// * `inbound_branch` - Branch of the top Via of the inbound request
+    /// * `fork` - Index of the target the request is forwarded to
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rsipstack::transaction::key::TransactionKey;
+    ///
+    /// let branch = TransactionKey::proxy_branch("z9hG4bK776asdhds", 0);
+    /// assert_eq!(branch, TransactionKey::proxy_branch("z9hG4bK776asdhds", 0));
+    /// assert_ne!(branch, TransactionKey::proxy_branch("z9hG4bK776asdhds", 1));
+    /// assert!(branch.to_string().starts_with(";branch=z9hG4bK"));
+    /// ```
+    pub fn proxy_branch(inbound_branch: &str, fork: u32) -> rsip::Param {
+        // FNV-1a, stable across processes and builds
+        let hash = inbound_branch
+            .bytes()
+            .chain(fork.to_be_bytes())
+            .fold(0xcbf29ce484222325u64, |hash, b| {
+                (hash ^ b as u64).wrapping_mul(0x100000001b3)
+            });
+        rsip::Param::Branch(format!("z9hG4bK{:016x}", hash).into())
+    }
+
     fn build_key(
         role: TransactionRole,
         via: Via,
@@ -106,6 +147,27 @@ impl TransactionKey {
     }
 }
 
+#[test]
+fn test_proxy_branch() {
+    let branch = TransactionKey::proxy_branch("z9hG4bKnashd92", 0);
+    assert_eq!(branch, TransactionKey::proxy_branch("z9hG4bKnashd92", 0));
+    assert_ne!(branch, TransactionKey::proxy_branch("z9hG4bKnashd93", 0));
+    // every fork of the request gets its own client transaction
+    assert_ne!(branch, TransactionKey::proxy_branch("z9hG4bKnashd92", 1));
+    assert_ne!(
+        TransactionKey::proxy_branch("z9hG4bKnashd92", 1),
+        TransactionKey::proxy_branch("z9hG4bKnashd92", 2)
+    );
+    match branch {
+        rsip::Param::Branch(branch) => {
+            let value = branch.to_string();
+            assert!(value.starts_with("z9hG4bK"));
+            assert_ne!(value, "z9hG4bKnashd92");
+        }
+        _ => panic!("not a branch param"),
+    }
+}
+
 #[test]
 fn test_transaction_key() -> Result<()> {
     use rsip::headers::*;
@@ -19,6 +19,12 @@ pub const TO_TAG_LEN: usize = 8;
 pub const BRANCH_LEN: usize = 12;
 pub const CNONCE_LEN: usize = 8;
 
+/// Largest CSeq value, RFC 3261 requires CSeq to be less than 2**31
+pub const MAX_CSEQ: u32 = 0x7FFF_FFFF;
+
+/// Authenticated retries sent for one request unless configured otherwise
+pub const DEFAULT_MAX_AUTH_RETRIES: u32 = 3;
+
 /// Generate a random CSeq value according to RFC 3261
 /// CSeq values MUST be expressible as a 32-bit unsigned integer
 /// and MUST be less than 2**31
@@ -27,6 +33,27 @@ pub fn generate_random_cseq() -> u32 {
     rand::random::<u32>() % 0x7FFFFFFE + 1
 }
 
+/// CSeq following `seq`
+///
+/// Wraps around to 1 after [`MAX_CSEQ`], so a long lived dialog or
+/// registration never leaves the range allowed by RFC 3261.
+///
+/// # Examples
+///
+/// ```rust
+/// use rsipstack::transaction::{next_cseq, MAX_CSEQ};
+///
+/// assert_eq!(next_cseq(41), 42);
+/// assert_eq!(next_cseq(MAX_CSEQ), 1);
+/// ```
+pub fn next_cseq(seq: u32) -> u32 {
+    if seq >= MAX_CSEQ {
+        1
+    } else {
+        seq + 1
+    }
+}
+
 pub struct IncomingRequest {
     pub request: rsip::Request,
     pub connection: SipConnection,
@@ -279,6 +306,22 @@ pub fn make_via_branch() -> rsip::Param {
     rsip::Param::Branch(format!("z9hG4bK{}", random_text(BRANCH_LEN)).into())
 }
 
+/// Make a Via branch carrying an application token, e.g. a trace id
+///
+/// The token is appended to a regular random branch, so the branch keeps the
+/// RFC 3261 magic cookie and stays unique. Characters that are not allowed in
+/// a SIP token are dropped from the supplied value.
+pub fn make_via_branch_with_token(token: &str) -> rsip::Param {
+    let token = token
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || "-.!%*_+`'~".contains(*c))
+        .collect::<String>();
+    if token.is_empty() {
+        return make_via_branch();
+    }
+    rsip::Param::Branch(format!("z9hG4bK{}-{}", random_text(BRANCH_LEN), token).into())
+}
+
 pub fn make_call_id(domain: Option<&str>) -> rsip::headers::CallId {
     format!("{}@{}", Uuid::new_v4(), domain.unwrap_or("restsend.com")).into()
 }
@@ -108,3 +108,136 @@ async fn test_client_transaction() -> Result<()> {
     }
     Ok(())
 }
+
+#[tokio::test]
+async fn test_client_transaction_over_inbound_connection() -> Result<()> {
+    use crate::transport::SipAddr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let endpoint = super::create_test_endpoint(None).await?;
+    let tcp_addr = endpoint
+        .listen(SipAddr::new(
+            rsip::Transport::Tcp,
+            rsip::HostWithPort::try_from("127.0.0.1:0")?,
+        ))
+        .await?;
+    let mut incoming = endpoint.incoming_transactions();
+    let inner = endpoint.inner.clone();
+    tokio::spawn(async move { inner.serve().await });
+
+    let mut peer = tokio::net::TcpStream::connect(tcp_addr.get_socketaddr()?).await?;
+    let peer_addr = peer.local_addr()?;
+    let options = rsip::Request {
+        method: rsip::Method::Options,
+        uri: rsip::Uri::try_from(format!("sip:bob@{}", tcp_addr.addr).as_str())?,
+        headers: vec![
+            Via::new(&format!("SIP/2.0/TCP {};branch=z9hG4bKinbound", peer_addr)).into(),
+            CSeq::new("1 OPTIONS").into(),
+            From::new("Alice <sip:alice@example.com>;tag=inbound").into(),
+            To::new("Bob <sip:bob@example.com>").into(),
+            CallId::new("inbound-connection@example.com").into(),
+            ContentLength::from(0).into(),
+        ]
+        .into(),
+        version: rsip::Version::V2,
+        body: Default::default(),
+    };
+    peer.write_all(options.to_string().as_bytes()).await?;
+
+    let inbound = tokio::time::timeout(Duration::from_secs(5), incoming.recv())
+        .await
+        .expect("no inbound transaction")
+        .expect("incoming closed");
+    let source = inbound.source.clone().expect("inbound source");
+    assert_eq!(source.get_socketaddr()?, peer_addr);
+    let connection = inbound.connection.clone().expect("inbound connection");
+
+    // the Request-URI points elsewhere, the request must still use the inbound connection
+    let notify = rsip::Request {
+        method: rsip::Method::Notify,
+        uri: rsip::Uri::try_from("sip:alice@192.0.2.1:5060;transport=tcp")?,
+        headers: vec![
+            Via::new("SIP/2.0/TCP 127.0.0.1:5060;branch=z9hG4bKoutbound").into(),
+            CSeq::new("1 NOTIFY").into(),
+            From::new("Bob <sip:bob@example.com>;tag=outbound").into(),
+            To::new("Alice <sip:alice@example.com>").into(),
+            CallId::new("outbound-connection@example.com").into(),
+        ]
+        .into(),
+        version: rsip::Version::V2,
+        body: Default::default(),
+    };
+    let key = TransactionKey::from_request(&notify, TransactionRole::Client)?;
+    let mut tx = Transaction::new_client_over(
+        key,
+        notify,
+        endpoint.inner.clone(),
+        connection,
+        Some(source),
+    );
+    tx.send().await?;
+
+    let mut received = String::new();
+    let mut buf = vec![0u8; 4096];
+    while !received.contains("NOTIFY sip:alice@192.0.2.1") {
+        let len = tokio::time::timeout(Duration::from_secs(5), peer.read(&mut buf))
+            .await
+            .expect("NOTIFY not sent over the inbound connection")?;
+        assert!(len > 0, "connection closed before NOTIFY");
+        received.push_str(&String::from_utf8_lossy(&buf[..len]));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_transaction_via_host_override() -> Result<()> {
+    use crate::transport::TransportLayer;
+    use rsip::prelude::{HeadersExt, UntypedHeader};
+    use tokio_util::sync::CancellationToken;
+
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    let udp = UdpConnection::create_connection("127.0.0.1:0".parse()?, None).await?;
+    let local_addr = udp.get_addr().get_socketaddr()?;
+    tl.add_transport(udp.into());
+    let endpoint = crate::EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_via_host_override(rsip::HostWithPort::try_from("sip.example.com:5080")?)
+        .build();
+
+    let peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let via = endpoint.inner.get_via(None, None)?;
+    let options = rsip::Request {
+        method: rsip::Method::Options,
+        uri: rsip::Uri::try_from(format!("sip:bob@{}", peer.local_addr()?).as_str())?,
+        headers: vec![
+            rsip::Header::Via(via.into()),
+            CSeq::new("1 OPTIONS").into(),
+            From::new("Alice <sip:alice@example.com>;tag=via-override").into(),
+            To::new("Bob <sip:bob@example.com>").into(),
+            CallId::new("via-override@example.com").into(),
+        ]
+        .into(),
+        version: rsip::Version::V2,
+        body: Default::default(),
+    };
+    let key = TransactionKey::from_request(&options, TransactionRole::Client)?;
+    let mut tx = Transaction::new_client(key, options, endpoint.inner.clone(), None);
+    tx.send().await?;
+
+    let mut buf = vec![0u8; 2048];
+    let (len, from) = tokio::time::timeout(Duration::from_secs(5), peer.recv_from(&mut buf))
+        .await
+        .expect("OPTIONS was not sent")?;
+    assert_eq!(from, local_addr);
+    let received = match SipMessage::try_from(std::str::from_utf8(&buf[..len]).unwrap())? {
+        SipMessage::Request(req) => req,
+        _ => panic!("expected a request"),
+    };
+    assert!(received
+        .via_header()?
+        .value()
+        .starts_with("SIP/2.0/UDP sip.example.com:5080;"));
+    Ok(())
+}
@@ -86,3 +86,229 @@ async fn test_endpoint_recvrequests() {
         }
     }
 }
+
+#[tokio::test]
+async fn test_endpoint_malformed_messages() {
+    let endpoint = super::create_test_endpoint(Some("127.0.0.1:0"))
+        .await
+        .expect("create_test_endpoint");
+    let addr = endpoint
+        .get_addrs()
+        .first()
+        .expect("must has connection")
+        .get_socketaddr()
+        .expect("socket addr");
+    let mut malformed = endpoint.malformed_messages();
+
+    let peer = tokio::net::UdpSocket::bind("127.0.0.1:0")
+        .await
+        .expect("bind peer");
+    let garbage = b"NOT SIP AT ALL\r\n\x01\x02garbage\r\n\r\n";
+
+    select! {
+        _ = endpoint.serve() => {
+            assert!(false, "must not reach here");
+        }
+        _ = sleep(Duration::from_secs(5)) => {
+            assert!(false, "no malformed message reported");
+        }
+        message = async {
+            peer.send_to(garbage, addr).await.expect("send garbage");
+            malformed.recv().await.expect("malformed")
+        } => {
+            assert_eq!(message.data, garbage.to_vec());
+            assert!(!message.error.is_empty());
+            assert_eq!(
+                message.source.get_socketaddr().expect("source"),
+                peer.local_addr().expect("peer addr")
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_endpoint_max_body_size() {
+    use crate::transaction::endpoint::EndpointBuilder;
+    use crate::transport::{udp::UdpConnection, TransportLayer};
+    use tokio_util::sync::CancellationToken;
+
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    let udp = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None)
+        .await
+        .expect("create_connection");
+    let addr = udp.get_addr().get_socketaddr().expect("socket addr");
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token)
+        .with_max_body_size(1024)
+        .build();
+    let mut incoming = endpoint.incoming_transactions();
+
+    let peer = tokio::net::UdpSocket::bind("127.0.0.1:0")
+        .await
+        .expect("bind peer");
+    let peer_addr = peer.local_addr().expect("peer addr");
+    let message = rsip::Request {
+        method: rsip::Method::Message,
+        uri: rsip::Uri::try_from(format!("sip:bob@{}", addr).as_str()).expect("uri"),
+        headers: vec![
+            Via::new(&format!("SIP/2.0/UDP {};branch=z9hG4bKlarge", peer_addr)).into(),
+            CSeq::new("1 MESSAGE").into(),
+            From::new("Alice <sip:alice@example.com>;tag=alice-tag").into(),
+            To::new("Bob <sip:bob@example.com>").into(),
+            CallId::new("large-body@example.com").into(),
+            ContentType::new("text/plain").into(),
+            ContentLength::from(2048u32).into(),
+        ]
+        .into(),
+        version: rsip::Version::V2,
+        body: vec![b'x'; 2048],
+    };
+
+    select! {
+        _ = endpoint.serve() => {
+            assert!(false, "must not reach here");
+        }
+        _ = sleep(Duration::from_secs(5)) => {
+            assert!(false, "no 413 received");
+        }
+        resp = async {
+            peer.send_to(message.to_string().as_bytes(), addr)
+                .await
+                .expect("send MESSAGE");
+            let mut buf = vec![0u8; 4096];
+            let (len, _) = peer.recv_from(&mut buf).await.expect("recv");
+            rsip::Response::try_from(&buf[..len]).expect("response")
+        } => {
+            assert_eq!(resp.status_code, rsip::StatusCode::RequestEntityTooLarge);
+            assert!(resp.body.is_empty());
+        }
+    }
+    // the oversized request never became a transaction
+    assert!(incoming.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn test_endpoint_merged_request() {
+    use crate::transaction::endpoint::EndpointBuilder;
+    use crate::transport::{udp::UdpConnection, TransportLayer};
+    use tokio_util::sync::CancellationToken;
+
+    let token = CancellationToken::new();
+    let tl = TransportLayer::new(token.child_token());
+    let udp = UdpConnection::create_connection("127.0.0.1:0".parse().unwrap(), None)
+        .await
+        .expect("create_connection");
+    let addr = udp.get_addr().get_socketaddr().expect("socket addr");
+    tl.add_transport(udp.into());
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_cancel_token(token)
+        .with_merged_request_detection(true)
+        .build();
+    let mut incoming = endpoint.incoming_transactions();
+
+    let peer = tokio::net::UdpSocket::bind("127.0.0.1:0")
+        .await
+        .expect("bind peer");
+    let peer_addr = peer.local_addr().expect("peer addr");
+    // the same INVITE forked by a proxy, reaching us over two branches
+    let fork = |branch: &str| rsip::Request {
+        method: rsip::Method::Invite,
+        uri: rsip::Uri::try_from(format!("sip:bob@{}", addr).as_str()).expect("uri"),
+        headers: vec![
+            Via::new(&format!("SIP/2.0/UDP {};branch={}", peer_addr, branch)).into(),
+            CSeq::new("1 INVITE").into(),
+            From::new("Alice <sip:alice@example.com>;tag=alice-tag").into(),
+            To::new("Bob <sip:bob@example.com>").into(),
+            CallId::new("forked@example.com").into(),
+            ContentLength::from(0u32).into(),
+        ]
+        .into(),
+        version: rsip::Version::V2,
+        body: vec![],
+    };
+
+    select! {
+        _ = endpoint.serve() => {
+            assert!(false, "must not reach here");
+        }
+        _ = sleep(Duration::from_secs(5)) => {
+            assert!(false, "no 482 received");
+        }
+        (tx, resp) = async {
+            peer.send_to(fork("z9hG4bKfork1").to_string().as_bytes(), addr)
+                .await
+                .expect("send first fork");
+            let tx = incoming.recv().await.expect("first fork becomes a transaction");
+            peer.send_to(fork("z9hG4bKfork2").to_string().as_bytes(), addr)
+                .await
+                .expect("send second fork");
+            let mut buf = vec![0u8; 4096];
+            loop {
+                let (len, _) = peer.recv_from(&mut buf).await.expect("recv");
+                let resp = rsip::Response::try_from(&buf[..len]).expect("response");
+                if resp.status_code != rsip::StatusCode::Trying {
+                    break (tx, resp);
+                }
+            }
+        } => {
+            assert_eq!(resp.status_code, rsip::StatusCode::LoopDetected);
+            assert!(resp.to_string().contains("branch=z9hG4bKfork2"));
+            assert!(tx.original.to_string().contains("branch=z9hG4bKfork1"));
+        }
+    }
+    // the merged copy never became a transaction
+    assert!(incoming.try_recv().is_err());
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_seen_requests_expiry_and_cap() {
+    use crate::transaction::endpoint::{SeenRequests, MAX_SEEN_REQUESTS};
+    use crate::transaction::key::{TransactionKey, TransactionRole};
+
+    let key = |branch: &str| {
+        let request = rsip::Request {
+            method: rsip::Method::Invite,
+            uri: rsip::Uri::try_from("sip:bob@example.com").expect("uri"),
+            headers: vec![
+                Via::new(&format!("SIP/2.0/UDP 127.0.0.1:5060;branch={}", branch)).into(),
+                CSeq::new("1 INVITE").into(),
+                From::new("Alice <sip:alice@example.com>;tag=alice-tag").into(),
+                To::new("Bob <sip:bob@example.com>").into(),
+                CallId::new("seen@example.com").into(),
+            ]
+            .into(),
+            version: rsip::Version::V2,
+            body: vec![],
+        };
+        TransactionKey::from_request(&request, TransactionRole::Server).expect("key")
+    };
+    let lifetime = Duration::from_secs(32);
+    let (first, second) = (key("z9hG4bKseen1"), key("z9hG4bKseen2"));
+    let mut seen = SeenRequests::default();
+
+    // the same request on another transaction is merged while remembered
+    assert!(!seen.check("request".to_string(), &first, lifetime));
+    assert!(!seen.check("request".to_string(), &first, lifetime));
+    assert!(seen.check("request".to_string(), &second, lifetime));
+
+    // and forgotten once its lifetime is over
+    tokio::time::advance(lifetime).await;
+    assert!(!seen.check("request".to_string(), &second, lifetime));
+
+    // at most MAX_SEEN_REQUESTS are remembered, the oldest go first
+    for i in 0..MAX_SEEN_REQUESTS {
+        assert!(!seen.check(format!("request-{}", i), &first, lifetime));
+    }
+    assert!(!seen.check("request".to_string(), &first, lifetime));
+    assert!(seen.check(
+        format!("request-{}", MAX_SEEN_REQUESTS - 1),
+        &second,
+        lifetime
+    ));
+}
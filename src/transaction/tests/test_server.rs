@@ -144,3 +144,84 @@ async fn test_server_transaction() {
         }
     }
 }
+
+async fn run_invite_auto_trying(auto_trying: bool) -> Option<rsip::StatusCode> {
+    let token = CancellationToken::new();
+    let addr = SipAddr {
+        r#type: Some(rsip::transport::Transport::Udp),
+        addr: "127.0.0.1:2026".try_into().expect("parse addr"),
+    };
+    let (incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, mut outgoing_rx) = unbounded_channel();
+
+    let mock_conn: SipConnection =
+        ChannelConnection::create_connection(incoming_rx, outgoing_tx, addr.clone())
+            .await
+            .expect("create_connection")
+            .into();
+
+    let tl = TransportLayer::new(token.child_token());
+    tl.add_transport(mock_conn.clone());
+
+    let endpoint = EndpointBuilder::new()
+        .with_user_agent("rsipstack-test")
+        .with_transport_layer(tl)
+        .with_auto_trying(auto_trying)
+        .build();
+    let mut incoming = endpoint.incoming_transactions();
+
+    let invite_req = rsip::message::Request {
+        method: rsip::method::Method::Invite,
+        uri: rsip::Uri::try_from("sip:bob@127.0.0.1:2026").expect("uri"),
+        headers: vec![
+            Via::new("SIP/2.0/UDP 127.0.0.1:5060;branch=z9hG4bKautotrying").into(),
+            CSeq::new("1 INVITE").into(),
+            From::new("Alice <sip:alice@127.0.0.1>;tag=alice-tag").into(),
+            To::new("Bob <sip:bob@127.0.0.1:2026>").into(),
+            CallId::new("auto-trying@127.0.0.1").into(),
+        ]
+        .into(),
+        version: rsip::Version::V2,
+        body: Default::default(),
+    };
+
+    let check = async {
+        incoming_tx
+            .send(TransportEvent::Incoming(
+                invite_req.into(),
+                mock_conn.clone(),
+                addr.clone(),
+            ))
+            .expect("incoming_tx.send");
+
+        // The application holds the transaction without responding
+        let tx = incoming.recv().await.expect("incoming");
+        assert_eq!(tx.original.method, rsip::method::Method::Invite);
+
+        let first = select! {
+            event = outgoing_rx.recv() => match event {
+                Some(TransportEvent::Incoming(rsip::SipMessage::Response(resp), _, _)) => {
+                    Some(resp.status_code)
+                }
+                _ => None,
+            },
+            _ = sleep(Duration::from_millis(200)) => None,
+        };
+        drop(tx);
+        first
+    };
+
+    select! {
+        first = check => first,
+        _ = endpoint.serve() => panic!("endpoint stopped"),
+    }
+}
+
+#[tokio::test]
+async fn test_server_invite_auto_trying() {
+    assert_eq!(
+        run_invite_auto_trying(true).await,
+        Some(rsip::StatusCode::Trying)
+    );
+    assert_eq!(run_invite_auto_trying(false).await, None);
+}
@@ -162,3 +162,102 @@ async fn test_transaction_types() -> crate::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_client_transaction_discards_cseq_method_mismatch() -> crate::Result<()> {
+    use crate::transaction::transaction::TransactionEvent;
+    use crate::transport::{channel::ChannelConnection, SipAddr, SipConnection};
+    use tokio::sync::mpsc::unbounded_channel;
+
+    let endpoint = create_test_endpoint(None).await?;
+    let addr = SipAddr::from("127.0.0.1:5060".parse::<std::net::SocketAddr>().unwrap());
+    let (_incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, _outgoing_rx) = unbounded_channel();
+    let conn: SipConnection = ChannelConnection::create_connection(incoming_rx, outgoing_tx, addr)
+        .await?
+        .into();
+
+    let invite_req = create_test_request(rsip::Method::Invite, "z9hG4bKcseqmismatch");
+    let key = TransactionKey::from_request(&invite_req, TransactionRole::Client)?;
+    let mut tx =
+        Transaction::new_client(key, invite_req.clone(), endpoint.inner.clone(), Some(conn));
+    tx.send().await?;
+
+    let response = |status: rsip::StatusCode, cseq: &str| {
+        let mut headers = invite_req.headers.clone();
+        headers.unique_push(CSeq::new(cseq).into());
+        headers.unique_push(To::new("Bob <sip:bob@example.com>;tag=bob").into());
+        rsip::Response {
+            status_code: status,
+            version: rsip::Version::V2,
+            headers,
+            body: Default::default(),
+        }
+    };
+    tx.tu_sender.send(TransactionEvent::Received(
+        response(rsip::StatusCode::OK, "1 BYE").into(),
+        None,
+    ))?;
+    tx.tu_sender.send(TransactionEvent::Received(
+        response(rsip::StatusCode::Ringing, "1 INVITE").into(),
+        None,
+    ))?;
+
+    match tokio::time::timeout(std::time::Duration::from_secs(1), tx.receive()).await {
+        Ok(Some(rsip::SipMessage::Response(resp))) => {
+            assert_eq!(resp.status_code, rsip::StatusCode::Ringing)
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(tx.state, TransactionState::Proceeding);
+    assert!(tx.last_response.is_some());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_invite_ack_terminates_on_reliable_transport() -> crate::Result<()> {
+    use crate::transaction::transaction::TransactionEvent;
+    use crate::transport::{channel::ChannelConnection, SipAddr, SipConnection};
+    use tokio::sync::mpsc::unbounded_channel;
+
+    let endpoint = create_test_endpoint(None).await?;
+    let addr = SipAddr::from("127.0.0.1:5060".parse::<std::net::SocketAddr>().unwrap());
+    let (_incoming_tx, incoming_rx) = unbounded_channel();
+    let (outgoing_tx, _outgoing_rx) = unbounded_channel();
+    let conn: SipConnection = ChannelConnection::create_connection(incoming_rx, outgoing_tx, addr)
+        .await?
+        .into();
+    assert!(conn.is_reliable());
+
+    let invite_req = create_test_request(rsip::Method::Invite, "z9hG4bKreliableack");
+    let key = TransactionKey::from_request(&invite_req, TransactionRole::Client)?;
+    let mut tx =
+        Transaction::new_client(key, invite_req.clone(), endpoint.inner.clone(), Some(conn));
+    tx.send().await?;
+
+    let mut headers = invite_req.headers.clone();
+    headers.unique_push(To::new("Bob <sip:bob@example.com>;tag=bob").into());
+    let ok = rsip::Response {
+        status_code: rsip::StatusCode::OK,
+        version: rsip::Version::V2,
+        headers,
+        body: Default::default(),
+    };
+    tx.tu_sender
+        .send(TransactionEvent::Received(ok.into(), None))?;
+    match tokio::time::timeout(std::time::Duration::from_secs(1), tx.receive()).await {
+        Ok(Some(rsip::SipMessage::Response(resp))) => {
+            assert_eq!(resp.status_code, rsip::StatusCode::OK)
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(tx.state, TransactionState::Completed);
+
+    // Timer D is zero on reliable transports, so the ACK ends the transaction
+    let mut ack = create_test_request(rsip::Method::Ack, "z9hG4bKreliableack2");
+    ack.headers
+        .unique_push(To::new("Bob <sip:bob@example.com>;tag=bob").into());
+    tx.send_ack(ack).await?;
+    assert_eq!(tx.state, TransactionState::Terminated);
+    Ok(())
+}
@@ -4,8 +4,10 @@ use std::{
         atomic::{AtomicU64, Ordering},
         RwLock,
     },
-    time::{Duration, Instant},
+    time::Duration,
 };
+// tokio's clock, so `tokio::time::pause` and `advance` drive the timers in tests
+use tokio::time::Instant;
 
 #[derive(Debug, PartialOrd, PartialEq, Eq, Clone)]
 struct TimerKey {
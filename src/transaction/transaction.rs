@@ -1,6 +1,7 @@
 use super::endpoint::EndpointInnerRef;
 use super::key::TransactionKey;
 use super::{SipConnection, TransactionState, TransactionTimer, TransactionType};
+use crate::rsip_ext::sanitize_for_log;
 use crate::transaction::make_tag;
 use crate::transport::SipAddr;
 use crate::{Error, Result};
@@ -151,10 +152,14 @@ pub struct Transaction {
     pub key: TransactionKey,
     pub original: Request,
     pub destination: Option<SipAddr>,
+    /// Remote address an inbound request was received from (server transactions)
+    pub source: Option<SipAddr>,
     pub state: TransactionState,
     pub endpoint_inner: EndpointInnerRef,
     pub connection: Option<SipConnection>,
     pub last_response: Option<Response>,
+    /// Transport the last response arrived over (client transactions)
+    pub response_transport: Option<rsip::Transport>,
     pub last_ack: Option<Request>,
     pub tu_receiver: TransactionEventReceiver,
     pub tu_sender: TransactionEventSender,
@@ -183,8 +188,10 @@ impl Transaction {
             key,
             original,
             destination: None,
+            source: None,
             state: TransactionState::Calling,
             last_response: None,
+            response_transport: None,
             last_ack: None,
             timer_a: None,
             timer_b: None,
@@ -213,6 +220,57 @@ impl Transaction {
         Transaction::new(tx_type, key, original, connection, endpoint_inner)
     }
 
+    /// Create a client transaction bound to an existing connection
+    ///
+    /// The request is sent over `connection` instead of one looked up from
+    /// the Request-URI, e.g. back over the connection an inbound request
+    /// arrived on. For datagram transports `destination` is the remote
+    /// address to send to, typically the `source` of that inbound request.
+    ///
+    /// # Parameters
+    ///
+    /// * `key` - Transaction key of `original`
+    /// * `original` - Request to send
+    /// * `endpoint_inner` - Endpoint owning the transaction
+    /// * `connection` - Connection the request egresses
+    /// * `destination` - Remote address for datagram connections
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::transaction::{
+    /// #     key::{TransactionKey, TransactionRole},
+    /// #     transaction::Transaction,
+    /// # };
+    /// # async fn example(inbound: Transaction, request: rsip::Request) -> rsipstack::Result<()> {
+    /// let connection = inbound.connection.clone().expect("inbound connection");
+    /// let key = TransactionKey::from_request(&request, TransactionRole::Client)?;
+    /// let mut tx = Transaction::new_client_over(
+    ///     key,
+    ///     request,
+    ///     inbound.endpoint_inner.clone(),
+    ///     connection,
+    ///     inbound.source.clone(),
+    /// );
+    /// tx.send().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new_client_over(
+        key: TransactionKey,
+        original: Request,
+        endpoint_inner: EndpointInnerRef,
+        connection: SipConnection,
+        destination: Option<SipAddr>,
+    ) -> Self {
+        let reliable = connection.is_reliable();
+        let mut tx = Self::new_client(key, original, endpoint_inner, Some(connection));
+        if !reliable {
+            tx.destination = destination;
+        }
+        tx
+    }
+
     pub fn new_server(
         key: TransactionKey,
         original: Request,
@@ -326,7 +384,7 @@ impl Transaction {
             "no connection found".to_string(),
             self.key.clone(),
         ))?;
-        debug!("responding with {}", response);
+        debug!("responding with {}", sanitize_for_log(&response.to_string()));
         connection
             .send(response.to_owned().into(), self.destination.as_ref())
             .await?;
@@ -345,6 +403,7 @@ impl Transaction {
             | (&TransactionState::Trying, &TransactionState::Completed)
             | (&TransactionState::Trying, &TransactionState::Confirmed)
             | (&TransactionState::Trying, &TransactionState::Terminated)
+            | (&TransactionState::Proceeding, &TransactionState::Proceeding) // further 1xx
             | (&TransactionState::Proceeding, &TransactionState::Completed)
             | (&TransactionState::Proceeding, &TransactionState::Confirmed)
             | (&TransactionState::Proceeding, &TransactionState::Terminated)
@@ -416,6 +475,16 @@ impl Transaction {
             .send(ack.to_owned().into(), self.destination.as_ref())
             .await?;
         self.last_ack.replace(ack);
+        let answered = self
+            .last_response
+            .as_ref()
+            .is_some_and(|resp| resp.status_code.kind() == rsip::StatusCodeKind::Successful);
+        if answered && !connection.is_reliable() {
+            // stay Completed until Timer D, so 2xx retransmissions and
+            // answers of other forks still reach the TU (RFC 6026 8.4);
+            // Timer D is zero on reliable transports
+            return Ok(());
+        }
         // client send ack and transition to Terminated
         self.transition(TransactionState::Terminated).map(|_| ())
     }
@@ -426,7 +495,12 @@ impl Transaction {
                 TransactionEvent::Received(msg, connection) => {
                     if let Some(msg) = match msg {
                         SipMessage::Request(req) => self.on_received_request(req, connection).await,
-                        SipMessage::Response(resp) => self.on_received_response(resp).await,
+                        SipMessage::Response(resp) => {
+                            if let Some(connection) = connection.as_ref() {
+                                self.response_transport = connection.get_addr().r#type;
+                            }
+                            self.on_received_response(resp).await
+                        }
                     } {
                         return Some(msg);
                     }
@@ -539,6 +613,45 @@ impl Transaction {
             _ => {}
         }
 
+        // A CSeq method other than the request's points at a routing or
+        // parsing error, so the response cannot belong to this transaction
+        match resp.cseq_header().and_then(|cseq| cseq.method()) {
+            Ok(method) if method == self.original.method => {}
+            Ok(method) => {
+                info!(
+                    "discarding {} response with CSeq method {} on {} transaction {}",
+                    resp.status_code, method, self.original.method, self.key
+                );
+                return None;
+            }
+            Err(e) => {
+                info!("discarding response with invalid CSeq on {}: {}", self.key, e);
+                return None;
+            }
+        }
+
+        // Some broken servers answer with another Call-ID, a dialog built
+        // from such a response could never match in-dialog requests
+        if let Ok(call_id) = self.original.call_id_header() {
+            if resp.call_id_header().ok() != Some(call_id) {
+                info!(
+                    "discarding {} response with mismatched Call-ID on {}",
+                    resp.status_code, self.key
+                );
+                return None;
+            }
+        }
+
+        // after the ACK, further 2xx are retransmissions or answers of other
+        // forks, which the TU has to ACK itself
+        if self.transaction_type == TransactionType::ClientInvite
+            && self.state == TransactionState::Completed
+            && self.last_ack.is_some()
+            && resp.status_code.kind() == rsip::StatusCodeKind::Successful
+        {
+            return Some(SipMessage::Response(resp));
+        }
+
         let new_state = match resp.status_code.kind() {
             rsip::StatusCodeKind::Provisional => {
                 if resp.status_code == rsip::StatusCode::Trying {
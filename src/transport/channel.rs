@@ -1,14 +1,16 @@
 use super::{
     connection::{TransportReceiver, TransportSender},
-    SipAddr, SipConnection,
+    SipAddr, SipConnection, TransportEvent,
 };
 use crate::Result;
 use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::unbounded_channel;
 
 struct ChannelInner {
     incoming: Mutex<Option<TransportReceiver>>,
     outgoing: TransportSender,
     addr: SipAddr,
+    loopback: bool,
 }
 
 #[derive(Clone)]
@@ -27,17 +29,69 @@ impl ChannelConnection {
                 incoming: Mutex::new(Some(incoming)),
                 outgoing,
                 addr,
+                loopback: false,
             }),
         };
         Ok(t)
     }
 
+    /// Create two in-process connections wired to each other
+    ///
+    /// Whatever one side sends is serialized, parsed again and delivered to
+    /// the other side as if it came off the wire from the sender's address.
+    /// Adding each half to its own endpoint's `TransportLayer` lets two
+    /// endpoints talk to each other without sockets, which makes full
+    /// UAC/UAS flows deterministic to test.
+    ///
+    /// Give each address `Transport::Udp` so URIs without a transport
+    /// parameter resolve to the channel.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rsipstack::transport::{channel::ChannelConnection, SipAddr, TransportLayer};
+    /// use tokio_util::sync::CancellationToken;
+    ///
+    /// let alice: SipAddr = "127.0.0.1:15060".parse::<std::net::SocketAddr>().unwrap().into();
+    /// let bob: SipAddr = "127.0.0.1:15070".parse::<std::net::SocketAddr>().unwrap().into();
+    /// let (alice_conn, bob_conn) = ChannelConnection::create_pair(
+    ///     SipAddr { r#type: Some(rsip::Transport::Udp), ..alice },
+    ///     SipAddr { r#type: Some(rsip::Transport::Udp), ..bob },
+    /// );
+    ///
+    /// let alice_tl = TransportLayer::new(CancellationToken::new());
+    /// alice_tl.add_transport(alice_conn.into());
+    /// let bob_tl = TransportLayer::new(CancellationToken::new());
+    /// bob_tl.add_transport(bob_conn.into());
+    /// ```
+    pub fn create_pair(addr_a: SipAddr, addr_b: SipAddr) -> (Self, Self) {
+        let (a_tx, a_rx) = unbounded_channel();
+        let (b_tx, b_rx) = unbounded_channel();
+        let make = |incoming: TransportReceiver, outgoing: TransportSender, addr: SipAddr| {
+            ChannelConnection {
+                inner: Arc::new(ChannelInner {
+                    incoming: Mutex::new(Some(incoming)),
+                    outgoing,
+                    addr,
+                    loopback: true,
+                }),
+            }
+        };
+        (make(a_rx, b_tx, addr_a), make(b_rx, a_tx, addr_b))
+    }
+
     pub async fn send(&self, msg: rsip::SipMessage) -> crate::Result<()> {
+        let msg = if self.inner.loopback {
+            // go through the wire format like a real transport would
+            rsip::SipMessage::try_from(msg.to_string().as_str())?
+        } else {
+            msg
+        };
         let transport = SipConnection::Channel(self.clone());
         let source = self.get_addr().clone();
         self.inner
             .outgoing
-            .send(super::TransportEvent::Incoming(msg, transport, source))
+            .send(TransportEvent::Incoming(msg, transport, source))
             .map_err(|e| e.into())
     }
 
@@ -54,6 +108,13 @@ impl ChannelConnection {
         }
         let mut incoming = incoming.unwrap();
         while let Some(event) = incoming.recv().await {
+            let event = match event {
+                // replies must go out through our half of the pair
+                TransportEvent::Incoming(msg, _, from) if self.inner.loopback => {
+                    TransportEvent::Incoming(msg, SipConnection::Channel(self.clone()), from)
+                }
+                event => event,
+            };
             sender.send(event)?;
         }
         Ok(())
@@ -1,5 +1,9 @@
 use super::{
-    channel::ChannelConnection, sip_addr::SipAddr, stream::StreamConnection, tcp::TcpConnection,
+    channel::ChannelConnection,
+    keepalive::{Keepalive, KeepaliveHandle, KeepaliveSet},
+    sip_addr::SipAddr,
+    stream::StreamConnection,
+    tcp::TcpConnection,
     udp::UdpConnection,
 };
 use crate::transport::tls::TlsConnection;
@@ -9,7 +13,7 @@ use rsip::{
     prelude::{HeadersExt, ToTypedHeader},
     Param, SipMessage,
 };
-use std::{fmt, net::SocketAddr};
+use std::{fmt, net::SocketAddr, sync::atomic::Ordering};
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 use tracing::debug;
 
@@ -25,6 +29,11 @@ use tracing::debug;
 /// * `Incoming` - A SIP message was received from the network
 /// * `New` - A new connection has been established
 /// * `Closed` - An existing connection has been closed
+/// * `Malformed` - Bytes were received that could not be parsed as SIP
+///
+/// The enum is `#[non_exhaustive]`, so matches outside this crate need a
+/// wildcard arm. This is a breaking change that came with `Malformed`;
+/// later events can be added without breaking them again.
 ///
 /// # Examples
 ///
@@ -45,14 +54,38 @@ use tracing::debug;
 ///         // Handle connection closure
 ///         println!("Connection closed");
 ///     }
+///     TransportEvent::Malformed(malformed) => {
+///         // Inspect what a non-compliant device sent
+///         println!("Unparseable message from {}", malformed.source);
+///     }
+///     _ => {}
 /// }
 /// # }
 /// ```
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum TransportEvent {
     Incoming(SipMessage, SipConnection, SipAddr),
     New(SipConnection),
     Closed(SipConnection),
+    Malformed(MalformedMessage),
+}
+
+/// Inbound message that could not be parsed
+///
+/// Reported by the transports instead of silently dropping what a
+/// non-compliant device sent. See `Endpoint::malformed_messages`.
+///
+/// # Fields
+///
+/// * `data` - The raw bytes that were discarded
+/// * `error` - Why parsing failed
+/// * `source` - Address the bytes were received from
+#[derive(Debug, Clone)]
+pub struct MalformedMessage {
+    pub data: Vec<u8>,
+    pub error: String,
+    pub source: SipAddr,
 }
 
 pub type TransportReceiver = UnboundedReceiver<TransportEvent>;
@@ -61,6 +94,35 @@ pub type TransportSender = UnboundedSender<TransportEvent>;
 pub const KEEPALIVE_REQUEST: &[u8] = b"\r\n\r\n";
 pub const KEEPALIVE_RESPONSE: &[u8] = b"\r\n";
 
+/// Length of the start line and headers of `data`, including the empty line
+pub(crate) fn header_len(data: &[u8]) -> Option<usize> {
+    data.windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map(|pos| pos + 4)
+}
+
+/// `Content-Length` of a message when it exceeds the limit in `max_body_size`
+///
+/// `headers` is the start line and headers of the message, a limit of 0
+/// means unlimited. Both the full and the compact (`l`) header name are
+/// recognized.
+pub(crate) fn oversized_body(headers: &[u8], max_body_size: usize) -> Option<usize> {
+    if max_body_size == 0 {
+        return None;
+    }
+    let headers = std::str::from_utf8(headers).ok()?;
+    let declared = headers.split("\r\n").find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        let name = name.trim();
+        if name.eq_ignore_ascii_case("content-length") || name.eq_ignore_ascii_case("l") {
+            value.trim().parse::<usize>().ok()
+        } else {
+            None
+        }
+    })?;
+    (declared > max_body_size).then_some(declared)
+}
+
 /// SIP Connection
 ///
 /// `SipConnection` is an enum that abstracts different transport protocols
@@ -169,6 +231,16 @@ impl SipConnection {
             SipConnection::WebSocket(transport) => transport.get_addr(),
         }
     }
+    /// Local socket address of the connection
+    ///
+    /// Same as `get_addr` except for UDP connections configured with an
+    /// external address, where this returns the address actually bound.
+    pub fn get_local_addr(&self) -> &SipAddr {
+        match self {
+            SipConnection::Udp(transport) => transport.get_local_addr(),
+            _ => self.get_addr(),
+        }
+    }
     pub async fn send(&self, msg: rsip::SipMessage, destination: Option<&SipAddr>) -> Result<()> {
         match self {
             SipConnection::Udp(transport) => transport.send(msg, destination).await,
@@ -197,8 +269,94 @@ impl SipConnection {
             }
         }
     }
-    pub async fn serve_loop(&self, sender: TransportSender) -> Result<()> {
+    /// Send raw bytes that are not a SIP message, such as keepalives
+    ///
+    /// `destination` is only used by UDP; stream connections write to their
+    /// established peer. Channel connections carry parsed messages only and
+    /// return an error.
+    pub async fn send_raw(&self, data: &[u8], destination: &SipAddr) -> Result<()> {
         match self {
+            SipConnection::Udp(transport) => transport.send_raw(data, destination).await,
+            SipConnection::Channel(transport) => Err(crate::Error::TransportLayerError(
+                "raw data is not supported on channel connections".to_string(),
+                transport.get_addr().to_owned(),
+            )),
+            SipConnection::Tcp(transport) => transport.send_raw(data).await,
+            #[cfg(feature = "rustls")]
+            SipConnection::Tls(transport) => transport.send_raw(data).await,
+            #[cfg(feature = "websocket")]
+            SipConnection::WebSocket(transport) => transport.send_raw(data).await,
+        }
+    }
+
+    /// Start sending keepalives to `target` over this connection
+    ///
+    /// The payload is sent right away and then every `keepalive.interval`.
+    /// Received data recognized by the keepalive's matcher is consumed as a
+    /// pong and recorded on the returned handle: datagrams from `target` on
+    /// UDP, and reads that start a new message on stream connections. A UDP
+    /// connection runs one keepalive per target; starting another one to
+    /// the same target stops the previous one. The keepalive stops when the
+    /// last clone of the handle is dropped or `serve_loop` returns.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use rsipstack::transport::{keepalive::Keepalive, SipAddr, SipConnection};
+    /// # use std::time::Duration;
+    /// # fn example(connection: SipConnection, sbc: SipAddr) {
+    /// let mut keepalive = Keepalive::new(Duration::from_secs(15));
+    /// keepalive.with_payload(b"OPTIONS-PING".to_vec(), |data| data == b"PONG");
+    /// let handle = connection.start_keepalive(sbc, keepalive);
+    /// // later
+    /// println!("last pong: {:?}", handle.last_pong());
+    /// handle.stop();
+    /// # }
+    /// ```
+    pub fn start_keepalive(&self, target: SipAddr, keepalive: Keepalive) -> KeepaliveHandle {
+        let handle = KeepaliveHandle::new(keepalive, target);
+        if let Some(keepalives) = self.keepalives() {
+            keepalives.insert(&handle);
+        }
+        handle.spawn(self.clone());
+        handle
+    }
+
+    fn keepalives(&self) -> Option<&KeepaliveSet> {
+        match self {
+            SipConnection::Udp(transport) => Some(&transport.keepalives),
+            SipConnection::Channel(_) => None,
+            SipConnection::Tcp(transport) => Some(&transport.inner.keepalives),
+            #[cfg(feature = "rustls")]
+            SipConnection::Tls(transport) => Some(&transport.keepalives),
+            #[cfg(feature = "websocket")]
+            SipConnection::WebSocket(transport) => Some(&transport.inner.keepalives),
+        }
+    }
+
+    /// Limit the body size of messages read from this connection
+    ///
+    /// A message whose `Content-Length` exceeds `max_body_size` is passed on
+    /// with its headers only, and its body is discarded as it is read
+    /// instead of being buffered. Applies to UDP and TCP; the other
+    /// connections parse every read on its own and do not accumulate data.
+    pub fn set_max_body_size(&self, max_body_size: Option<usize>) {
+        let limit = match self {
+            SipConnection::Udp(transport) => &transport.max_body_size,
+            SipConnection::Tcp(transport) => &transport.inner.max_body_size,
+            _ => return,
+        };
+        limit.store(max_body_size.unwrap_or_default(), Ordering::Relaxed);
+    }
+
+    /// Record `data` as the pong of a keepalive started on this connection
+    pub(crate) fn on_keepalive_received(&self, source: Option<&SipAddr>, data: &[u8]) -> bool {
+        self.keepalives()
+            .is_some_and(|keepalives| keepalives.on_received(source, data))
+    }
+
+    pub async fn serve_loop(&self, sender: TransportSender) -> Result<()> {
+        let result = match self {
             SipConnection::Udp(transport) => transport.serve_loop(sender).await,
             SipConnection::Channel(transport) => transport.serve_loop(sender).await,
             SipConnection::Tcp(transport) => transport.serve_loop(sender).await,
@@ -206,6 +364,15 @@ impl SipConnection {
             SipConnection::Tls(transport) => transport.serve_loop(sender).await,
             #[cfg(feature = "websocket")]
             SipConnection::WebSocket(transport) => transport.serve_loop(sender).await,
+        };
+        self.stop_keepalives();
+        result
+    }
+
+    /// Stop the keepalives of a connection that is no longer served
+    pub(crate) fn stop_keepalives(&self) {
+        if let Some(keepalives) = self.keepalives() {
+            keepalives.stop_all();
         }
     }
 
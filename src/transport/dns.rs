@@ -0,0 +1,188 @@
+//! DNS resolution of SIP servers
+//!
+//! Requests and registrations locate their next hop with RFC 3263 lookups.
+//! [`DnsConfig`] bounds how long a lookup may take and which records are
+//! queried, and lets applications plug in their own [`DnsResolver`].
+use super::SipAddr;
+use crate::{Error, Result};
+use rsip::HostWithPort;
+use rsip_dns::{trust_dns_resolver::TokioAsyncResolver, ResolvableExt};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::debug;
+
+/// Order of the DNS lookups locating a SIP server
+///
+/// * `Naptr` - NAPTR, then SRV, then A/AAAA records (RFC 3263)
+/// * `Srv` - SRV, then A/AAAA records, for networks whose resolvers do not
+///   answer NAPTR queries
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DnsLookupOrder {
+    #[default]
+    Naptr,
+    Srv,
+}
+
+/// Resolver locating the server of a SIP URI
+///
+/// The transport layer uses an RFC 3263 resolver by default. Implement this
+/// trait to use another resolver, e.g. one with static records.
+#[async_trait::async_trait]
+pub trait DnsResolver: Send + Sync {
+    /// Resolve `uri` to the address and transport to send to
+    async fn resolve(&self, uri: &rsip::Uri, order: DnsLookupOrder) -> Result<SipAddr>;
+}
+
+/// DNS settings of a transport layer
+///
+/// # Fields
+///
+/// * `timeout` - Longest time a lookup may take before failing with
+///   `Error::DnsResolutionError`
+/// * `order` - Records queried to locate a server
+/// * `resolver` - Resolver used instead of the default RFC 3263 one
+#[derive(Clone)]
+pub struct DnsConfig {
+    pub timeout: Duration,
+    pub order: DnsLookupOrder,
+    pub resolver: Option<Arc<dyn DnsResolver>>,
+}
+
+impl Default for DnsConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(5),
+            order: DnsLookupOrder::default(),
+            resolver: None,
+        }
+    }
+}
+
+impl DnsConfig {
+    /// Resolve `uri`, failing once `timeout` has passed
+    pub async fn resolve(&self, uri: &rsip::Uri) -> Result<SipAddr> {
+        let lookup = async {
+            match &self.resolver {
+                Some(resolver) => resolver.resolve(uri, self.order).await,
+                None => resolve(uri, self.order).await,
+            }
+        };
+        match tokio::time::timeout(self.timeout, lookup).await {
+            Ok(result) => result,
+            Err(_) => Err(Error::DnsResolutionError(format!(
+                "timed out after {:?}: {}",
+                self.timeout, uri
+            ))),
+        }
+    }
+}
+
+fn system_resolver() -> Result<TokioAsyncResolver> {
+    TokioAsyncResolver::tokio(Default::default(), Default::default())
+        .map_err(|e| Error::DnsResolutionError(e.to_string()))
+}
+
+async fn resolve(uri: &rsip::Uri, order: DnsLookupOrder) -> Result<SipAddr> {
+    match order {
+        DnsLookupOrder::Naptr => resolve_naptr(uri).await,
+        DnsLookupOrder::Srv => resolve_srv(uri).await,
+    }
+}
+
+async fn resolve_naptr(uri: &rsip::Uri) -> Result<SipAddr> {
+    let context = rsip_dns::Context::initialize_from(
+        uri.clone(),
+        rsip_dns::AsyncTrustDnsClient::new(system_resolver()?),
+        rsip_dns::SupportedTransports::any(),
+    )?;
+
+    let mut lookup = rsip_dns::Lookup::from(context);
+    match lookup.resolve_next().await {
+        Some(mut target) => {
+            if let rsip::Host::IpAddr(_) = uri.host_with_port.host {
+                if let Some(port) = uri.host_with_port.port {
+                    target.port = port;
+                }
+            }
+            Ok(SipAddr {
+                r#type: Some(target.transport),
+                addr: HostWithPort::from(SocketAddr::new(target.ip_addr, u16::from(target.port))),
+            })
+        }
+        None => Err(Error::DnsResolutionError(format!(
+            "DNS resolution error: {}",
+            uri
+        ))),
+    }
+}
+
+/// SRV, then A/AAAA lookup, skipping NAPTR
+async fn resolve_srv(uri: &rsip::Uri) -> Result<SipAddr> {
+    let secure = matches!(uri.scheme, Some(rsip::Scheme::Sips));
+    let transport = uri.transport().copied();
+    // transport and port of the URI itself, used without an SRV record
+    let fallback = SipAddr::from_uri(uri)?;
+
+    let domain = match &uri.host_with_port.host {
+        rsip::Host::IpAddr(_) => return Ok(fallback),
+        rsip::Host::Domain(domain) => domain.to_string(),
+    };
+    let resolver = system_resolver()?;
+
+    // an explicit port means the host is used as is (RFC 3263 4.2)
+    if uri.host_with_port.port.is_none() {
+        let services = match (transport, secure) {
+            (Some(rsip::Transport::Tcp), false) => vec![("_sip._tcp", rsip::Transport::Tcp)],
+            (Some(rsip::Transport::Udp), _) => vec![("_sip._udp", rsip::Transport::Udp)],
+            (Some(_), _) | (None, true) => vec![("_sips._tcp", rsip::Transport::Tls)],
+            (None, false) => vec![
+                ("_sip._udp", rsip::Transport::Udp),
+                ("_sip._tcp", rsip::Transport::Tcp),
+                ("_sips._tcp", rsip::Transport::Tls),
+            ],
+        };
+        for (service, transport) in services {
+            let name = format!("{}.{}.", service, domain);
+            let records = match resolver.srv_lookup(name.as_str()).await {
+                Ok(records) => records,
+                Err(e) => {
+                    debug!("no SRV records for {}: {}", name, e);
+                    continue;
+                }
+            };
+            let srv = match records.iter().min_by_key(|srv| srv.priority()) {
+                Some(srv) => srv,
+                None => continue,
+            };
+            let target = srv.target().to_utf8();
+            if let Some(ip) = resolver
+                .lookup_ip(target.as_str())
+                .await
+                .ok()
+                .and_then(|ips| ips.iter().next())
+            {
+                return Ok(SipAddr {
+                    r#type: Some(transport),
+                    addr: HostWithPort::from(SocketAddr::new(ip, srv.port())),
+                });
+            }
+        }
+    }
+
+    match resolver
+        .lookup_ip(domain.as_str())
+        .await
+        .ok()
+        .and_then(|ips| ips.iter().next())
+    {
+        Some(ip) => Ok(SipAddr {
+            r#type: fallback.r#type,
+            addr: HostWithPort::from(SocketAddr::new(ip, fallback.port_or_default())),
+        }),
+        None => Err(Error::DnsResolutionError(format!(
+            "DNS resolution error: {}",
+            uri
+        ))),
+    }
+}
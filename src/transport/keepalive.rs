@@ -0,0 +1,280 @@
+use super::{
+    connection::{KEEPALIVE_REQUEST, KEEPALIVE_RESPONSE},
+    SipAddr, SipConnection,
+};
+use std::{
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+use tokio::{select, time::Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+
+/// Interval between keepalives when none is configured
+pub const DEFAULT_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Recognizes the answer to a keepalive payload
+pub type KeepaliveMatcher = Arc<dyn Fn(&[u8]) -> bool + Send + Sync>;
+
+/// Transport level keepalive
+///
+/// Sent periodically to a peer to keep NAT bindings and flows open. By
+/// default this is the double CRLF ping of RFC 5626, answered with a single
+/// CRLF. Some SBCs expect a vendor specific ping instead, which
+/// [`Keepalive::with_payload`] plugs in together with the matcher that
+/// recognizes the pong.
+///
+/// # Examples
+///
+/// ```rust
+/// use rsipstack::transport::keepalive::Keepalive;
+/// use std::time::Duration;
+///
+/// let mut keepalive = Keepalive::new(Duration::from_secs(20));
+/// keepalive.with_payload(b"PING".to_vec(), |data| data.starts_with(b"PONG"));
+/// assert!(keepalive.is_pong(b"PONG 1"));
+/// assert!(!keepalive.is_pong(b"\r\n"));
+/// ```
+#[derive(Clone)]
+pub struct Keepalive {
+    pub interval: Duration,
+    pub payload: Vec<u8>,
+    pub matcher: KeepaliveMatcher,
+}
+
+impl Default for Keepalive {
+    fn default() -> Self {
+        Self::new(DEFAULT_KEEPALIVE_INTERVAL)
+    }
+}
+
+impl std::fmt::Debug for Keepalive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Keepalive")
+            .field("interval", &self.interval)
+            .field("payload", &String::from_utf8_lossy(&self.payload))
+            .finish()
+    }
+}
+
+impl Keepalive {
+    /// Create a CRLF keepalive sent every `interval`
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            payload: KEEPALIVE_REQUEST.to_vec(),
+            matcher: Arc::new(|data: &[u8]| data == KEEPALIVE_RESPONSE),
+        }
+    }
+
+    /// Create a CRLF keepalive honoring a `Flow-Timer` (RFC 5626)
+    ///
+    /// The registrar expects a keepalive at least every `flow_timer`
+    /// seconds. Keepalives are sent at 80% of that, the lower bound of
+    /// the range RFC 5626 section 4.4.1 recommends.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rsipstack::transport::keepalive::Keepalive;
+    /// use std::time::Duration;
+    ///
+    /// let keepalive = Keepalive::from_flow_timer(25);
+    /// assert_eq!(keepalive.interval, Duration::from_secs(20));
+    /// ```
+    pub fn from_flow_timer(flow_timer: u32) -> Self {
+        let interval = Duration::from_millis(flow_timer as u64 * 800);
+        Self::new(interval.max(Duration::from_secs(1)))
+    }
+
+    /// Replace the CRLF ping with a custom payload
+    ///
+    /// # Parameters
+    ///
+    /// * `payload` - Bytes sent on every keepalive tick
+    /// * `matcher` - Returns true for data received in answer to `payload`
+    pub fn with_payload<F>(&mut self, payload: Vec<u8>, matcher: F) -> &mut Self
+    where
+        F: Fn(&[u8]) -> bool + Send + Sync + 'static,
+    {
+        self.payload = payload;
+        self.matcher = Arc::new(matcher);
+        self
+    }
+
+    /// Check whether `data` answers this keepalive
+    pub fn is_pong(&self, data: &[u8]) -> bool {
+        (self.matcher)(data)
+    }
+}
+
+struct KeepaliveInner {
+    keepalive: Keepalive,
+    target: SipAddr,
+    last_pong: Mutex<Option<Instant>>,
+    cancel_token: CancellationToken,
+}
+
+impl Drop for KeepaliveInner {
+    fn drop(&mut self) {
+        self.cancel_token.cancel();
+    }
+}
+
+/// A running keepalive, see [`SipConnection::start_keepalive`]
+///
+/// Cloning the handle does not start another keepalive. The keepalive runs
+/// until [`KeepaliveHandle::stop`] is called, the last clone of the handle
+/// is dropped or the connection stops being served.
+#[derive(Clone)]
+pub struct KeepaliveHandle {
+    inner: Arc<KeepaliveInner>,
+}
+
+impl KeepaliveHandle {
+    pub(crate) fn new(keepalive: Keepalive, target: SipAddr) -> Self {
+        Self {
+            inner: Arc::new(KeepaliveInner {
+                keepalive,
+                target,
+                last_pong: Mutex::new(None),
+                cancel_token: CancellationToken::new(),
+            }),
+        }
+    }
+
+    pub fn keepalive(&self) -> &Keepalive {
+        &self.inner.keepalive
+    }
+
+    pub fn target(&self) -> &SipAddr {
+        &self.inner.target
+    }
+
+    /// When the last pong was received, if any
+    pub fn last_pong(&self) -> Option<Instant> {
+        *self.inner.last_pong.lock().unwrap()
+    }
+
+    pub fn stop(&self) {
+        self.inner.cancel_token.cancel();
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.inner.cancel_token.is_cancelled()
+    }
+
+    /// Whether data from `source` can answer this keepalive
+    fn is_from(&self, source: &SipAddr) -> bool {
+        let target = &self.inner.target;
+        target.addr == source.addr
+            || matches!(
+                (target.get_socketaddr(), source.get_socketaddr()),
+                (Ok(target), Ok(source)) if target == source
+            )
+    }
+
+    /// Record `data` as a pong if the matcher recognizes it
+    pub(crate) fn on_received(&self, data: &[u8]) -> bool {
+        if self.is_stopped() || !self.inner.keepalive.is_pong(data) {
+            return false;
+        }
+        debug!("keepalive pong from {}", self.inner.target);
+        self.inner.last_pong.lock().unwrap().replace(Instant::now());
+        true
+    }
+
+    /// Send the keepalive on `connection` until it is stopped
+    ///
+    /// The task only holds a weak reference, so dropping the last handle
+    /// ends it.
+    pub(crate) fn spawn(&self, connection: SipConnection) {
+        let inner = Arc::downgrade(&self.inner);
+        let cancel_token = self.inner.cancel_token.clone();
+        let target = self.inner.target.clone();
+        let interval = self.inner.keepalive.interval.max(Duration::from_millis(1));
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                select! {
+                    _ = cancel_token.cancelled() => break,
+                    _ = ticker.tick() => {
+                        let payload = match inner.upgrade() {
+                            Some(inner) => inner.keepalive.payload.clone(),
+                            None => break,
+                        };
+                        if let Err(e) = connection.send_raw(&payload, &target).await {
+                            warn!("keepalive to {} failed: {}", target, e);
+                        }
+                    }
+                }
+            }
+            debug!("keepalive to {} stopped", target);
+        });
+    }
+}
+
+/// Keepalives running on one connection
+///
+/// A UDP socket may keep several peers alive, with one keepalive per
+/// target; starting another keepalive to the same target stops the old
+/// one. Stream connections have a single peer.
+///
+/// Only weak references are kept, so the set does not keep a keepalive
+/// running once its handles are dropped.
+#[derive(Clone, Default)]
+pub(crate) struct KeepaliveSet {
+    handles: Arc<Mutex<Vec<Weak<KeepaliveInner>>>>,
+}
+
+impl std::fmt::Debug for KeepaliveSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.running().iter().map(|h| h.target().clone()))
+            .finish()
+    }
+}
+
+impl KeepaliveSet {
+    pub(crate) fn insert(&self, handle: &KeepaliveHandle) {
+        let mut handles = self.handles.lock().unwrap();
+        handles.retain(|running| match running.upgrade() {
+            Some(running) => {
+                if running.target == handle.inner.target {
+                    running.cancel_token.cancel();
+                }
+                !running.cancel_token.is_cancelled()
+            }
+            None => false,
+        });
+        handles.push(Arc::downgrade(&handle.inner));
+    }
+
+    /// Stop every keepalive, once the connection is no longer served
+    pub(crate) fn stop_all(&self) {
+        for handle in self.running() {
+            handle.stop();
+        }
+    }
+
+    fn running(&self) -> Vec<KeepaliveHandle> {
+        self.handles
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .map(|inner| KeepaliveHandle { inner })
+            .collect()
+    }
+
+    /// Record `data` as a pong of the keepalive it answers
+    ///
+    /// `source` is the sender of a datagram; data read from a stream is
+    /// matched against every keepalive of the connection.
+    pub(crate) fn on_received(&self, source: Option<&SipAddr>, data: &[u8]) -> bool {
+        self.running()
+            .iter()
+            .filter(|handle| source.is_none_or(|source| handle.is_from(source)))
+            .any(|handle| handle.on_received(data))
+    }
+}
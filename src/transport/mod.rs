@@ -1,5 +1,7 @@
 pub mod channel;
 pub mod connection;
+pub mod dns;
+pub mod keepalive;
 pub mod sip_addr;
 pub mod stream;
 pub mod tcp;
@@ -96,6 +96,44 @@ impl SipAddr {
         }
     }
 
+    /// Build a SipAddr from a SIP URI
+    ///
+    /// The transport comes from the `transport` URI parameter, defaulting to
+    /// TLS for `sips:` URIs and UDP otherwise. A missing port is filled in
+    /// with the transport's default (5061 for TLS/WSS, 5060 otherwise).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rsipstack::transport::SipAddr;
+    ///
+    /// let uri = rsip::Uri::try_from("sips:bob@example.com").unwrap();
+    /// let addr = SipAddr::from_uri(&uri).unwrap();
+    /// assert_eq!(addr.r#type, Some(rsip::Transport::Tls));
+    /// assert_eq!(addr.port_or_default(), 5061);
+    /// ```
+    pub fn from_uri(uri: &rsip::Uri) -> Result<Self> {
+        let transport = match uri.transport() {
+            Some(transport) => *transport,
+            None if matches!(uri.scheme, Some(rsip::Scheme::Sips)) => {
+                rsip::transport::Transport::Tls
+            }
+            None => rsip::transport::Transport::Udp,
+        };
+        let mut addr = uri.host_with_port.clone();
+        if addr.port.is_none() {
+            addr.port = Some(default_port(Some(&transport)).into());
+        }
+        Ok(SipAddr::new(transport, addr))
+    }
+
+    /// The port of this address, or the transport's default port
+    pub fn port_or_default(&self) -> u16 {
+        self.addr
+            .port
+            .map_or(default_port(self.r#type.as_ref()), |p| *p.value())
+    }
+
     pub fn get_socketaddr(&self) -> Result<SocketAddr> {
         match &self.addr.host {
             host_with_port::Host::Domain(domain) => Err(crate::Error::Error(format!(
@@ -103,8 +141,7 @@ impl SipAddr {
                 domain
             ))),
             host_with_port::Host::IpAddr(ip_addr) => {
-                let port = self.addr.port.map_or(5060, |p| p.value().to_owned());
-                Ok(SocketAddr::new(ip_addr.to_owned(), port))
+                Ok(SocketAddr::new(ip_addr.to_owned(), self.port_or_default()))
             }
         }
     }
@@ -136,6 +173,10 @@ impl From<rsip::host_with_port::HostWithPort> for SipAddr {
     }
 }
 
+/// Take the transport parameter and port of a URI as they are
+///
+/// Both stay `None` when the URI has none; use [`SipAddr::from_uri`] to
+/// fill in the RFC 3263 defaults.
 impl TryFrom<&rsip::Uri> for SipAddr {
     type Error = crate::Error;
 
@@ -147,3 +188,11 @@ impl TryFrom<&rsip::Uri> for SipAddr {
         })
     }
 }
+
+/// Default SIP port for a transport: 5061 for TLS and WSS, 5060 otherwise
+pub fn default_port(transport: Option<&rsip::transport::Transport>) -> u16 {
+    match transport {
+        Some(rsip::transport::Transport::Tls) | Some(rsip::transport::Transport::Wss) => 5061,
+        _ => 5060,
+    }
+}
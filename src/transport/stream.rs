@@ -1,6 +1,10 @@
 use crate::{
+    rsip_ext::sanitize_for_log,
     transport::{
-        connection::{TransportSender, KEEPALIVE_REQUEST, KEEPALIVE_RESPONSE},
+        connection::{
+            header_len, oversized_body, MalformedMessage, TransportSender, KEEPALIVE_REQUEST,
+            KEEPALIVE_RESPONSE,
+        },
         SipAddr, SipConnection, TransportEvent,
     },
     Result,
@@ -19,14 +23,43 @@ const MAX_SIP_MESSAGE_SIZE: usize = 65535;
 
 pub struct SipCodec {
     max_size: usize,
+    max_body_size: usize,
+    skip_body: usize,
+    discarded: Option<Vec<u8>>,
 }
 
 impl SipCodec {
     pub fn new() -> Self {
         Self {
             max_size: MAX_SIP_MESSAGE_SIZE,
+            max_body_size: 0,
+            skip_body: 0,
+            discarded: None,
         }
     }
+
+    /// Limit the body size of decoded messages
+    ///
+    /// A message whose `Content-Length` exceeds the limit is decoded with
+    /// its headers only, and its body is dropped from the buffer as it
+    /// arrives. A limit of 0 means unlimited.
+    pub fn with_max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Drop body bytes of an oversized message, returns true once all are gone
+    fn skip_oversized_body(&mut self, src: &mut BytesMut) -> bool {
+        let n = self.skip_body.min(src.len());
+        src.advance(n);
+        self.skip_body -= n;
+        self.skip_body == 0
+    }
+
+    /// Bytes dropped by the last decode that failed to parse
+    pub fn take_discarded(&mut self) -> Option<Vec<u8>> {
+        self.discarded.take()
+    }
 }
 
 impl Default for SipCodec {
@@ -40,6 +73,10 @@ impl Decoder for SipCodec {
     type Error = crate::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>> {
+        if !self.skip_oversized_body(src) {
+            return Ok(None);
+        }
+
         if src.len() >= 4 && &src[0..4] == KEEPALIVE_REQUEST {
             src.advance(4);
             return Err(crate::Error::Keepalive);
@@ -50,6 +87,21 @@ impl Decoder for SipCodec {
             return Err(crate::Error::Keepalive);
         }
 
+        if let Some(len) = header_len(src) {
+            if let Some(declared) = oversized_body(&src[..len], self.max_body_size) {
+                if let Ok(msg) = std::str::from_utf8(&src[..len])
+                    .map_err(|e| e.to_string())
+                    .and_then(|headers| SipMessage::try_from(headers).map_err(|e| e.to_string()))
+                {
+                    warn!("dropping body of {} bytes over the size limit", declared);
+                    src.advance(len);
+                    self.skip_body = declared;
+                    self.skip_oversized_body(src);
+                    return Ok(Some(msg));
+                }
+            }
+        }
+
         let data = match std::str::from_utf8(&src[..]) {
             Ok(s) => s,
             Err(_) => {
@@ -74,11 +126,11 @@ impl Decoder for SipCodec {
                 Ok(Some(msg))
             }
             Err(e) => {
-                if let Some(pos) = data[1..].find("\r\n\r\n") {
-                    src.advance(pos + 5);
-                } else {
-                    src.clear();
-                }
+                let consumed = match data[1..].find("\r\n\r\n") {
+                    Some(pos) => pos + 5,
+                    None => src.len(),
+                };
+                self.discarded = Some(src.split_to(consumed).to_vec());
                 Err(crate::Error::Error(format!(
                     "Failed to parse SIP message: {}",
                     e
@@ -138,12 +190,19 @@ where
                 break;
             }
             Ok(n) => {
+                if buffer.is_empty() && connection.on_keepalive_received(None, &read_buf[..n]) {
+                    continue;
+                }
                 buffer.extend_from_slice(&read_buf[0..n]);
 
                 loop {
                     match codec.decode(&mut buffer) {
                         Ok(Some(msg)) => {
-                            debug!("Received message from {}: {:?}", remote_addr, msg);
+                            debug!(
+                                "Received message from {}: {}",
+                                remote_addr,
+                                sanitize_for_log(&msg.to_string())
+                            );
 
                             sender.send(TransportEvent::Incoming(
                                 msg,
@@ -161,6 +220,13 @@ where
                         }
                         Err(e) => {
                             warn!("Error decoding message from {}: {:?}", remote_addr, e);
+                            if let Some(data) = codec.take_discarded() {
+                                sender.send(TransportEvent::Malformed(MalformedMessage {
+                                    data,
+                                    error: e.to_string(),
+                                    source: remote_addr.clone(),
+                                }))?;
+                            }
                         }
                     }
                 }
@@ -1,6 +1,8 @@
 use crate::{
+    rsip_ext::sanitize_for_log,
     transport::{
-        connection::TransportSender,
+        connection::{MalformedMessage, TransportSender},
+        keepalive::KeepaliveSet,
         sip_addr::SipAddr,
         stream::{send_raw_to_stream, send_to_stream, StreamConnection},
         SipConnection, TransportEvent,
@@ -8,7 +10,13 @@ use crate::{
     Result,
 };
 use rsip::SipMessage;
-use std::{fmt, sync::Arc};
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::{TcpListener, TcpStream},
@@ -20,6 +28,8 @@ pub struct TcpInner {
     pub remote_addr: Option<SipAddr>,
     pub read_half: Arc<Mutex<tokio::io::ReadHalf<TcpStream>>>,
     pub write_half: Arc<Mutex<tokio::io::WriteHalf<TcpStream>>>,
+    pub(crate) keepalives: KeepaliveSet,
+    pub(crate) max_body_size: AtomicUsize,
 }
 
 #[derive(Clone)]
@@ -45,6 +55,8 @@ impl TcpConnection {
                 remote_addr: Some(remote.clone()),
                 read_half: Arc::new(Mutex::new(read_half)),
                 write_half: Arc::new(Mutex::new(write_half)),
+                keepalives: KeepaliveSet::default(),
+                max_body_size: AtomicUsize::new(0),
             }),
         };
 
@@ -72,6 +84,8 @@ impl TcpConnection {
                 remote_addr: Some(remote_sip_addr),
                 read_half: Arc::new(Mutex::new(read_half)),
                 write_half: Arc::new(Mutex::new(write_half)),
+                keepalives: KeepaliveSet::default(),
+                max_body_size: AtomicUsize::new(0),
             }),
         };
 
@@ -102,6 +116,16 @@ impl TcpConnection {
         listener: TcpListener,
         local_addr: SipAddr,
         sender: TransportSender,
+    ) -> Result<()> {
+        Self::serve_listener_with_limit(listener, local_addr, sender, None).await
+    }
+
+    /// Serve a TCP listener, limiting the body size on accepted connections
+    pub(crate) async fn serve_listener_with_limit(
+        listener: TcpListener,
+        local_addr: SipAddr,
+        sender: TransportSender,
+        max_body_size: Option<usize>,
     ) -> Result<()> {
         info!("Starting TCP listener on {}", local_addr);
 
@@ -112,12 +136,14 @@ impl TcpConnection {
 
                     let tcp_connection =
                         TcpConnection::from_stream(stream, local_addr.clone()).await?;
-                    let sip_connection = SipConnection::Tcp(tcp_connection.clone());
+                    let sip_connection = SipConnection::Tcp(tcp_connection);
+                    sip_connection.set_max_body_size(max_body_size);
 
+                    let serving = sip_connection.clone();
                     let sender_clone = sender.clone();
 
                     tokio::spawn(async move {
-                        if let Err(e) = tcp_connection.serve_loop(sender_clone).await {
+                        if let Err(e) = serving.serve_loop(sender_clone).await {
                             error!("Error handling TCP connection: {:?}", e);
                         }
                     });
@@ -141,7 +167,7 @@ impl StreamConnection for TcpConnection {
     }
 
     async fn send_message(&self, msg: SipMessage) -> Result<()> {
-        info!("TcpConnection send:{}", msg);
+        info!("TcpConnection send:{}", sanitize_for_log(&msg.to_string()));
         send_to_stream(&self.inner.write_half, msg).await
     }
 
@@ -160,7 +186,8 @@ impl StreamConnection for TcpConnection {
         use bytes::BytesMut;
         use tokio_util::codec::Decoder;
 
-        let mut codec = SipCodec::new();
+        let mut codec =
+            SipCodec::new().with_max_body_size(self.inner.max_body_size.load(Ordering::Relaxed));
         let mut buffer = BytesMut::with_capacity(4096);
         let mut read_buf = [0u8; 4096];
         let mut read_half = self.inner.read_half.lock().await;
@@ -172,12 +199,20 @@ impl StreamConnection for TcpConnection {
                     break;
                 }
                 Ok(n) => {
+                    if buffer.is_empty() && self.inner.keepalives.on_received(None, &read_buf[..n])
+                    {
+                        continue;
+                    }
                     buffer.extend_from_slice(&read_buf[0..n]);
 
                     loop {
                         match codec.decode(&mut buffer) {
                             Ok(Some(msg)) => {
-                                info!("TCP received message from {}: {}", remote_addr, msg);
+                                info!(
+                                    "TCP received message from {}: {}",
+                                    remote_addr,
+                                    sanitize_for_log(&msg.to_string())
+                                );
 
                                 if let Err(e) = sender.send(TransportEvent::Incoming(
                                     msg,
@@ -200,6 +235,15 @@ impl StreamConnection for TcpConnection {
                             Err(e) => {
                                 error!("Error decoding message from {}: {:?}", remote_addr, e);
                                 // Continue processing despite decode errors
+                                if let Some(data) = codec.take_discarded() {
+                                    sender
+                                        .send(TransportEvent::Malformed(MalformedMessage {
+                                            data,
+                                            error: e.to_string(),
+                                            source: remote_addr.clone(),
+                                        }))
+                                        .ok();
+                                }
                             }
                         }
                     }
@@ -254,6 +254,89 @@ async fn test_outbound_connection() -> Result<()> {
     Ok(())
 }
 
+/// A dropped outbound TCP connection is dialed again by the next request
+#[tokio::test]
+async fn test_tcp_reconnect_after_drop() -> Result<()> {
+    use tokio::io::AsyncReadExt;
+
+    let cancel_token = CancellationToken::new();
+    let transport_layer = TransportLayer::new(cancel_token.clone());
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+
+    // Mock registrar that drops the connection after the first request
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let port = listener.local_addr()?.port();
+    let uri: rsip::Uri = format!("sip:registrar@127.0.0.1:{};transport=tcp", port).try_into()?;
+
+    let (conn, target) = transport_layer.lookup(&uri, sender.clone()).await?;
+    conn.send(create_test_message("reconnect-1"), Some(&target))
+        .await?;
+    let (mut stream, _) = timeout(Duration::from_secs(5), listener.accept())
+        .await
+        .expect("registrar accept")?;
+    let mut buf = vec![0u8; 4096];
+    let n = stream.read(&mut buf).await?;
+    assert!(String::from_utf8_lossy(&buf[..n]).contains("reconnect-1"));
+
+    // While connected, the pooled connection is reused
+    let (pooled, _) = transport_layer.lookup(&uri, sender.clone()).await?;
+    assert_eq!(pooled.get_addr(), conn.get_addr());
+
+    drop(stream);
+    loop {
+        if let TransportEvent::Closed(_) = wait_for_event(&mut receiver).await? {
+            break;
+        }
+    }
+
+    // The next request reconnects and goes through
+    let (conn, target) = transport_layer.lookup(&uri, sender.clone()).await?;
+    conn.send(create_test_message("reconnect-2"), Some(&target))
+        .await?;
+    let (mut stream, _) = timeout(Duration::from_secs(5), listener.accept())
+        .await
+        .expect("registrar accept")?;
+    let n = stream.read(&mut buf).await?;
+    assert!(String::from_utf8_lossy(&buf[..n]).contains("reconnect-2"));
+
+    cancel_token.cancel();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_concurrent_lookups_share_one_connection() -> Result<()> {
+    let cancel_token = CancellationToken::new();
+    let transport_layer = TransportLayer::new(cancel_token.clone());
+    let (sender, _receiver) = mpsc::unbounded_channel();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let port = listener.local_addr()?.port();
+    let uri: rsip::Uri = format!("sip:registrar@127.0.0.1:{};transport=tcp", port).try_into()?;
+
+    // Requests racing for a new connection must not each dial the target
+    let (first, second, third) = tokio::join!(
+        transport_layer.lookup(&uri, sender.clone()),
+        transport_layer.lookup(&uri, sender.clone()),
+        transport_layer.lookup(&uri, sender.clone()),
+    );
+    let (first, _) = first?;
+    assert_eq!(second?.0.get_addr(), first.get_addr());
+    assert_eq!(third?.0.get_addr(), first.get_addr());
+
+    timeout(Duration::from_secs(5), listener.accept())
+        .await
+        .expect("registrar accept")?;
+    assert!(
+        timeout(Duration::from_millis(200), listener.accept())
+            .await
+            .is_err(),
+        "only one connection should be dialed"
+    );
+
+    cancel_token.cancel();
+    Ok(())
+}
+
 async fn wait_for_event(
     receiver: &mut UnboundedReceiver<TransportEvent>,
 ) -> Result<TransportEvent> {
@@ -280,3 +363,75 @@ fn create_test_message(call_id: &str) -> SipMessage {
 
     SipMessage::try_from(test_message.as_str()).expect("parse SIP message")
 }
+
+/// Test custom keepalive pongs read from a TCP stream
+#[tokio::test]
+async fn test_tcp_custom_keepalive_pong() -> Result<()> {
+    use crate::transport::{keepalive::Keepalive, SipAddr, SipConnection};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let server_addr = SipAddr {
+        r#type: Some(Transport::Tcp),
+        addr: listener.local_addr()?.into(),
+    };
+    let client = TcpConnection::connect(&server_addr).await?;
+    let (mut peer, _) = listener.accept().await?;
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let serving = client.clone();
+    tokio::spawn(async move { serving.serve_loop(sender).await });
+
+    let mut keepalive = Keepalive::new(Duration::from_secs(60));
+    keepalive.with_payload(b"VENDOR-PING".to_vec(), |data| {
+        data.starts_with(b"VENDOR-PONG")
+    });
+    let handle = SipConnection::Tcp(client).start_keepalive(server_addr, keepalive);
+
+    let mut buf = [0u8; 64];
+    let n = timeout(Duration::from_secs(2), peer.read(&mut buf))
+        .await
+        .expect("no keepalive sent")?;
+    assert_eq!(&buf[..n], b"VENDOR-PING");
+
+    // the pong is consumed by the keepalive instead of the SIP parser
+    peer.write_all(b"VENDOR-PONG 42").await?;
+    for _ in 0..100 {
+        if handle.last_pong().is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(handle.last_pong().is_some(), "pong was not recognized");
+    assert!(receiver.try_recv().is_err());
+    handle.stop();
+    Ok(())
+}
+
+/// Test that a keepalive stops once its connection is closed
+#[tokio::test]
+async fn test_tcp_keepalive_stops_when_connection_closes() -> Result<()> {
+    use crate::transport::{keepalive::Keepalive, SipAddr, SipConnection};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let server_addr = SipAddr {
+        r#type: Some(Transport::Tcp),
+        addr: listener.local_addr()?.into(),
+    };
+    let client = SipConnection::Tcp(TcpConnection::connect(&server_addr).await?);
+    let (peer, _) = listener.accept().await?;
+    let (sender, _receiver) = mpsc::unbounded_channel();
+    let serving = client.clone();
+    let served = tokio::spawn(async move { serving.serve_loop(sender).await });
+
+    let handle = client.start_keepalive(server_addr, Keepalive::new(Duration::from_secs(60)));
+    assert!(!handle.is_stopped());
+
+    // the peer goes away, the serve loop ends and takes the keepalive with it
+    drop(peer);
+    timeout(Duration::from_secs(2), served)
+        .await
+        .expect("serve loop did not end")
+        .expect("serve task")?;
+    assert!(handle.is_stopped());
+    Ok(())
+}
@@ -1,5 +1,5 @@
 use crate::transport::{SipAddr, SipConnection};
-use rsip::{headers::*, prelude::HeadersExt, HostWithPort, SipMessage};
+use rsip::{headers::*, prelude::HeadersExt, HostWithPort, SipMessage, Transport};
 
 #[test]
 fn test_via_received() {
@@ -61,3 +61,38 @@ fn test_sipaddr() {
         }
     );
 }
+
+#[test]
+fn test_sipaddr_from_uri() {
+    let cases = [
+        ("sip:bob@example.com", Transport::Udp, 5060),
+        ("sips:bob@example.com", Transport::Tls, 5061),
+        ("sip:bob@example.com:5080", Transport::Udp, 5080),
+        ("sip:bob@example.com;transport=tcp", Transport::Tcp, 5060),
+        (
+            "sip:bob@192.168.1.10:5070;transport=tcp",
+            Transport::Tcp,
+            5070,
+        ),
+        ("sips:bob@example.com;transport=wss", Transport::Wss, 5061),
+    ];
+    for (uri, transport, port) in cases {
+        let uri = rsip::Uri::try_from(uri).expect("parse uri");
+        let addr = SipAddr::from_uri(&uri).expect("SipAddr::from_uri");
+        assert_eq!(addr.r#type, Some(transport), "{}", uri);
+        assert_eq!(addr.addr.port, Some(port.into()), "{}", uri);
+        assert_eq!(addr.port_or_default(), port, "{}", uri);
+    }
+
+    // TryFrom keeps what the URI says, without defaults
+    let uri = rsip::Uri::try_from("sips:bob@example.com").expect("parse uri");
+    let addr = SipAddr::try_from(&uri).expect("SipAddr::try_from");
+    assert_eq!(addr.r#type, None);
+    assert_eq!(addr.addr.port, None);
+
+    let addr = SipAddr::new(Transport::Tls, HostWithPort::try_from("10.0.0.1").unwrap());
+    assert_eq!(
+        addr.get_socketaddr().unwrap(),
+        "10.0.0.1:5061".parse().unwrap()
+    );
+}
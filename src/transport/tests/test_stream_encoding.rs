@@ -254,3 +254,70 @@ fn test_sip_codec_size_limit() {
     let result = codec.decode(&mut buffer);
     assert!(result.is_err(), "Should error on oversized message");
 }
+
+/// Test that the body of a message over the body size limit is not buffered
+#[test]
+fn test_sip_codec_max_body_size() {
+    let mut codec = SipCodec::new().with_max_body_size(1024);
+    let mut buffer = BytesMut::new();
+
+    let headers = "MESSAGE sip:example.com SIP/2.0\r\n\
+                   Via: SIP/2.0/TCP 127.0.0.1:5060;branch=z9hG4bK-large\r\n\
+                   From: <sip:alice@example.com>;tag=test\r\n\
+                   To: <sip:bob@example.com>\r\n\
+                   Call-ID: large-body\r\n\
+                   CSeq: 1 MESSAGE\r\n\
+                   Content-Type: text/plain\r\n\
+                   Content-Length: 100000\r\n\r\n";
+    buffer.extend_from_slice(headers.as_bytes());
+    buffer.extend_from_slice(&[b'x'; 4096]);
+
+    // the headers are decoded right away, without the body
+    let msg = codec
+        .decode(&mut buffer)
+        .expect("decode should succeed")
+        .expect("headers of the oversized message");
+    match msg {
+        SipMessage::Request(req) => {
+            assert_eq!(req.method, rsip::Method::Message);
+            assert!(req.body.is_empty());
+            assert!(req.headers.iter().any(|h| matches!(
+                h,
+                rsip::Header::ContentLength(len) if len.value() == "100000"
+            )));
+        }
+        _ => panic!("Expected request message"),
+    }
+    assert_eq!(buffer.len(), 0, "body bytes already read are dropped");
+
+    // the rest of the body is dropped as it arrives
+    let mut remaining = 100000 - 4096;
+    while remaining > 4096 {
+        buffer.extend_from_slice(&[b'x'; 4096]);
+        assert!(codec.decode(&mut buffer).expect("decode").is_none());
+        assert_eq!(buffer.len(), 0, "body must not be buffered");
+        remaining -= 4096;
+    }
+
+    // the next message on the stream is decoded normally
+    let next = "OPTIONS sip:example.com SIP/2.0\r\n\
+                Via: SIP/2.0/TCP 127.0.0.1:5060;branch=z9hG4bK-next\r\n\
+                From: <sip:alice@example.com>;tag=test\r\n\
+                To: <sip:bob@example.com>\r\n\
+                Call-ID: after-large-body\r\n\
+                CSeq: 2 OPTIONS\r\n\
+                Content-Length: 0\r\n\r\n";
+    buffer.extend_from_slice(&vec![b'x'; remaining]);
+    buffer.extend_from_slice(next.as_bytes());
+    let msg = codec
+        .decode(&mut buffer)
+        .expect("decode should succeed")
+        .expect("message after the dropped body");
+    match msg {
+        SipMessage::Request(req) => {
+            assert_eq!(req.call_id_header().unwrap().value(), "after-large-body");
+        }
+        _ => panic!("Expected request message"),
+    }
+    assert_eq!(buffer.len(), 0);
+}
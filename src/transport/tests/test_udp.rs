@@ -85,3 +85,148 @@ async fn test_udp_recv_sip_message() -> Result<()> {
     };
     Ok(())
 }
+
+#[tokio::test]
+async fn test_udp_custom_keepalive_payload() -> Result<()> {
+    use crate::transport::{keepalive::Keepalive, SipConnection};
+
+    let alice = UdpConnection::create_connection("127.0.0.1:0".parse()?, None).await?;
+    let sbc = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let sbc_addr: crate::transport::SipAddr = sbc.local_addr()?.into();
+    let (alice_tx, mut alice_rx) = unbounded_channel::<TransportEvent>();
+    let connection = SipConnection::Udp(alice.clone());
+    tokio::spawn(async move { alice.serve_loop(alice_tx).await });
+
+    let mut keepalive = Keepalive::new(Duration::from_secs(60));
+    keepalive.with_payload(b"VENDOR-PING".to_vec(), |data| {
+        data.starts_with(b"VENDOR-PONG")
+    });
+    let handle = connection.start_keepalive(sbc_addr, keepalive);
+
+    // the custom payload is sent instead of CRLF
+    let mut buf = [0u8; 2048];
+    let (n, from) = tokio::time::timeout(Duration::from_secs(2), sbc.recv_from(&mut buf))
+        .await
+        .expect("no keepalive sent")?;
+    assert_eq!(&buf[..n], b"VENDOR-PING");
+    assert!(handle.last_pong().is_none());
+
+    // the matcher recognizes the pong, which never reaches the SIP layer
+    sbc.send_to(b"VENDOR-PONG 42", from).await?;
+    let mut recognized = false;
+    for _ in 0..100 {
+        if handle.last_pong().is_some() {
+            recognized = true;
+            break;
+        }
+        sleep(Duration::from_millis(10)).await;
+    }
+    assert!(recognized, "pong was not recognized");
+    assert!(alice_rx.try_recv().is_err());
+    handle.stop();
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_udp_reuse_port() -> Result<()> {
+    use crate::transport::{transport_layer::TransportConfig, TransportLayer};
+    use tokio_util::sync::CancellationToken;
+
+    let first =
+        UdpConnection::create_connection_with_reuse("127.0.0.1:0".parse()?, None, true, true)
+            .await?;
+    let addr = first.get_addr().get_socketaddr()?;
+
+    // a restarted endpoint binds the very same port through its transport layer
+    let config = TransportConfig {
+        reuse_address: true,
+        reuse_port: true,
+        ..Default::default()
+    };
+    let transport_layer = TransportLayer::with_config(CancellationToken::new(), config);
+    let second = transport_layer.add_udp_listener(addr).await?;
+    assert_eq!(second.get_socketaddr()?, addr);
+
+    // without reuse the port stays taken
+    assert!(UdpConnection::create_connection(addr, None).await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_udp_keepalive_per_target() -> Result<()> {
+    use crate::transport::{keepalive::Keepalive, SipConnection};
+
+    let alice = UdpConnection::create_connection("127.0.0.1:0".parse()?, None).await?;
+    let sbc1 = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let sbc2 = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let (alice_tx, _alice_rx) = unbounded_channel::<TransportEvent>();
+    let connection = SipConnection::Udp(alice.clone());
+    tokio::spawn(async move { alice.serve_loop(alice_tx).await });
+
+    let keepalive = Keepalive::new(Duration::from_secs(60));
+    let stale = connection.start_keepalive(sbc1.local_addr()?.into(), keepalive.clone());
+    let handle1 = connection.start_keepalive(sbc1.local_addr()?.into(), keepalive.clone());
+    let handle2 = connection.start_keepalive(sbc2.local_addr()?.into(), keepalive);
+    // a second keepalive to the same target replaces the first one
+    assert!(stale.is_stopped());
+    assert!(!handle1.is_stopped());
+
+    let mut buf = [0u8; 2048];
+    let (n, from) = tokio::time::timeout(Duration::from_secs(2), sbc1.recv_from(&mut buf))
+        .await
+        .expect("no keepalive sent")?;
+    assert_eq!(&buf[..n], KEEPALIVE_REQUEST);
+
+    // the CRLF pong is recorded on the keepalive of the peer that sent it
+    sbc1.send_to(KEEPALIVE_RESPONSE, from).await?;
+    for _ in 0..100 {
+        if handle1.last_pong().is_some() {
+            break;
+        }
+        sleep(Duration::from_millis(10)).await;
+    }
+    assert!(handle1.last_pong().is_some(), "pong was not recognized");
+    assert!(handle2.last_pong().is_none());
+    handle1.stop();
+    handle2.stop();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_udp_keepalive_stops_when_handle_dropped() -> Result<()> {
+    use crate::transport::{keepalive::Keepalive, SipConnection};
+
+    let alice = UdpConnection::create_connection("127.0.0.1:0".parse()?, None).await?;
+    let sbc = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    let connection = SipConnection::Udp(alice);
+
+    let handle = connection.start_keepalive(
+        sbc.local_addr()?.into(),
+        Keepalive::new(Duration::from_millis(20)),
+    );
+    let clone = handle.clone();
+    let mut buf = [0u8; 2048];
+    tokio::time::timeout(Duration::from_secs(2), sbc.recv_from(&mut buf))
+        .await
+        .expect("no keepalive sent")?;
+
+    // a remaining clone keeps it running
+    drop(handle);
+    assert!(!clone.is_stopped());
+    tokio::time::timeout(Duration::from_secs(2), sbc.recv_from(&mut buf))
+        .await
+        .expect("keepalive stopped with a clone left")?;
+
+    // dropping the last handle stops it
+    drop(clone);
+    sleep(Duration::from_millis(50)).await;
+    while sbc.try_recv_from(&mut buf).is_ok() {}
+    assert!(
+        tokio::time::timeout(Duration::from_millis(200), sbc.recv_from(&mut buf))
+            .await
+            .is_err(),
+        "keepalive still sent after the last handle was dropped"
+    );
+    Ok(())
+}
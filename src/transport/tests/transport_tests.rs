@@ -65,6 +65,9 @@ async fn test_tcp_client_server() -> Result<()> {
         TransportEvent::New(_conn) => {
             info!("Connection created");
         }
+        TransportEvent::Malformed(malformed) => {
+            panic!("unexpected malformed message: {}", malformed.error);
+        }
     }
 
     // Close connection
@@ -1,10 +1,11 @@
 use super::{
-    connection::{TransportSender, KEEPALIVE_REQUEST, KEEPALIVE_RESPONSE},
-    sip_addr::SipAddr,
+    connection::{MalformedMessage, TransportSender, KEEPALIVE_REQUEST, KEEPALIVE_RESPONSE},
+    keepalive::KeepaliveSet,
+    sip_addr::{default_port, SipAddr},
     stream::StreamConnection,
     SipConnection, TransportEvent,
 };
-use crate::{error::Error, Result};
+use crate::{error::Error, rsip_ext::sanitize_for_log, Result};
 use rsip::SipMessage;
 use rustls::client::danger::ServerCertVerifier;
 use std::{fmt, net::SocketAddr, sync::Arc};
@@ -55,6 +56,7 @@ pub struct TlsConnection {
     remote_addr: SipAddr,
     read_half: Arc<Mutex<Option<tokio::io::ReadHalf<TlsClientStream>>>>,
     write_half: Arc<Mutex<Option<tokio::io::WriteHalf<TlsClientStream>>>>,
+    pub(crate) keepalives: KeepaliveSet,
 }
 
 impl TlsConnection {
@@ -67,6 +69,7 @@ impl TlsConnection {
             remote_addr: addr,
             read_half,
             write_half,
+            keepalives: KeepaliveSet::default(),
         }
     }
 
@@ -94,15 +97,13 @@ impl TlsConnection {
         let connector = TlsConnector::from(Arc::new(config));
 
         // Connect to remote server
+        let tls_port = default_port(Some(&rsip::transport::Transport::Tls));
+        let port = remote_addr.addr.port.as_ref().map_or(tls_port, |p| *p.value());
         let socket_addr = match &remote_addr.addr.host {
             rsip::host_with_port::Host::Domain(domain) => {
-                let port = remote_addr.addr.port.as_ref().map_or(5061, |p| *p.value());
                 format!("{}:{}", domain.to_string(), port).parse()?
             }
-            rsip::host_with_port::Host::IpAddr(ip) => {
-                let port = remote_addr.addr.port.as_ref().map_or(5061, |p| *p.value());
-                SocketAddr::new(*ip, port)
-            }
+            rsip::host_with_port::Host::IpAddr(ip) => SocketAddr::new(*ip, port),
         };
 
         let domain_string = match &remote_addr.addr.host {
@@ -127,6 +128,7 @@ impl TlsConnection {
             remote_addr: remote_addr.clone(),
             read_half: Arc::new(Mutex::new(Some(read_half))),
             write_half: Arc::new(Mutex::new(Some(write_half))),
+            keepalives: KeepaliveSet::default(),
         };
 
         Ok(connection)
@@ -142,6 +144,7 @@ impl TlsConnection {
             remote_addr,
             read_half: Arc::new(Mutex::new(Some(read_half))),
             write_half: Arc::new(Mutex::new(Some(write_half))),
+            keepalives: KeepaliveSet::default(),
         };
 
         Ok(connection)
@@ -337,7 +340,7 @@ impl StreamConnection for TlsConnection {
     }
 
     async fn send_message(&self, msg: SipMessage) -> Result<()> {
-        info!("TlsConnection send:{}", msg);
+        info!("TlsConnection send:{}", sanitize_for_log(&msg.to_string()));
         let mut write_half_guard = self.write_half.lock().await;
         if let Some(write_half) = &mut *write_half_guard {
             let mut buf = Vec::new();
@@ -386,6 +389,9 @@ impl StreamConnection for TlsConnection {
                 continue;
             }
 
+            if self.keepalives.on_received(None, &buf[..len]) {
+                continue;
+            }
             match &buf[..len] {
                 KEEPALIVE_REQUEST => match self.send_raw(KEEPALIVE_RESPONSE).await {
                     Ok(_) => continue,
@@ -406,6 +412,13 @@ impl StreamConnection for TlsConnection {
                 Ok(s) => s,
                 Err(e) => {
                     info!("decoding text ferror: {} buf: {:?}", e, &buf[..len]);
+                    sender
+                        .send(TransportEvent::Malformed(MalformedMessage {
+                            data: buf[..len].to_vec(),
+                            error: e.to_string(),
+                            source: remote_addr.clone(),
+                        }))
+                        .ok();
                     continue;
                 }
             };
@@ -414,6 +427,13 @@ impl StreamConnection for TlsConnection {
                 Ok(msg) => msg,
                 Err(e) => {
                     info!("error parsing SIP message error: {} buf: {}", e, undecoded);
+                    sender
+                        .send(TransportEvent::Malformed(MalformedMessage {
+                            data: buf[..len].to_vec(),
+                            error: e.to_string(),
+                            source: remote_addr.clone(),
+                        }))
+                        .ok();
                     continue;
                 }
             };
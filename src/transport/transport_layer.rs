@@ -1,13 +1,13 @@
+use super::dns::DnsConfig;
 use super::tls::{TlsConfig, TlsConnection};
 use super::websocket::WebSocketConnection;
 use super::{connection::TransportSender, sip_addr::SipAddr, tcp::TcpConnection, SipConnection};
 use crate::{transport::TransportEvent, Result};
-use rsip::HostWithPort;
-use rsip_dns::{trust_dns_resolver::TokioAsyncResolver, ResolvableExt};
 use std::net::SocketAddr;
 use std::{
     collections::HashMap,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 use tokio::select;
 use tokio_util::sync::CancellationToken;
@@ -20,12 +20,48 @@ pub struct TransportConfig {
     pub tls: Option<TlsConfig>,
     pub enable_ws: bool,
     pub enable_wss: bool,
+    /// Retry policy for dialing TCP/TLS/WebSocket peers
+    pub reconnect: ReconnectBackoff,
+    /// Bind UDP listeners with `SO_REUSEADDR`
+    pub reuse_address: bool,
+    /// Bind UDP listeners with `SO_REUSEPORT` (unix only)
+    pub reuse_port: bool,
+    /// Timeout, lookup order and resolver for locating servers
+    pub dns: DnsConfig,
+    /// Largest body read from UDP and TCP connections, see `SipConnection::set_max_body_size`
+    pub max_body_size: Option<usize>,
+}
+
+/// Exponential backoff for (re)connecting connection-oriented transports
+///
+/// Outbound TCP, TLS and WebSocket connections are kept in a pool and reused
+/// by later requests to the same target. When one drops, the next request
+/// dials again, waiting `initial`, then twice as long after each failure up
+/// to `max`, for at most `max_attempts` retries.
+#[derive(Clone, Debug)]
+pub struct ReconnectBackoff {
+    pub initial: Duration,
+    pub max: Duration,
+    pub max_attempts: u32,
+}
+
+impl Default for ReconnectBackoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(200),
+            max: Duration::from_secs(5),
+            max_attempts: 3,
+        }
+    }
 }
 
 #[derive(Default)]
 pub struct TransportLayerInner {
     cancel_token: CancellationToken,
     listens: Arc<Mutex<HashMap<SipAddr, SipConnection>>>, // listening transports
+    connections: Arc<Mutex<HashMap<SipAddr, SipConnection>>>, // outbound stream connections
+    dialing: Mutex<HashMap<SipAddr, Arc<tokio::sync::Mutex<()>>>>, // targets being dialed
+    listeners: Mutex<Vec<SipAddr>>, // addresses of stream listeners accepting connections
     config: Arc<Mutex<TransportConfig>>,
 }
 
@@ -40,6 +76,9 @@ impl TransportLayer {
         let inner = TransportLayerInner {
             cancel_token,
             listens: Arc::new(Mutex::new(HashMap::new())),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            dialing: Mutex::new(HashMap::new()),
+            listeners: Mutex::new(vec![]),
             config: Arc::new(Mutex::new(TransportConfig::default())),
         };
         Self {
@@ -52,6 +91,9 @@ impl TransportLayer {
         let inner = TransportLayerInner {
             cancel_token,
             listens: Arc::new(Mutex::new(HashMap::new())),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            dialing: Mutex::new(HashMap::new()),
+            listeners: Mutex::new(vec![]),
             config: Arc::new(Mutex::new(config)),
         };
         Self {
@@ -76,6 +118,63 @@ impl TransportLayer {
         self.inner.lookup(uri, self.outbound.as_ref(), sender).await
     }
 
+    /// Resolve a URI and connect using a forced transport
+    ///
+    /// Works like `lookup`, but replaces the resolved transport type with
+    /// `transport` before connecting, e.g. to send one large request over
+    /// TCP to a peer otherwise reached over UDP. The resolved address and
+    /// port are kept.
+    pub async fn lookup_with_transport(
+        &self,
+        uri: &rsip::uri::Uri,
+        transport: rsip::Transport,
+        sender: TransportSender,
+    ) -> Result<(SipConnection, SipAddr)> {
+        let mut target = self.inner.resolve(uri, self.outbound.as_ref()).await?;
+        target.r#type = Some(transport);
+        info!("lookup target: {} -> {} (forced)", uri, target);
+        self.inner.connect(&target, sender).await
+    }
+
+    /// Resolve the server of `uri` without connecting
+    ///
+    /// Uses the outbound proxy if one is set, otherwise the DNS settings of
+    /// [`TransportConfig::dns`]. A lookup exceeding the DNS timeout fails
+    /// with `Error::DnsResolutionError`.
+    pub async fn resolve(&self, uri: &rsip::uri::Uri) -> Result<SipAddr> {
+        self.inner.resolve(uri, self.outbound.as_ref()).await
+    }
+
+    /// Replace the DNS settings used to locate servers
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rsipstack::transport::dns::{DnsConfig, DnsLookupOrder};
+    /// use rsipstack::transport::TransportLayer;
+    /// use std::time::Duration;
+    ///
+    /// let transport_layer = TransportLayer::new(Default::default());
+    /// transport_layer.set_dns_config(DnsConfig {
+    ///     timeout: Duration::from_secs(2),
+    ///     order: DnsLookupOrder::Srv,
+    ///     resolver: None,
+    /// });
+    /// ```
+    pub fn set_dns_config(&self, dns: DnsConfig) {
+        self.inner.config.lock().unwrap().dns = dns;
+    }
+
+    /// Limit the body size of inbound messages
+    ///
+    /// Applies to the connections served afterwards, including TCP
+    /// connections accepted by listeners added afterwards. A message whose
+    /// `Content-Length` exceeds `max_body_size` is passed on with its headers
+    /// only and its body is discarded while it is read.
+    pub fn set_max_body_size(&self, max_body_size: Option<usize>) {
+        self.inner.config.lock().unwrap().max_body_size = max_body_size;
+    }
+
     pub async fn serve_listens(&self, sender: TransportSender) -> Result<()> {
         let listens = self.inner.listens.lock().unwrap().clone();
         for (_, transport) in listens {
@@ -84,15 +183,64 @@ impl TransportLayer {
         Ok(())
     }
 
+    /// Get all local addresses the transport layer listens on
+    ///
+    /// Includes the datagram transports as well as TCP and WebSocket
+    /// listeners, each with its transport type.
     pub fn get_addrs(&self) -> Vec<SipAddr> {
-        self.inner.listens.lock().unwrap().keys().cloned().collect()
+        let mut addrs: Vec<SipAddr> = self.inner.listens.lock().unwrap().keys().cloned().collect();
+        for addr in self.inner.listeners.lock().unwrap().iter() {
+            if !addrs.contains(addr) {
+                addrs.push(addr.clone());
+            }
+        }
+        addrs
+    }
+
+    /// Bind a listener for the transport of `addr`
+    ///
+    /// Dispatches to the UDP, TCP, TLS or WebSocket listener by the
+    /// transport type of `addr`; an address without one listens on UDP.
+    /// Call it once per transport to listen on several at the same time,
+    /// e.g. UDP and TCP on 5060 plus TLS on 5061.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(SipAddr)` - The bound address, with the actual port if `addr` used port 0
+    /// * `Err(Error)` - Binding failed or the transport is not supported
+    pub async fn add_listener(&self, addr: SipAddr, sender: TransportSender) -> Result<SipAddr> {
+        let local = addr.get_socketaddr()?;
+        match addr.r#type {
+            Some(rsip::transport::Transport::Udp) | None => self.add_udp_listener(local).await,
+            Some(rsip::transport::Transport::Tcp) => self.add_tcp_listener(local, sender).await,
+            Some(rsip::transport::Transport::Tls) => self.add_tls_listener(local, sender).await,
+            Some(rsip::transport::Transport::Ws) => {
+                self.add_ws_listener(local, sender, false).await
+            }
+            Some(rsip::transport::Transport::Wss) => {
+                self.add_ws_listener(local, sender, true).await
+            }
+            Some(transport) => Err(crate::Error::TransportLayerError(
+                format!("unsupported listen transport: {}", transport),
+                addr,
+            )),
+        }
     }
 
     /// Create and add UDP listener
     pub async fn add_udp_listener(&self, local: SocketAddr) -> Result<SipAddr> {
         use super::udp::UdpConnection;
 
-        let connection = UdpConnection::create_connection(local, None).await?;
+        let (reuse_address, reuse_port) = {
+            let config = self.inner.config.lock().unwrap();
+            (config.reuse_address, config.reuse_port)
+        };
+        let connection = if reuse_address || reuse_port {
+            UdpConnection::create_connection_with_reuse(local, None, reuse_address, reuse_port)
+                .await?
+        } else {
+            UdpConnection::create_connection(local, None).await?
+        };
         let addr = connection.get_addr().clone();
         self.add_transport(connection.into());
         Ok(addr)
@@ -105,6 +253,7 @@ impl TransportLayer {
         sender: TransportSender,
     ) -> Result<SipAddr> {
         let (listener, addr) = TcpConnection::create_listener(local).await?;
+        let max_body_size = self.inner.config.lock().unwrap().max_body_size;
 
         let cancel_token = self.inner.cancel_token.child_token();
         let addr_clone = addr.clone();
@@ -115,7 +264,7 @@ impl TransportLayer {
                 _ = cancel_token.cancelled() => {
                     info!("TCP listener cancelled: {}", addr_clone);
                 }
-                result = TcpConnection::serve_listener(listener, addr_clone.clone(), sender_clone) => {
+                result = TcpConnection::serve_listener_with_limit(listener, addr_clone.clone(), sender_clone, max_body_size) => {
                     if let Err(e) = result {
                         warn!("TCP listener error: {}: {:?}", addr_clone, e);
                     }
@@ -123,6 +272,7 @@ impl TransportLayer {
             }
         });
 
+        self.inner.listeners.lock().unwrap().push(addr.clone());
         Ok(addr)
     }
 
@@ -214,6 +364,7 @@ impl TransportLayer {
             }
         });
 
+        self.inner.listeners.lock().unwrap().push(addr.clone());
         Ok(addr)
     }
 }
@@ -230,54 +381,34 @@ impl TransportLayerInner {
         self.listens.lock().unwrap().remove(addr);
     }
 
-    async fn lookup<'a>(
-        &'a self,
+    async fn lookup(
+        &self,
         uri: &rsip::uri::Uri,
-        outbound: Option<&'a SipAddr>,
+        outbound: Option<&SipAddr>,
         sender: TransportSender,
     ) -> Result<(SipConnection, SipAddr)> {
-        let target = if let Some(addr) = outbound {
-            addr
-        } else {
-            let context = rsip_dns::Context::initialize_from(
-                uri.clone(),
-                rsip_dns::AsyncTrustDnsClient::new(
-                    TokioAsyncResolver::tokio(Default::default(), Default::default()).unwrap(),
-                ),
-                rsip_dns::SupportedTransports::any(),
-            )?;
-
-            let mut lookup = rsip_dns::Lookup::from(context);
-            match lookup.resolve_next().await {
-                Some(mut target) => {
-                    match uri.host_with_port.host {
-                        rsip::Host::IpAddr(_) => {
-                            if let Some(port) = uri.host_with_port.port {
-                                target.port = port;
-                            }
-                        }
-                        _ => {}
-                    }
-                    &SipAddr {
-                        r#type: Some(target.transport),
-                        addr: HostWithPort::from(SocketAddr::new(
-                            target.ip_addr,
-                            u16::from(target.port),
-                        )),
-                    }
-                }
-                None => {
-                    return Err(crate::Error::DnsResolutionError(format!(
-                        "DNS resolution error: {}",
-                        uri
-                    )))
-                }
-            }
-        };
-
+        let target = self.resolve(uri, outbound).await?;
         info!("lookup target: {} -> {}", uri, target);
+        self.connect(&target, sender).await
+    }
+
+    async fn resolve(&self, uri: &rsip::uri::Uri, outbound: Option<&SipAddr>) -> Result<SipAddr> {
+        if let Some(addr) = outbound {
+            return Ok(addr.clone());
+        }
+        let dns = self.config.lock().unwrap().dns.clone();
+        dns.resolve(uri).await
+    }
 
-        if let Some(transport) = self.listens.lock().unwrap().get(&target) {
+    async fn connect(
+        &self,
+        target: &SipAddr,
+        sender: TransportSender,
+    ) -> Result<(SipConnection, SipAddr)> {
+        if let Some(transport) = self.listens.lock().unwrap().get(target) {
+            return Ok((transport.clone(), target.clone()));
+        }
+        if let Some(transport) = self.connections.lock().unwrap().get(target) {
             return Ok((transport.clone(), target.clone()));
         }
 
@@ -290,22 +421,11 @@ impl TransportLayerInner {
                     }
                 }
             }
-            Some(rsip::transport::Transport::Tcp) => {
-                let connection = TcpConnection::connect(target).await?;
-                let sip_connection = SipConnection::Tcp(connection);
-                self.start_serve(sip_connection.clone(), sender);
-                return Ok((sip_connection, target.clone()));
-            }
-            Some(rsip::transport::Transport::Tls) => {
-                let connection = TlsConnection::connect(target, None).await?;
-                let sip_connection = SipConnection::Tls(connection);
-                self.start_serve(sip_connection.clone(), sender);
-                return Ok((sip_connection, target.clone()));
-            }
-            Some(rsip::transport::Transport::Ws) | Some(rsip::transport::Transport::Wss) => {
-                let connection = WebSocketConnection::connect(target).await?;
-                let sip_connection = SipConnection::WebSocket(connection);
-                self.start_serve(sip_connection.clone(), sender);
+            Some(rsip::transport::Transport::Tcp)
+            | Some(rsip::transport::Transport::Tls)
+            | Some(rsip::transport::Transport::Ws)
+            | Some(rsip::transport::Transport::Wss) => {
+                let sip_connection = self.dial_once(target, sender).await?;
                 return Ok((sip_connection, target.clone()));
             }
             _ => {}
@@ -317,10 +437,83 @@ impl TransportLayerInner {
         ));
     }
 
+    /// Dial `target` unless a concurrent request already did
+    ///
+    /// Dials to the same target are serialized, so requests racing for a
+    /// new connection share the one the first of them opens, and only they
+    /// wait out its reconnect backoff.
+    async fn dial_once(&self, target: &SipAddr, sender: TransportSender) -> Result<SipConnection> {
+        let dial_lock = self
+            .dialing
+            .lock()
+            .unwrap()
+            .entry(target.clone())
+            .or_default()
+            .clone();
+        let _dialing = dial_lock.lock().await;
+
+        let pooled = self.connections.lock().unwrap().get(target).cloned();
+        let result = match pooled {
+            Some(connection) => Ok(connection),
+            None => self.dial_with_backoff(target).await.map(|connection| {
+                self.connections
+                    .lock()
+                    .unwrap()
+                    .insert(target.clone(), connection.clone());
+                self.start_serve(connection.clone(), sender);
+                connection
+            }),
+        };
+
+        // the map and this call hold the lock; more references are waiters
+        let mut dialing = self.dialing.lock().unwrap();
+        if Arc::strong_count(&dial_lock) <= 2 {
+            dialing.remove(target);
+        }
+        result
+    }
+
+    async fn dial_with_backoff(&self, target: &SipAddr) -> Result<SipConnection> {
+        let backoff = self.config.lock().unwrap().reconnect.clone();
+        let mut delay = backoff.initial;
+        let mut attempt = 0;
+        loop {
+            match Self::dial(target).await {
+                Ok(connection) => {
+                    if attempt > 0 {
+                        info!("reconnected to {} after {} retries", target, attempt);
+                    }
+                    return Ok(connection);
+                }
+                Err(e) if attempt < backoff.max_attempts => {
+                    attempt += 1;
+                    warn!("connect to {} failed: {}, retry in {:?}", target, e, delay);
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(backoff.max);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn dial(target: &SipAddr) -> Result<SipConnection> {
+        match target.r#type {
+            Some(rsip::transport::Transport::Tls) => Ok(SipConnection::Tls(
+                TlsConnection::connect(target, None).await?,
+            )),
+            Some(rsip::transport::Transport::Ws) | Some(rsip::transport::Transport::Wss) => Ok(
+                SipConnection::WebSocket(WebSocketConnection::connect(target).await?),
+            ),
+            _ => Ok(SipConnection::Tcp(TcpConnection::connect(target).await?)),
+        }
+    }
+
     pub fn start_serve(&self, transport: SipConnection, sender: TransportSender) {
+        transport.set_max_body_size(self.config.lock().unwrap().max_body_size);
         let sub_token = self.cancel_token.child_token();
         let sender_clone = sender.clone();
         let listens_ref = self.listens.clone();
+        let connections_ref = self.connections.clone();
 
         tokio::spawn(async move {
             select! {
@@ -328,7 +521,12 @@ impl TransportLayerInner {
                 _ = transport.serve_loop(sender_clone.clone()) => {
                 }
             }
+            transport.stop_keepalives();
             listens_ref.lock().unwrap().remove(transport.get_addr());
+            connections_ref
+                .lock()
+                .unwrap()
+                .retain(|_, c| c.get_addr() != transport.get_addr());
             warn!("transport serve_loop exited: {}", transport.get_addr());
             sender_clone.send(TransportEvent::Closed(transport)).ok();
         });
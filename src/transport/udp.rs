@@ -1,12 +1,21 @@
-use super::{connection::TransportSender, SipAddr, SipConnection};
+use super::{connection::TransportSender, keepalive::KeepaliveSet, SipAddr, SipConnection};
 use crate::{
+    rsip_ext::sanitize_for_log,
     transport::{
-        connection::{KEEPALIVE_REQUEST, KEEPALIVE_RESPONSE},
+        connection::{
+            header_len, oversized_body, MalformedMessage, KEEPALIVE_REQUEST, KEEPALIVE_RESPONSE,
+        },
         TransportEvent,
     },
     Result,
 };
-use std::{net::SocketAddr, sync::Arc};
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 use tokio::net::UdpSocket;
 use tracing::{debug, error, info, instrument};
 pub struct UdpInner {
@@ -18,6 +27,8 @@ pub struct UdpInner {
 pub struct UdpConnection {
     pub external: Option<SipAddr>,
     inner: Arc<UdpInner>,
+    pub(crate) keepalives: KeepaliveSet,
+    pub(crate) max_body_size: Arc<AtomicUsize>,
 }
 
 impl UdpConnection {
@@ -28,6 +39,8 @@ impl UdpConnection {
                 addr: addr.into(),
             }),
             inner: Arc::new(inner),
+            keepalives: KeepaliveSet::default(),
+            max_body_size: Arc::new(AtomicUsize::new(0)),
         }
     }
 
@@ -36,7 +49,46 @@ impl UdpConnection {
         external: Option<SocketAddr>,
     ) -> Result<Self> {
         let conn = UdpSocket::bind(local).await?;
+        Self::from_socket(conn, external)
+    }
 
+    /// Create a UDP connection on a socket bound with address/port reuse
+    ///
+    /// Setting `SO_REUSEADDR` and `SO_REUSEPORT` lets a restarted server
+    /// bind the same port again right away, so NAT bindings towards its
+    /// peers stay valid. `reuse_port` is only supported on unix platforms.
+    ///
+    /// # Parameters
+    ///
+    /// * `local` - Local address to bind
+    /// * `external` - Public address advertised instead of the local one
+    /// * `reuse_address` - Set `SO_REUSEADDR`
+    /// * `reuse_port` - Set `SO_REUSEPORT`
+    pub async fn create_connection_with_reuse(
+        local: SocketAddr,
+        external: Option<SocketAddr>,
+        reuse_address: bool,
+        reuse_port: bool,
+    ) -> Result<Self> {
+        let socket = socket2::Socket::new(
+            socket2::Domain::for_address(local),
+            socket2::Type::DGRAM,
+            Some(socket2::Protocol::UDP),
+        )?;
+        socket.set_reuse_address(reuse_address)?;
+        #[cfg(unix)]
+        socket.set_reuse_port(reuse_port)?;
+        #[cfg(not(unix))]
+        if reuse_port {
+            info!("SO_REUSEPORT is not supported on this platform, ignored");
+        }
+        socket.set_nonblocking(true)?;
+        socket.bind(&local.into())?;
+        let conn = UdpSocket::from_std(socket.into())?;
+        Self::from_socket(conn, external)
+    }
+
+    fn from_socket(conn: UdpSocket, external: Option<SocketAddr>) -> Result<Self> {
         let addr = SipAddr {
             r#type: Some(rsip::transport::Transport::Udp),
             addr: conn.local_addr()?.into(),
@@ -48,6 +100,8 @@ impl UdpConnection {
                 addr: addr.into(),
             }),
             inner: Arc::new(UdpInner { addr, conn }),
+            keepalives: KeepaliveSet::default(),
+            max_body_size: Arc::new(AtomicUsize::new(0)),
         };
         info!("created UDP connection: {} external: {:?}", t, external);
         Ok(t)
@@ -64,6 +118,16 @@ impl UdpConnection {
                 }
             };
 
+            let source = SipAddr {
+                r#type: Some(rsip::transport::Transport::Udp),
+                addr: addr.into(),
+            };
+
+            // pongs of our own keepalives, including the CRLF one, are
+            // recorded before the built-in keepalive handling drops them
+            if self.keepalives.on_received(Some(&source), &buf[..len]) {
+                continue;
+            }
             match &buf[..len] {
                 KEEPALIVE_REQUEST => {
                     self.inner.conn.send_to(KEEPALIVE_RESPONSE, addr).await.ok();
@@ -76,6 +140,28 @@ impl UdpConnection {
                     }
                 }
             }
+            // an oversized body is cut off here, the endpoint answers the
+            // headers with 413
+            let len = match header_len(&buf[..len]) {
+                Some(headers) => match oversized_body(
+                    &buf[..headers],
+                    self.max_body_size.load(Ordering::Relaxed),
+                ) {
+                    Some(declared) => {
+                        info!("dropping body of {} bytes from {}", declared, addr);
+                        headers
+                    }
+                    None => len,
+                },
+                None => len,
+            };
+            let malformed = |error: String| {
+                TransportEvent::Malformed(MalformedMessage {
+                    data: buf[..len].to_vec(),
+                    error,
+                    source: source.clone(),
+                })
+            };
 
             let undecoded = match std::str::from_utf8(&buf[..len]) {
                 Ok(s) => s,
@@ -86,6 +172,7 @@ impl UdpConnection {
                         e,
                         &buf[..len]
                     );
+                    sender.send(malformed(e.to_string()))?;
                     continue;
                 }
             };
@@ -95,8 +182,11 @@ impl UdpConnection {
                 Err(e) => {
                     info!(
                         "error parsing SIP message from: {} error: {} buf: {}",
-                        addr, e, undecoded
+                        addr,
+                        e,
+                        sanitize_for_log(undecoded)
                     );
+                    sender.send(malformed(e.to_string()))?;
                     continue;
                 }
             };
@@ -110,7 +200,9 @@ impl UdpConnection {
                 Err(e) => {
                     info!(
                         "error updating SIP via from: {} error: {:?} buf: {}",
-                        addr, e, undecoded
+                        addr,
+                        e,
+                        sanitize_for_log(undecoded)
                     );
                     continue;
                 }
@@ -121,16 +213,13 @@ impl UdpConnection {
                 len,
                 addr,
                 self.get_addr(),
-                undecoded
+                sanitize_for_log(undecoded)
             );
 
             sender.send(TransportEvent::Incoming(
                 msg,
                 SipConnection::Udp(self.clone()),
-                SipAddr {
-                    r#type: Some(rsip::transport::Transport::Udp),
-                    addr: addr.into(),
-                },
+                source,
             ))?;
         }
     }
@@ -146,7 +235,12 @@ impl UdpConnection {
             None => SipConnection::get_destination(&msg),
         }?;
         let buf = msg.to_string();
-        debug!("send {} -> {} {}", buf.len(), destination, buf);
+        debug!(
+            "send {} -> {} {}",
+            buf.len(),
+            destination,
+            sanitize_for_log(&buf)
+        );
 
         self.inner
             .conn
@@ -191,6 +285,11 @@ impl UdpConnection {
             &self.inner.addr
         }
     }
+
+    /// The address the socket is actually bound to, ignoring any external address
+    pub fn get_local_addr(&self) -> &SipAddr {
+        &self.inner.addr
+    }
 }
 
 impl std::fmt::Display for UdpConnection {
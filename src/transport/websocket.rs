@@ -1,6 +1,8 @@
 use crate::{
+    rsip_ext::sanitize_for_log,
     transport::{
-        connection::{TransportSender, KEEPALIVE_REQUEST, KEEPALIVE_RESPONSE},
+        connection::{MalformedMessage, TransportSender, KEEPALIVE_REQUEST, KEEPALIVE_RESPONSE},
+        keepalive::KeepaliveSet,
         sip_addr::SipAddr,
         stream::StreamConnection,
         SipConnection, TransportEvent,
@@ -29,6 +31,7 @@ pub struct WebSocketInner {
     pub remote_addr: Option<SipAddr>,
     pub ws_sink: Arc<Mutex<WsSink>>,
     pub ws_read: Arc<Mutex<WsRead>>,
+    pub(crate) keepalives: KeepaliveSet,
 }
 
 #[derive(Clone)]
@@ -48,7 +51,7 @@ impl WebSocketConnection {
             rsip::host_with_port::Host::IpAddr(ip) => ip.to_string(),
         };
 
-        let port = remote.addr.port.as_ref().map_or(5060, |p| *p.value());
+        let port = remote.port_or_default();
         let url = format!("{}://{}:{}/sip", scheme, host, port);
 
         let (ws_stream, _) = connect_async(&url).await?;
@@ -69,6 +72,7 @@ impl WebSocketConnection {
                 remote_addr: Some(remote.clone()),
                 ws_sink: Arc::new(Mutex::new(ws_sink)),
                 ws_read: Arc::new(Mutex::new(_ws_stream)),
+                keepalives: KeepaliveSet::default(),
             }),
         };
 
@@ -132,9 +136,10 @@ impl WebSocketConnection {
                                 remote_addr: Some(remote_sip_addr.clone()),
                                 ws_sink: Arc::new(Mutex::new(ws_sink)),
                                 ws_read: Arc::new(Mutex::new(ws_stream)),
+                                keepalives: KeepaliveSet::default(),
                             }),
                         };
-                        let sip_connection = SipConnection::WebSocket(connection.clone());
+                        let sip_connection = SipConnection::WebSocket(connection);
 
                         if let Err(e) =
                             sender_clone.send(TransportEvent::New(sip_connection.clone()))
@@ -142,7 +147,7 @@ impl WebSocketConnection {
                             error!("Error sending new connection event: {:?}", e);
                             return;
                         }
-                        match connection.serve_loop(sender_clone.clone()).await {
+                        match sip_connection.serve_loop(sender_clone.clone()).await {
                             Ok(_) => {}
                             Err(e) => {
                                 error!("Error serving WebSocket connection: {:?}", e);
@@ -167,7 +172,7 @@ impl StreamConnection for WebSocketConnection {
     async fn send_message(&self, msg: SipMessage) -> Result<()> {
         let data = msg.to_string();
         let mut sink = self.inner.ws_sink.lock().await;
-        info!("WebSocket send:{}", data);
+        info!("WebSocket send:{}", sanitize_for_log(&data));
         sink.send(Message::Text(data.into())).await?;
         Ok(())
     }
@@ -183,6 +188,17 @@ impl StreamConnection for WebSocketConnection {
         let remote_addr = self.inner.remote_addr.clone().unwrap().clone();
         let mut ws_read = self.inner.ws_read.lock().await;
         while let Some(msg) = ws_read.next().await {
+            let pong = match &msg {
+                Ok(Message::Text(text)) => self
+                    .inner
+                    .keepalives
+                    .on_received(None, text.as_str().as_bytes()),
+                Ok(Message::Binary(bin)) => self.inner.keepalives.on_received(None, bin),
+                _ => false,
+            };
+            if pong {
+                continue;
+            }
             match msg {
                 Ok(Message::Text(text)) => match SipMessage::try_from(text.as_str()) {
                     Ok(sip_msg) => {
@@ -197,6 +213,13 @@ impl StreamConnection for WebSocketConnection {
                     }
                     Err(e) => {
                         warn!("Error parsing SIP message: {}", e);
+                        sender
+                            .send(TransportEvent::Malformed(MalformedMessage {
+                                data: text.as_str().as_bytes().to_vec(),
+                                error: e.to_string(),
+                                source: remote_addr.clone(),
+                            }))
+                            .ok();
                     }
                 },
                 Ok(Message::Binary(bin)) => {
@@ -221,10 +244,24 @@ impl StreamConnection for WebSocketConnection {
                             }
                             Err(e) => {
                                 warn!("Error parsing SIP message: {}", e);
+                                sender
+                                    .send(TransportEvent::Malformed(MalformedMessage {
+                                        data: bin.to_vec(),
+                                        error: e.to_string(),
+                                        source: remote_addr.clone(),
+                                    }))
+                                    .ok();
                             }
                         },
                         Err(e) => {
                             warn!("Error decoding binary message: {}", e);
+                            sender
+                                .send(TransportEvent::Malformed(MalformedMessage {
+                                    data: bin.to_vec(),
+                                    error: e.to_string(),
+                                    source: remote_addr.clone(),
+                                }))
+                                .ok();
                         }
                     }
                 }